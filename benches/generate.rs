@@ -0,0 +1,29 @@
+//! Run with and without `--features simd` to compare compositing paths.
+
+use std::hint::black_box;
+
+use captchagen::{BlendMode, Config, Generator, LineStyle};
+use criterion::{Criterion, criterion_group, criterion_main};
+
+fn generate(c: &mut Criterion) {
+    let generator = Generator::new(Config::default()).unwrap();
+    c.bench_function("generate", |b| b.iter(|| black_box(generator.generate())));
+
+    // Every noise stroke is converted and composited over the full canvas.
+    let wide = Generator::new(Config {
+        width: 960,
+        height: 320,
+        line_style: LineStyle {
+            blend: BlendMode::Normal,
+            ..LineStyle::default()
+        },
+        ..Config::default()
+    })
+    .unwrap();
+    c.bench_function("generate 960x320", |b| {
+        b.iter(|| black_box(wide.generate()))
+    });
+}
+
+criterion_group!(benches, generate);
+criterion_main!(benches);
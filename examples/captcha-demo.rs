@@ -0,0 +1,253 @@
+//! A tiny server for trying captchas in a browser:
+//!
+//! ```sh
+//! cargo run --example captcha-demo [address]
+//! ```
+//!
+//! then open http://127.0.0.1:8080. With `--features theme`, a theme file
+//! can be passed after the address.
+
+use std::{
+    collections::HashMap,
+    io::{BufRead, BufReader, Read, Write},
+    net::{TcpListener, TcpStream},
+    time::Duration,
+};
+
+use captchagen::{
+    CaptchaStore, Config, Difficulty, Generator, ImageCache, MemoryStore, Mode, OverrideOptions,
+    SizeQuestion, VerifyOutcome,
+};
+
+const MODES: [(&str, Mode); 4] = [
+    ("plain", Mode::Plain),
+    ("positions", Mode::Positions { count: 3 }),
+    ("colors", Mode::Colors { count: 2 }),
+    ("sizes", Mode::Sizes(SizeQuestion::Largest)),
+];
+
+const DIFFICULTIES: [(&str, Option<Difficulty>); 4] = [
+    ("default", None),
+    ("easy", Some(Difficulty::Easy)),
+    ("medium", Some(Difficulty::Medium)),
+    ("hard", Some(Difficulty::Hard)),
+];
+
+struct Demo {
+    generators: Vec<Generator>,
+    store: MemoryStore,
+    images: ImageCache,
+}
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let mut args = std::env::args().skip(1);
+    let address = args.next().unwrap_or_else(|| "127.0.0.1:8080".to_string());
+
+    #[allow(unused_mut)]
+    let mut base = Config {
+        expires_in: Some(Duration::from_secs(300)),
+        ..Config::default()
+    };
+    #[cfg(feature = "theme")]
+    if let Some(path) = args.next() {
+        captchagen::Theme::from_toml(&std::fs::read_to_string(path)?)?.apply(&mut base);
+    }
+
+    let generators = MODES
+        .iter()
+        .map(|&(_, mode)| {
+            Generator::new(Config {
+                mode,
+                ..base.clone()
+            })
+        })
+        .collect::<Result<_, _>>()?;
+    let demo = Demo {
+        generators,
+        store: MemoryStore::new(),
+        images: ImageCache::new(Duration::from_secs(300)),
+    };
+
+    let listener = TcpListener::bind(&address)?;
+    println!("listening on http://{address}");
+    for stream in listener.incoming() {
+        if let Err(err) = demo.handle(stream?) {
+            eprintln!("request failed: {err}");
+        }
+    }
+    Ok(())
+}
+
+impl Demo {
+    fn handle(&self, stream: TcpStream) -> Result<(), Box<dyn std::error::Error>> {
+        let mut reader = BufReader::new(&stream);
+        let mut request_line = String::new();
+        reader.read_line(&mut request_line)?;
+        let mut content_length = 0;
+        loop {
+            let mut line = String::new();
+            reader.read_line(&mut line)?;
+            if line.trim().is_empty() {
+                break;
+            }
+            if let Some((name, value)) = line.split_once(':')
+                && name.eq_ignore_ascii_case("content-length")
+            {
+                content_length = value.trim().parse().unwrap_or(0).min(4096);
+            }
+        }
+
+        let mut parts = request_line.split_whitespace();
+        let method = parts.next().unwrap_or_default();
+        let target = parts.next().unwrap_or("/");
+        let (path, query) = target.split_once('?').unwrap_or((target, ""));
+        let mut params = parse_form(query);
+
+        match (method, path) {
+            ("GET", "/") => self.page(&stream, &params, None),
+            ("POST", "/verify") => {
+                let mut body = vec![0; content_length];
+                reader.read_exact(&mut body)?;
+                params.extend(parse_form(&String::from_utf8_lossy(&body)));
+                let id = params.get("id").map_or("", String::as_str);
+                let answer = params.get("answer").map_or("", String::as_str);
+                let outcome = self.store.verify(id, answer);
+                self.images.remove(id);
+                self.page(&stream, &params, Some(outcome))
+            }
+            ("GET", path) if path.starts_with("/image/") => {
+                match self.images.get(&path["/image/".len()..]) {
+                    Some(image) => respond(&stream, "200 OK", &image.headers(None), &image.image),
+                    None => respond(&stream, "404 Not Found", &[], b"expired"),
+                }
+            }
+            _ => respond(&stream, "404 Not Found", &[], b"not found"),
+        }
+    }
+
+    fn page(
+        &self,
+        stream: &TcpStream,
+        params: &HashMap<String, String>,
+        outcome: Option<VerifyOutcome>,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        // Only known names end up in the page.
+        let (index, (mode, _)) = MODES
+            .iter()
+            .enumerate()
+            .find(|(_, (name, _))| params.get("mode").is_some_and(|mode| mode == name))
+            .unwrap_or((0, &MODES[0]));
+        let &(difficulty, preset) = DIFFICULTIES
+            .iter()
+            .find(|(name, _)| {
+                params
+                    .get("difficulty")
+                    .is_some_and(|difficulty| difficulty == name)
+            })
+            .unwrap_or(&DIFFICULTIES[0]);
+        let overrides = OverrideOptions {
+            difficulty: preset,
+            ..OverrideOptions::default()
+        };
+
+        let captcha = self.generators[index].generate_with_overrides(&overrides)?;
+        self.store.issue(&captcha);
+        self.images.insert(&captcha);
+
+        let result = match outcome {
+            Some(outcome) => format!("<p><b>{outcome:?}</b></p>"),
+            None => String::new(),
+        };
+        let query = format!("mode={mode}&difficulty={difficulty}");
+        let html = format!(
+            r#"<!doctype html>
+<html>
+<head><meta charset="utf-8"><title>captchagen demo</title></head>
+<body style="font-family: sans-serif">
+<form method="get" action="/">
+  {modes}
+  {difficulties}
+  <button>Show</button>
+</form>
+{result}
+<p><img src="/image/{id}" width="{width}" height="{height}" alt="Captcha"></p>
+<form method="post" action="/verify?{query}">
+  <input type="hidden" name="id" value="{id}">
+  <input name="answer" autocomplete="off" autofocus inputmode="{input_mode}">
+  <button>Verify</button>
+  <a href="/?{query}">Refresh</a>
+</form>
+</body>
+</html>"#,
+            modes = select("mode", MODES.map(|(name, _)| name), mode),
+            difficulties = select("difficulty", DIFFICULTIES.map(|(name, _)| name), difficulty),
+            id = captcha.id,
+            width = captcha.width,
+            height = captcha.height,
+            input_mode = captcha.input_hint.input_mode(),
+        );
+        respond(
+            stream,
+            "200 OK",
+            &[("Content-Type", "text/html; charset=utf-8".to_string())],
+            html.as_bytes(),
+        )
+    }
+}
+
+fn select<const N: usize>(name: &str, options: [&str; N], selected: &str) -> String {
+    let options: String = options
+        .iter()
+        .map(|option| {
+            let mark = if *option == selected { " selected" } else { "" };
+            format!("<option{mark}>{option}</option>")
+        })
+        .collect();
+    format!(r#"<select name="{name}">{options}</select>"#)
+}
+
+fn respond(
+    mut stream: &TcpStream,
+    status: &str,
+    headers: &[(&str, String)],
+    body: &[u8],
+) -> Result<(), Box<dyn std::error::Error>> {
+    let mut response = format!("HTTP/1.1 {status}\r\nConnection: close\r\n");
+    for (name, value) in headers {
+        response.push_str(&format!("{name}: {value}\r\n"));
+    }
+    if !headers.iter().any(|(name, _)| *name == "Content-Length") {
+        response.push_str(&format!("Content-Length: {}\r\n", body.len()));
+    }
+    response.push_str("\r\n");
+    stream.write_all(response.as_bytes())?;
+    stream.write_all(body)?;
+    Ok(())
+}
+
+/// Decodes `a=1&b=2` with `+` and percent escapes.
+fn parse_form(form: &str) -> HashMap<String, String> {
+    form.split('&')
+        .filter_map(|pair| pair.split_once('='))
+        .map(|(name, value)| (decode(name), decode(value)))
+        .collect()
+}
+
+fn decode(value: &str) -> String {
+    let mut bytes = Vec::with_capacity(value.len());
+    let mut input = value.bytes();
+    while let Some(byte) = input.next() {
+        match byte {
+            b'+' => bytes.push(b' '),
+            b'%' => {
+                let hex: Vec<u8> = input.by_ref().take(2).collect();
+                let decoded = std::str::from_utf8(&hex)
+                    .ok()
+                    .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+                bytes.push(decoded.unwrap_or(b'?'));
+            }
+            byte => bytes.push(byte),
+        }
+    }
+    String::from_utf8_lossy(&bytes).into_owned()
+}
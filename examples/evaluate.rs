@@ -0,0 +1,89 @@
+//! Estimates how well each config resists machine solving, to help pick a
+//! difficulty preset:
+//!
+//! ```sh
+//! cargo run --release --example evaluate --features calibrate,embedded-font [samples] [preset...]
+//! ```
+//!
+//! Renders `samples` captchas per preset, 100 by default, lets the
+//! reference solver of `Generator::calibrate` read them and prints the
+//! estimated solve rates next to the average generation time. Without
+//! presets, the default config, `easy`, `medium` and `hard`, and two
+//! variants of `medium` with other lengths and charsets are compared.
+//!
+//! The solve rates come from the crate's built-in template matching
+//! solver, not from a real OCR model. Real solvers do better, so only
+//! compare the numbers with each other.
+
+use std::time::Instant;
+
+use captchagen::{Config, Difficulty, Generator, Preset, Theme};
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let medium = Theme {
+        difficulty: Some(Difficulty::Medium),
+        ..Theme::default()
+    };
+    Config::register_preset(
+        "short-digits",
+        Preset {
+            length: Some(4),
+            charset: Some("0123456789".into()),
+            theme: medium.clone(),
+            ..Preset::default()
+        },
+    )?;
+    Config::register_preset(
+        "long-mixed",
+        Preset {
+            length: Some(6),
+            charset: Some("ABCDEFGHJKLMNPQRSTUVWXYZabcdefghijkmnpqrstuvwxyz23456789".into()),
+            width: Some(180),
+            theme: medium,
+            ..Preset::default()
+        },
+    )?;
+
+    let mut args = std::env::args().skip(1);
+    let samples: u32 = args.next().map_or(100, |arg| arg.parse().expect("samples"));
+    let mut presets: Vec<String> = args.collect();
+    if presets.is_empty() {
+        presets = [
+            "default",
+            "easy",
+            "medium",
+            "hard",
+            "short-digits",
+            "long-mixed",
+        ]
+        .map(String::from)
+        .to_vec();
+    }
+
+    println!(
+        "{:<12} {:>10} {:>14} {:>16}",
+        "preset", "solve rate", "char accuracy", "generation time"
+    );
+    for name in &presets {
+        let config = match name.as_str() {
+            "default" => Config::default(),
+            name => Config::preset(name).ok_or_else(|| format!("unknown preset {name:?}"))?,
+        };
+        let generator = Generator::new(config)?;
+
+        let started = Instant::now();
+        for _ in 0..samples {
+            generator.generate()?;
+        }
+        let generation_time = started.elapsed() / samples.max(1);
+        let calibration = generator.calibrate(samples)?;
+
+        println!(
+            "{name:<12} {:>9.1}% {:>13.1}% {:>13.2} ms",
+            calibration.solve_rate() * 100.0,
+            calibration.char_accuracy * 100.0,
+            generation_time.as_secs_f64() * 1000.0,
+        );
+    }
+    Ok(())
+}
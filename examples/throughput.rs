@@ -0,0 +1,33 @@
+//! Measures how many captchas this machine can issue with the default
+//! config, to size instances:
+//!
+//! ```sh
+//! cargo run --release --example throughput [threads] [seconds]
+//! ```
+//!
+//! Run it once per thread count to find where throughput stops growing.
+
+use std::time::Duration;
+
+use captchagen::{Config, bench};
+
+fn main() {
+    let mut args = std::env::args().skip(1);
+    let threads = args.next().map_or(4, |arg| arg.parse().expect("threads"));
+    let seconds = args.next().map_or(10, |arg| arg.parse().expect("seconds"));
+
+    let report = bench::throughput(Config::default(), threads, Duration::from_secs(seconds))
+        .expect("benchmark failed");
+    println!(
+        "{} threads: {:.0} captchas/s, latency p50 {:?} p99 {:?} max {:?}, {} bytes per image",
+        report.threads,
+        report.per_second(),
+        report.p50_latency,
+        report.p99_latency,
+        report.max_latency,
+        report.mean_bytes,
+    );
+    if report.failed_verifications > 0 {
+        println!("{} verifications failed", report.failed_verifications);
+    }
+}
@@ -0,0 +1,24 @@
+#![no_main]
+
+use captchagen::{ArithmeticConfig, CaptchaStore, MemoryStore};
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|input: (ArithmeticConfig, &str)| {
+    let (config, guess) = input;
+    let challenge = config.generate();
+    let _ = challenge.verify(guess);
+
+    if config.expires_in.is_none() {
+        assert!(challenge.verify(challenge.answer.expose()));
+
+        let store = MemoryStore::new();
+        store.issue(&challenge);
+        let alternate = store.issue_alternate(&challenge.id, &config).unwrap();
+        assert_eq!(alternate.id, challenge.id);
+        assert!(
+            store
+                .verify(&alternate.id, alternate.answer.expose())
+                .is_correct()
+        );
+    }
+});
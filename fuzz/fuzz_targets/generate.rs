@@ -0,0 +1,16 @@
+#![no_main]
+
+use captchagen::{Config, Generator};
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|config: Config| {
+    let never_expires = config.expires_in.is_none();
+    let Ok(captcha) = Generator::new(config).and_then(|generator| generator.generate()) else {
+        return;
+    };
+
+    assert!(!captcha.image.is_empty());
+    if never_expires {
+        assert!(captcha.verify(captcha.text.expose()));
+    }
+});
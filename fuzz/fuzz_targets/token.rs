@@ -0,0 +1,50 @@
+#![no_main]
+
+use std::time::Duration;
+
+use captchagen::{ArithmeticConfig, KeyRing, MemoryReplayCache, TokenKey, VerifyOutcome};
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|input: (&[u8], &str, &str)| {
+    let (key, token, answer) = input;
+    let key = TokenKey::new(key);
+    let _ = key.verify_token(token, answer);
+
+    // Single-use verification needs tokens that expire.
+    let challenge = ArithmeticConfig {
+        expires_in: Some(Duration::from_secs(60)),
+        ..ArithmeticConfig::default()
+    }
+    .generate();
+    let token = key.issue_token(&challenge);
+    assert!(
+        key.verify_token(&token, challenge.answer.expose())
+            .is_correct()
+    );
+
+    let cache = MemoryReplayCache::new();
+    let _ = key.verify_token_once(&token, answer, &cache);
+    assert_eq!(
+        key.verify_token_once(&token, challenge.answer.expose(), &cache),
+        VerifyOutcome::AlreadyUsed
+    );
+
+    let session_token = key.issue_session_token(&challenge, answer);
+    assert!(
+        key.verify_session_token(&session_token, challenge.answer.expose(), answer)
+            .is_correct()
+    );
+    assert_eq!(
+        key.verify_token(&session_token, challenge.answer.expose()),
+        VerifyOutcome::UnknownId
+    );
+
+    let ring = KeyRing::new("old", input.0).unwrap();
+    let _ = ring.verify_token(input.1, answer);
+    let ring_token = ring.issue_token(&challenge);
+    ring.rotate("new", b"rotated".as_slice()).unwrap();
+    assert!(
+        ring.verify_token(&ring_token, challenge.answer.expose())
+            .is_correct()
+    );
+});
@@ -0,0 +1,13 @@
+#![no_main]
+
+use captchagen::{hash_answer, verify_hashed};
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|input: (&str, &str)| {
+    let (hash, answer) = input;
+    let _ = verify_hashed(hash, answer);
+
+    let hash = hash_answer(answer);
+    assert!(verify_hashed(&hash, answer));
+    assert!(verify_hashed(&hash, &answer.to_ascii_uppercase()));
+});
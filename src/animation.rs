@@ -0,0 +1,367 @@
+use std::{f32::consts::TAU, time::Duration};
+
+use image::RgbaImage;
+
+use crate::{CaptchaError, Format, encode::png_error, pipeline};
+
+/// Renders the captcha as a looping animation whose interference lines
+/// move every frame, so no single frame shows the text undisturbed
+/// everywhere. [`Config::format`] picks the codec: [`Format::Png`] makes an
+/// APNG, and with their features GIF and WebP are animated too. Other
+/// formats can't be animated and [`Config::max_bytes`] isn't applied.
+/// A [`Ripple`] also makes the text itself move.
+///
+/// [`Config::format`]: crate::Config::format
+/// [`Config::max_bytes`]: crate::Config::max_bytes
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Animation {
+    pub frames: u32,
+    pub frame_delay: Duration,
+    pub ripple: Option<Ripple>,
+    /// Also encodes this frame as a static PNG into
+    /// [`Captcha::fallback_image`], for `<picture>` fallbacks on clients
+    /// that don't play the animation.
+    ///
+    /// [`Captcha::fallback_image`]: crate::Captcha::fallback_image
+    pub fallback: Option<FallbackFrame>,
+}
+
+impl Default for Animation {
+    fn default() -> Self {
+        Self {
+            frames: 8,
+            frame_delay: Duration::from_millis(150),
+            ripple: None,
+            fallback: None,
+        }
+    }
+}
+
+/// Which frame of an [`Animation`] its static fallback shows.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub enum FallbackFrame {
+    Middle,
+    /// Counted from 0, clamped to the last frame.
+    Index(u32),
+}
+
+impl FallbackFrame {
+    pub(crate) fn index(self, frames: usize) -> usize {
+        match self {
+            FallbackFrame::Middle => frames / 2,
+            FallbackFrame::Index(index) => (index as usize).min(frames.saturating_sub(1)),
+        }
+    }
+}
+
+/// A wave travelling across the text over the frames, bending glyphs a
+/// little differently in every frame. People follow the moving shapes
+/// easily, while any single frame is distorted.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub struct Ripple {
+    /// Largest displacement, in pixels.
+    pub amplitude: f32,
+    /// Distance between wave crests, in pixels.
+    pub wavelength: f32,
+    /// Wavelengths the wave travels per loop of the animation. Whole
+    /// numbers loop seamlessly.
+    pub speed: f32,
+}
+
+impl Default for Ripple {
+    fn default() -> Self {
+        Self {
+            amplitude: 3.0,
+            wavelength: 60.0,
+            speed: 1.0,
+        }
+    }
+}
+
+impl Ripple {
+    /// The ripple at `progress`, from 0 to 1, through the loop.
+    pub(crate) fn apply(&self, img: &RgbaImage, progress: f32) -> RgbaImage {
+        if self.amplitude == 0.0 || !self.wavelength.is_normal() {
+            return img.clone();
+        }
+        let phase = TAU * self.speed * progress;
+        let (amplitude, wavelength) = (self.amplitude, self.wavelength);
+        pipeline::warp(img, |x, y| {
+            // Mostly vertical waves moving right, with a weaker horizontal
+            // sway.
+            let dy = amplitude * (TAU * x / wavelength - phase).sin();
+            let dx = amplitude * 0.5 * (TAU * y / wavelength - phase).sin();
+            (x + dx, y + dy)
+        })
+    }
+}
+
+/// Encodes `frames`, all of the same size, as an endlessly looping
+/// animation.
+pub(crate) fn encode(
+    frames: &[RgbaImage],
+    format: Format,
+    delay: Duration,
+) -> Result<Vec<u8>, CaptchaError> {
+    match format {
+        Format::Png => encode_apng(frames, delay),
+        #[cfg(feature = "gif")]
+        Format::Gif => encode_gif(frames, delay),
+        #[cfg(feature = "webp")]
+        Format::WebP => encode_webp(frames, delay),
+        #[allow(unreachable_patterns)]
+        other => Err(CaptchaError::InvalidInput(format!(
+            "{other:?} output can't be animated"
+        ))),
+    }
+}
+
+fn encode_apng(frames: &[RgbaImage], delay: Duration) -> Result<Vec<u8>, CaptchaError> {
+    let (width, height) = frames[0].dimensions();
+
+    let mut buffer = Vec::new();
+    let mut encoder = png::Encoder::new(&mut buffer, width, height);
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+    encoder
+        .set_animated(frames.len() as u32, 0)
+        .map_err(png_error)?;
+    let millis = delay.as_millis().min(u16::MAX as u128) as u16;
+    encoder.set_frame_delay(millis, 1000).map_err(png_error)?;
+
+    let mut writer = encoder.write_header().map_err(png_error)?;
+    for frame in frames {
+        writer.write_image_data(frame.as_raw()).map_err(png_error)?;
+    }
+    writer.finish().map_err(png_error)?;
+
+    Ok(buffer)
+}
+
+#[cfg(feature = "gif")]
+fn encode_gif(frames: &[RgbaImage], delay: Duration) -> Result<Vec<u8>, CaptchaError> {
+    use image::{
+        Delay, Frame,
+        codecs::gif::{GifEncoder, Repeat},
+    };
+
+    let mut buffer = Vec::new();
+    {
+        let mut encoder = GifEncoder::new(&mut buffer);
+        encoder.set_repeat(Repeat::Infinite)?;
+        encoder.encode_frames(frames.iter().map(|frame| {
+            Frame::from_parts(frame.clone(), 0, 0, Delay::from_saturating_duration(delay))
+        }))?;
+    }
+    Ok(buffer)
+}
+
+/// An animated WebP of lossless frames. `image` only writes still WebPs,
+/// so their bitstreams are wrapped in the animation container here.
+#[cfg(feature = "webp")]
+fn encode_webp(frames: &[RgbaImage], delay: Duration) -> Result<Vec<u8>, CaptchaError> {
+    use image::{ExtendedColorType, codecs::webp::WebPEncoder};
+
+    let (width, height) = frames[0].dimensions();
+    let millis = delay.as_millis().min(0xFF_FFFF) as u32;
+
+    let mut body = b"WEBP".to_vec();
+
+    let mut vp8x = vec![0x02 | 0x10, 0, 0, 0];
+    vp8x.extend_from_slice(&(width - 1).to_le_bytes()[..3]);
+    vp8x.extend_from_slice(&(height - 1).to_le_bytes()[..3]);
+    write_chunk(&mut body, b"VP8X", &vp8x);
+
+    // Opaque white background, looping forever.
+    write_chunk(&mut body, b"ANIM", &[0xFF, 0xFF, 0xFF, 0xFF, 0, 0]);
+
+    for frame in frames {
+        let mut still = Vec::new();
+        WebPEncoder::new_lossless(&mut still).encode(
+            frame.as_raw(),
+            width,
+            height,
+            ExtendedColorType::Rgba8,
+        )?;
+        // A simple-format file: RIFF header, then a single VP8L chunk.
+        let bitstream = still
+            .get(12..)
+            .filter(|chunk| chunk.starts_with(b"VP8L"))
+            .ok_or_else(|| CaptchaError::InvalidInput("unexpected WebP encoder output".into()))?;
+
+        let mut anmf = vec![0; 6];
+        anmf.extend_from_slice(&(width - 1).to_le_bytes()[..3]);
+        anmf.extend_from_slice(&(height - 1).to_le_bytes()[..3]);
+        anmf.extend_from_slice(&millis.to_le_bytes()[..3]);
+        // Replace the canvas instead of blending, keep it after the frame.
+        anmf.push(0x02);
+        anmf.extend_from_slice(bitstream);
+        write_chunk(&mut body, b"ANMF", &anmf);
+    }
+
+    let mut file = b"RIFF".to_vec();
+    file.extend_from_slice(&(body.len() as u32).to_le_bytes());
+    file.extend_from_slice(&body);
+    Ok(file)
+}
+
+#[cfg(feature = "webp")]
+fn write_chunk(out: &mut Vec<u8>, fourcc: &[u8; 4], data: &[u8]) {
+    out.extend_from_slice(fourcc);
+    out.extend_from_slice(&(data.len() as u32).to_le_bytes());
+    out.extend_from_slice(data);
+    if data.len() % 2 == 1 {
+        out.push(0);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use image::{AnimationDecoder, Frame, Rgba};
+
+    use super::*;
+
+    fn frames() -> Vec<RgbaImage> {
+        (0..3u8)
+            .map(|index| RgbaImage::from_pixel(6, 4, Rgba([index * 100, 0, 0, 255])))
+            .collect()
+    }
+
+    fn check(decoded: Vec<Frame>) {
+        let pixels: Vec<_> = decoded
+            .iter()
+            .map(|frame| *frame.buffer().get_pixel(0, 0))
+            .collect();
+        assert_eq!(
+            pixels,
+            frames()
+                .iter()
+                .map(|frame| *frame.get_pixel(0, 0))
+                .collect::<Vec<_>>()
+        );
+        for frame in decoded {
+            assert_eq!(frame.buffer().dimensions(), (6, 4));
+            assert_eq!(Duration::from(frame.delay()), Duration::from_millis(150));
+        }
+    }
+
+    #[test]
+    fn encodes_looping_apngs() {
+        let apng = encode(&frames(), Format::Png, Duration::from_millis(150)).unwrap();
+        let decoder = image::codecs::png::PngDecoder::new(std::io::Cursor::new(apng)).unwrap();
+        assert!(decoder.is_apng().unwrap());
+        check(
+            decoder
+                .apng()
+                .unwrap()
+                .into_frames()
+                .collect_frames()
+                .unwrap(),
+        );
+    }
+
+    #[cfg(feature = "gif")]
+    #[test]
+    fn encodes_looping_gifs() {
+        let gif = encode(&frames(), Format::Gif, Duration::from_millis(150)).unwrap();
+        let decoder = image::codecs::gif::GifDecoder::new(std::io::Cursor::new(gif)).unwrap();
+        check(decoder.into_frames().collect_frames().unwrap());
+    }
+
+    #[cfg(feature = "webp")]
+    #[test]
+    fn encodes_looping_webps() {
+        use image::ImageDecoder;
+
+        let webp = encode(&frames(), Format::WebP, Duration::from_millis(150)).unwrap();
+        let decoder = image::codecs::webp::WebPDecoder::new(std::io::Cursor::new(webp)).unwrap();
+        assert!(decoder.has_animation());
+        assert_eq!(decoder.dimensions(), (6, 4));
+        check(decoder.into_frames().collect_frames().unwrap());
+    }
+
+    #[test]
+    fn adds_static_fallbacks_to_animations_only() {
+        use crate::Config;
+
+        let generate = |animation| {
+            Config {
+                animation,
+                ..Config::default()
+            }
+            .generate()
+            .unwrap()
+        };
+        let captcha = generate(Some(Animation {
+            fallback: Some(FallbackFrame::Middle),
+            ..Animation::default()
+        }));
+        let fallback = captcha.fallback_image.unwrap();
+        let decoder = image::codecs::png::PngDecoder::new(std::io::Cursor::new(&fallback)).unwrap();
+        assert!(!decoder.is_apng().unwrap());
+        let image = image::load_from_memory(&fallback).unwrap();
+        assert_eq!(
+            (image.width(), image.height()),
+            (captcha.width, captcha.height)
+        );
+
+        assert!(
+            generate(Some(Animation::default()))
+                .fallback_image
+                .is_none()
+        );
+        assert!(generate(None).fallback_image.is_none());
+
+        assert_eq!(FallbackFrame::Middle.index(8), 4);
+        assert_eq!(FallbackFrame::Index(20).index(8), 7);
+    }
+
+    fn stripes() -> RgbaImage {
+        RgbaImage::from_fn(60, 20, |x, _| match x % 6 < 3 {
+            true => Rgba([0, 0, 0, 255]),
+            false => Rgba([255, 255, 255, 255]),
+        })
+    }
+
+    #[test]
+    fn ripples_differently_every_frame() {
+        let img = stripes();
+        let ripple = Ripple {
+            amplitude: 3.0,
+            wavelength: 20.0,
+            speed: 1.0,
+        };
+        let frames: Vec<_> = [0.0, 0.25, 0.5]
+            .map(|progress| ripple.apply(&img, progress))
+            .into();
+        assert!(
+            frames
+                .iter()
+                .all(|frame| frame.dimensions() == img.dimensions())
+        );
+        assert_ne!(frames[0], img);
+        assert_ne!(frames[0], frames[1]);
+        assert_ne!(frames[1], frames[2]);
+        assert!(
+            frames
+                .iter()
+                .flat_map(|frame| frame.pixels())
+                .all(|pixel| pixel[3] == 255)
+        );
+
+        let flat = Ripple {
+            amplitude: 0.0,
+            ..ripple
+        };
+        assert_eq!(flat.apply(&img, 0.0), img);
+        assert_eq!(flat.apply(&img, 0.5), img);
+    }
+
+    #[test]
+    fn refuses_formats_without_animation() {
+        let result = encode(&frames(), Format::Ansi, Duration::from_millis(150));
+        assert!(matches!(result, Err(CaptchaError::InvalidInput(_))));
+    }
+}
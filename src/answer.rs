@@ -0,0 +1,179 @@
+use std::{borrow::Cow, fmt};
+
+use crate::{grid::selection_answer, numerals::to_ascii};
+
+/// The expected answer of a captcha. Its `Debug` output is redacted so the
+/// answer can't leak through logs by accident; read it with
+/// [`Answer::expose`]. With the `zeroize` feature its memory is wiped on
+/// drop.
+#[derive(Clone, PartialEq, Eq)]
+pub struct Answer(String);
+
+impl Answer {
+    pub fn new(answer: String) -> Self {
+        Self(answer)
+    }
+
+    pub fn expose(&self) -> &str {
+        &self.0
+    }
+
+    /// Answers are compared case-insensitively, and digits of any
+    /// [`Numerals`] system count as their ASCII digit.
+    ///
+    /// [`Numerals`]: crate::Numerals
+    pub fn matches(&self, input: &str) -> bool {
+        self.0.eq_ignore_ascii_case(&to_ascii(input))
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.chars().count()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// The form answers are hashed in.
+    pub(crate) fn normalized(answer: &str) -> Self {
+        Self(to_ascii(answer).to_ascii_lowercase())
+    }
+}
+
+/// How a kind of challenge brings submitted answers into the canonical
+/// form of [`CaptchaChallenge::expected_answer`], so the challenge itself,
+/// stores and tokens all accept the same answers.
+///
+/// [`CaptchaChallenge::expected_answer`]: crate::CaptchaChallenge::expected_answer
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum AnswerFormat {
+    /// Taken as typed apart from surrounding whitespace; case and
+    /// numerals are normalized when comparing.
+    #[default]
+    Text,
+    /// Comma-separated cell indices in any order and spacing, as in
+    /// `"4, 3, 2, 0"`, see [`selection_answer`].
+    Selection,
+}
+
+impl AnswerFormat {
+    /// Answers that aren't valid selections are left alone, so they still
+    /// come out wrong.
+    pub fn normalize(self, answer: &str) -> Cow<'_, str> {
+        match self {
+            Self::Text => Cow::Borrowed(answer.trim()),
+            Self::Selection => {
+                let selected: Result<Vec<usize>, _> = answer
+                    .split(',')
+                    .filter(|s| !s.trim().is_empty())
+                    .map(|s| s.trim().parse())
+                    .collect();
+                match selected {
+                    Ok(selected) => Cow::Owned(selection_answer(&selected)),
+                    Err(_) => Cow::Borrowed(answer),
+                }
+            }
+        }
+    }
+
+    /// Short form in tokens.
+    pub(crate) fn code(self) -> &'static str {
+        match self {
+            Self::Text => "t",
+            Self::Selection => "s",
+        }
+    }
+
+    pub(crate) fn from_code(code: &str) -> Option<Self> {
+        match code {
+            "t" => Some(Self::Text),
+            "s" => Some(Self::Selection),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(feature = "zeroize")]
+impl Drop for Answer {
+    fn drop(&mut self) {
+        zeroize::Zeroize::zeroize(&mut self.0);
+    }
+}
+
+impl fmt::Debug for Answer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Answer(<redacted>)")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn normalizes_per_format() {
+        assert_eq!(AnswerFormat::Text.normalize(" 4, 3 "), "4, 3");
+        assert_eq!(AnswerFormat::Selection.normalize("4, 3,, 0 ,3"), "0,3,4");
+        assert_eq!(AnswerFormat::Selection.normalize(""), "");
+        assert_eq!(AnswerFormat::Selection.normalize("4, x"), "4, x");
+        for format in [AnswerFormat::Text, AnswerFormat::Selection] {
+            assert_eq!(AnswerFormat::from_code(format.code()), Some(format));
+        }
+        assert_eq!(AnswerFormat::from_code("x"), None);
+    }
+
+    #[test]
+    fn matches_case_and_numerals_loosely() {
+        let answer = Answer::new("x7Kp".into());
+        assert!(answer.matches("X7kP"));
+        assert!(answer.matches("x\u{667}kp"));
+        assert!(!answer.matches("x7K"));
+        assert_eq!(Answer::normalized("X\u{96D}").expose(), "x7");
+    }
+
+    #[test]
+    fn redacts_debug_output() {
+        let answer = Answer::new("x7Kp".into());
+        assert_eq!(format!("{answer:?}"), "Answer(<redacted>)");
+
+        let captcha = crate::Captcha::new(answer, vec![0; 3], crate::Format::Png, (1, 1), None);
+        let debug = format!("{captcha:?}");
+        assert!(debug.contains("text: Answer(<redacted>), image: <3 bytes>"));
+        assert!(!debug.contains("x7Kp"));
+    }
+
+    #[cfg(feature = "log")]
+    #[test]
+    fn logs_generation_without_the_answer() {
+        use std::sync::Mutex;
+
+        static RECORDS: Mutex<Vec<String>> = Mutex::new(Vec::new());
+        struct Recorder;
+        impl log::Log for Recorder {
+            fn enabled(&self, _: &log::Metadata) -> bool {
+                true
+            }
+            fn log(&self, record: &log::Record) {
+                RECORDS.lock().unwrap().push(record.args().to_string());
+            }
+            fn flush(&self) {}
+        }
+        log::set_logger(&Recorder).unwrap();
+        log::set_max_level(log::LevelFilter::Debug);
+
+        let config = crate::Config {
+            charset: Some("ABCDEFGHJK".into()),
+            length: 6,
+            ..crate::Config::default()
+        };
+        let captcha = crate::Generator::new(config).unwrap().generate().unwrap();
+        let records = RECORDS.lock().unwrap();
+        let line = format!("generated captcha id={} length=6 ", captcha.id);
+        assert!(records.iter().any(|record| record.starts_with(&line)));
+        assert!(
+            !records
+                .iter()
+                .any(|record| record.contains(captcha.text.expose()))
+        );
+    }
+}
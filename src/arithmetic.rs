@@ -0,0 +1,225 @@
+use std::time::{Duration, SystemTime};
+
+use rand::{Rng, rng};
+
+use crate::{
+    Answer, AnswerFormat,
+    captcha::{expires_at, is_expired, random_id},
+};
+
+/// Settings for text-only arithmetic questions, the accessible alternative
+/// to image captchas.
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub struct ArithmeticConfig {
+    /// Largest number appearing in a question.
+    pub max_operand: u32,
+    pub expires_in: Option<Duration>,
+    /// How often [`CaptchaStore::issue_alternate`] swaps the same captcha
+    /// for a question, counted in [`StoredCaptcha::alternates`]. Every swap
+    /// lets a client skip the image, so keep this low; 0 turns alternates
+    /// off.
+    ///
+    /// [`CaptchaStore::issue_alternate`]: crate::CaptchaStore::issue_alternate
+    /// [`StoredCaptcha::alternates`]: crate::StoredCaptcha::alternates
+    pub max_alternates: u32,
+}
+
+impl Default for ArithmeticConfig {
+    fn default() -> Self {
+        Self {
+            max_operand: 10,
+            expires_in: None,
+            max_alternates: 1,
+        }
+    }
+}
+
+/// A question like "What is seven plus three?" that screen readers can read
+/// out as is. Numbers are spelled out.
+pub struct ArithmeticChallenge {
+    pub id: String,
+    pub question: String,
+    pub answer: Answer,
+    pub issued_at: SystemTime,
+    pub expires_at: Option<SystemTime>,
+}
+
+impl ArithmeticConfig {
+    pub fn generate(&self) -> ArithmeticChallenge {
+        let issued_at = SystemTime::now();
+        self.question(
+            random_id(),
+            issued_at,
+            expires_at(issued_at, self.expires_in),
+        )
+    }
+
+    /// A question taking over `id` and its expiry from another challenge.
+    pub(crate) fn question(
+        &self,
+        id: String,
+        issued_at: SystemTime,
+        expires_at: Option<SystemTime>,
+    ) -> ArithmeticChallenge {
+        let mut rng = rng();
+        let max = self.max_operand.clamp(1, 20);
+        let a = rng.random_range(0..=max);
+        let b = rng.random_range(0..=max);
+
+        let (question, answer) = match rng.random_range(0..3) {
+            0 => (format!("{} plus {}", number(a), number(b)), a + b),
+            1 => {
+                let (a, b) = (a.max(b), a.min(b));
+                (format!("{} minus {}", number(a), number(b)), a - b)
+            }
+            _ => {
+                let (a, b) = (a.min(5), b.min(5));
+                (format!("{} times {}", number(a), number(b)), a * b)
+            }
+        };
+
+        ArithmeticChallenge {
+            id,
+            question: format!("What is {question}?"),
+            answer: Answer::new(answer.to_string()),
+            issued_at,
+            expires_at,
+        }
+    }
+}
+
+impl ArithmeticChallenge {
+    pub fn is_expired(&self) -> bool {
+        is_expired(self.expires_at)
+    }
+
+    /// Accepts the answer in digits, e.g. "10".
+    pub fn verify(&self, answer: &str) -> bool {
+        !self.is_expired() && self.answer.matches(&AnswerFormat::Text.normalize(answer))
+    }
+}
+
+fn number(n: u32) -> &'static str {
+    const NAMES: [&str; 21] = [
+        "zero",
+        "one",
+        "two",
+        "three",
+        "four",
+        "five",
+        "six",
+        "seven",
+        "eight",
+        "nine",
+        "ten",
+        "eleven",
+        "twelve",
+        "thirteen",
+        "fourteen",
+        "fifteen",
+        "sixteen",
+        "seventeen",
+        "eighteen",
+        "nineteen",
+        "twenty",
+    ];
+    NAMES[n as usize]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        CaptchaChallenge, CaptchaStore, Config, Generator, MemoryStore, TokenKey, VerifyOutcome,
+        test_util::{challenge, in_a_minute},
+    };
+
+    #[test]
+    fn asks_questions_with_their_answer() {
+        let config = ArithmeticConfig::default();
+        for _ in 0..50 {
+            let challenge = config.generate();
+            assert!(challenge.question.starts_with("What is "));
+            assert!(!challenge.question.chars().any(|c| c.is_ascii_digit()));
+            let answer: u32 = challenge.answer.expose().parse().unwrap();
+            assert!(answer <= 25);
+            assert!(challenge.verify(challenge.answer.expose()));
+        }
+    }
+
+    #[test]
+    fn accepts_surrounding_whitespace_everywhere() {
+        let challenge = challenge("a", in_a_minute());
+        assert!(challenge.verify(" 7 "));
+        assert!(CaptchaChallenge::verify(&challenge, " 7 "));
+
+        let store = MemoryStore::new();
+        store.issue(&challenge);
+        assert_eq!(store.verify("a", " 7 "), VerifyOutcome::Correct);
+
+        let key = TokenKey::new("secret");
+        let token = key.issue_token(&challenge);
+        assert_eq!(key.verify_token(&token, " 7 "), VerifyOutcome::Correct);
+        assert_eq!(key.verify_token(&token, "7 7"), VerifyOutcome::Wrong);
+    }
+
+    #[test]
+    fn swaps_captchas_for_questions_under_the_same_id() {
+        let store = MemoryStore::new();
+        let expires_at = in_a_minute();
+        store.issue(&challenge("a", expires_at));
+
+        let alternate = store
+            .issue_alternate("a", &ArithmeticConfig::default())
+            .unwrap();
+        assert_eq!(alternate.id, "a");
+        assert_eq!(alternate.expires_at, expires_at);
+        assert!(
+            store
+                .issue_alternate("b", &ArithmeticConfig::default())
+                .is_none()
+        );
+        assert_eq!(
+            store.verify("a", alternate.answer.expose()),
+            VerifyOutcome::Correct
+        );
+    }
+
+    #[test]
+    fn caps_swaps_per_id() {
+        let store = MemoryStore::new();
+        store.issue(&challenge("a", in_a_minute()));
+        let config = ArithmeticConfig {
+            max_alternates: 2,
+            ..ArithmeticConfig::default()
+        };
+        assert!(store.issue_alternate("a", &config).is_some());
+        let last = store.issue_alternate("a", &config).unwrap();
+        assert!(store.issue_alternate("a", &config).is_none());
+        // The last question stays answerable.
+        assert_eq!(
+            store.verify("a", last.answer.expose()),
+            VerifyOutcome::Correct
+        );
+
+        store.issue(&challenge("b", in_a_minute()));
+        let off = ArithmeticConfig {
+            max_alternates: 0,
+            ..ArithmeticConfig::default()
+        };
+        assert!(store.issue_alternate("b", &off).is_none());
+        assert_eq!(store.verify("b", "7"), VerifyOutcome::Correct);
+    }
+
+    #[test]
+    fn keeps_counting_swaps_across_refreshes() {
+        let store = MemoryStore::new();
+        let generator = Generator::new(Config::default()).unwrap();
+        store.issue(&challenge("a", in_a_minute()));
+        let config = ArithmeticConfig::default();
+        assert!(store.issue_alternate("a", &config).is_some());
+        store.refresh("a", &generator).unwrap().unwrap();
+        assert!(store.issue_alternate("a", &config).is_none());
+    }
+}
@@ -0,0 +1,186 @@
+use std::{collections::HashMap, f32::consts::PI, sync::Arc};
+
+use image::{GrayImage, Luma, imageops};
+use imageproc::geometric_transformations::{Interpolation, rotate_about_center};
+use rand::Rng;
+
+use crate::{CaptchaError, Config, glyph::Mask, outline::LoadedFont, render::rotated_rect_size};
+
+/// Rotations every glyph is pre-rendered at, evenly spread over the range
+/// regular rendering picks from.
+const ROTATIONS: usize = 16;
+
+/// Every charset glyph rasterized, styled and rotated up front, so
+/// rendering a captcha only blits coverage.
+pub(crate) struct Atlas {
+    /// The config the sprites were made for.
+    pub config: Arc<Config>,
+    /// Font size of the sprites.
+    pub size: f32,
+    /// By the resolved font's place in the chain and the character.
+    sprites: HashMap<(usize, char), Vec<Sprite>>,
+}
+
+pub(crate) struct Sprite {
+    /// Coverage after rotation.
+    pub mask: Mask,
+    pub advance: f32,
+    /// How far the rotated box sticks out left of the upright glyph.
+    pub inset: i64,
+    pub angle: f32,
+}
+
+impl Atlas {
+    pub fn build(config: Arc<Config>, font: Option<&LoadedFont>) -> Result<Self, CaptchaError> {
+        config.check_size()?;
+        let mut rng = config.visual_rng.rng();
+        let size = config.font_size(font);
+        config.glyph_style.check(size)?;
+
+        let mut sprites = HashMap::new();
+        for c in config.charset().chars().map(|c| config.glyph_char(c)) {
+            let mut rotations = Vec::with_capacity(ROTATIONS);
+            for step in 0..ROTATIONS {
+                let angle = PI / 8.0 * (2.0 * step as f32 / (ROTATIONS - 1) as f32 - 1.0);
+                let (mut mask, advance) =
+                    config
+                        .typeface
+                        .rasterize(font, c, size, &config.glyph_style, &mut rng)?;
+                config
+                    .glyph_style
+                    .apply(&mut mask, config.max_pixels, &mut rng)?;
+                rotations.push(rotate(mask, advance, angle));
+            }
+            sprites.insert(key(font, c), rotations);
+        }
+
+        Ok(Self {
+            config,
+            size,
+            sprites,
+        })
+    }
+
+    /// Whether the sprites look the same as glyphs rasterized for `config`
+    /// would. Characters missing from the atlas are rasterized as usual.
+    pub fn fits(&self, config: &Config, font: Option<&LoadedFont>) -> bool {
+        std::ptr::eq(&*self.config, config)
+            || (self.size == config.font_size(font)
+                && self.config.typeface == config.typeface
+                && self.config.glyph_style == config.glyph_style)
+    }
+
+    /// Every rotation of `c`, from most counterclockwise.
+    #[cfg(feature = "calibrate")]
+    pub fn rotations(&self, font: Option<&LoadedFont>, c: char) -> &[Sprite] {
+        self.sprites.get(&key(font, c)).map_or(&[], Vec::as_slice)
+    }
+
+    /// A sprite of `c` at a random rotation. Only unscaled glyphs are
+    /// pre-rendered.
+    pub fn sprite(
+        &self,
+        font: Option<&LoadedFont>,
+        c: char,
+        scale: f32,
+        rng: &mut impl Rng,
+    ) -> Option<&Sprite> {
+        if scale != 1.0 {
+            return None;
+        }
+        let rotations = self.sprites.get(&key(font, c))?;
+        rotations.get(rng.random_range(0..rotations.len()))
+    }
+}
+
+fn key(font: Option<&LoadedFont>, c: char) -> (usize, char) {
+    (font.map_or(0, |font| font.resolve(c).0), c)
+}
+
+fn rotate(mask: Mask, advance: f32, angle: f32) -> Sprite {
+    if mask.width == 0 || mask.height == 0 {
+        return Sprite {
+            mask,
+            advance,
+            inset: 0,
+            angle,
+        };
+    }
+    let (rotated_width, rotated_height) =
+        rotated_rect_size(mask.width as f32, mask.height as f32, angle);
+    let upright = GrayImage::from_raw(mask.width, mask.height, mask.alpha).unwrap();
+
+    let mut expanded = GrayImage::new(rotated_width as u32, rotated_height as u32);
+    imageops::overlay(
+        &mut expanded,
+        &upright,
+        ((rotated_width as u32 - upright.width()) / 2) as i64,
+        ((rotated_height as u32 - upright.height()) / 2) as i64,
+    );
+    let rotated = rotate_about_center(&expanded, angle, Interpolation::Bilinear, Luma([0]));
+
+    Sprite {
+        mask: Mask::new(
+            rotated.width() as usize,
+            rotated.height() as usize,
+            rotated.into_raw(),
+        ),
+        advance,
+        inset: (rotated_width as i64 - upright.width() as i64) / 2,
+        angle,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Generator, RngSource, Typeface};
+
+    fn dots() -> Config {
+        Config {
+            typeface: Typeface::DotMatrix { dot: 0.8 },
+            visual_rng: RngSource::Seeded(1),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn prerenders_every_rotation_of_the_charset() {
+        let config = Arc::new(dots());
+        let atlas = Atlas::build(config.clone(), None).unwrap();
+        assert_eq!(atlas.sprites.len(), config.charset().chars().count());
+        for rotations in atlas.sprites.values() {
+            assert_eq!(rotations.len(), ROTATIONS);
+            assert!(
+                rotations
+                    .windows(2)
+                    .all(|pair| pair[0].angle < pair[1].angle)
+            );
+            assert!((rotations[0].angle + PI / 8.0).abs() < 1e-6);
+        }
+
+        let mut rng = rand::rng();
+        assert!(atlas.sprite(None, 'A', 1.0, &mut rng).is_some());
+        assert!(atlas.sprite(None, 'A', 1.5, &mut rng).is_none());
+        assert!(atlas.sprite(None, '\u{E000}', 1.0, &mut rng).is_none());
+    }
+
+    #[test]
+    fn fits_configs_drawing_the_same_glyphs() {
+        let atlas = Atlas::build(Arc::new(dots()), None).unwrap();
+        assert!(atlas.fits(&atlas.config, None));
+        assert!(atlas.fits(&dots(), None));
+        let other = Config {
+            typeface: Typeface::DotMatrix { dot: 0.5 },
+            ..dots()
+        };
+        assert!(!atlas.fits(&other, None));
+    }
+
+    #[test]
+    fn generates_from_the_atlas() {
+        let generator = Generator::without_font(dots()).with_sprite_atlas().unwrap();
+        assert!(generator.atlas_for(&generator.config()).is_some());
+        assert!(generator.generate().is_ok());
+    }
+}
@@ -0,0 +1,197 @@
+use image::{Rgba, RgbaImage};
+use rand::{Rng, seq::SliceRandom};
+
+/// Fill of the canvas behind the text. Textured backgrounds are generated
+/// at runtime and darken `background_color` by up to `intensity` (0 to 1),
+/// which defeats simple background-color subtraction.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum Background {
+    #[default]
+    Solid,
+    /// Perlin noise clouds; `scale` is the feature size in pixels.
+    Perlin { scale: f32, intensity: f32 },
+    /// Worley (cellular) noise with about `cells` cells.
+    Worley { cells: u32, intensity: f32 },
+    /// Fine per-pixel paper grain.
+    Grain { intensity: f32 },
+}
+
+impl Background {
+    pub(crate) fn render(
+        &self,
+        width: u32,
+        height: u32,
+        color: Rgba<u8>,
+        rng: &mut impl Rng,
+    ) -> RgbaImage {
+        let mut img = RgbaImage::from_pixel(width, height, color);
+
+        match *self {
+            Background::Solid => {}
+            Background::Perlin { scale, intensity } => {
+                let perlin = Perlin::new(rng);
+                let scale = scale.max(1.0);
+                shade(&mut img, intensity, |x, y| {
+                    perlin.fractal(x as f32 / scale, y as f32 / scale)
+                });
+            }
+            Background::Worley { cells, intensity } => {
+                let points: Vec<(f32, f32)> = (0..cells.max(1))
+                    .map(|_| {
+                        (
+                            rng.random_range(0.0..width as f32),
+                            rng.random_range(0.0..height as f32),
+                        )
+                    })
+                    .collect();
+                // Typical distance between neighboring points.
+                let spacing = ((width * height) as f32 / points.len() as f32).sqrt();
+                shade(&mut img, intensity, |x, y| {
+                    let nearest = points
+                        .iter()
+                        .map(|&(px, py)| (px - x as f32).powi(2) + (py - y as f32).powi(2))
+                        .fold(f32::INFINITY, f32::min)
+                        .sqrt();
+                    (nearest / spacing).min(1.0)
+                });
+            }
+            Background::Grain { intensity } => {
+                let grain: Vec<f32> = (0..width * height).map(|_| rng.random()).collect();
+                shade(&mut img, intensity, |x, y| grain[(y * width + x) as usize]);
+            }
+        }
+
+        img
+    }
+}
+
+/// Darkens every pixel by `intensity * noise(x, y)`, noise being in 0..=1.
+fn shade(img: &mut RgbaImage, intensity: f32, noise: impl Fn(u32, u32) -> f32) {
+    let intensity = intensity.clamp(0.0, 1.0);
+    for (x, y, pixel) in img.enumerate_pixels_mut() {
+        let factor = 1.0 - intensity * noise(x, y).clamp(0.0, 1.0);
+        for channel in &mut pixel.0[..3] {
+            *channel = (*channel as f32 * factor) as u8;
+        }
+    }
+}
+
+/// Classic 2D gradient noise with a shuffled permutation table.
+struct Perlin {
+    permutation: [u8; 512],
+}
+
+impl Perlin {
+    fn new(rng: &mut impl Rng) -> Self {
+        let mut table: Vec<u8> = (0..=255).collect();
+        table.shuffle(rng);
+
+        let mut permutation = [0; 512];
+        for (i, p) in permutation.iter_mut().enumerate() {
+            *p = table[i % 256];
+        }
+        Self { permutation }
+    }
+
+    /// Three octaves, mapped to 0..=1.
+    fn fractal(&self, x: f32, y: f32) -> f32 {
+        let mut value = 0.0;
+        let mut amplitude = 0.5;
+        let mut frequency = 1.0;
+        for _ in 0..3 {
+            value += amplitude * self.noise(x * frequency, y * frequency);
+            amplitude *= 0.5;
+            frequency *= 2.0;
+        }
+        (value / 0.875 + 1.0) / 2.0
+    }
+
+    /// Roughly -1..=1.
+    fn noise(&self, x: f32, y: f32) -> f32 {
+        let (xi, yi) = (x.floor() as i32 & 255, y.floor() as i32 & 255);
+        let (xf, yf) = (x - x.floor(), y - y.floor());
+        let (u, v) = (fade(xf), fade(yf));
+
+        let p = &self.permutation;
+        let hash = |i: i32, j: i32| p[p[i as usize] as usize + j as usize];
+        let (aa, ab) = (hash(xi, yi), hash(xi, yi + 1));
+        let (ba, bb) = (hash(xi + 1, yi), hash(xi + 1, yi + 1));
+
+        let x1 = lerp(gradient(aa, xf, yf), gradient(ba, xf - 1.0, yf), u);
+        let x2 = lerp(
+            gradient(ab, xf, yf - 1.0),
+            gradient(bb, xf - 1.0, yf - 1.0),
+            u,
+        );
+        lerp(x1, x2, v)
+    }
+}
+
+fn fade(t: f32) -> f32 {
+    t * t * t * (t * (t * 6.0 - 15.0) + 10.0)
+}
+
+fn lerp(a: f32, b: f32, t: f32) -> f32 {
+    a + t * (b - a)
+}
+
+fn gradient(hash: u8, x: f32, y: f32) -> f32 {
+    match hash & 7 {
+        0 => x + y,
+        1 => x - y,
+        2 => -x + y,
+        3 => -x - y,
+        4 => x,
+        5 => -x,
+        6 => y,
+        _ => -y,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn darkens_by_at_most_the_intensity() {
+        let color = Rgba([200, 220, 240, 255]);
+        let textured = [
+            Background::Perlin {
+                scale: 20.0,
+                intensity: 0.3,
+            },
+            Background::Worley {
+                cells: 12,
+                intensity: 0.3,
+            },
+            Background::Grain { intensity: 0.3 },
+        ];
+        for background in textured {
+            let img = background.render(60, 30, color, &mut rand::rng());
+            for pixel in img.pixels() {
+                for (channel, base) in pixel.0.iter().zip(color.0) {
+                    assert!(*channel <= base && *channel as f32 >= base as f32 * 0.7 - 1.0);
+                }
+                assert_eq!(pixel.0[3], 255);
+            }
+            let first = img.get_pixel(0, 0);
+            assert!(img.pixels().any(|pixel| pixel != first));
+        }
+        let solid = Background::Solid.render(3, 2, color, &mut rand::rng());
+        assert!(solid.pixels().all(|pixel| *pixel == color));
+    }
+
+    #[test]
+    fn keeps_perlin_noise_in_range() {
+        let perlin = Perlin::new(&mut rand::rng());
+        for y in 0..50 {
+            for x in 0..50 {
+                let value = perlin.fractal(x as f32 * 0.37, y as f32 * 0.23);
+                assert!((0.0..=1.0).contains(&value), "{value}");
+            }
+        }
+    }
+}
@@ -0,0 +1,152 @@
+//! Load tests for sizing instances, run against the hardware and config
+//! used in production.
+
+use std::{
+    sync::Arc,
+    thread,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    CaptchaError, CaptchaPool, CaptchaStore, Config, Generator, ImageCache, MemoryStore,
+    VerifyOutcome,
+};
+
+/// Pre-rendered captchas the pool holds per request thread.
+const POOL_PER_THREAD: usize = 4;
+
+/// Results of [`throughput`].
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Report {
+    pub threads: usize,
+    pub elapsed: Duration,
+    /// Captchas issued, served and verified.
+    pub captchas: usize,
+    /// Mean size of the served images.
+    pub mean_bytes: usize,
+    /// Time from asking the pool for a captcha until it is in the image
+    /// cache, which includes rendering inline whenever the pool ran dry.
+    pub p50_latency: Duration,
+    pub p99_latency: Duration,
+    pub max_latency: Duration,
+    /// Verifications that didn't come out [`VerifyOutcome::Correct`],
+    /// which points at a broken store rather than a slow one.
+    pub failed_verifications: usize,
+}
+
+impl Report {
+    pub fn per_second(&self) -> f64 {
+        self.captchas as f64 / self.elapsed.as_secs_f64().max(f64::EPSILON)
+    }
+}
+
+/// Handles captcha requests on `threads` threads for `duration`, the way a
+/// server does: each one pops a captcha from a [`CaptchaPool`] backed by a
+/// [`MemoryStore`], puts it into an [`ImageCache`], serves the image from
+/// there and verifies the correct answer. The pool is refilled by its usual
+/// background thread and starts out empty, so short runs mostly measure
+/// inline rendering. Every thread handles at least one request, however
+/// short `duration` is.
+pub fn throughput(
+    config: Config,
+    threads: usize,
+    duration: Duration,
+) -> Result<Report, CaptchaError> {
+    let threads = threads.max(1);
+    let pool = CaptchaPool::new(
+        Generator::new(config)?,
+        threads * POOL_PER_THREAD,
+        // Nothing goes stale within a run.
+        Duration::MAX,
+        Arc::new(MemoryStore::new()),
+    );
+    let cache = ImageCache::new(Duration::from_secs(60));
+
+    let started = Instant::now();
+    let samples = thread::scope(|scope| {
+        let workers: Vec<_> = (0..threads)
+            .map(|_| scope.spawn(|| handle_requests(&pool, &cache, started + duration)))
+            .collect();
+
+        workers
+            .into_iter()
+            .map(|worker| worker.join().expect("benchmark worker panicked"))
+            .collect::<Result<Vec<_>, _>>()
+    })?;
+    let elapsed = started.elapsed();
+
+    let mut samples: Vec<_> = samples.into_iter().flatten().collect();
+    samples.sort_unstable_by_key(|sample| sample.latency);
+    let percentile = |p: usize| {
+        samples
+            .get(samples.len().saturating_sub(1) * p / 100)
+            .map_or(Duration::ZERO, |sample| sample.latency)
+    };
+
+    Ok(Report {
+        threads,
+        elapsed,
+        captchas: samples.len(),
+        mean_bytes: samples.iter().map(|sample| sample.bytes).sum::<usize>() / samples.len().max(1),
+        p50_latency: percentile(50),
+        p99_latency: percentile(99),
+        max_latency: percentile(100),
+        failed_verifications: samples.iter().filter(|sample| !sample.verified).count(),
+    })
+}
+
+struct Sample {
+    latency: Duration,
+    bytes: usize,
+    verified: bool,
+}
+
+fn handle_requests(
+    pool: &CaptchaPool<MemoryStore>,
+    cache: &ImageCache,
+    until: Instant,
+) -> Result<Vec<Sample>, CaptchaError> {
+    let mut samples = Vec::new();
+    loop {
+        let requested = Instant::now();
+        let captcha = pool.pop()?;
+        cache.insert(&captcha);
+        let latency = requested.elapsed();
+
+        let bytes = cache
+            .get(&captcha.id)
+            .map_or(0, |cached| cached.image.len());
+        let outcome = pool.store().verify(&captcha.id, captcha.text.expose());
+        cache.remove(&captcha.id);
+
+        samples.push(Sample {
+            latency,
+            bytes,
+            verified: outcome == VerifyOutcome::Correct,
+        });
+        if Instant::now() >= until {
+            return Ok(samples);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn serves_and_verifies_captchas() {
+        let report = throughput(Config::default(), 2, Duration::from_millis(50)).unwrap();
+        assert_eq!(report.threads, 2);
+        assert!(report.captchas >= 2, "{report:?}");
+        assert_eq!(report.failed_verifications, 0);
+        assert!(report.mean_bytes > 0);
+        assert!(report.p50_latency <= report.p99_latency);
+        assert!(report.p99_latency <= report.max_latency);
+        assert!(report.per_second() > 0.0);
+
+        let instant = throughput(Config::default(), 1, Duration::ZERO).unwrap();
+        assert_eq!(instant.captchas, 1);
+        assert_eq!(instant.failed_verifications, 0);
+    }
+}
@@ -0,0 +1,221 @@
+use image::RgbaImage;
+
+use crate::composite;
+
+/// How a layer (glyphs or noise) is composited onto what's below it.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum BlendMode {
+    /// Plain alpha-over.
+    #[default]
+    Normal,
+    Multiply,
+    Screen,
+    Overlay,
+    /// Bitwise XOR of the color channels, so e.g. a line inverts the text
+    /// color where it crosses a glyph.
+    Xor,
+}
+
+impl BlendMode {
+    fn apply(&self, src: u8, dst: u8) -> u8 {
+        let (s, d) = (src as f32 / 255.0, dst as f32 / 255.0);
+        let value = match self {
+            BlendMode::Normal => s,
+            BlendMode::Multiply => s * d,
+            BlendMode::Screen => 1.0 - (1.0 - s) * (1.0 - d),
+            BlendMode::Overlay if d < 0.5 => 2.0 * s * d,
+            BlendMode::Overlay => 1.0 - 2.0 * (1.0 - s) * (1.0 - d),
+            BlendMode::Xor => return src ^ dst,
+        };
+        (value * 255.0).round() as u8
+    }
+}
+
+/// Composites `top` onto `bottom` at (`x`, `y`) with the given mode, both
+/// premultiplied.
+pub(crate) fn blend(bottom: &mut RgbaImage, top: &RgbaImage, x: i64, y: i64, mode: BlendMode) {
+    if mode == BlendMode::Normal {
+        over(bottom, top, x, y);
+        return;
+    }
+
+    for (tx, ty, src) in top.enumerate_pixels() {
+        let (bx, by) = (x + tx as i64, y + ty as i64);
+        if src[3] == 0
+            || bx < 0
+            || by < 0
+            || bx >= bottom.width() as i64
+            || by >= bottom.height() as i64
+        {
+            continue;
+        }
+
+        // Modes are defined on straight colors. Where the bottom is
+        // transparent the source color shows as it is, so edges over
+        // transparent pixels don't darken.
+        let dst = bottom.get_pixel_mut(bx as u32, by as u32);
+        let (straight_src, straight_dst) = (
+            composite::unpremultiply_pixel(*src),
+            composite::unpremultiply_pixel(*dst),
+        );
+        let (src_alpha, dst_alpha) = (src[3] as f32 / 255.0, dst[3] as f32 / 255.0);
+        let mut mixed = *dst;
+        for c in 0..3 {
+            let (s, d) = (straight_src[c] as f32, straight_dst[c] as f32);
+            let blended = mode.apply(straight_src[c], straight_dst[c]) as f32;
+            let shown = s + (blended - s) * dst_alpha;
+            mixed[c] = (shown * src_alpha + d * dst_alpha * (1.0 - src_alpha)).round() as u8;
+        }
+        mixed[3] = src[3].saturating_add(composite::div_255(dst[3] as u16 * (255 - src[3]) as u16));
+        *dst = mixed;
+    }
+}
+
+/// Plain alpha-over of the overlapping rows.
+fn over(bottom: &mut RgbaImage, top: &RgbaImage, x: i64, y: i64) {
+    let (bottom_width, bottom_height) = (bottom.width() as i64, bottom.height() as i64);
+    let (left, right) = (x.max(0), (x + top.width() as i64).min(bottom_width));
+    let (upper, lower) = (y.max(0), (y + top.height() as i64).min(bottom_height));
+    if left >= right || upper >= lower {
+        return;
+    }
+
+    let top_stride = top.width() as usize * 4;
+    let bottom_stride = bottom_width as usize * 4;
+    let row_bytes = (right - left) as usize * 4;
+    let (bottom_data, top_data): (&mut [u8], &[u8]) = (bottom, top);
+    for by in upper..lower {
+        let top_start = (by - y) as usize * top_stride + (left - x) as usize * 4;
+        let bottom_start = by as usize * bottom_stride + left as usize * 4;
+        composite::over_row(
+            &mut bottom_data[bottom_start..bottom_start + row_bytes],
+            &top_data[top_start..top_start + row_bytes],
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use image::Rgba;
+
+    use super::*;
+
+    #[test]
+    fn mixes_channels_per_mode() {
+        let mixed = [
+            BlendMode::Normal,
+            BlendMode::Multiply,
+            BlendMode::Screen,
+            BlendMode::Overlay,
+            BlendMode::Xor,
+        ]
+        .map(|mode| [mode.apply(128, 64), mode.apply(128, 192)]);
+        assert_eq!(
+            mixed,
+            [[128, 128], [32, 96], [160, 224], [64, 192], [192, 64]]
+        );
+    }
+
+    #[test]
+    fn blends_only_where_layers_overlap() {
+        let mut bottom = RgbaImage::from_pixel(4, 2, Rgba([200, 100, 0, 255]));
+        // Premultiplied half-transparent gray.
+        let top = RgbaImage::from_pixel(3, 3, Rgba([64, 64, 64, 128]));
+        blend(&mut bottom, &top, -2, -1, BlendMode::Multiply);
+
+        assert_eq!(*bottom.get_pixel(0, 0), Rgba([150, 75, 0, 255]));
+        assert_eq!(*bottom.get_pixel(0, 1), Rgba([150, 75, 0, 255]));
+        assert_eq!(*bottom.get_pixel(1, 0), Rgba([200, 100, 0, 255]));
+
+        let mut normal = RgbaImage::from_pixel(4, 2, Rgba([200, 100, 0, 255]));
+        blend(&mut normal, &top, 3, 1, BlendMode::Normal);
+        assert_eq!(*normal.get_pixel(3, 1), Rgba([164, 114, 64, 255]));
+        assert_eq!(*normal.get_pixel(2, 1), Rgba([200, 100, 0, 255]));
+        blend(&mut normal, &top, 4, 0, BlendMode::Screen);
+    }
+
+    /// Straight-alpha compositing of `src` over `dst` per the W3C
+    /// compositing spec, in floats.
+    fn reference(src: Rgba<u8>, dst: Rgba<u8>, mode: BlendMode) -> Rgba<u8> {
+        let (sa, da) = (src[3] as f32 / 255.0, dst[3] as f32 / 255.0);
+        let alpha = sa + da * (1.0 - sa);
+        let mut out = Rgba([0, 0, 0, (alpha * 255.0).round() as u8]);
+        for c in 0..3 {
+            let (s, d) = (src[c] as f32, dst[c] as f32);
+            let blended = mode.apply(src[c], dst[c]) as f32;
+            let shown = (1.0 - da) * s + da * blended;
+            out[c] = ((sa * shown + (1.0 - sa) * da * d) / alpha).round() as u8;
+        }
+        out
+    }
+
+    #[test]
+    fn matches_straight_alpha_compositing() {
+        use rand::{Rng, SeedableRng, rngs::StdRng};
+
+        let mut rng = StdRng::seed_from_u64(1);
+        for mode in [
+            BlendMode::Normal,
+            BlendMode::Multiply,
+            BlendMode::Screen,
+            BlendMode::Overlay,
+            BlendMode::Xor,
+        ] {
+            for _ in 0..200 {
+                let mut pixel = || {
+                    let alpha = rng.random_range(64..=255);
+                    Rgba([rng.random(), rng.random(), rng.random(), alpha])
+                };
+                let (src, dst) = (
+                    composite::premultiply_pixel(pixel()),
+                    composite::premultiply_pixel(pixel()),
+                );
+                let mut bottom = RgbaImage::from_pixel(1, 1, dst);
+                blend(&mut bottom, &RgbaImage::from_pixel(1, 1, src), 0, 0, mode);
+
+                let got = composite::unpremultiply_pixel(*bottom.get_pixel(0, 0));
+                // The straight colors premultiplied pixels stand for.
+                let (src, dst) = (
+                    composite::unpremultiply_pixel(src),
+                    composite::unpremultiply_pixel(dst),
+                );
+                let expected = reference(src, dst, mode);
+                assert_eq!(got[3], expected[3], "{mode:?} {src:?} over {dst:?}");
+                // Premultiplied channels round to 1/255 of the alpha.
+                let tolerance = (2.0 * 255.0 / got[3] as f32).ceil() as u8;
+                for c in 0..3 {
+                    assert!(
+                        got[c].abs_diff(expected[c]) <= tolerance,
+                        "{mode:?} {src:?} over {dst:?}: {got:?}, expected {expected:?}"
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn keeps_half_transparent_edges_bright() {
+        let white = composite::premultiply_pixel(Rgba([255, 255, 255, 128]));
+        for mode in [BlendMode::Normal, BlendMode::Screen, BlendMode::Overlay] {
+            let mut bottom = RgbaImage::new(2, 1);
+            bottom.put_pixel(1, 0, Rgba([255, 255, 255, 255]));
+            blend(&mut bottom, &RgbaImage::from_pixel(2, 1, white), 0, 0, mode);
+            composite::unpremultiply(&mut bottom);
+            // Over transparent pixels, the color stays white rather than
+            // mixing with the transparent black.
+            assert_eq!(
+                *bottom.get_pixel(0, 0),
+                Rgba([255, 255, 255, 128]),
+                "{mode:?}"
+            );
+            assert_eq!(
+                *bottom.get_pixel(1, 0),
+                Rgba([255, 255, 255, 255]),
+                "{mode:?}"
+            );
+        }
+    }
+}
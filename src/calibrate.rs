@@ -0,0 +1,206 @@
+use std::sync::Arc;
+
+use image::{GrayImage, Luma, RgbaImage, imageops};
+
+use crate::{
+    CaptchaError, Config, Generator, GlyphBox, GlyphStyle, atlas::Atlas, outline::LoadedFont,
+};
+
+/// How a simple template matching solver fares against a config, see
+/// [`Generator::calibrate`].
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Calibration {
+    pub samples: u32,
+    /// Captchas whose every character was recognized.
+    pub solved: u32,
+    /// Share of single characters recognized, from 0 to 1.
+    pub char_accuracy: f32,
+}
+
+impl Calibration {
+    /// Estimated probability of a machine solving one captcha, from 0 to 1.
+    pub fn solve_rate(&self) -> f32 {
+        if self.samples == 0 {
+            return 0.0;
+        }
+        self.solved as f32 / self.samples as f32
+    }
+}
+
+impl Generator {
+    /// Renders `samples` captchas with the active config and lets a
+    /// reference solver read them, offline and without any model files.
+    ///
+    /// The solver compares each glyph with clean renderings of every
+    /// charset character at several rotations, scaled down to a common
+    /// size. It is told where the glyphs are, so it measures how well the
+    /// distortion and noise hide their shapes, not how hard they are to
+    /// separate. Real OCR does better than
+    /// this, so treat the result as a lower bound to compare configs and
+    /// [`Difficulty`] presets by, not as an absolute number.
+    ///
+    /// [`Difficulty`]: crate::Difficulty
+    pub fn calibrate(&self, samples: u32) -> Result<Calibration, CaptchaError> {
+        let config = self.config();
+        let templates = templates(&config, self.font.as_ref())?;
+        let background = config.background_color.to_rgba();
+
+        let mut calibration = Calibration {
+            samples,
+            ..Calibration::default()
+        };
+        let (mut glyphs, mut recognized) = (0u32, 0u32);
+        for _ in 0..samples {
+            let rendered = self.render(&config, None, self.next_sequence())?;
+            let ink = ink_map(&rendered.frames[0], background);
+            let mut all =
+                rendered.layout.glyphs.len() == rendered.layout.text.expose().chars().count();
+            for glyph in &rendered.layout.glyphs {
+                let read = read_glyph(&ink, glyph, &templates);
+                glyphs += 1;
+                if read == Some(glyph.char) {
+                    recognized += 1;
+                } else {
+                    all = false;
+                }
+            }
+            if all {
+                calibration.solved += 1;
+            }
+        }
+        if glyphs > 0 {
+            calibration.char_accuracy = recognized as f32 / glyphs as f32;
+        }
+        Ok(calibration)
+    }
+}
+
+/// How far each pixel is from the background color, as a solver that
+/// knows the background would see it.
+fn ink_map(img: &RgbaImage, background: [u8; 4]) -> GrayImage {
+    GrayImage::from_fn(img.width(), img.height(), |x, y| {
+        let pixel = img.get_pixel(x, y);
+        let distance = (0..3)
+            .map(|channel| pixel[channel].abs_diff(background[channel]))
+            .max()
+            .unwrap_or(0);
+        Luma([distance])
+    })
+}
+
+/// Side of the square templates and glyphs are compared at.
+const SIZE: u32 = 24;
+
+/// Clean renderings of every charset character at half the atlas
+/// rotations, each scaled to `SIZE` squared.
+fn templates(
+    config: &Config,
+    font: Option<&LoadedFont>,
+) -> Result<Vec<(char, Vec<u8>)>, CaptchaError> {
+    let clean = Config {
+        glyph_style: GlyphStyle {
+            blend: config.glyph_style.blend,
+            ..GlyphStyle::default()
+        },
+        ..config.clone()
+    };
+    let atlas = Atlas::build(Arc::new(clean), font)?;
+    let mut chars: Vec<char> = config.charset().chars().collect();
+    chars.sort_unstable();
+    chars.dedup();
+
+    let mut templates = Vec::new();
+    for c in chars {
+        // Every other rotation is close enough and halves the work.
+        for sprite in atlas
+            .rotations(font, config.glyph_char(c))
+            .iter()
+            .step_by(2)
+        {
+            let mask = &sprite.mask;
+            if let Some(coverage) = GrayImage::from_raw(mask.width, mask.height, mask.alpha.clone())
+                .filter(|_| mask.width > 0 && mask.height > 0)
+            {
+                templates.push((c, scale(&coverage)));
+            }
+        }
+    }
+    Ok(templates)
+}
+
+fn scale(img: &GrayImage) -> Vec<u8> {
+    imageops::resize(img, SIZE, SIZE, imageops::FilterType::Triangle).into_raw()
+}
+
+/// The character whose template correlates best with the ink in the
+/// glyph's box.
+fn read_glyph(ink: &GrayImage, glyph: &GlyphBox, templates: &[(char, Vec<u8>)]) -> Option<char> {
+    let x = glyph.x.clamp(0, ink.width() as i32) as u32;
+    let y = glyph.y.clamp(0, ink.height() as i32) as u32;
+    let width = glyph.width.min(ink.width() - x);
+    let height = glyph.height.min(ink.height() - y);
+    if width == 0 || height == 0 {
+        return None;
+    }
+    let crop = scale(&imageops::crop_imm(ink, x, y, width, height).to_image());
+
+    templates
+        .iter()
+        .map(|(c, template)| (*c, correlation(&crop, template)))
+        .max_by(|a, b| a.1.total_cmp(&b.1))
+        .map(|(c, _)| c)
+}
+
+/// Pearson correlation of two equally sized coverage images, from -1 to
+/// 1, 0 when either is flat.
+fn correlation(a: &[u8], b: &[u8]) -> f32 {
+    let n = a.len() as f32;
+    let mean = |values: &[u8]| values.iter().map(|&v| v as f32).sum::<f32>() / n;
+    let (mean_a, mean_b) = (mean(a), mean(b));
+    let (mut covariance, mut variance_a, mut variance_b) = (0.0, 0.0, 0.0);
+    for (&a, &b) in a.iter().zip(b) {
+        let (da, db) = (a as f32 - mean_a, b as f32 - mean_b);
+        covariance += da * db;
+        variance_a += da * da;
+        variance_b += db * db;
+    }
+    if variance_a == 0.0 || variance_b == 0.0 {
+        return 0.0;
+    }
+    covariance / (variance_a * variance_b).sqrt()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Difficulty, RngSource};
+
+    #[test]
+    fn reports_rates_from_zero_to_one() {
+        for difficulty in [Difficulty::Easy, Difficulty::Hard] {
+            let mut config = Config {
+                answer_rng: RngSource::Seeded(1),
+                visual_rng: RngSource::Seeded(2),
+                ..Config::default()
+            };
+            difficulty.apply(&mut config);
+            let calibration = Generator::new(config).unwrap().calibrate(4).unwrap();
+            assert_eq!(calibration.samples, 4);
+            assert!(calibration.solved <= calibration.samples);
+            assert!(
+                (0.0..=1.0).contains(&calibration.solve_rate()),
+                "{calibration:?}"
+            );
+            assert!(
+                (0.0..=1.0).contains(&calibration.char_accuracy),
+                "{calibration:?}"
+            );
+        }
+
+        let empty = Generator::new(Config::default())
+            .unwrap()
+            .calibrate(0)
+            .unwrap();
+        assert_eq!((empty.solve_rate(), empty.char_accuracy), (0.0, 0.0));
+    }
+}
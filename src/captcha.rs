@@ -0,0 +1,381 @@
+use std::{
+    fmt,
+    time::{Duration, SystemTime},
+};
+
+use rand::Rng;
+
+use crate::{
+    Answer, AnswerFormat, CaptchaError, DifficultyReport, Format, InputHint, Instruction,
+    StageTiming,
+    hash::{sha256_hex, to_hex},
+    png_chunks,
+};
+
+pub struct Captcha {
+    pub id: String,
+    pub text: Answer,
+    pub image: Vec<u8>,
+    pub format: Format,
+    /// A static PNG of one frame of an animated `image`, see
+    /// [`Animation::fallback`].
+    ///
+    /// [`Animation::fallback`]: crate::Animation::fallback
+    pub fallback_image: Option<Vec<u8>>,
+    /// Pixel size of the image, including any instruction strip, so pages
+    /// can reserve space before it loads.
+    pub width: u32,
+    pub height: u32,
+    pub issued_at: SystemTime,
+    /// `None` when the config has no validity duration.
+    pub expires_at: Option<SystemTime>,
+    /// Set when only part of the shown characters is the answer.
+    pub instruction: Option<Instruction>,
+    pub input_hint: InputHint,
+    pub difficulty: DifficultyReport,
+    /// Lowercase hex SHA-256 of `image` when [`Config::hash_image`] is set,
+    /// so caches and audit logs can refer to the exact image served.
+    ///
+    /// [`Config::hash_image`]: crate::Config::hash_image
+    pub image_sha256: Option<String>,
+    /// Time spent in each stage of [`Config::pipeline`], or the pipeline
+    /// picked from [`Config::pipeline_choices`], in order, to see which
+    /// stages to skip when rendering can't keep up.
+    ///
+    /// [`Config::pipeline`]: crate::Config::pipeline
+    /// [`Config::pipeline_choices`]: crate::Config::pipeline_choices
+    pub stage_timings: Vec<StageTiming>,
+}
+
+impl Captcha {
+    pub(crate) fn new(
+        text: Answer,
+        image: Vec<u8>,
+        format: Format,
+        (width, height): (u32, u32),
+        expires_in: Option<Duration>,
+    ) -> Self {
+        let issued_at = SystemTime::now();
+        Self {
+            id: random_id(),
+            text,
+            image,
+            format,
+            fallback_image: None,
+            width,
+            height,
+            issued_at,
+            expires_at: expires_at(issued_at, expires_in),
+            instruction: None,
+            input_hint: InputHint::default(),
+            difficulty: DifficultyReport::default(),
+            image_sha256: None,
+            stage_timings: Vec::new(),
+        }
+    }
+
+    /// Restarts the validity window, e.g. when a pre-rendered captcha is handed out.
+    pub(crate) fn reissue(&mut self, expires_in: Option<Duration>) {
+        self.issued_at = SystemTime::now();
+        self.expires_at = expires_at(self.issued_at, expires_in);
+    }
+
+    pub fn is_expired(&self) -> bool {
+        is_expired(self.expires_at)
+    }
+
+    pub fn verify(&self, answer: &str) -> bool {
+        !self.is_expired() && self.text.matches(&AnswerFormat::Text.normalize(answer))
+    }
+
+    pub fn content_type(&self) -> &'static str {
+        self.format.content_type()
+    }
+
+    /// Adds a comment to the image, e.g. the captcha id for tracing images
+    /// back to logs: a `tEXt` chunk with the keyword `Comment` in PNGs, a
+    /// COM segment in JPEGs. Other formats, non-ASCII text and JPEG
+    /// comments longer than a segment holds, 65533 bytes, are rejected, as
+    /// are malformed images, which are left unchanged. Updates
+    /// [`Captcha::image_sha256`] if set.
+    pub fn set_comment(&mut self, text: &str) -> Result<(), CaptchaError> {
+        if !text
+            .bytes()
+            .all(|byte| byte.is_ascii_graphic() || byte == b' ')
+        {
+            return Err(CaptchaError::InvalidInput(
+                "comments must be printable ASCII".into(),
+            ));
+        }
+        match self.format {
+            Format::Png | Format::Dithered { .. } => {
+                let mut data = b"Comment\0".to_vec();
+                data.extend(text.as_bytes());
+                png_chunks::insert(&mut self.image, b"tEXt", &data)?;
+            }
+            #[cfg(feature = "jpeg")]
+            Format::Jpeg { .. } => {
+                let Some(length) = u16::try_from(text.len() + 2).ok() else {
+                    return Err(CaptchaError::InvalidInput(format!(
+                        "JPEG comments can be at most {} bytes, got {}",
+                        u16::MAX - 2,
+                        text.len()
+                    )));
+                };
+                let mut segment = vec![0xFF, 0xFE];
+                segment.extend(length.to_be_bytes());
+                segment.extend(text.as_bytes());
+                // After the JFIF header, which must come first.
+                let at = match self.image.get(..6) {
+                    Some([0xFF, 0xD8, 0xFF, 0xE0, high, low]) => {
+                        Some(4 + u16::from_be_bytes([*high, *low]) as usize)
+                    }
+                    _ => self.image.starts_with(&[0xFF, 0xD8]).then_some(2),
+                };
+                let Some(at) = at.filter(|&at| at <= self.image.len()) else {
+                    return Err(CaptchaError::InvalidInput(
+                        "image is not a valid JPEG".into(),
+                    ));
+                };
+                self.image.splice(at..at, segment);
+            }
+            format => {
+                return Err(CaptchaError::InvalidInput(format!(
+                    "can't add a comment to {format:?} images"
+                )));
+            }
+        }
+        if self.image_sha256.is_some() {
+            self.image_sha256 = Some(sha256_hex(&self.image));
+        }
+        Ok(())
+    }
+
+    /// An `<svg>` element showing the image, to embed directly in server
+    /// rendered HTML. It has no external references, scripts or styles,
+    /// and its only id is derived from the captcha id. The image itself is
+    /// a `data:` URI, so a Content Security Policy must allow
+    /// `img-src data:`. `label` is the accessible name, e.g. "Captcha".
+    #[cfg(feature = "svg")]
+    pub fn inline_html(&self, label: &str) -> String {
+        crate::svg::inline(
+            &self.image,
+            self.content_type(),
+            self.width,
+            self.height,
+            &self.id,
+            label,
+        )
+    }
+}
+
+impl fmt::Debug for Captcha {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Captcha")
+            .field("id", &self.id)
+            .field("text", &self.text)
+            .field("image", &ByteCount(self.image.len()))
+            .field("format", &self.format)
+            .field(
+                "fallback_image",
+                &self
+                    .fallback_image
+                    .as_ref()
+                    .map(|image| ByteCount(image.len())),
+            )
+            .field("width", &self.width)
+            .field("height", &self.height)
+            .field("issued_at", &self.issued_at)
+            .field("expires_at", &self.expires_at)
+            .field("instruction", &self.instruction)
+            .field("input_hint", &self.input_hint)
+            .field("difficulty", &self.difficulty)
+            .field("image_sha256", &self.image_sha256)
+            .field("stage_timings", &self.stage_timings)
+            .finish()
+    }
+}
+
+/// Shows image data by its size only.
+struct ByteCount(usize);
+
+impl fmt::Debug for ByteCount {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "<{} bytes>", self.0)
+    }
+}
+
+/// Validity durations too long to represent never expire.
+pub(crate) fn expires_at(
+    issued_at: SystemTime,
+    expires_in: Option<Duration>,
+) -> Option<SystemTime> {
+    expires_in.and_then(|duration| issued_at.checked_add(duration))
+}
+
+pub(crate) fn is_expired(expires_at: Option<SystemTime>) -> bool {
+    expires_at.is_some_and(|expires_at| SystemTime::now() >= expires_at)
+}
+
+/// Keeps ASCII letters, digits, `-` and `_`, so ids can't break out of
+/// attributes or headers, or collide with ids the page defines.
+#[cfg(any(feature = "svg", feature = "email"))]
+pub(crate) fn sanitize_id(id: &str) -> String {
+    id.chars()
+        .filter(|c| c.is_ascii_alphanumeric() || *c == '-' || *c == '_')
+        .collect()
+}
+
+pub(crate) fn random_id() -> String {
+    let bytes: [u8; 16] = rand::rng().random();
+    to_hex(&bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{CaptchaStore, Config, MemoryStore, VerifyOutcome};
+
+    fn issued(expires_in: Option<Duration>) -> Captcha {
+        Captcha::new(
+            Answer::new("x7Kp".into()),
+            Vec::new(),
+            Format::Png,
+            (1, 1),
+            expires_in,
+        )
+    }
+
+    #[test]
+    fn expires_after_the_validity_duration() {
+        let captcha = issued(Some(Duration::from_secs(60)));
+        assert_eq!(
+            captcha.expires_at,
+            Some(captcha.issued_at + Duration::from_secs(60))
+        );
+        assert!(!captcha.is_expired());
+        assert!(captcha.verify("X7KP"));
+
+        let expired = issued(Some(Duration::ZERO));
+        assert!(expired.is_expired());
+        assert!(!expired.verify("x7Kp"));
+
+        let store = MemoryStore::new();
+        store.issue(&expired);
+        assert_eq!(store.verify(&expired.id, "x7Kp"), VerifyOutcome::Expired);
+    }
+
+    #[test]
+    fn never_expires_without_a_duration() {
+        assert_eq!(issued(None).expires_at, None);
+        assert_eq!(issued(Some(Duration::MAX)).expires_at, None);
+        assert!(!issued(None).is_expired());
+    }
+
+    #[test]
+    fn reissuing_restarts_the_validity_window() {
+        let mut captcha = issued(Some(Duration::ZERO));
+        captcha.reissue(Some(Duration::from_secs(60)));
+        assert!(!captcha.is_expired());
+    }
+
+    #[test]
+    fn takes_the_duration_from_the_config() {
+        let config = Config {
+            expires_in: Some(Duration::from_secs(90)),
+            ..Config::default()
+        };
+        let captcha = config.generate().unwrap();
+        assert_eq!(
+            captcha.expires_at,
+            Some(captcha.issued_at + Duration::from_secs(90))
+        );
+    }
+
+    #[test]
+    fn gives_every_captcha_its_own_id() {
+        assert_eq!(random_id().len(), 32);
+        assert_ne!(random_id(), random_id());
+    }
+
+    fn generated(format: Format) -> Captcha {
+        let config = Config {
+            format,
+            hash_image: true,
+            ..Config::default()
+        };
+        config.generate().unwrap()
+    }
+
+    #[test]
+    fn comments_png_images() {
+        let mut captcha = generated(Format::Png);
+        let hash = captcha.image_sha256.clone();
+        captcha.set_comment("id 42").unwrap();
+        assert!(
+            png_chunks::chunks(&captcha.image)
+                .any(|(_, kind, data)| kind == b"tEXt" && data == b"Comment\0id 42")
+        );
+        assert_ne!(captcha.image_sha256, hash);
+        assert!(image::load_from_memory(&captcha.image).is_ok());
+
+        assert!(captcha.set_comment("caf\u{e9}").is_err());
+        assert!(generated(Format::Ansi).set_comment("id 42").is_err());
+    }
+
+    #[cfg(feature = "jpeg")]
+    #[test]
+    fn comments_jpeg_images() {
+        let mut captcha = generated(Format::Jpeg { quality: 80 });
+        captcha.set_comment("id 42").unwrap();
+        let comment = b"\xFF\xFE\x00\x07id 42";
+        assert!(captcha.image.windows(comment.len()).any(|at| at == comment));
+        assert!(image::load_from_memory(&captcha.image).is_ok());
+
+        let longest = "x".repeat(u16::MAX as usize - 2);
+        assert!(captcha.set_comment(&longest).is_ok());
+        let err = captcha.set_comment(&format!("{longest}x")).unwrap_err();
+        assert!(err.to_string().contains("at most 65533 bytes"));
+
+        // An APP0 segment running past the end, and no JPEG at all.
+        for image in [vec![0xFF, 0xD8, 0xFF, 0xE0, 0x00, 0x10, 0x4A], vec![0xFF]] {
+            captcha.image = image.clone();
+            assert!(captcha.set_comment("id 42").is_err());
+            assert_eq!(captcha.image, image);
+        }
+    }
+
+    #[test]
+    fn reports_the_image_size_and_type() {
+        let captcha = generated(Format::Png);
+        let image = image::load_from_memory(&captcha.image).unwrap();
+        assert_eq!(
+            (captcha.width, captcha.height),
+            (image.width(), image.height())
+        );
+        assert_eq!(captcha.content_type(), "image/png");
+
+        let json = crate::CaptchaChallenge::serialize(&captcha);
+        assert!(json.contains(&format!("\"width\":{}", captcha.width)));
+        assert!(json.contains(&format!("\"height\":{}", captcha.height)));
+        assert!(json.contains("\"content_type\":\"image/png\""));
+    }
+
+    #[test]
+    fn hashes_the_served_image_on_request() {
+        let captcha = generated(Format::Png);
+        let hash = captcha.image_sha256.clone().unwrap();
+        assert_eq!(hash, crate::hash::sha256_hex(&captcha.image));
+        assert_eq!(hash.len(), 64);
+        assert!(
+            crate::CaptchaChallenge::serialize(&captcha)
+                .contains(&format!("\"image_sha256\":\"{hash}\""))
+        );
+
+        let store = MemoryStore::new();
+        store.issue(&captcha);
+        assert_eq!(store.take(&captcha.id).unwrap().image_sha256, Some(hash));
+
+        assert_eq!(Config::default().generate().unwrap().image_sha256, None);
+    }
+}
@@ -0,0 +1,273 @@
+use crate::{
+    CaptchaChallenge, CaptchaStore, StoredCaptcha, VerifyOutcome,
+    captcha::{is_expired, random_id},
+    hash::verify_hashed,
+};
+
+/// Several challenges, possibly of different kinds, that have to be solved
+/// one after the other under a single id, e.g. for password resets. The
+/// store tracks the progress: each step is stored under the chain id, and
+/// between steps a placeholder that no answer verifies waits for the next
+/// challenge. A wrong answer ends the chain. Steps can't be refreshed or
+/// swapped for an alternate, and [`CaptchaStore::verify`] never accepts
+/// them.
+#[derive(Clone, Debug)]
+pub struct ChallengeChain {
+    pub id: String,
+    pub steps: u32,
+}
+
+/// What [`ChallengeChain::verify`] found.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ChainProgress {
+    /// The answer was right and `remaining` more steps have to be solved;
+    /// issue the next one with [`ChallengeChain::issue_next`].
+    Next { remaining: u32 },
+    /// The last step was solved.
+    Complete,
+    /// The chain can't be continued, for the reason given: a
+    /// [`VerifyOutcome::Wrong`] answer or a captcha issued outside a chain,
+    /// an [`VerifyOutcome::Expired`] chain, a [`VerifyOutcome::UnknownId`],
+    /// or [`VerifyOutcome::AlreadyUsed`] when the step was answered and the
+    /// next one isn't issued yet.
+    Failed(VerifyOutcome),
+}
+
+impl ChallengeChain {
+    /// A chain of `steps` challenges, at least one, with a fresh id.
+    pub fn new(steps: u32) -> Self {
+        Self {
+            id: random_id(),
+            steps: steps.max(1),
+        }
+    }
+
+    /// Issues `challenge` as the first step.
+    pub fn start<C: CaptchaChallenge>(&self, store: &impl CaptchaStore, challenge: &C) {
+        store.insert(
+            &self.id,
+            StoredCaptcha {
+                chain_steps: self.steps,
+                ..challenge.to_stored()
+            },
+        );
+    }
+
+    /// Issues `challenge` as the next step of chain `id`, after
+    /// [`ChallengeChain::verify`] returned [`ChainProgress::Next`]. The step
+    /// expires with the chain, whatever the expiry of `challenge`. Returns
+    /// false, issuing nothing, when the chain isn't waiting for a step.
+    pub fn issue_next<C: CaptchaChallenge>(
+        store: &impl CaptchaStore,
+        id: &str,
+        challenge: &C,
+    ) -> bool {
+        let Some(entry) = store.take(id) else {
+            return false;
+        };
+        if !is_waiting(&entry) {
+            store.insert(id, entry);
+            return false;
+        }
+        if is_expired(entry.expires_at) {
+            return false;
+        }
+        store.insert(
+            id,
+            StoredCaptcha {
+                expires_at: entry.expires_at,
+                chain_steps: entry.chain_steps,
+                refreshes: entry.refreshes,
+                alternates: entry.alternates,
+                ..challenge.to_stored()
+            },
+        );
+        true
+    }
+
+    /// Checks `answer` against the current step of chain `id` and advances
+    /// the chain. Like [`CaptchaStore::verify`], every step can only be
+    /// answered once. Captchas issued outside a chain never verify here.
+    pub fn verify(store: &impl CaptchaStore, id: &str, answer: &str) -> ChainProgress {
+        let Some(entry) = store.take(id) else {
+            return ChainProgress::Failed(VerifyOutcome::UnknownId);
+        };
+        if is_expired(entry.expires_at) {
+            return ChainProgress::Failed(VerifyOutcome::Expired);
+        }
+        if in_chain(&entry) && is_waiting(&entry) {
+            return ChainProgress::Failed(VerifyOutcome::AlreadyUsed);
+        }
+        if !in_chain(&entry)
+            || !verify_hashed(&entry.answer_hash, &entry.answer_format.normalize(answer))
+        {
+            return ChainProgress::Failed(VerifyOutcome::Wrong);
+        }
+        let remaining = entry.chain_steps.saturating_sub(1);
+        if remaining == 0 {
+            return ChainProgress::Complete;
+        }
+
+        // The next step has as long as the solved one had left.
+        store.insert(
+            id,
+            StoredCaptcha {
+                answer_hash: String::new(),
+                chain_steps: remaining,
+                ..entry
+            },
+        );
+        ChainProgress::Next { remaining }
+    }
+}
+
+/// Whether `entry` is a step of a chain, or waiting for one.
+pub(crate) fn in_chain(entry: &StoredCaptcha) -> bool {
+    entry.chain_steps > 0
+}
+
+fn is_waiting(entry: &StoredCaptcha) -> bool {
+    entry.answer_hash.is_empty()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, SystemTime};
+
+    use super::*;
+    use crate::{
+        ArithmeticConfig, Generator, MemoryStore,
+        test_util::{a_minute_ago, challenge, in_a_minute},
+    };
+
+    fn started(steps: u32) -> (MemoryStore, ChallengeChain) {
+        let store = MemoryStore::new();
+        let chain = ChallengeChain::new(steps);
+        chain.start(&store, &challenge(&chain.id, in_a_minute()));
+        (store, chain)
+    }
+
+    #[test]
+    fn solves_steps_in_order() {
+        let (store, chain) = started(3);
+        let next = challenge(&chain.id, in_a_minute());
+        assert_eq!(
+            ChallengeChain::verify(&store, &chain.id, "7"),
+            ChainProgress::Next { remaining: 2 }
+        );
+        assert_eq!(
+            ChallengeChain::verify(&store, &chain.id, "7"),
+            ChainProgress::Failed(VerifyOutcome::AlreadyUsed)
+        );
+
+        let (store, chain) = started(3);
+        ChallengeChain::verify(&store, &chain.id, "7");
+        assert!(ChallengeChain::issue_next(&store, &chain.id, &next));
+        assert!(!ChallengeChain::issue_next(&store, &chain.id, &next));
+        assert_eq!(
+            ChallengeChain::verify(&store, &chain.id, "7"),
+            ChainProgress::Next { remaining: 1 }
+        );
+        assert!(ChallengeChain::issue_next(&store, &chain.id, &next));
+        assert_eq!(
+            ChallengeChain::verify(&store, &chain.id, "7"),
+            ChainProgress::Complete
+        );
+        assert!(store.take(&chain.id).is_none());
+    }
+
+    #[test]
+    fn ends_on_wrong_answers() {
+        let (store, chain) = started(2);
+        assert_eq!(
+            ChallengeChain::verify(&store, &chain.id, "8"),
+            ChainProgress::Failed(VerifyOutcome::Wrong)
+        );
+        let next = challenge(&chain.id, in_a_minute());
+        assert!(!ChallengeChain::issue_next(&store, &chain.id, &next));
+    }
+
+    #[test]
+    fn reports_why_chains_fail() {
+        let store = MemoryStore::new();
+        let chain = ChallengeChain::new(2);
+        chain.start(&store, &challenge(&chain.id, a_minute_ago()));
+        let failed = |id: &str| match ChallengeChain::verify(&store, id, "7") {
+            ChainProgress::Failed(outcome) => outcome,
+            progress => panic!("expected a failure, got {progress:?}"),
+        };
+        assert_eq!(failed(&chain.id), VerifyOutcome::Expired);
+        assert_eq!(failed(&chain.id), VerifyOutcome::UnknownId);
+    }
+
+    #[test]
+    fn keeps_steps_from_being_swapped() {
+        let generator = Generator::new(Default::default()).unwrap();
+        let config = ArithmeticConfig::default();
+        let next = challenge("", in_a_minute());
+
+        // Mid-chain, waiting for a step, and on the last step.
+        let (store, chain) = started(2);
+        for step in 0..3 {
+            assert!(store.refresh(&chain.id, &generator).unwrap().is_none());
+            assert!(store.issue_alternate(&chain.id, &config).is_none());
+            match step {
+                0 => {
+                    ChallengeChain::verify(&store, &chain.id, "7");
+                }
+                1 => assert!(ChallengeChain::issue_next(&store, &chain.id, &next)),
+                _ => {}
+            }
+        }
+        assert_eq!(
+            ChallengeChain::verify(&store, &chain.id, "7"),
+            ChainProgress::Complete
+        );
+    }
+
+    #[test]
+    fn never_completes_with_plain_captchas() {
+        let store = MemoryStore::new();
+        store.issue(&challenge("plain", in_a_minute()));
+        assert_eq!(
+            ChallengeChain::verify(&store, "plain", "7"),
+            ChainProgress::Failed(VerifyOutcome::Wrong)
+        );
+
+        let (store, chain) = started(2);
+        store.insert(&chain.id, challenge(&chain.id, in_a_minute()).to_stored());
+        assert_eq!(
+            ChallengeChain::verify(&store, &chain.id, "7"),
+            ChainProgress::Failed(VerifyOutcome::Wrong)
+        );
+    }
+
+    #[test]
+    fn keeps_the_deadline_of_the_chain() {
+        let (store, chain) = started(2);
+        let deadline = store.take(&chain.id).unwrap().expires_at;
+        chain.start(&store, &challenge(&chain.id, deadline));
+        ChallengeChain::verify(&store, &chain.id, "7");
+
+        let later = Some(SystemTime::now() + Duration::from_secs(3600));
+        assert!(ChallengeChain::issue_next(
+            &store,
+            &chain.id,
+            &challenge(&chain.id, later)
+        ));
+        assert_eq!(store.take(&chain.id).unwrap().expires_at, deadline);
+    }
+
+    #[test]
+    fn never_verifies_steps_as_plain_captchas() {
+        let (store, chain) = started(2);
+        assert_eq!(store.verify(&chain.id, "7"), VerifyOutcome::Wrong);
+
+        let (store, chain) = started(1);
+        assert_eq!(store.verify(&chain.id, "7"), VerifyOutcome::Wrong);
+
+        let (store, chain) = started(2);
+        ChallengeChain::verify(&store, &chain.id, "7");
+        assert_eq!(store.verify(&chain.id, ""), VerifyOutcome::Wrong);
+    }
+}
@@ -0,0 +1,342 @@
+use std::{
+    borrow::Cow,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use crate::{
+    Answer, AnswerFormat, ArithmeticChallenge, ArithmeticConfig, Captcha, CaptchaError, Config,
+    GridCaptcha, GridConfig, LabeledImage, StoredCaptcha,
+    captcha::is_expired,
+    grid::selection_answer,
+    hash::hash_answer,
+    json::{self, JsonObject},
+};
+
+/// A kind of captcha challenge. Every kind is issued, stored and verified
+/// the same way, so custom kinds work with any [`crate::CaptchaStore`].
+pub trait CaptchaChallenge: Sized {
+    /// What [`CaptchaChallenge::generate`] needs, usually a config.
+    type Options;
+
+    /// Short identifier of the kind, e.g. `"text"`.
+    const KIND: &'static str;
+
+    /// How submitted answers are normalized before they are compared
+    /// against [`CaptchaChallenge::expected_answer`], here and by stores and
+    /// tokens.
+    const ANSWER_FORMAT: AnswerFormat = AnswerFormat::Text;
+
+    fn generate(options: &Self::Options) -> Result<Self, CaptchaError>;
+
+    fn id(&self) -> &str;
+
+    fn expires_at(&self) -> Option<SystemTime>;
+
+    /// When the challenge was generated, for measuring how long solving
+    /// took. `None` if unknown, which never counts as too fast.
+    fn issued_at(&self) -> Option<SystemTime> {
+        None
+    }
+
+    /// The answer in the canonical string form stores hash and [`verify`]
+    /// compares against.
+    ///
+    /// [`verify`]: CaptchaChallenge::verify
+    fn expected_answer(&self) -> Cow<'_, Answer>;
+
+    fn verify(&self, answer: &str) -> bool {
+        !is_expired(self.expires_at())
+            && self
+                .expected_answer()
+                .matches(&Self::ANSWER_FORMAT.normalize(answer))
+    }
+
+    /// Public, answer-free JSON description for the client.
+    fn serialize(&self) -> String;
+
+    fn to_stored(&self) -> StoredCaptcha {
+        StoredCaptcha {
+            answer_hash: hash_answer(
+                &Self::ANSWER_FORMAT.normalize(self.expected_answer().expose()),
+            ),
+            answer_format: Self::ANSWER_FORMAT,
+            expires_at: self.expires_at(),
+            issued_at: self.issued_at(),
+            image_sha256: None,
+            refreshes: 0,
+            alternates: 0,
+            chain_steps: 0,
+        }
+    }
+}
+
+impl CaptchaChallenge for Captcha {
+    type Options = Config;
+
+    const KIND: &'static str = "text";
+
+    fn generate(options: &Config) -> Result<Self, CaptchaError> {
+        options.generate()
+    }
+
+    fn id(&self) -> &str {
+        &self.id
+    }
+
+    fn expires_at(&self) -> Option<SystemTime> {
+        self.expires_at
+    }
+
+    fn issued_at(&self) -> Option<SystemTime> {
+        Some(self.issued_at)
+    }
+
+    fn expected_answer(&self) -> Cow<'_, Answer> {
+        Cow::Borrowed(&self.text)
+    }
+
+    fn serialize(&self) -> String {
+        JsonObject::new()
+            .string("kind", Self::KIND)
+            .string("id", &self.id)
+            .raw("expires_at", &unix_seconds(self.expires_at))
+            .number("width", self.width)
+            .number("height", self.height)
+            .string("content_type", self.content_type())
+            .string("input_hint", self.input_hint.as_str())
+            .optional_string(
+                "instruction",
+                self.instruction.as_ref().map(|i| i.text.as_str()),
+            )
+            .optional_string("image_sha256", self.image_sha256.as_deref())
+            .finish()
+    }
+
+    fn to_stored(&self) -> StoredCaptcha {
+        StoredCaptcha {
+            answer_hash: hash_answer(self.text.expose()),
+            answer_format: Self::ANSWER_FORMAT,
+            expires_at: self.expires_at,
+            issued_at: Some(self.issued_at),
+            image_sha256: self.image_sha256.clone(),
+            refreshes: 0,
+            alternates: 0,
+            chain_steps: 0,
+        }
+    }
+}
+
+impl CaptchaChallenge for GridCaptcha {
+    type Options = (GridConfig, Vec<LabeledImage>);
+
+    const KIND: &'static str = "grid";
+
+    const ANSWER_FORMAT: AnswerFormat = AnswerFormat::Selection;
+
+    fn generate((config, images): &Self::Options) -> Result<Self, CaptchaError> {
+        config.generate(images)
+    }
+
+    fn id(&self) -> &str {
+        &self.id
+    }
+
+    fn expires_at(&self) -> Option<SystemTime> {
+        self.expires_at
+    }
+
+    fn issued_at(&self) -> Option<SystemTime> {
+        Some(self.issued_at)
+    }
+
+    fn expected_answer(&self) -> Cow<'_, Answer> {
+        Cow::Owned(Answer::new(selection_answer(&self.correct)))
+    }
+
+    fn serialize(&self) -> String {
+        let cells = json::array(self.cells.iter().map(|cell| {
+            JsonObject::new()
+                .number("x", cell.x)
+                .number("y", cell.y)
+                .number("width", cell.width)
+                .number("height", cell.height)
+                .finish()
+        }));
+
+        JsonObject::new()
+            .string("kind", Self::KIND)
+            .string("id", &self.id)
+            .raw("expires_at", &unix_seconds(self.expires_at))
+            .number("width", self.width)
+            .number("height", self.height)
+            .string("content_type", self.content_type())
+            .string("instruction", &self.instruction)
+            .raw("cells", &cells)
+            .finish()
+    }
+}
+
+impl CaptchaChallenge for ArithmeticChallenge {
+    type Options = ArithmeticConfig;
+
+    const KIND: &'static str = "arithmetic";
+
+    fn generate(options: &ArithmeticConfig) -> Result<Self, CaptchaError> {
+        Ok(options.generate())
+    }
+
+    fn id(&self) -> &str {
+        &self.id
+    }
+
+    fn expires_at(&self) -> Option<SystemTime> {
+        self.expires_at
+    }
+
+    fn issued_at(&self) -> Option<SystemTime> {
+        Some(self.issued_at)
+    }
+
+    fn expected_answer(&self) -> Cow<'_, Answer> {
+        Cow::Borrowed(&self.answer)
+    }
+
+    fn serialize(&self) -> String {
+        JsonObject::new()
+            .string("kind", Self::KIND)
+            .string("id", &self.id)
+            .raw("expires_at", &unix_seconds(self.expires_at))
+            .string("question", &self.question)
+            .finish()
+    }
+}
+
+fn unix_seconds(time: Option<SystemTime>) -> String {
+    match time.and_then(|t| t.duration_since(UNIX_EPOCH).ok()) {
+        Some(duration) => duration.as_secs().to_string(),
+        None => "null".to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::{
+        CaptchaStore, Format, MemoryStore, MinSolveTime, TokenKey, VerifyOutcome,
+        test_util::{challenge, in_a_minute},
+    };
+
+    fn grid(correct: Vec<usize>) -> GridCaptcha {
+        GridCaptcha {
+            id: "g".to_string(),
+            label: "cat".to_string(),
+            instruction: "Select all images with a cat".to_string(),
+            image: Vec::new(),
+            format: Format::Png,
+            width: 1,
+            height: 1,
+            cells: Vec::new(),
+            correct,
+            issued_at: SystemTime::now(),
+            expires_at: in_a_minute(),
+        }
+    }
+
+    #[test]
+    fn normalizes_selections_the_same_everywhere() {
+        let grid = grid(vec![0, 2, 3, 4]);
+        let store = MemoryStore::new();
+        let key = TokenKey::new("secret");
+        let token = key.issue_token(&grid);
+
+        for (answer, correct) in [("4, 3, 2, 0", true), ("0,2,3,4,4", true), ("0,2,3", false)] {
+            assert_eq!(CaptchaChallenge::verify(&grid, answer), correct);
+            store.issue(&grid);
+            assert_eq!(store.verify("g", answer).is_correct(), correct);
+            assert_eq!(key.verify_token(&token, answer).is_correct(), correct);
+        }
+    }
+
+    #[test]
+    fn keeps_answer_formats_apart() {
+        let grid = grid(vec![7]);
+        let key = TokenKey::new("secret");
+        let token = key.issue_token(&grid);
+        let text = key.issue_token(&challenge("g", grid.expires_at));
+        // Same id and expiry, but the format is signed along.
+        let swapped = text.replacen(".t.", ".s.", 1);
+        assert_eq!(key.verify_token(&token, " 7 "), VerifyOutcome::Correct);
+        assert_eq!(key.verify_token(&swapped, "7"), VerifyOutcome::UnknownId);
+    }
+
+    /// A third-party kind with ids namespaced by its kind.
+    struct Audio {
+        id: String,
+        answer: Answer,
+    }
+
+    impl CaptchaChallenge for Audio {
+        type Options = ();
+
+        const KIND: &'static str = "audio";
+
+        fn generate(_: &()) -> Result<Self, CaptchaError> {
+            Ok(Self {
+                id: format!("{}.v1.42", Self::KIND),
+                answer: Answer::new("seven".into()),
+            })
+        }
+
+        fn id(&self) -> &str {
+            &self.id
+        }
+
+        fn expires_at(&self) -> Option<SystemTime> {
+            in_a_minute()
+        }
+
+        fn expected_answer(&self) -> Cow<'_, Answer> {
+            Cow::Borrowed(&self.answer)
+        }
+
+        fn serialize(&self) -> String {
+            JsonObject::new().string("kind", Self::KIND).finish()
+        }
+    }
+
+    #[test]
+    fn verifies_custom_kinds_with_dotted_ids() {
+        let audio = Audio::generate(&()).unwrap();
+        let store = MemoryStore::new();
+        store.issue(&audio);
+        assert_eq!(store.verify("audio.v1.42", "seven"), VerifyOutcome::Correct);
+
+        let key = TokenKey::new("secret");
+        let token = key.issue_token(&audio);
+        assert_eq!(key.verify_token(&token, "SEVEN"), VerifyOutcome::Correct);
+        assert_eq!(key.verify_token(&token, "six"), VerifyOutcome::Wrong);
+
+        // Without an issue time, answers are never too fast.
+        let policy = MinSolveTime(Duration::from_secs(60));
+        assert!(token.contains(".-."));
+        assert_eq!(
+            key.verify_token_timed(&token, "seven", &policy),
+            VerifyOutcome::Correct
+        );
+    }
+
+    #[test]
+    fn serializes_without_the_answer() {
+        let challenge = challenge("a", None);
+        let json = challenge.serialize();
+        assert!(json.contains(r#""kind":"arithmetic""#));
+        assert!(json.contains(r#""expires_at":null"#));
+        assert!(!json.contains('7'));
+
+        let json = grid(vec![1]).serialize();
+        assert!(json.contains(r#""kind":"grid""#));
+        assert!(json.contains(r#""cells":[]"#));
+    }
+}
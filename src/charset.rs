@@ -0,0 +1,391 @@
+use rand::{
+    Rng,
+    seq::{IndexedRandom, index},
+};
+
+use crate::CaptchaError;
+
+/// How answer text is put together.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum TextStyle {
+    /// Independently drawn characters.
+    #[default]
+    Random,
+    /// Alternating consonants and vowels, e.g. "kebu", which users
+    /// transcribe with fewer mistakes. Digits and
+    /// [`CharWeights::min_digits`] are left out.
+    Pronounceable,
+}
+
+impl TextStyle {
+    pub(crate) fn generate(
+        &self,
+        weights: &CharWeights,
+        charset: &str,
+        length: u32,
+        rng: &mut impl Rng,
+    ) -> Result<String, CaptchaError> {
+        match self {
+            TextStyle::Random => weights.sample(charset, length, rng),
+            TextStyle::Pronounceable => weights.sample_pronounceable(charset, length, rng),
+        }
+    }
+
+    /// Min-entropy in bits of every character of a `length` long text, so
+    /// the sum of any of them bounds how hard that part is to guess.
+    /// Characters only differing in case count as one, like they do when
+    /// answers are checked.
+    pub(crate) fn position_bits(
+        &self,
+        weights: &CharWeights,
+        charset: &str,
+        length: u32,
+    ) -> Vec<f64> {
+        let length = length as usize;
+        match self {
+            TextStyle::Random => {
+                let all = min_entropy(&weights.weighted(charset, |_| true));
+                let digits = weights.weighted(charset, |c| c.is_ascii_digit());
+                let forced = if digits.is_empty() {
+                    0
+                } else {
+                    (weights.min_digits as usize).min(length)
+                };
+                let mut bits = vec![min_entropy(&digits); forced];
+                bits.resize(length, all);
+                bits
+            }
+            TextStyle::Pronounceable => {
+                let consonants =
+                    min_entropy(&weights.weighted(charset, |c| c.is_alphabetic() && !is_vowel(c)));
+                let vowels = min_entropy(&weights.weighted(charset, is_vowel));
+                (0..length)
+                    .map(|i| if i % 2 == 0 { consonants } else { vowels })
+                    .collect()
+            }
+        }
+    }
+}
+
+/// How likely every character of the charset is to appear in the answer.
+/// Weights are relative; a weight of 0 keeps a character out entirely,
+/// e.g. one the font renders poorly.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub struct CharWeights {
+    pub digits: f32,
+    /// Weight of a, e, i, o and u in either case.
+    pub vowels: f32,
+    pub consonants: f32,
+    /// Weights of single characters, replacing their class weight.
+    pub overrides: Vec<(char, f32)>,
+    /// Every answer contains at least this many digits, if the charset has
+    /// any with a weight above 0.
+    pub min_digits: u32,
+}
+
+impl Default for CharWeights {
+    fn default() -> Self {
+        Self {
+            digits: 1.0,
+            vowels: 1.0,
+            consonants: 1.0,
+            overrides: Vec::new(),
+            min_digits: 0,
+        }
+    }
+}
+
+impl CharWeights {
+    pub fn weight(&self, c: char) -> f32 {
+        if let Some(&(_, weight)) = self.overrides.iter().find(|(o, _)| *o == c) {
+            return weight;
+        }
+        if c.is_ascii_digit() {
+            self.digits
+        } else if is_vowel(c) {
+            self.vowels
+        } else {
+            self.consonants
+        }
+    }
+
+    /// The characters of `charset` that can appear at all.
+    pub(crate) fn usable(&self, charset: &str) -> String {
+        charset
+            .chars()
+            .filter(|&c| usable_weight(self.weight(c)))
+            .collect()
+    }
+
+    /// Draws `length` characters from `charset`.
+    pub(crate) fn sample(
+        &self,
+        charset: &str,
+        length: u32,
+        rng: &mut impl Rng,
+    ) -> Result<String, CaptchaError> {
+        let weighted = self.weighted(charset, |_| true);
+        if weighted.is_empty() {
+            return Err(CaptchaError::InvalidInput(
+                "every character of the charset has a weight of 0".into(),
+            ));
+        }
+        let digits: Vec<(char, f32)> = weighted
+            .iter()
+            .copied()
+            .filter(|(c, _)| c.is_ascii_digit())
+            .collect();
+
+        let mut text: Vec<char> = (0..length).map(|_| pick(&weighted, rng)).collect();
+
+        let missing = (self.min_digits as usize)
+            .min(text.len())
+            .saturating_sub(text.iter().filter(|c| c.is_ascii_digit()).count());
+        if missing > 0 && !digits.is_empty() {
+            let letters: Vec<usize> = (0..text.len())
+                .filter(|&i| !text[i].is_ascii_digit())
+                .collect();
+            for i in index::sample(rng, letters.len(), missing) {
+                text[letters[i]] = pick(&digits, rng);
+            }
+        }
+
+        Ok(finish(text))
+    }
+
+    /// Draws `length` characters from `charset`, consonants and vowels
+    /// taking turns.
+    pub(crate) fn sample_pronounceable(
+        &self,
+        charset: &str,
+        length: u32,
+        rng: &mut impl Rng,
+    ) -> Result<String, CaptchaError> {
+        let consonants = self.weighted(charset, |c| c.is_alphabetic() && !is_vowel(c));
+        let vowels = self.weighted(charset, is_vowel);
+        if consonants.is_empty() || vowels.is_empty() {
+            return Err(CaptchaError::InvalidInput(
+                "pronounceable text needs both consonants and vowels in the charset".into(),
+            ));
+        }
+
+        let text = (0..length)
+            .map(|i| match i % 2 {
+                0 => pick(&consonants, rng),
+                _ => pick(&vowels, rng),
+            })
+            .collect();
+        Ok(finish(text))
+    }
+
+    fn weighted(&self, charset: &str, filter: impl Fn(char) -> bool) -> Vec<(char, f32)> {
+        charset
+            .chars()
+            .filter(|&c| filter(c))
+            .map(|c| (c, self.weight(c)))
+            .filter(|&(_, weight)| usable_weight(weight))
+            .collect()
+    }
+}
+
+/// `-log2` of the most likely pick, with case-insensitive duplicates
+/// merged.
+fn min_entropy(weighted: &[(char, f32)]) -> f64 {
+    let mut folded: Vec<(char, f64)> = Vec::new();
+    for &(c, weight) in weighted {
+        let c = c.to_ascii_lowercase();
+        match folded.iter_mut().find(|(f, _)| *f == c) {
+            Some((_, total)) => *total += weight as f64,
+            None => folded.push((c, weight as f64)),
+        }
+    }
+
+    let total: f64 = folded.iter().map(|(_, weight)| weight).sum();
+    let max = folded.iter().map(|(_, weight)| *weight).fold(0.0, f64::max);
+    if max > 0.0 && total.is_finite() {
+        (total / max).log2()
+    } else {
+        0.0
+    }
+}
+
+fn is_vowel(c: char) -> bool {
+    "aeiou".contains(c.to_ascii_lowercase())
+}
+
+#[cfg_attr(not(feature = "zeroize"), allow(unused_mut))]
+fn finish(mut text: Vec<char>) -> String {
+    let answer = text.iter().collect();
+
+    #[cfg(feature = "zeroize")]
+    zeroize::Zeroize::zeroize(&mut text);
+
+    answer
+}
+
+fn usable_weight(weight: f32) -> bool {
+    weight.is_finite() && weight > 0.0
+}
+
+fn pick(weighted: &[(char, f32)], rng: &mut impl Rng) -> char {
+    weighted
+        .choose_weighted(rng, |&(_, weight)| weight)
+        .map(|&(c, _)| c)
+        .unwrap_or(weighted[0].0)
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::{SeedableRng, rngs::StdRng};
+
+    use super::*;
+    use crate::{Config, Mode};
+
+    const CHARSET: &str = "23456789ABCDEFGHJKLMNPQRSTUVWXYZ";
+
+    #[test]
+    fn weighs_characters_by_class_unless_overridden() {
+        let weights = CharWeights {
+            digits: 2.0,
+            vowels: 0.5,
+            consonants: 1.0,
+            overrides: vec![('Q', 0.0)],
+            min_digits: 0,
+        };
+        assert_eq!(weights.weight('7'), 2.0);
+        assert_eq!(weights.weight('e'), 0.5);
+        assert_eq!(weights.weight('E'), 0.5);
+        assert_eq!(weights.weight('K'), 1.0);
+        assert_eq!(weights.weight('Q'), 0.0);
+        assert!(!weights.usable(CHARSET).contains('Q'));
+    }
+
+    #[test]
+    fn never_samples_characters_weighted_zero() {
+        let weights = CharWeights {
+            digits: 0.0,
+            overrides: vec![('A', 0.0), ('B', f32::NAN)],
+            ..Default::default()
+        };
+        let mut rng = StdRng::seed_from_u64(1);
+        let text = weights.sample(CHARSET, 2000, &mut rng).unwrap();
+        assert_eq!(text.chars().count(), 2000);
+        assert!(!text.contains(|c: char| c.is_ascii_digit() || c == 'A' || c == 'B'));
+
+        let nothing = CharWeights {
+            digits: 0.0,
+            ..Default::default()
+        };
+        assert!(nothing.sample("234", 4, &mut rng).is_err());
+    }
+
+    #[test]
+    fn puts_in_at_least_the_minimum_of_digits() {
+        let weights = CharWeights {
+            digits: 0.001,
+            min_digits: 2,
+            ..Default::default()
+        };
+        let mut rng = StdRng::seed_from_u64(1);
+        for _ in 0..50 {
+            let text = weights.sample(CHARSET, 5, &mut rng).unwrap();
+            assert!(
+                text.chars().filter(char::is_ascii_digit).count() >= 2,
+                "{text}"
+            );
+        }
+        // More than the length, or a charset without digits, isn't an error.
+        let text = weights.sample(CHARSET, 1, &mut rng).unwrap();
+        assert!(text.chars().all(|c| c.is_ascii_digit()));
+        assert!(weights.sample("ABC", 4, &mut rng).is_ok());
+    }
+
+    #[test]
+    fn alternates_consonants_and_vowels() {
+        let mut rng = StdRng::seed_from_u64(1);
+        for _ in 0..50 {
+            let text = TextStyle::Pronounceable
+                .generate(&CharWeights::default(), "23bdkmaeiu", 7, &mut rng)
+                .unwrap();
+            assert_eq!(text.len(), 7);
+            for (i, c) in text.chars().enumerate() {
+                assert_eq!(is_vowel(c), i % 2 == 1, "{text}");
+                assert!(c.is_alphabetic(), "{text}");
+            }
+        }
+    }
+
+    #[test]
+    fn needs_consonants_and_vowels_to_be_pronounceable() {
+        let mut rng = StdRng::seed_from_u64(1);
+        let weights = CharWeights::default();
+        assert!(weights.sample_pronounceable(CHARSET, 4, &mut rng).is_ok());
+        assert!(weights.sample_pronounceable("BCDF23", 4, &mut rng).is_err());
+        let silent = CharWeights {
+            vowels: 0.0,
+            ..Default::default()
+        };
+        assert!(silent.sample_pronounceable(CHARSET, 4, &mut rng).is_err());
+    }
+
+    fn assert_bits(config: Config, expected: f64) {
+        let bits = config.entropy_bits();
+        assert!((bits - expected).abs() < 1e-9, "{bits} != {expected}");
+    }
+
+    #[test]
+    fn counts_case_variants_as_one_character() {
+        // 8 digits and 25 letters, 23 of them in both cases.
+        let per_char = 28f64.log2();
+        assert_bits(Config::default(), 4.0 * per_char);
+        assert_bits(
+            Config {
+                charset: Some("abAB".into()),
+                length: 3,
+                ..Default::default()
+            },
+            3.0,
+        );
+        assert_bits(
+            Config {
+                mode: Mode::Positions { count: 2 },
+                ..Default::default()
+            },
+            2.0 * per_char,
+        );
+    }
+
+    #[test]
+    fn takes_weights_and_styles_into_account() {
+        let config = |char_weights, text_style| Config {
+            charset: Some("0123456789abcdef".into()),
+            char_weights,
+            text_style,
+            ..Default::default()
+        };
+        assert_bits(config(CharWeights::default(), TextStyle::Random), 16.0);
+
+        // The most likely character bounds how hard guessing is.
+        let skewed = CharWeights {
+            overrides: vec![('a', 15.0)],
+            ..Default::default()
+        };
+        assert_bits(config(skewed, TextStyle::Random), 4.0);
+
+        let digits = CharWeights {
+            min_digits: 1,
+            ..Default::default()
+        };
+        assert_bits(config(digits, TextStyle::Random), 10f64.log2() + 3.0 * 4.0);
+
+        // "bcdf" and "ae" take turns.
+        assert_bits(
+            config(CharWeights::default(), TextStyle::Pronounceable),
+            2.0 * 2.0 + 2.0 * 1.0,
+        );
+    }
+}
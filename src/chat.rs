@@ -0,0 +1,128 @@
+use std::time::{Duration, SystemTime};
+
+use crate::Captcha;
+
+/// A captcha as a chat bot posts it: the image as a file with a caption
+/// telling users what to do, see [`Captcha::chat_message`]. With the
+/// `teloxide` and `serenity` features it converts to their attachment
+/// types. Telegram and Discord only show PNG, JPEG, GIF and WebP inline.
+#[derive(Clone, Debug)]
+pub struct ChatMessage {
+    pub file_name: String,
+    pub image: Vec<u8>,
+    pub caption: String,
+    /// Time left to answer, for deleting the message once it's useless.
+    pub expires_in: Option<Duration>,
+}
+
+impl Captcha {
+    /// The instruction, or a request to type the characters, as caption,
+    /// followed by the time left to answer.
+    pub fn chat_message(&self) -> ChatMessage {
+        let expires_in = self.expires_at.map(|expires_at| {
+            expires_at
+                .duration_since(SystemTime::now())
+                .unwrap_or_default()
+        });
+        let mut caption = match &self.instruction {
+            Some(instruction) => instruction.text.clone(),
+            None => "Type the characters shown".to_string(),
+        };
+        match expires_in {
+            Some(left) if left.as_secs() >= 120 => {
+                caption.push_str(&format!(" within {} minutes.", left.as_secs() / 60))
+            }
+            Some(left) => caption.push_str(&format!(" within {} seconds.", left.as_secs())),
+            None => caption.push('.'),
+        }
+        ChatMessage {
+            file_name: format!("captcha.{}", self.format.extension()),
+            image: self.image.clone(),
+            caption,
+            expires_in,
+        }
+    }
+}
+
+impl ChatMessage {
+    /// The image for teloxide's `send_photo`, with
+    /// [`ChatMessage::caption`] set separately through `.caption(..)`.
+    #[cfg(feature = "teloxide")]
+    pub fn to_teloxide(&self) -> teloxide_core::types::InputFile {
+        teloxide_core::types::InputFile::memory(self.image.clone())
+            .file_name(self.file_name.clone())
+    }
+
+    /// The image for serenity's `CreateMessage::add_file`, next to
+    /// [`ChatMessage::caption`] as the content.
+    #[cfg(feature = "serenity")]
+    pub fn to_serenity(&self) -> serenity::builder::CreateAttachment {
+        serenity::builder::CreateAttachment::bytes(self.image.clone(), self.file_name.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Config, Generator, Mode};
+
+    fn generate(config: Config) -> Captcha {
+        Generator::new(config).unwrap().generate().unwrap()
+    }
+
+    #[test]
+    fn captions_without_the_answer() {
+        let captcha = generate(Config {
+            expires_in: Some(Duration::from_secs(300)),
+            ..Config::default()
+        });
+        let message = captcha.chat_message();
+        assert_eq!(message.file_name, "captcha.png");
+        assert_eq!(message.image, captcha.image);
+        assert_eq!(
+            message.caption,
+            "Type the characters shown within 4 minutes."
+        );
+        assert!(message.expires_in.unwrap() <= Duration::from_secs(300));
+
+        let captcha = generate(Config {
+            mode: Mode::Positions { count: 2 },
+            expires_in: Some(Duration::from_secs(90)),
+            ..Config::default()
+        });
+        let message = captcha.chat_message();
+        // Only the instruction and the time left, never the answer.
+        let instruction = &captcha.instruction.as_ref().unwrap().text;
+        let seconds = message.expires_in.unwrap().as_secs();
+        assert_eq!(
+            message.caption,
+            format!("{instruction} within {seconds} seconds.")
+        );
+        assert_eq!(message.file_name, "captcha.png");
+
+        let captcha = generate(Config {
+            expires_in: None,
+            ..Config::default()
+        });
+        assert_eq!(captcha.chat_message().caption, "Type the characters shown.");
+    }
+
+    #[cfg(feature = "serenity")]
+    #[test]
+    fn attaches_to_discord_messages() {
+        let message = generate(Config::default()).chat_message();
+        let attachment = message.to_serenity();
+        assert_eq!(attachment.filename, "captcha.png");
+        assert_eq!(attachment.data, message.image);
+        assert_eq!(attachment.description, None);
+    }
+
+    #[cfg(feature = "teloxide")]
+    #[test]
+    fn attaches_to_telegram_messages() {
+        let captcha = generate(Config::default());
+        let file = format!("{:?}", captcha.chat_message().to_teloxide());
+        assert!(file.contains("captcha.png"));
+        assert!(!file.contains(captcha.text.expose()));
+    }
+}
@@ -0,0 +1,199 @@
+//! Per-pixel loops of compositing, with SSE2 versions behind the `simd`
+//! feature on x86_64. Both paths produce identical results.
+//!
+//! While a captcha renders, every canvas and layer holds premultiplied
+//! alpha, like raqote's draw targets do, so resampling and compositing
+//! never bleed the color of transparent pixels into edges. Frames are
+//! converted back to straight alpha once, before they are encoded.
+
+use image::{Rgba, RgbaImage};
+
+/// Converts raqote's premultiplied ARGB words into premultiplied RGBA
+/// bytes, appending to `dst`.
+pub(crate) fn argb_to_rgba(src: &[u32], dst: &mut Vec<u8>) {
+    dst.reserve(src.len() * 4);
+
+    #[cfg(all(feature = "simd", target_arch = "x86_64"))]
+    let src = {
+        let chunks = src.len() / 4 * 4;
+        // SAFETY: SSE2 is part of the x86_64 baseline.
+        unsafe { sse2::argb_to_rgba(&src[..chunks], dst) };
+        &src[chunks..]
+    };
+
+    for &argb in src {
+        dst.extend_from_slice(&swap_red_blue(argb).to_le_bytes());
+    }
+}
+
+fn swap_red_blue(argb: u32) -> u32 {
+    (argb & 0xFF00_FF00) | ((argb >> 16) & 0xFF) | ((argb & 0xFF) << 16)
+}
+
+/// Alpha-composites the premultiplied RGBA row `top` over `bottom`, which
+/// must have the same length.
+pub(crate) fn over_row(bottom: &mut [u8], top: &[u8]) {
+    #[cfg(all(feature = "simd", target_arch = "x86_64"))]
+    let (bottom, top) = {
+        let chunks = bottom.len() / 16 * 16;
+        let (simd_bottom, rest_bottom) = bottom.split_at_mut(chunks);
+        let (simd_top, rest_top) = top.split_at(chunks);
+        // SAFETY: SSE2 is part of the x86_64 baseline.
+        unsafe { sse2::over_row(simd_bottom, simd_top) };
+        (rest_bottom, rest_top)
+    };
+
+    for (dst, src) in bottom.chunks_exact_mut(4).zip(top.chunks_exact(4)) {
+        over_pixel(dst, src);
+    }
+}
+
+fn over_pixel(dst: &mut [u8], src: &[u8]) {
+    let alpha = src[3] as u16;
+    if alpha == 0 {
+        return;
+    }
+    for c in 0..4 {
+        dst[c] = src[c].saturating_add(div_255(dst[c] as u16 * (255 - alpha)));
+    }
+}
+
+/// Rounded division by 255 for values up to 255 * 255.
+pub(crate) fn div_255(value: u16) -> u8 {
+    let value = value + 128;
+    ((value + (value >> 8)) >> 8) as u8
+}
+
+pub(crate) fn premultiply_pixel(Rgba([r, g, b, a]): Rgba<u8>) -> Rgba<u8> {
+    let scale = |channel: u8| div_255(channel as u16 * a as u16);
+    Rgba([scale(r), scale(g), scale(b), a])
+}
+
+pub(crate) fn unpremultiply_pixel(Rgba([r, g, b, a]): Rgba<u8>) -> Rgba<u8> {
+    if a == 0 {
+        return Rgba([0, 0, 0, 0]);
+    }
+    let scale = |channel: u8| ((channel as u32 * 255 + a as u32 / 2) / a as u32).min(255) as u8;
+    Rgba([scale(r), scale(g), scale(b), a])
+}
+
+pub(crate) fn unpremultiply(img: &mut RgbaImage) {
+    img.pixels_mut()
+        .filter(|pixel| pixel[3] != 255)
+        .for_each(|pixel| *pixel = unpremultiply_pixel(*pixel));
+}
+
+#[cfg(all(feature = "simd", target_arch = "x86_64"))]
+mod sse2 {
+    use std::arch::x86_64::*;
+
+    pub(super) unsafe fn argb_to_rgba(src: &[u32], dst: &mut Vec<u8>) {
+        unsafe {
+            let keep = _mm_set1_epi32(0xFF00_FF00_u32 as i32);
+            let low = _mm_set1_epi32(0xFF);
+            let mut out = [0u8; 16];
+            for chunk in src.chunks_exact(4) {
+                let argb = _mm_loadu_si128(chunk.as_ptr().cast());
+                let red = _mm_and_si128(_mm_srli_epi32(argb, 16), low);
+                let blue = _mm_slli_epi32(_mm_and_si128(argb, low), 16);
+                let rgba = _mm_or_si128(_mm_and_si128(argb, keep), _mm_or_si128(red, blue));
+                _mm_storeu_si128(out.as_mut_ptr().cast(), rgba);
+                dst.extend_from_slice(&out);
+            }
+        }
+    }
+
+    /// Four pixels at a time, in the same integer math as `over_pixel`.
+    pub(super) unsafe fn over_row(bottom: &mut [u8], top: &[u8]) {
+        unsafe {
+            let zero = _mm_setzero_si128();
+            let full = _mm_set1_epi16(255);
+            let rounding = _mm_set1_epi16(128);
+
+            for (dst, src) in bottom.chunks_exact_mut(16).zip(top.chunks_exact(16)) {
+                let s = _mm_loadu_si128(src.as_ptr().cast());
+                let d = _mm_loadu_si128(dst.as_ptr().cast());
+                let scale = |s: __m128i, d: __m128i| {
+                    // Broadcast every pixel's alpha to its four lanes.
+                    let alpha = _mm_shufflehi_epi16(_mm_shufflelo_epi16(s, 0xFF), 0xFF);
+                    let value =
+                        _mm_add_epi16(_mm_mullo_epi16(d, _mm_sub_epi16(full, alpha)), rounding);
+                    _mm_srli_epi16(_mm_add_epi16(value, _mm_srli_epi16(value, 8)), 8)
+                };
+                let low = scale(_mm_unpacklo_epi8(s, zero), _mm_unpacklo_epi8(d, zero));
+                let high = scale(_mm_unpackhi_epi8(s, zero), _mm_unpackhi_epi8(d, zero));
+                let out = _mm_adds_epu8(s, _mm_packus_epi16(low, high));
+                _mm_storeu_si128(dst.as_mut_ptr().cast(), out);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::{Rng, SeedableRng, rngs::StdRng};
+
+    use super::*;
+
+    /// Random premultiplied pixels, some of them transparent or opaque.
+    fn pixels(count: usize, rng: &mut impl Rng) -> Vec<u8> {
+        (0..count)
+            .flat_map(|_| {
+                let alpha = match rng.random_range(0..4) {
+                    0 => 0,
+                    1 => 255,
+                    _ => rng.random(),
+                };
+                let rgba = Rgba([rng.random(), rng.random(), rng.random(), alpha]);
+                premultiply_pixel(rgba).0
+            })
+            .collect()
+    }
+
+    #[test]
+    fn divides_by_255_rounded() {
+        for value in 0..=255 * 255 {
+            let expected = (value as f32 / 255.0).round() as u8;
+            assert_eq!(div_255(value), expected, "{value}");
+        }
+    }
+
+    #[test]
+    fn converts_argb_words_of_any_length() {
+        let src: Vec<u32> = (0..11).map(|i| 0x10203040 * i + i).collect();
+        let mut dst = vec![1, 2];
+        argb_to_rgba(&src, &mut dst);
+        assert_eq!(dst.len(), 2 + src.len() * 4);
+        for (word, rgba) in src.iter().zip(dst[2..].chunks_exact(4)) {
+            let [b, g, r, a] = word.to_le_bytes();
+            assert_eq!(rgba, [r, g, b, a]);
+        }
+    }
+
+    #[test]
+    fn composites_rows_like_single_pixels() {
+        let mut rng = StdRng::seed_from_u64(1);
+        // Not a multiple of four pixels, so fast paths leave a remainder.
+        let (top, bottom) = (pixels(37, &mut rng), pixels(37, &mut rng));
+        let mut row = bottom.clone();
+        over_row(&mut row, &top);
+        let mut expected = bottom;
+        for (dst, src) in expected.chunks_exact_mut(4).zip(top.chunks_exact(4)) {
+            over_pixel(dst, src);
+        }
+        assert_eq!(row, expected);
+    }
+
+    #[test]
+    fn round_trips_premultiplied_pixels() {
+        let opaque = Rgba([10, 200, 30, 255]);
+        assert_eq!(premultiply_pixel(opaque), opaque);
+        assert_eq!(unpremultiply_pixel(premultiply_pixel(opaque)), opaque);
+
+        let half = premultiply_pixel(Rgba([200, 100, 0, 128]));
+        assert_eq!(half, Rgba([100, 50, 0, 128]));
+        let back = unpremultiply_pixel(half);
+        assert!(back.0[0].abs_diff(200) <= 1 && back.0[1].abs_diff(100) <= 1);
+        assert_eq!(unpremultiply_pixel(Rgba([5, 5, 5, 0])), Rgba([0, 0, 0, 0]));
+    }
+}
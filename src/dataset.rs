@@ -0,0 +1,388 @@
+use std::{
+    fs::{self, File},
+    io::{BufWriter, Write},
+    path::Path,
+    sync::{Arc, Mutex, mpsc::sync_channel},
+    thread,
+};
+
+use sha2::{Digest, Sha256};
+
+use crate::{
+    Captcha, CaptchaError, Config, Generator, Layout, generator::Composed, hash::to_hex,
+    json::JsonObject,
+};
+
+/// Writes captchas to a directory as training data for OCR models.
+///
+/// Next to the images, `manifest.jsonl` gets one line per captcha with its
+/// file name (relative to the directory), answer, full [`Layout`] and a
+/// hash of the config that produced it.
+///
+/// [`Layout`]: crate::Layout
+#[derive(Clone, Debug)]
+pub struct DatasetOptions {
+    pub count: usize,
+    /// Spreads the images over `shard-NNNNN` subdirectories of this many
+    /// images each. `None` puts all of them into the directory itself.
+    pub shard_size: Option<usize>,
+    /// Number of threads rendering captchas.
+    pub threads: usize,
+    /// Number of extra threads encoding and writing the images, so the
+    /// rendering threads go on with the next captchas meanwhile. With 0
+    /// the rendering threads encode their own captchas.
+    pub encode_threads: usize,
+    /// What draws the captchas.
+    pub backend: RendererBackend,
+}
+
+/// What [`DatasetOptions::export`] draws captchas with.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum RendererBackend {
+    #[default]
+    Cpu,
+    /// Experimental: draws whole batches of captchas in one pass with
+    /// wgpu, using the first adapter found, which may be a software one.
+    /// Only configs without animations, blend modes, lines kept off the
+    /// glyphs and stages other than lines and curves are supported; others
+    /// fail with [`CaptchaError::InvalidInput`]. The noise is laid out the
+    /// same as on the CPU, but antialiased a little differently.
+    #[cfg(feature = "gpu")]
+    Gpu,
+}
+
+impl Default for DatasetOptions {
+    fn default() -> Self {
+        Self {
+            count: 1000,
+            shard_size: None,
+            threads: 1,
+            encode_threads: 0,
+            backend: RendererBackend::Cpu,
+        }
+    }
+}
+
+impl DatasetOptions {
+    pub fn export(&self, generator: &Generator, dir: impl AsRef<Path>) -> Result<(), CaptchaError> {
+        let dir = dir.as_ref();
+        if self.shard_size == Some(0) {
+            return Err(CaptchaError::InvalidInput(
+                "shard size must be at least 1".into(),
+            ));
+        }
+        fs::create_dir_all(dir)?;
+
+        let threads = self.threads.clamp(1, self.count.max(1));
+        let mut lines = match self.backend {
+            #[cfg(feature = "gpu")]
+            RendererBackend::Gpu => self.export_gpu(generator, dir, threads)?,
+            RendererBackend::Cpu if self.encode_threads == 0 => {
+                self.export_inline(generator, dir, threads)?
+            }
+            RendererBackend::Cpu => self.export_pipelined(generator, dir, threads)?,
+        };
+        lines.sort_unstable_by_key(|(index, _)| *index);
+
+        let mut manifest = BufWriter::new(File::create(dir.join("manifest.jsonl"))?);
+        for (_, line) in lines {
+            writeln!(manifest, "{line}")?;
+        }
+        manifest.flush()?;
+
+        Ok(())
+    }
+
+    fn export_inline(
+        &self,
+        generator: &Generator,
+        dir: &Path,
+        threads: usize,
+    ) -> Result<Vec<(usize, String)>, CaptchaError> {
+        let lines = thread::scope(|scope| {
+            let workers: Vec<_> = (0..threads)
+                .map(|worker| {
+                    scope.spawn(move || {
+                        (worker..self.count)
+                            .step_by(threads)
+                            .map(|index| self.generate_sample(generator, dir, index))
+                            .collect::<Result<Vec<_>, _>>()
+                    })
+                })
+                .collect();
+
+            workers
+                .into_iter()
+                .map(|worker| worker.join().expect("dataset worker panicked"))
+                .collect::<Result<Vec<_>, _>>()
+        })?
+        .into_iter()
+        .flatten()
+        .collect();
+        Ok(lines)
+    }
+
+    fn export_pipelined(
+        &self,
+        generator: &Generator,
+        dir: &Path,
+        threads: usize,
+    ) -> Result<Vec<(usize, String)>, CaptchaError> {
+        let (sender, receiver) = sync_channel::<(usize, Composed)>(self.encode_threads);
+        // Every encoder holds the receiver, so rendering stops once all of
+        // them have failed.
+        let receiver = Arc::new(Mutex::new(receiver));
+
+        thread::scope(|scope| {
+            let renderers: Vec<_> = (0..threads)
+                .map(|worker| {
+                    let sender = sender.clone();
+                    scope.spawn(move || {
+                        for index in (worker..self.count).step_by(threads) {
+                            let composed = generator.compose(generator.config(), index as u64)?;
+                            if sender.send((index, composed)).is_err() {
+                                break;
+                            }
+                        }
+                        Ok::<_, CaptchaError>(())
+                    })
+                })
+                .collect();
+            drop(sender);
+
+            let encoders: Vec<_> = (0..self.encode_threads)
+                .map(|_| {
+                    let receiver = receiver.clone();
+                    scope.spawn(move || {
+                        let mut lines = Vec::new();
+                        loop {
+                            let next = receiver.lock().unwrap().recv();
+                            let Ok((index, composed)) = next else {
+                                return Ok(lines);
+                            };
+                            let config = composed.config.clone();
+                            let (captcha, layout) = composed.encode()?;
+                            lines.push(self.write_sample(dir, index, &config, captcha, layout)?);
+                        }
+                    })
+                })
+                .collect();
+            drop(receiver);
+
+            let lines = encoders
+                .into_iter()
+                .map(|encoder| encoder.join().expect("dataset worker panicked"))
+                .collect::<Result<Vec<Vec<_>>, CaptchaError>>();
+            // A renderer's error explains why the encoders ran out of work.
+            for renderer in renderers {
+                renderer.join().expect("dataset worker panicked")?;
+            }
+            Ok(lines?.into_iter().flatten().collect())
+        })
+    }
+
+    /// Renders on the GPU in chunks, encoding each chunk on `threads`
+    /// threads before the next one is rendered.
+    #[cfg(feature = "gpu")]
+    fn export_gpu(
+        &self,
+        generator: &Generator,
+        dir: &Path,
+        threads: usize,
+    ) -> Result<Vec<(usize, String)>, CaptchaError> {
+        let config = generator.config();
+        crate::gpu::check(&config)?;
+        let renderer = crate::gpu::GpuRenderer::new()?;
+
+        let mut lines = Vec::with_capacity(self.count);
+        let mut start = 0;
+        while start < self.count {
+            let count = GPU_CHUNK.min(self.count - start);
+            let rendered = renderer.render(generator, &config, start as u64, count)?;
+            let per_thread = rendered.len().div_ceil(threads);
+            let mut rendered = rendered.into_iter().enumerate();
+            let chunk = thread::scope(|scope| {
+                let workers: Vec<_> = (0..threads)
+                    .map(|_| {
+                        let part: Vec<_> = rendered.by_ref().take(per_thread).collect();
+                        let config = &config;
+                        scope.spawn(move || {
+                            part.into_iter()
+                                .map(|(offset, rendered)| {
+                                    let composed = Composed::new(config.clone(), rendered);
+                                    let (captcha, layout) = composed.encode()?;
+                                    let index = start + offset;
+                                    self.write_sample(dir, index, config, captcha, layout)
+                                })
+                                .collect::<Result<Vec<_>, _>>()
+                        })
+                    })
+                    .collect();
+
+                workers
+                    .into_iter()
+                    .map(|worker| worker.join().expect("dataset worker panicked"))
+                    .collect::<Result<Vec<_>, _>>()
+            })?;
+            lines.extend(chunk.into_iter().flatten());
+            start += count;
+        }
+        Ok(lines)
+    }
+
+    fn generate_sample(
+        &self,
+        generator: &Generator,
+        dir: &Path,
+        index: usize,
+    ) -> Result<(usize, String), CaptchaError> {
+        let config = generator.config();
+        let (captcha, layout) = generator.generate_at(config.clone(), index as u64)?;
+        self.write_sample(dir, index, &config, captcha, layout)
+    }
+
+    fn write_sample(
+        &self,
+        dir: &Path,
+        index: usize,
+        config: &Config,
+        captcha: Captcha,
+        layout: Layout,
+    ) -> Result<(usize, String), CaptchaError> {
+        let file_name = format!("{index:06}.{}", config.format.extension());
+        let file = match self.shard_size {
+            Some(shard_size) => {
+                let shard = format!("shard-{:05}", index / shard_size);
+                fs::create_dir_all(dir.join(&shard))?;
+                format!("{shard}/{file_name}")
+            }
+            None => file_name,
+        };
+        fs::write(dir.join(&file), &captcha.image)?;
+
+        let line = JsonObject::new()
+            .string("file", &file)
+            .string("answer", captcha.text.expose())
+            .raw("layout", &layout.to_json())
+            .string("config", &config_hash(config))
+            .finish();
+
+        Ok((index, line))
+    }
+}
+
+/// Captchas rendered before they are encoded, bounding the memory the GPU
+/// backend needs.
+#[cfg(feature = "gpu")]
+const GPU_CHUNK: usize = 1024;
+
+/// Identifies the settings a sample was rendered with. Custom
+/// `instruction_text` functions aren't covered.
+fn config_hash(config: &Config) -> String {
+    let description = format!(
+        "{} {} {} {:?} {:?} {:?} {:?} {:?} {:?} {:?} {:?} {:?} {:?} {:?} {:?} {:?} {:?} {:?} {:?} {:?} {:?} {:?} {:?} {:?} {:?} {:?} {:?} {:?}",
+        config.length,
+        config.width,
+        config.height,
+        config.crop_margin,
+        config.color,
+        config.color_scheme,
+        config.background_color,
+        config.background,
+        config.expires_in,
+        config.mode,
+        config.format,
+        config.max_bytes,
+        config.line_style,
+        config.glyph_style,
+        config.glyph_spacing,
+        config.max_glyph_overlap,
+        config.typeface,
+        config.decoys,
+        config.pipeline,
+        config.pipeline_choices,
+        config.animation,
+        config.charset,
+        config.text_style,
+        config.text_provider.is_some(),
+        config.char_weights,
+        config.numerals,
+        config.answer_rng,
+        config.visual_rng,
+    );
+    to_hex(&Sha256::digest(description.as_bytes()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::RngSource;
+
+    fn export(backend: RendererBackend, encode_threads: usize) -> Result<String, CaptchaError> {
+        let dir = std::env::temp_dir().join(format!(
+            "captchagen-dataset-{backend:?}-{encode_threads}-{}",
+            std::process::id()
+        ));
+        let generator = Generator::new(Config {
+            answer_rng: RngSource::Seeded(1),
+            visual_rng: RngSource::Seeded(2),
+            ..Config::default()
+        })?;
+        let options = DatasetOptions {
+            count: 5,
+            shard_size: Some(2),
+            threads: 2,
+            encode_threads,
+            backend,
+        };
+        let result = options.export(&generator, &dir).and_then(|()| {
+            assert!(dir.join("shard-00002/000004.png").is_file());
+            Ok(fs::read_to_string(dir.join("manifest.jsonl"))?)
+        });
+        let _ = fs::remove_dir_all(&dir);
+        result
+    }
+
+    #[test]
+    fn writes_images_and_manifest() {
+        let manifest = export(RendererBackend::Cpu, 0).unwrap();
+        let entries: Vec<serde_json::Value> = manifest
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        for entry in &entries {
+            assert_eq!(entry["answer"].as_str().unwrap().len(), 4);
+            assert!(entry["layout"]["glyphs"].is_array());
+            assert_eq!(entry["config"].as_str().unwrap().len(), 64);
+        }
+        let files: Vec<_> = entries
+            .iter()
+            .map(|entry| entry["file"].as_str().unwrap())
+            .collect();
+        assert_eq!(
+            files,
+            [
+                "shard-00000/000000.png",
+                "shard-00000/000001.png",
+                "shard-00001/000002.png",
+                "shard-00001/000003.png",
+                "shard-00002/000004.png",
+            ]
+        );
+    }
+
+    #[test]
+    fn encodes_the_same_on_separate_threads() {
+        let manifest = export(RendererBackend::Cpu, 3).unwrap();
+        assert_eq!(manifest.lines().count(), 5);
+        assert_eq!(manifest, export(RendererBackend::Cpu, 0).unwrap());
+    }
+
+    #[cfg(feature = "gpu")]
+    #[test]
+    #[ignore = "needs a GPU adapter"]
+    fn lays_out_the_same_on_the_gpu() {
+        let manifest = export(RendererBackend::Gpu, 0).unwrap();
+        assert_eq!(manifest, export(RendererBackend::Cpu, 0).unwrap());
+    }
+}
@@ -0,0 +1,144 @@
+use image::RgbaImage;
+use rand::{Rng, seq::IndexedRandom};
+
+use crate::{
+    BlendMode, CaptchaError, ColorScheme, GlyphStyle, NoisePath, Typeface, blend::blend,
+    outline::LoadedFont,
+};
+
+/// Faint halves of characters scattered over the background, so connected
+/// component analysis finds many character-like blobs besides the real
+/// glyphs. Only characters that aren't shown are used, and every one is
+/// clipped to half, so none reads as part of the answer.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+pub struct DecoyStyle {
+    pub count: u32,
+    /// Alpha of the fragments, from 0 to 255.
+    pub opacity: u8,
+}
+
+impl Default for DecoyStyle {
+    fn default() -> Self {
+        Self {
+            count: 0,
+            opacity: 70,
+        }
+    }
+}
+
+impl DecoyStyle {
+    #[allow(clippy::too_many_arguments)]
+    pub(crate) fn stamp(
+        &self,
+        img: &mut RgbaImage,
+        typeface: &Typeface,
+        font: Option<&LoadedFont>,
+        shown: &str,
+        size: f32,
+        color: [u8; 3],
+        rng: &mut impl Rng,
+    ) -> Result<Vec<NoisePath>, CaptchaError> {
+        let candidates: Vec<char> = typeface
+            .charset()
+            .chars()
+            .filter(|c| !shown.chars().any(|s| s.eq_ignore_ascii_case(c)))
+            .collect();
+
+        let mut fragments = Vec::new();
+        for _ in 0..self.count {
+            let Some(&c) = candidates.choose(rng) else {
+                break;
+            };
+            let (mut mask, _) = typeface.rasterize(
+                font,
+                c,
+                size * rng.random_range(0.5..0.9),
+                &GlyphStyle::default(),
+                rng,
+            )?;
+            if mask.width == 0 || mask.height == 0 {
+                continue;
+            }
+
+            // Keep the left, right, top or bottom half.
+            let side = rng.random_range(0..4);
+            let (width, height) = (mask.width, mask.height);
+            for y in 0..height {
+                for x in 0..width {
+                    let keep = match side {
+                        0 => x < width / 2,
+                        1 => x >= width / 2,
+                        2 => y < height / 2,
+                        _ => y >= height / 2,
+                    };
+                    let alpha = &mut mask.alpha[(y * width + x) as usize];
+                    *alpha = if keep {
+                        (*alpha as u16 * self.opacity as u16 / 255) as u8
+                    } else {
+                        0
+                    };
+                }
+            }
+
+            let x = rng.random_range(-(width as i32) / 2..img.width() as i32 - width as i32 / 2);
+            let y = rng.random_range(-(height as i32) / 2..img.height() as i32 - height as i32 / 2);
+            let stamp = ColorScheme::Solid.paint(&mask, color, 255);
+            blend(img, &stamp, x as i64, y as i64, BlendMode::Normal);
+
+            fragments.push(NoisePath::Fragment {
+                x,
+                y,
+                width,
+                height,
+            });
+        }
+
+        Ok(fragments)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::{SeedableRng, rngs::StdRng};
+
+    use super::*;
+
+    const DOTS: Typeface = Typeface::DotMatrix { dot: 0.8 };
+
+    fn stamp(style: DecoyStyle, shown: &str) -> (RgbaImage, Vec<NoisePath>) {
+        let mut img = RgbaImage::new(120, 40);
+        let mut rng = StdRng::seed_from_u64(1);
+        let fragments = style
+            .stamp(&mut img, &DOTS, None, shown, 30.0, [0, 0, 0], &mut rng)
+            .unwrap();
+        (img, fragments)
+    }
+
+    #[test]
+    fn stamps_faint_fragments() {
+        let style = DecoyStyle {
+            count: 12,
+            ..Default::default()
+        };
+        assert_eq!(stamp(style, "AB").1.len(), 12);
+
+        let (img, _) = stamp(DecoyStyle { count: 1, ..style }, "AB");
+        assert!(img.pixels().any(|pixel| pixel[3] > 0));
+        assert!(img.pixels().all(|pixel| pixel[3] <= style.opacity));
+    }
+
+    #[test]
+    fn never_stamps_shown_characters() {
+        let style = DecoyStyle {
+            count: 12,
+            ..Default::default()
+        };
+        let every = DOTS.charset().to_lowercase();
+        let (img, fragments) = stamp(style, &every);
+        assert!(fragments.is_empty());
+        assert!(img.pixels().all(|pixel| pixel[3] == 0));
+    }
+}
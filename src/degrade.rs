@@ -0,0 +1,103 @@
+use image::{
+    Rgba, RgbaImage,
+    imageops::{self, FilterType},
+};
+
+use crate::{Captcha, CaptchaError, Format, encode::encode};
+
+/// Something that happens to an image between the server and the user's
+/// eyes. Applied with [`degrade`], to check that captchas stay readable
+/// after real-world rendering paths, not just as generated.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Degradation {
+    /// Re-encoding as JPEG at `quality`, 1 to 100, as proxies and image
+    /// optimizers do.
+    Jpeg { quality: u8 },
+    /// Display at `scale` times the original size, smoothed.
+    Resize { scale: f32 },
+    /// A screenshot of the image displayed at `scale`, e.g. 1.25 for a
+    /// 125% desktop, scaled back to the original size: transparency is
+    /// flattened onto white and every pixel is resampled twice.
+    Screenshot { scale: f32 },
+}
+
+/// Decodes the captcha image and applies `degradations` in order. Fails
+/// when the image format can't be decoded with the enabled features.
+pub fn degrade(captcha: &Captcha, degradations: &[Degradation]) -> Result<RgbaImage, CaptchaError> {
+    let mut img = image::load_from_memory(&captcha.image)?.into_rgba8();
+    for degradation in degradations {
+        img = degradation.apply(&img)?;
+    }
+    Ok(img)
+}
+
+impl Degradation {
+    pub fn apply(&self, img: &RgbaImage) -> Result<RgbaImage, CaptchaError> {
+        Ok(match *self {
+            Degradation::Jpeg { quality } => {
+                let jpeg = encode(img, Format::Jpeg { quality })?;
+                image::load_from_memory(&jpeg)?.into_rgba8()
+            }
+            Degradation::Resize { scale } => {
+                let (width, height) = scaled(img, scale)?;
+                imageops::resize(img, width, height, FilterType::Triangle)
+            }
+            Degradation::Screenshot { scale } => {
+                let (width, height) = scaled(img, scale)?;
+                let mut flat = RgbaImage::from_pixel(img.width(), img.height(), Rgba([255; 4]));
+                imageops::overlay(&mut flat, img, 0, 0);
+                let shown = imageops::resize(&flat, width, height, FilterType::CatmullRom);
+                imageops::resize(&shown, img.width(), img.height(), FilterType::Triangle)
+            }
+        })
+    }
+}
+
+fn scaled(img: &RgbaImage, scale: f32) -> Result<(u32, u32), CaptchaError> {
+    if !(scale.is_finite() && scale > 0.0) {
+        return Err(CaptchaError::InvalidInput(format!(
+            "scale {scale} is not a positive number"
+        )));
+    }
+    let size = |pixels: u32| ((pixels as f32 * scale).round() as u32).max(1);
+    Ok((size(img.width()), size(img.height())))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Config;
+
+    #[test]
+    fn degrades_generated_captchas() {
+        let captcha = Config::default().generate().unwrap();
+        let original = degrade(&captcha, &[]).unwrap();
+        let degraded = degrade(
+            &captcha,
+            &[
+                Degradation::Screenshot { scale: 1.25 },
+                Degradation::Jpeg { quality: 30 },
+            ],
+        )
+        .unwrap();
+        assert_eq!(degraded.dimensions(), original.dimensions());
+        assert_ne!(degraded, original);
+        // Screenshots flatten transparency, JPEG keeps it flat.
+        assert!(degraded.pixels().all(|pixel| pixel[3] == 255));
+    }
+
+    #[test]
+    fn resizes_by_the_scale() {
+        let img = RgbaImage::from_pixel(40, 20, Rgba([10, 20, 30, 255]));
+        let resized = Degradation::Resize { scale: 0.5 }.apply(&img).unwrap();
+        assert_eq!(resized.dimensions(), (20, 10));
+        assert_eq!(resized.get_pixel(5, 5), &Rgba([10, 20, 30, 255]));
+
+        let tiny = Degradation::Resize { scale: 0.001 }.apply(&img).unwrap();
+        assert_eq!(tiny.dimensions(), (1, 1));
+        for scale in [0.0, -1.0, f32::NAN, f32::INFINITY] {
+            assert!(Degradation::Resize { scale }.apply(&img).is_err());
+            assert!(Degradation::Screenshot { scale }.apply(&img).is_err());
+        }
+    }
+}
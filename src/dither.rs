@@ -0,0 +1,117 @@
+use image::{
+    ImageError, ImageFormat, RgbaImage,
+    error::{EncodingError, ImageFormatHint},
+};
+
+use crate::encode::pack_rows;
+
+/// Encodes `img` as a grayscale PNG of `bits` per pixel, 1 or 2, with
+/// Floyd-Steinberg dithering. Transparency is flattened onto white, as
+/// paper and e-ink have no alpha.
+pub(crate) fn encode(img: &RgbaImage, bits: u8) -> Result<Vec<u8>, ImageError> {
+    let bits = bits.clamp(1, 2) as usize;
+    let levels = dither(img, (1 << bits) - 1);
+
+    let packed = pack_rows(&levels, img.width() as usize, bits);
+
+    let mut buffer = Vec::new();
+    let mut encoder = png::Encoder::new(&mut buffer, img.width(), img.height());
+    encoder.set_color(png::ColorType::Grayscale);
+    encoder.set_depth(match bits {
+        1 => png::BitDepth::One,
+        _ => png::BitDepth::Two,
+    });
+    encoder
+        .write_header()
+        .and_then(|mut writer| writer.write_image_data(&packed))
+        .map_err(|err| {
+            ImageError::Encoding(EncodingError::new(
+                ImageFormatHint::Exact(ImageFormat::Png),
+                err,
+            ))
+        })?;
+    Ok(buffer)
+}
+
+/// Gray levels from 0 (black) to `max` per pixel, row by row, with the
+/// quantization error of each pixel spread to its unvisited neighbours.
+fn dither(img: &RgbaImage, max: u8) -> Vec<u8> {
+    let (width, height) = (img.width() as usize, img.height() as usize);
+    let mut gray: Vec<f32> = img
+        .pixels()
+        .map(|pixel| {
+            let [r, g, b, a] = pixel.0.map(|channel| channel as f32 / 255.0);
+            let luma = 0.299 * r + 0.587 * g + 0.114 * b;
+            luma * a + (1.0 - a)
+        })
+        .collect();
+
+    let steps = max as f32;
+    let mut levels = vec![0; gray.len()];
+    for y in 0..height {
+        for x in 0..width {
+            let index = y * width + x;
+            let level = (gray[index] * steps).round().clamp(0.0, steps);
+            levels[index] = level as u8;
+            let error = gray[index] - level / steps;
+
+            let mut spread = |dx: isize, dy: usize, weight: f32| {
+                let nx = x as isize + dx;
+                if nx >= 0 && (nx as usize) < width && y + dy < height {
+                    gray[(y + dy) * width + nx as usize] += error * weight;
+                }
+            };
+            spread(1, 0, 7.0 / 16.0);
+            spread(-1, 1, 3.0 / 16.0);
+            spread(0, 1, 5.0 / 16.0);
+            spread(1, 1, 1.0 / 16.0);
+        }
+    }
+    levels
+}
+
+#[cfg(test)]
+mod tests {
+    use image::Rgba;
+
+    use super::*;
+
+    #[test]
+    fn dithers_grays_into_dots() {
+        let gray = RgbaImage::from_pixel(16, 16, Rgba([128, 128, 128, 255]));
+        let levels = dither(&gray, 1);
+        let white = levels.iter().filter(|&&level| level == 1).count();
+        assert!((120..=136).contains(&white), "{white}");
+
+        let transparent = RgbaImage::from_pixel(4, 4, Rgba([0, 0, 0, 0]));
+        assert!(dither(&transparent, 3).iter().all(|&level| level == 3));
+    }
+
+    #[test]
+    fn encodes_grayscale_pngs_of_few_levels() {
+        let gradient = RgbaImage::from_fn(64, 8, |x, _| {
+            Rgba([x as u8 * 4, x as u8 * 4, x as u8 * 4, 255])
+        });
+        for (bits, levels) in [(1, vec![0, 255]), (2, vec![0, 85, 170, 255])] {
+            let png = encode(&gradient, bits).unwrap();
+            let decoded = image::load_from_memory(&png).unwrap();
+            assert_eq!(decoded.color(), image::ColorType::L8);
+            let mut seen: Vec<u8> = decoded.to_luma8().into_raw();
+            seen.sort_unstable();
+            seen.dedup();
+            assert_eq!(seen, levels);
+        }
+    }
+
+    #[test]
+    fn packs_rows_most_significant_first() {
+        assert_eq!(
+            pack_rows(&[1, 0, 1, 1, 0, 1], 3, 1),
+            [0b1010_0000, 0b1010_0000]
+        );
+        assert_eq!(
+            pack_rows(&[3, 0, 2, 1, 3], 5, 2),
+            [0b1100_1001, 0b1100_0000]
+        );
+    }
+}
@@ -0,0 +1,316 @@
+use std::f32::consts::TAU;
+
+use image::{Rgba, RgbaImage};
+use rand::Rng;
+
+/// A pixel filter, run as a [`Stage::Effect`] of the pipeline. Applies to
+/// every frame of an animation, before any instruction strip is added.
+///
+/// [`Stage::Effect`]: crate::Stage::Effect
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum Effect {
+    /// Shifts the red and blue channels by up to `offset` pixels in random
+    /// directions, leaving green in place, so edges split into colored
+    /// fringes. On flat backgrounds only text and noise change. 1 to 2 is
+    /// subtle and stays readable.
+    ChromaticAberration { offset: f32 },
+    /// Pixelates two horizontal bands at random heights into `block` pixel
+    /// squares. Together the bands cover `coverage` of the height, at most
+    /// half, so every glyph keeps a sharp part.
+    Pixelate { block: u32, coverage: f32 },
+    /// CRT-style scanlines: every `spacing`-th row is darkened by
+    /// `intensity`, from 0 to 1, and shifted sideways by up to `shift`
+    /// pixels, breaking up horizontal strokes.
+    Scanlines {
+        spacing: u32,
+        intensity: f32,
+        shift: u32,
+    },
+    /// A faint high-frequency pattern against machine solvers: a few
+    /// gratings with periods of two to four pixels at random angles, plus
+    /// per-pixel noise, moving brightness by at most `strength` of 255
+    /// levels. Convolutional OCR models pick up such frequencies that
+    /// people hardly notice; up to 4 stays invisible, and it is clamped
+    /// to 16. Run it last, since blurs remove it, and with a lossless
+    /// format, since JPEG largely does as well.
+    Perturbation { strength: f32 },
+}
+
+impl Effect {
+    pub(crate) fn apply(&self, img: &mut RgbaImage, rng: &mut impl Rng) {
+        match *self {
+            Effect::ChromaticAberration { offset } => {
+                let offset = offset.clamp(0.0, 16.0);
+                if offset.is_nan() || offset < 0.5 {
+                    return;
+                }
+                let shifts = [random_shift(offset, rng), (0, 0), random_shift(offset, rng)];
+                shift_channels(img, shifts);
+            }
+            Effect::Pixelate { block, coverage } => {
+                let block = block.clamp(2, 32);
+                let band = (img.height() as f32 * coverage.clamp(0.0, 0.5) / 2.0) as u32;
+                if band == 0 {
+                    return;
+                }
+                for _ in 0..2 {
+                    let top = rng.random_range(0..=img.height() - band);
+                    pixelate(img, top, band, block);
+                }
+            }
+            Effect::Scanlines {
+                spacing,
+                intensity,
+                shift,
+            } => {
+                let keep = 1.0 - intensity.clamp(0.0, 1.0);
+                let shift = shift.min(img.width() / 4) as i64;
+                for y in (0..img.height()).step_by(spacing.max(2) as usize) {
+                    let offset = rng.random_range(-shift..=shift);
+                    scanline(img, y, keep, offset);
+                }
+            }
+            Effect::Perturbation { strength } => {
+                let strength = strength.clamp(0.0, 16.0);
+                if strength >= 0.5 {
+                    perturb(img, strength, rng);
+                }
+            }
+        }
+    }
+}
+
+fn random_shift(offset: f32, rng: &mut impl Rng) -> (i64, i64) {
+    let angle = rng.random_range(0.0..TAU);
+    let distance = rng.random_range(0.5..=offset);
+    (
+        (angle.cos() * distance).round() as i64,
+        (angle.sin() * distance).round() as i64,
+    )
+}
+
+/// Gratings summed in a perturbation; more average out to a weaker one.
+const GRATINGS: usize = 3;
+
+/// Adds `strength` times a mix of high-frequency gratings and random
+/// signs to the colors of every visible pixel.
+fn perturb(img: &mut RgbaImage, strength: f32, rng: &mut impl Rng) {
+    let gratings: [(f32, f32, f32); GRATINGS] = std::array::from_fn(|_| {
+        let angle = rng.random_range(0.0..TAU);
+        let frequency = TAU / rng.random_range(2.0..4.0);
+        let phase = rng.random_range(0.0..TAU);
+        (angle.cos() * frequency, angle.sin() * frequency, phase)
+    });
+    for (x, y, pixel) in img.enumerate_pixels_mut() {
+        let sign = if rng.random() { 1.0 } else { -1.0 };
+        if pixel[3] == 0 {
+            continue;
+        }
+        let wave = gratings
+            .iter()
+            .map(|&(fx, fy, phase)| (fx * x as f32 + fy * y as f32 + phase).sin())
+            .sum::<f32>()
+            / GRATINGS as f32;
+        let delta = strength * (0.7 * wave + 0.3 * sign);
+        // Premultiplied colors can't exceed the alpha.
+        let alpha = pixel[3] as f32;
+        for channel in &mut pixel.0[..3] {
+            *channel = (*channel as f32 + delta).round().clamp(0.0, alpha) as u8;
+        }
+    }
+}
+
+/// Darkens row `y` to `keep` of its brightness and moves it `offset` pixels
+/// right, repeating the edge pixel.
+fn scanline(img: &mut RgbaImage, y: u32, keep: f32, offset: i64) {
+    let max_x = img.width() as i64 - 1;
+    let row: Vec<Rgba<u8>> = (0..img.width()).map(|x| *img.get_pixel(x, y)).collect();
+    for x in 0..img.width() {
+        let mut pixel = row[(x as i64 - offset).clamp(0, max_x) as usize];
+        for channel in &mut pixel.0[..3] {
+            *channel = (*channel as f32 * keep) as u8;
+        }
+        img.put_pixel(x, y, pixel);
+    }
+}
+
+/// Replaces `block` sized squares of the rows from `top` on with their
+/// average color.
+fn pixelate(img: &mut RgbaImage, top: u32, rows: u32, block: u32) {
+    let bottom = top + rows;
+    for y0 in (top..bottom).step_by(block as usize) {
+        let y1 = (y0 + block).min(bottom);
+        for x0 in (0..img.width()).step_by(block as usize) {
+            let x1 = (x0 + block).min(img.width());
+
+            let mut sum = [0u32; 4];
+            for y in y0..y1 {
+                for x in x0..x1 {
+                    for (sum, &value) in sum.iter_mut().zip(&img.get_pixel(x, y).0) {
+                        *sum += value as u32;
+                    }
+                }
+            }
+            let count = (x1 - x0) * (y1 - y0);
+            let average = Rgba(sum.map(|sum| (sum / count) as u8));
+            for y in y0..y1 {
+                for x in x0..x1 {
+                    img.put_pixel(x, y, average);
+                }
+            }
+        }
+    }
+}
+
+/// Moves each of the red, green and blue channels by its shift, repeating
+/// edge pixels where a channel moved away from the border.
+fn shift_channels(img: &mut RgbaImage, shifts: [(i64, i64); 3]) {
+    let source = img.clone();
+    let (max_x, max_y) = (img.width() as i64 - 1, img.height() as i64 - 1);
+    for (x, y, pixel) in img.enumerate_pixels_mut() {
+        for (channel, (dx, dy)) in shifts.into_iter().enumerate() {
+            let sx = (x as i64 - dx).clamp(0, max_x) as u32;
+            let sy = (y as i64 - dy).clamp(0, max_y) as u32;
+            // Premultiplied colors can't exceed the alpha.
+            pixel[channel] = source.get_pixel(sx, sy)[channel].min(pixel[3]);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::{SeedableRng, rngs::StdRng};
+
+    use super::*;
+
+    fn image() -> RgbaImage {
+        RgbaImage::from_fn(24, 16, |x, y| {
+            let value = ((x * 10 + y * 3) % 256) as u8;
+            Rgba([value, value / 2, 255 - value, 255])
+        })
+    }
+
+    #[test]
+    fn ignores_non_finite_settings() {
+        for value in [f32::NAN, f32::INFINITY, f32::NEG_INFINITY] {
+            for effect in [
+                Effect::ChromaticAberration { offset: value },
+                Effect::Pixelate {
+                    block: 4,
+                    coverage: value,
+                },
+                Effect::Perturbation { strength: value },
+            ] {
+                effect.apply(&mut image(), &mut rand::rng());
+            }
+        }
+
+        let mut img = image();
+        Effect::ChromaticAberration { offset: f32::NAN }.apply(&mut img, &mut rand::rng());
+        assert_eq!(img, image());
+    }
+
+    #[test]
+    fn shifts_only_red_and_blue() {
+        let mut img = image();
+        Effect::ChromaticAberration { offset: 4.0 }.apply(&mut img, &mut rand::rng());
+        for (shifted, original) in img.pixels().zip(image().pixels()) {
+            assert_eq!(shifted[1], original[1]);
+            assert_eq!(shifted[3], original[3]);
+        }
+    }
+
+    /// Rows of `img` differing from [`image`].
+    fn changed_rows(img: &RgbaImage) -> Vec<u32> {
+        let original = image();
+        (0..img.height())
+            .filter(|&y| (0..img.width()).any(|x| img.get_pixel(x, y) != original.get_pixel(x, y)))
+            .collect()
+    }
+
+    #[test]
+    fn pixelates_bands_into_blocks() {
+        let mut img = image();
+        let effect = Effect::Pixelate {
+            block: 4,
+            coverage: 0.5,
+        };
+        effect.apply(&mut img, &mut StdRng::seed_from_u64(1));
+        assert_eq!(img.dimensions(), image().dimensions());
+        // Two bands of a quarter of the height each.
+        let rows = changed_rows(&img);
+        assert!(!rows.is_empty() && rows.len() <= 8, "{rows:?}");
+        for y in rows {
+            for x in 0..img.width() {
+                assert_eq!(img.get_pixel(x, y), img.get_pixel(x - x % 4, y));
+            }
+        }
+    }
+
+    #[test]
+    fn darkens_spaced_rows() {
+        let mut img = image();
+        let effect = Effect::Scanlines {
+            spacing: 3,
+            intensity: 0.5,
+            shift: 2,
+        };
+        effect.apply(&mut img, &mut StdRng::seed_from_u64(1));
+        assert_eq!(img.dimensions(), image().dimensions());
+        assert_eq!(changed_rows(&img), [0, 3, 6, 9, 12, 15]);
+        for y in changed_rows(&img) {
+            let brightness = |img: &RgbaImage| -> u32 {
+                (0..img.width())
+                    .map(|x| {
+                        img.get_pixel(x, y).0[..3]
+                            .iter()
+                            .map(|&c| c as u32)
+                            .sum::<u32>()
+                    })
+                    .sum()
+            };
+            assert!(brightness(&img) * 10 < brightness(&image()) * 6);
+        }
+    }
+
+    #[test]
+    fn perturbs_visible_pixels_faintly() {
+        // Premultiplied gray on the right, transparent on the left.
+        let original = RgbaImage::from_fn(24, 16, |x, _| match x < 12 {
+            true => Rgba([0, 0, 0, 0]),
+            false => Rgba([128, 128, 128, 255]),
+        });
+        let mut img = original.clone();
+        Effect::Perturbation { strength: 4.0 }.apply(&mut img, &mut StdRng::seed_from_u64(1));
+        assert_eq!(img.dimensions(), original.dimensions());
+        let mut changed = 0;
+        for (x, y, pixel) in img.enumerate_pixels() {
+            let before = original.get_pixel(x, y);
+            if x < 12 {
+                assert_eq!(pixel, before);
+                continue;
+            }
+            assert_eq!(pixel[3], 255);
+            for channel in 0..3 {
+                assert!(pixel[channel].abs_diff(before[channel]) <= 4);
+            }
+            changed += (pixel != before) as u32;
+        }
+        assert!(changed > 12 * 16 / 2, "{changed}");
+    }
+
+    #[cfg(feature = "theme")]
+    #[test]
+    fn themes_with_nan_offsets_generate() {
+        let theme = crate::Theme::from_toml(
+            "pipeline = [{ effect = { chromatic_aberration = { offset = nan } } }]",
+        )
+        .unwrap();
+        let mut config = crate::Config::default();
+        theme.apply(&mut config);
+        config.generate().unwrap();
+    }
+}
@@ -0,0 +1,119 @@
+use base64::{Engine, engine::general_purpose};
+
+use crate::{Captcha, captcha::sanitize_id};
+
+/// A captcha image packaged for an HTML email, see
+/// [`Captcha::email_image`]. Images shown inline, rather than the code as
+/// text, stay hard to scrape from mailboxes.
+#[derive(Clone, Debug)]
+pub struct EmailImage {
+    /// Without the angle brackets.
+    pub content_id: String,
+    pub content_type: &'static str,
+    /// `captcha.<extension>`.
+    pub file_name: String,
+    pub image: Vec<u8>,
+}
+
+impl Captcha {
+    /// The image with a Content-ID derived from the captcha id, to attach
+    /// to a `multipart/related` message and reference from its HTML part.
+    /// Only ASCII letters, digits, `-` and `_` of the id are kept, so
+    /// caller chosen ids can't inject headers or break out of the HTML.
+    pub fn email_image(&self) -> EmailImage {
+        EmailImage {
+            content_id: format!("captcha-{}@captchagen", sanitize_id(&self.id)),
+            content_type: self.content_type(),
+            file_name: format!("captcha.{}", self.format.extension()),
+            image: self.image.clone(),
+        }
+    }
+}
+
+impl EmailImage {
+    /// An `<img>` element referencing the image by its Content-ID.
+    pub fn html(&self, alt: &str) -> String {
+        let alt = alt
+            .replace('&', "&amp;")
+            .replace('"', "&quot;")
+            .replace('<', "&lt;");
+        format!(r#"<img src="cid:{}" alt="{alt}">"#, self.content_id)
+    }
+
+    /// The image as a MIME body part, headers included, for composing
+    /// `multipart/related` messages by hand. Lines end in CRLF.
+    pub fn mime_part(&self) -> String {
+        let mut part = format!(
+            "Content-Type: {}\r\n\
+             Content-Transfer-Encoding: base64\r\n\
+             Content-ID: <{}>\r\n\
+             Content-Disposition: inline; filename=\"{}\"\r\n\r\n",
+            self.content_type, self.content_id, self.file_name
+        );
+        let encoded = general_purpose::STANDARD.encode(&self.image);
+        // All ASCII, so any split is on a character boundary.
+        for line in encoded.as_bytes().chunks(76) {
+            part.push_str(std::str::from_utf8(line).unwrap_or_default());
+            part.push_str("\r\n");
+        }
+        part
+    }
+
+    /// The image as an inline lettre part, to add to a
+    /// `MultiPart::related()` next to the HTML.
+    #[cfg(feature = "lettre")]
+    pub fn to_lettre(&self) -> lettre::message::SinglePart {
+        use lettre::message::{Attachment, header::ContentType};
+
+        let content_type = ContentType::parse(self.content_type).expect("valid content type");
+        Attachment::new_inline(self.content_id.clone()).body(self.image.clone(), content_type)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Answer, Format};
+
+    fn captcha(id: &str) -> Captcha {
+        let mut captcha = Captcha::new(
+            Answer::new("x7Kp".into()),
+            b"png bytes".to_vec(),
+            Format::Png,
+            (1, 1),
+            None,
+        );
+        captcha.id = id.into();
+        captcha
+    }
+
+    #[test]
+    fn sanitizes_content_ids() {
+        let email = captcha("a>\r\nBcc: x@example.com\"").email_image();
+        assert_eq!(email.content_id, "captcha-aBccxexamplecom@captchagen");
+        assert_eq!(
+            email.html("<Code>"),
+            r#"<img src="cid:captcha-aBccxexamplecom@captchagen" alt="&lt;Code>">"#
+        );
+    }
+
+    #[test]
+    fn lays_out_mime_parts() {
+        let part = captcha("abc").email_image().mime_part();
+        assert_eq!(
+            part,
+            "Content-Type: image/png\r\n\
+             Content-Transfer-Encoding: base64\r\n\
+             Content-ID: <captcha-abc@captchagen>\r\n\
+             Content-Disposition: inline; filename=\"captcha.png\"\r\n\r\n\
+             cG5nIGJ5dGVz\r\n"
+        );
+
+        let mut large = captcha("abc").email_image();
+        large.image = vec![0; 100];
+        let part = large.mime_part();
+        let body = part.split("\r\n\r\n").nth(1).unwrap();
+        let lines: Vec<_> = body.split_terminator("\r\n").collect();
+        assert_eq!(lines.iter().map(|l| l.len()).collect::<Vec<_>>(), [76, 60]);
+    }
+}
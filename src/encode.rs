@@ -0,0 +1,355 @@
+use std::io::Cursor;
+
+use image::{
+    ImageError, ImageFormat, RgbaImage,
+    error::{EncodingError, ImageFormatHint},
+};
+
+use crate::CaptchaError;
+
+/// Encoding of the generated image. Formats besides PNG each need their
+/// cargo feature.
+///
+/// Images carry no metadata, such as timestamps or the encoding library,
+/// that could tell captchas or deployments apart; see
+/// [`Captcha::set_comment`] to add some deliberately.
+///
+/// [`Captcha::set_comment`]: crate::Captcha::set_comment
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum Format {
+    #[default]
+    Png,
+    /// A grayscale PNG with `bits` per pixel, 1 for black and white or 2
+    /// for four gray levels, dithered, for e-ink displays and thermal
+    /// printers. Noise curves are drawn opaque, since half-transparent
+    /// strokes would dither into faint speckles.
+    Dithered { bits: u8 },
+    /// Text for terminals, e.g. CLIs and SSH gateways: every character
+    /// shows two pixels stacked, in 24-bit ANSI colors. Generate at a
+    /// coarse size, around 80 by 32 pixels for an 80 by 16 character
+    /// captcha.
+    Ansi,
+    /// Text of Unicode Braille characters showing two by four pixels
+    /// each, without colors, for terminals without 24-bit color support.
+    /// 120 by 48 pixels make 60 by 12 characters.
+    Braille,
+    /// A Sixel escape sequence, showing the actual image in terminals that
+    /// support it, such as xterm, foot and WezTerm.
+    Sixel,
+    /// An iTerm2 inline image escape sequence wrapping a PNG, for iTerm2,
+    /// WezTerm and Konsole.
+    #[cfg(feature = "base64")]
+    Iterm2,
+    /// `quality` ranges from 1 to 100. JPEG has no alpha channel, which is
+    /// fine since captchas are composed on an opaque background.
+    #[cfg(feature = "jpeg")]
+    Jpeg { quality: u8 },
+    /// Lossless WebP.
+    #[cfg(feature = "webp")]
+    WebP,
+    #[cfg(feature = "gif")]
+    Gif,
+    #[cfg(feature = "tiff")]
+    Tiff,
+    /// A single-page document sized to the image, for print workflows.
+    #[cfg(feature = "pdf")]
+    Pdf,
+    /// An SVG embedding the image as a PNG `data:` URI, without external
+    /// references, scripts or styles. Pages showing it inline need a
+    /// Content Security Policy allowing `img-src data:`.
+    #[cfg(feature = "svg")]
+    Svg,
+}
+
+impl Format {
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            Format::Png | Format::Dithered { .. } => "image/png",
+            Format::Ansi | Format::Braille | Format::Sixel => crate::terminal::TEXT_CONTENT_TYPE,
+            #[cfg(feature = "base64")]
+            Format::Iterm2 => crate::terminal::TEXT_CONTENT_TYPE,
+            #[cfg(feature = "jpeg")]
+            Format::Jpeg { .. } => "image/jpeg",
+            #[cfg(feature = "webp")]
+            Format::WebP => "image/webp",
+            #[cfg(feature = "gif")]
+            Format::Gif => "image/gif",
+            #[cfg(feature = "tiff")]
+            Format::Tiff => "image/tiff",
+            #[cfg(feature = "pdf")]
+            Format::Pdf => "application/pdf",
+            #[cfg(feature = "svg")]
+            Format::Svg => crate::svg::SVG_CONTENT_TYPE,
+        }
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Format::Png | Format::Dithered { .. } => "png",
+            Format::Ansi | Format::Braille | Format::Sixel => "txt",
+            #[cfg(feature = "base64")]
+            Format::Iterm2 => "txt",
+            #[cfg(feature = "jpeg")]
+            Format::Jpeg { .. } => "jpg",
+            #[cfg(feature = "webp")]
+            Format::WebP => "webp",
+            #[cfg(feature = "gif")]
+            Format::Gif => "gif",
+            #[cfg(feature = "tiff")]
+            Format::Tiff => "tiff",
+            #[cfg(feature = "pdf")]
+            Format::Pdf => "pdf",
+            #[cfg(feature = "svg")]
+            Format::Svg => "svg",
+        }
+    }
+}
+
+pub(crate) fn encode(img: &RgbaImage, format: Format) -> Result<Vec<u8>, image::ImageError> {
+    let mut buffer = Cursor::new(Vec::new());
+
+    match format {
+        Format::Png => img.write_to(&mut buffer, ImageFormat::Png)?,
+        Format::Dithered { bits } => return crate::dither::encode(img, bits),
+        Format::Ansi => return Ok(crate::terminal::ansi_blocks(img).into_bytes()),
+        Format::Braille => return Ok(crate::terminal::braille(img).into_bytes()),
+        Format::Sixel => return Ok(crate::terminal::sixel(img).into_bytes()),
+        #[cfg(feature = "base64")]
+        Format::Iterm2 => {
+            let png = encode(img, Format::Png)?;
+            let (width, height) = img.dimensions();
+            return Ok(crate::terminal::iterm2(&png, width, height).into_bytes());
+        }
+        #[cfg(feature = "jpeg")]
+        Format::Jpeg { quality } => {
+            let rgb = image::DynamicImage::ImageRgba8(img.clone()).into_rgb8();
+            let encoder = image::codecs::jpeg::JpegEncoder::new_with_quality(
+                &mut buffer,
+                quality.clamp(1, 100),
+            );
+            rgb.write_with_encoder(encoder)?;
+        }
+        #[cfg(feature = "webp")]
+        Format::WebP => img.write_to(&mut buffer, ImageFormat::WebP)?,
+        #[cfg(feature = "gif")]
+        Format::Gif => img.write_to(&mut buffer, ImageFormat::Gif)?,
+        #[cfg(feature = "tiff")]
+        Format::Tiff => img.write_to(&mut buffer, ImageFormat::Tiff)?,
+        #[cfg(feature = "pdf")]
+        Format::Pdf => return crate::pdf::encode(img).map_err(ImageError::IoError),
+        #[cfg(feature = "svg")]
+        Format::Svg => {
+            let png = encode(img, Format::Png)?;
+            let (width, height) = img.dimensions();
+            return Ok(crate::svg::wrap(&png, "image/png", width, height).into_bytes());
+        }
+    }
+
+    let mut encoded = buffer.into_inner();
+    if format == Format::Png {
+        // The other PNG writers here only ever write image chunks.
+        crate::png_chunks::strip(&mut encoded);
+    }
+    Ok(encoded)
+}
+
+/// Palette sizes tried, largest first, when a PNG exceeds its byte budget.
+const PALETTE_SIZES: [usize; 4] = [256, 64, 16, 4];
+
+/// Encodes `img` as [`encode`] does, then, if the result exceeds `max_bytes`,
+/// lowers JPEG quality or switches PNG to ever smaller palettes until it
+/// fits. Other formats can't be shrunk this way. When nothing fits
+/// the smallest encoding is returned.
+pub(crate) fn encode_within(
+    img: &RgbaImage,
+    format: Format,
+    max_bytes: Option<usize>,
+) -> Result<Vec<u8>, CaptchaError> {
+    let encoded = encode(img, format)?;
+    let Some(max_bytes) = max_bytes else {
+        return Ok(encoded);
+    };
+    if encoded.len() <= max_bytes {
+        return Ok(encoded);
+    }
+
+    match format {
+        Format::Png => {
+            let mut smallest = encoded;
+            for colors in PALETTE_SIZES {
+                let candidate = encode_indexed_png(img, colors)?;
+                if candidate.len() <= max_bytes {
+                    return Ok(candidate);
+                }
+                if candidate.len() < smallest.len() {
+                    smallest = candidate;
+                }
+            }
+            Ok(smallest)
+        }
+        #[cfg(feature = "jpeg")]
+        Format::Jpeg { quality } => {
+            // Binary search for the highest quality that fits.
+            let (mut low, mut high) = (1, quality.clamp(1, 100) - 1);
+            let mut best = None;
+            while low <= high && high > 0 {
+                let mid = low + (high - low) / 2;
+                let candidate = encode(img, Format::Jpeg { quality: mid })?;
+                if candidate.len() <= max_bytes {
+                    best = Some(candidate);
+                    low = mid + 1;
+                } else {
+                    high = mid - 1;
+                }
+            }
+            match best {
+                Some(best) => Ok(best),
+                None => Ok(encode(img, Format::Jpeg { quality: 1 })?),
+            }
+        }
+        #[allow(unreachable_patterns)]
+        _ => Ok(encoded),
+    }
+}
+
+/// Encodes an indexed PNG with at most `colors` palette entries.
+fn encode_indexed_png(img: &RgbaImage, colors: usize) -> Result<Vec<u8>, CaptchaError> {
+    let quantizer = color_quant::NeuQuant::new(10, colors, img.as_raw());
+    let palette_rgba = quantizer.color_map_rgba();
+    let indices: Vec<u8> = img
+        .pixels()
+        .map(|pixel| quantizer.index_of(&pixel.0) as u8)
+        .collect();
+
+    let bits: usize = match colors {
+        0..=2 => 1,
+        3..=4 => 2,
+        5..=16 => 4,
+        _ => 8,
+    };
+    let packed = pack_rows(&indices, img.width() as usize, bits);
+
+    let palette: Vec<u8> = palette_rgba
+        .chunks(4)
+        .flat_map(|c| [c[0], c[1], c[2]])
+        .collect();
+    let transparency: Vec<u8> = palette_rgba.chunks(4).map(|c| c[3]).collect();
+
+    let mut buffer = Vec::new();
+    let mut encoder = png::Encoder::new(&mut buffer, img.width(), img.height());
+    encoder.set_color(png::ColorType::Indexed);
+    encoder.set_depth(match bits {
+        1 => png::BitDepth::One,
+        2 => png::BitDepth::Two,
+        4 => png::BitDepth::Four,
+        _ => png::BitDepth::Eight,
+    });
+    encoder.set_palette(palette);
+    if transparency.iter().any(|&alpha| alpha < 255) {
+        encoder.set_trns(transparency);
+    }
+    encoder
+        .write_header()
+        .and_then(|mut writer| writer.write_image_data(&packed))
+        .map_err(png_error)?;
+
+    Ok(buffer)
+}
+
+/// Packs `bits` wide values into bytes, most significant first, each row
+/// starting on a new byte as PNG wants it.
+pub(crate) fn pack_rows(values: &[u8], width: usize, bits: usize) -> Vec<u8> {
+    let row_bytes = (width * bits).div_ceil(8);
+    let mut packed = vec![0u8; row_bytes * values.len().div_ceil(width.max(1))];
+    for (y, row) in values.chunks(width.max(1)).enumerate() {
+        for (x, &value) in row.iter().enumerate() {
+            let bit = x * bits;
+            packed[y * row_bytes + bit / 8] |= value << (8 - bits - bit % 8);
+        }
+    }
+    packed
+}
+
+pub(crate) fn png_error(err: png::EncodingError) -> CaptchaError {
+    CaptchaError::Image(ImageError::Encoding(EncodingError::new(
+        ImageFormatHint::Exact(ImageFormat::Png),
+        err,
+    )))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn noise(width: u32, height: u32) -> RgbaImage {
+        let mut state = 1u32;
+        RgbaImage::from_fn(width, height, |_, _| {
+            state ^= state << 13;
+            state ^= state >> 17;
+            state ^= state << 5;
+            image::Rgba([state as u8, (state >> 8) as u8, (state >> 16) as u8, 255])
+        })
+    }
+
+    #[test]
+    fn encodes_every_enabled_raster_format() {
+        let img = noise(24, 10);
+        let formats = [
+            Format::Png,
+            #[cfg(feature = "jpeg")]
+            Format::Jpeg { quality: 80 },
+            #[cfg(feature = "webp")]
+            Format::WebP,
+            #[cfg(feature = "gif")]
+            Format::Gif,
+            #[cfg(feature = "tiff")]
+            Format::Tiff,
+        ];
+        for format in formats {
+            let encoded = encode(&img, format).unwrap();
+            let guessed = image::guess_format(&encoded).unwrap();
+            assert_eq!(guessed.to_mime_type(), format.content_type());
+            assert!(guessed.extensions_str().contains(&format.extension()));
+            let decoded = image::load_from_memory_with_format(&encoded, guessed).unwrap();
+            assert_eq!((decoded.width(), decoded.height()), (24, 10));
+        }
+    }
+
+    #[test]
+    fn shrinks_pngs_to_the_budget() {
+        let img = noise(64, 64);
+        let full = encode(&img, Format::Png).unwrap();
+        assert_eq!(
+            encode_within(&img, Format::Png, Some(full.len())).unwrap(),
+            full
+        );
+
+        let budget = full.len() / 2;
+        let shrunk = encode_within(&img, Format::Png, Some(budget)).unwrap();
+        assert!(shrunk.len() <= budget);
+        let decoded = image::load_from_memory_with_format(&shrunk, ImageFormat::Png).unwrap();
+        assert_eq!((decoded.width(), decoded.height()), (64, 64));
+
+        // Out of reach, the smallest attempt comes back.
+        let smallest = encode_within(&img, Format::Png, Some(10)).unwrap();
+        assert!(smallest.len() < shrunk.len());
+    }
+
+    #[cfg(feature = "jpeg")]
+    #[test]
+    fn lowers_jpeg_quality_to_the_budget() {
+        let img = noise(64, 64);
+        let format = Format::Jpeg { quality: 90 };
+        let (best, worst) = (
+            encode(&img, format).unwrap(),
+            encode(&img, Format::Jpeg { quality: 1 }).unwrap(),
+        );
+        let budget = (best.len() + worst.len()) / 2;
+        let shrunk = encode_within(&img, format, Some(budget)).unwrap();
+        assert!(shrunk.len() <= budget && shrunk.len() > worst.len());
+        assert_eq!(encode_within(&img, format, Some(10)).unwrap(), worst);
+    }
+}
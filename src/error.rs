@@ -0,0 +1,91 @@
+use std::{error::Error, fmt, io, time::Duration};
+
+#[derive(Debug)]
+pub enum CaptchaError {
+    Io(io::Error),
+    Font(&'static str),
+    /// The font, or the font-free [`Typeface`], lacks glyphs for these
+    /// characters of the charset, which would render as blanks or
+    /// placeholders.
+    ///
+    /// [`Typeface`]: crate::Typeface
+    FontMissingGlyph(Vec<char>),
+    Image(image::ImageError),
+    /// The config or arguments can't produce a captcha.
+    InvalidInput(String),
+    /// The canvas or a glyph would have more pixels than
+    /// [`Config::max_pixels`], or a grid more than
+    /// [`GridConfig::max_pixels`].
+    ///
+    /// [`Config::max_pixels`]: crate::Config::max_pixels
+    /// [`GridConfig::max_pixels`]: crate::GridConfig::max_pixels
+    TooLarge {
+        pixels: u64,
+        max: u64,
+    },
+    /// No generation slot became free in time, see
+    /// [`crate::Generator::with_concurrency_limit`]. Usually answered with
+    /// HTTP 503.
+    Overloaded,
+    /// The client already got as many captchas as an [`IssuanceQuota`]
+    /// allows. Usually answered with HTTP 429 and a `Retry-After` header.
+    ///
+    /// [`IssuanceQuota`]: crate::IssuanceQuota
+    QuotaExceeded {
+        retry_after: Duration,
+    },
+    /// The GPU backend found no usable device or the device failed, see
+    /// [`RendererBackend::Gpu`].
+    ///
+    /// [`RendererBackend::Gpu`]: crate::RendererBackend::Gpu
+    #[cfg(feature = "gpu")]
+    Gpu(String),
+}
+
+impl fmt::Display for CaptchaError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CaptchaError::Io(err) => write!(f, "I/O error: {err}"),
+            CaptchaError::Font(err) => write!(f, "invalid font: {err}"),
+            CaptchaError::FontMissingGlyph(chars) => {
+                let chars: String = chars.iter().collect();
+                write!(f, "the typeface has no glyphs for {chars:?}")
+            }
+            CaptchaError::Image(err) => write!(f, "failed to encode image: {err}"),
+            CaptchaError::InvalidInput(message) => f.write_str(message),
+            CaptchaError::TooLarge { pixels, max } => {
+                write!(f, "image of {pixels} pixels exceeds the limit of {max}")
+            }
+            CaptchaError::Overloaded => f.write_str("too many captchas are being generated"),
+            CaptchaError::QuotaExceeded { retry_after } => write!(
+                f,
+                "captcha quota exceeded, retry in {}s",
+                retry_after.as_secs_f32().ceil()
+            ),
+            #[cfg(feature = "gpu")]
+            CaptchaError::Gpu(err) => write!(f, "GPU rendering failed: {err}"),
+        }
+    }
+}
+
+impl Error for CaptchaError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            CaptchaError::Io(err) => Some(err),
+            CaptchaError::Image(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for CaptchaError {
+    fn from(err: io::Error) -> Self {
+        CaptchaError::Io(err)
+    }
+}
+
+impl From<image::ImageError> for CaptchaError {
+    fn from(err: image::ImageError) -> Self {
+        CaptchaError::Image(err)
+    }
+}
@@ -0,0 +1,170 @@
+use image::RgbaImage;
+use rand::Rng;
+use raqote::DrawTarget;
+
+use crate::{LineStyle, color::delta_e};
+
+/// Times a noise color or path is re-rolled before giving up.
+const ATTEMPTS: usize = 16;
+
+/// Keeps interference lines from making glyphs unreadable, see
+/// [`LineStyle::min_color_distance`] and [`LineStyle::max_glyph_coverage`].
+pub(crate) struct NoiseGuard {
+    text_colors: Vec<[u8; 3]>,
+    min_distance: f32,
+    max_coverage: f32,
+    /// Per canvas pixel, 1 + the index of the glyph covering it, or 0. Left
+    /// empty when coverage isn't limited.
+    owner: Vec<u16>,
+    /// Pixel count of every glyph.
+    pixels: Vec<u32>,
+    width: u32,
+}
+
+impl NoiseGuard {
+    pub fn new(style: &LineStyle, text_colors: Vec<[u8; 3]>, width: u32, height: u32) -> Self {
+        let limited = style.max_glyph_coverage < 1.0;
+        Self {
+            text_colors,
+            min_distance: style.min_color_distance,
+            max_coverage: style.max_glyph_coverage.max(0.0),
+            owner: if limited {
+                vec![0; (width * height) as usize]
+            } else {
+                Vec::new()
+            },
+            pixels: Vec::new(),
+            width,
+        }
+    }
+
+    /// Records the mostly opaque pixels of a glyph drawn at (`x`, `y`).
+    pub fn add_glyph(&mut self, glyph: &RgbaImage, x: i64, y: i64) {
+        if self.owner.is_empty() || self.pixels.len() >= u16::MAX as usize {
+            return;
+        }
+        self.pixels.push(0);
+        let id = self.pixels.len() as u16;
+
+        let height = (self.owner.len() / self.width as usize) as i64;
+        for (gx, gy, pixel) in glyph.enumerate_pixels() {
+            let (cx, cy) = (x + gx as i64, y + gy as i64);
+            if pixel[3] < 128 || cx < 0 || cy < 0 || cx >= self.width as i64 || cy >= height {
+                continue;
+            }
+            let owner = &mut self.owner[(cy * self.width as i64 + cx) as usize];
+            if *owner != 0 {
+                self.pixels[*owner as usize - 1] -= 1;
+            }
+            *owner = id;
+            self.pixels[id as usize - 1] += 1;
+        }
+    }
+
+    /// A random line color far enough from every text color, or the
+    /// farthest one tried.
+    pub fn color(&self, rng: &mut impl Rng) -> [u8; 3] {
+        let mut best = ([0; 3], f32::NEG_INFINITY);
+        for _ in 0..ATTEMPTS {
+            let color = [rng.random(), rng.random(), rng.random()];
+            let distance = self
+                .text_colors
+                .iter()
+                .map(|&text| delta_e(color, text))
+                .fold(f32::INFINITY, f32::min);
+            if distance >= self.min_distance {
+                return color;
+            }
+            if distance > best.1 {
+                best = (color, distance);
+            }
+        }
+        best.0
+    }
+
+    /// Whether the line stroked into `dt` leaves enough of every glyph.
+    pub fn allows(&self, dt: &DrawTarget) -> bool {
+        if self.owner.is_empty() {
+            return true;
+        }
+        let mut covered = vec![0u32; self.pixels.len()];
+        for (&argb, &owner) in dt.get_data().iter().zip(&self.owner) {
+            if owner != 0 && argb >> 24 >= 64 {
+                covered[owner as usize - 1] += 1;
+            }
+        }
+        covered
+            .iter()
+            .zip(&self.pixels)
+            .all(|(&covered, &pixels)| covered as f32 <= pixels as f32 * self.max_coverage)
+    }
+
+    pub fn attempts(&self) -> usize {
+        if self.owner.is_empty() { 1 } else { ATTEMPTS }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use image::Rgba;
+    use rand::{SeedableRng, rngs::StdRng};
+    use raqote::{DrawOptions, SolidSource, Source};
+
+    use super::*;
+
+    fn guard(min_color_distance: f32, max_glyph_coverage: f32) -> NoiseGuard {
+        let style = LineStyle {
+            min_color_distance,
+            max_glyph_coverage,
+            ..Default::default()
+        };
+        NoiseGuard::new(&style, vec![[0, 0, 0], [255, 255, 255]], 20, 20)
+    }
+
+    /// A line stroked over the top `rows` rows of the canvas.
+    fn line(rows: f32) -> DrawTarget {
+        let mut dt = DrawTarget::new(20, 20);
+        let black = Source::Solid(SolidSource::from_unpremultiplied_argb(255, 0, 0, 0));
+        dt.fill_rect(0.0, 0.0, 20.0, rows, &black, &DrawOptions::new());
+        dt
+    }
+
+    #[test]
+    fn measures_color_differences_in_lab() {
+        assert_eq!(delta_e([10, 20, 30], [10, 20, 30]), 0.0);
+        assert!((delta_e([0, 0, 0], [255, 255, 255]) - 100.0).abs() < 0.1);
+        assert!(delta_e([255, 0, 0], [250, 0, 0]) < delta_e([255, 0, 0], [0, 0, 255]));
+    }
+
+    #[test]
+    fn picks_line_colors_away_from_the_text() {
+        let guard = guard(30.0, 1.0);
+        let mut rng = StdRng::seed_from_u64(1);
+        for _ in 0..100 {
+            let color = guard.color(&mut rng);
+            assert!(delta_e(color, [0, 0, 0]) >= 30.0);
+            assert!(delta_e(color, [255, 255, 255]) >= 30.0);
+        }
+    }
+
+    #[test]
+    fn rejects_lines_covering_too_much_of_a_glyph() {
+        let mut guard = guard(0.0, 0.5);
+        assert_eq!(guard.attempts(), ATTEMPTS);
+        guard.add_glyph(&RgbaImage::from_pixel(10, 10, Rgba([0, 0, 0, 255])), 0, 0);
+        assert!(guard.allows(&line(3.0)));
+        assert!(!guard.allows(&line(8.0)));
+
+        // Glyphs drawn over others take their pixels.
+        guard.add_glyph(&RgbaImage::from_pixel(10, 5, Rgba([0, 0, 0, 255])), 0, 0);
+        assert!(!guard.allows(&line(3.0)));
+    }
+
+    #[test]
+    fn allows_any_line_without_a_coverage_limit() {
+        let mut guard = guard(0.0, 1.0);
+        guard.add_glyph(&RgbaImage::from_pixel(10, 10, Rgba([0, 0, 0, 255])), 0, 0);
+        assert_eq!(guard.attempts(), 1);
+        assert!(guard.allows(&line(20.0)));
+    }
+}
@@ -0,0 +1,131 @@
+use image::{Rgba, RgbaImage};
+use raqote::{Color, DrawOptions, DrawTarget, Gradient, GradientStop, Point, Source, Spread};
+
+use crate::{composite, glyph::Mask};
+
+/// How glyphs are filled.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum ColorScheme {
+    /// Every glyph in a single color, usually [`Config::color`].
+    ///
+    /// [`Config::color`]: crate::Config::color
+    #[default]
+    Solid,
+    /// Every glyph fades from its own color to `to`, which defeats
+    /// thresholding on a single color. [`Mode::Colors`] captchas stay solid
+    /// so the asked for color remains recognizable. The alpha of `to` is
+    /// ignored.
+    ///
+    /// [`Mode::Colors`]: crate::Mode::Colors
+    Gradient {
+        to: crate::Color,
+        direction: GradientDirection,
+    },
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum GradientDirection {
+    /// From the top of the glyph to its bottom.
+    #[default]
+    Vertical,
+    /// From the left of the glyph to its right, along the baseline.
+    Horizontal,
+}
+
+impl ColorScheme {
+    /// Colors the coverage of a glyph whose base color is `color`, at
+    /// `opacity` from 0 to 255, premultiplied.
+    pub(crate) fn paint(&self, mask: &Mask, color: [u8; 3], opacity: u8) -> RgbaImage {
+        let fill: Vec<[u8; 3]> = match *self {
+            ColorScheme::Solid => vec![color; mask.alpha.len()],
+            ColorScheme::Gradient { to, direction } => {
+                let (width, height) = (mask.width as f32, mask.height as f32);
+                let end = match direction {
+                    GradientDirection::Vertical => Point::new(0.0, height),
+                    GradientDirection::Horizontal => Point::new(width, 0.0),
+                };
+                let stop = |position, [r, g, b]: [u8; 3]| GradientStop {
+                    position,
+                    color: Color::new(255, r, g, b),
+                };
+                let source = Source::new_linear_gradient(
+                    Gradient {
+                        stops: vec![stop(0.0, color), stop(1.0, to.to_rgb())],
+                    },
+                    Point::new(0.0, 0.0),
+                    end,
+                    Spread::Pad,
+                );
+
+                let mut dt = DrawTarget::new(mask.width as i32, mask.height as i32);
+                dt.fill_rect(0.0, 0.0, width, height, &source, &DrawOptions::new());
+                dt.get_data()
+                    .iter()
+                    .map(|pixel| [(pixel >> 16) as u8, (pixel >> 8) as u8, *pixel as u8])
+                    .collect()
+            }
+        };
+
+        let data = fill
+            .into_iter()
+            .zip(&mask.alpha)
+            .flat_map(|([r, g, b], &alpha)| {
+                let alpha = (alpha as u16 * opacity as u16 / 255) as u8;
+                composite::premultiply_pixel(Rgba([r, g, b, alpha])).0
+            })
+            .collect();
+        RgbaImage::from_raw(mask.width, mask.height, data).unwrap()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn opaque(width: usize, height: usize) -> Mask {
+        Mask::new(width, height, vec![255; width * height])
+    }
+
+    fn gradient(direction: GradientDirection) -> ColorScheme {
+        ColorScheme::Gradient {
+            to: crate::Color::rgb(0, 0, 255),
+            direction,
+        }
+    }
+
+    #[test]
+    fn fills_solid_glyphs_in_one_color() {
+        let painted = ColorScheme::Solid.paint(&opaque(4, 4), [200, 10, 10], 255);
+        assert!(painted.pixels().all(|pixel| pixel.0 == [200, 10, 10, 255]));
+    }
+
+    #[test]
+    fn fades_from_the_glyph_color_in_the_chosen_direction() {
+        let vertical =
+            gradient(GradientDirection::Vertical).paint(&opaque(8, 32), [255, 0, 0], 255);
+        let (top, bottom) = (vertical.get_pixel(4, 0).0, vertical.get_pixel(4, 31).0);
+        assert!(top[0] > 240 && top[2] < 15);
+        assert!(bottom[0] < 15 && bottom[2] > 240);
+        assert_eq!(vertical.get_pixel(0, 16), vertical.get_pixel(7, 16));
+
+        let horizontal =
+            gradient(GradientDirection::Horizontal).paint(&opaque(32, 8), [255, 0, 0], 255);
+        let (left, right) = (horizontal.get_pixel(0, 4).0, horizontal.get_pixel(31, 4).0);
+        assert!(left[0] > 240 && right[2] > 240);
+        assert_eq!(horizontal.get_pixel(16, 0), horizontal.get_pixel(16, 7));
+    }
+
+    #[test]
+    fn scales_coverage_by_the_opacity() {
+        let mask = Mask::new(2, 1, vec![255, 0]);
+        let painted = gradient(GradientDirection::Vertical).paint(&mask, [255, 0, 0], 128);
+        assert_eq!(painted.get_pixel(0, 0).0[3], 128);
+        assert_eq!(painted.get_pixel(1, 0).0, [0, 0, 0, 0]);
+    }
+}
@@ -0,0 +1,499 @@
+#[cfg(feature = "embedded-font")]
+use std::sync::OnceLock;
+use std::{
+    sync::{
+        Arc, PoisonError, RwLock,
+        atomic::{AtomicU64, Ordering},
+    },
+    time::Duration,
+};
+
+use crate::{
+    Captcha, CaptchaError, Config, DifficultyReport, FallbackFont, Format, InputHint, Layout,
+    OverrideOptions, animation,
+    atlas::Atlas,
+    encode,
+    hash::sha256_hex,
+    limit::{Limiter, Permit},
+    outline::LoadedFont,
+    render::Rendered,
+};
+
+/// A config together with its parsed font, for rendering many captchas
+/// without loading the font every time.
+pub struct Generator {
+    config: RwLock<Arc<Config>>,
+    pub(crate) font: Option<LoadedFont>,
+    limiter: Option<Limiter>,
+    atlas: RwLock<Option<Arc<Atlas>>>,
+    /// Numbers the captchas, so seeded [`RngSource`]s give each its own
+    /// stream.
+    ///
+    /// [`RngSource`]: crate::RngSource
+    sequence: AtomicU64,
+}
+
+impl Generator {
+    /// Uses the bundled font with the `embedded-font` feature, otherwise
+    /// loads `Arial.ttf` from the working directory.
+    pub fn new(config: Config) -> Result<Self, CaptchaError> {
+        #[cfg(feature = "embedded-font")]
+        return Self::with_font(config, EMBEDDED_FONT);
+
+        #[cfg(not(feature = "embedded-font"))]
+        {
+            let font_data = std::fs::read("Arial.ttf")?;
+            Self::with_font(config, &font_data)
+        }
+    }
+
+    /// Fails with [`CaptchaError::FontMissingGlyph`] when the font can't
+    /// draw every character of the charset.
+    pub fn with_font(config: Config, font_data: &[u8]) -> Result<Self, CaptchaError> {
+        Self::with_loaded_font(config, LoadedFont::new(font_data)?)
+    }
+
+    /// Draws every character with the first font of `fonts` that has a
+    /// glyph for it, trying fonts routed to the character's [`Script`]
+    /// first, so mixed-script charsets render fully. Instructions use the
+    /// first font. Fails with [`CaptchaError::FontMissingGlyph`] when no
+    /// font can draw some character of the charset.
+    ///
+    /// [`Script`]: crate::Script
+    pub fn with_fonts(config: Config, fonts: &[FallbackFont]) -> Result<Self, CaptchaError> {
+        Self::with_loaded_font(config, LoadedFont::chain(fonts)?)
+    }
+
+    fn with_loaded_font(config: Config, font: LoadedFont) -> Result<Self, CaptchaError> {
+        config.check_glyphs(Some(&font))?;
+        Ok(Self {
+            config: RwLock::new(Arc::new(config)),
+            font: Some(font),
+            limiter: None,
+            atlas: RwLock::new(None),
+            sequence: AtomicU64::new(0),
+        })
+    }
+
+    /// A generator for the font-free typefaces, dot matrix and seven
+    /// segments. Generating fails with [`CaptchaError::Font`] when the
+    /// config needs a font after all, i.e. for [`Typeface::Font`] or modes
+    /// that render an instruction, and with
+    /// [`CaptchaError::FontMissingGlyph`] for charset characters the
+    /// typeface has no shape for.
+    ///
+    /// [`Typeface::Font`]: crate::Typeface::Font
+    pub fn without_font(config: Config) -> Self {
+        Self {
+            config: RwLock::new(Arc::new(config)),
+            font: None,
+            limiter: None,
+            atlas: RwLock::new(None),
+            sequence: AtomicU64::new(0),
+        }
+    }
+
+    /// Lets at most `max` captchas render at once, so bursts can't occupy
+    /// every core. Callers beyond that wait up to `timeout` for a free slot
+    /// and then get [`CaptchaError::Overloaded`].
+    pub fn with_concurrency_limit(mut self, max: usize, timeout: Duration) -> Self {
+        self.limiter = Some(Limiter::new(max, timeout));
+        self
+    }
+
+    /// A snapshot of the active config; later updates don't affect it.
+    pub fn config(&self) -> Arc<Config> {
+        self.config
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone()
+    }
+
+    /// Swaps the config used by subsequent captchas. Captchas already being
+    /// rendered finish with the config they started with. A sprite atlas is
+    /// rebuilt for the new config before it takes effect. Fails like
+    /// [`Generator::with_font`] when the font can't draw the new charset,
+    /// or when the atlas can't be built, keeping the old config.
+    pub fn update_config(&self, config: Config) -> Result<(), CaptchaError> {
+        config.check_glyphs(self.font.as_ref())?;
+        let config = Arc::new(config);
+        let mut atlas = self.atlas.write().unwrap_or_else(PoisonError::into_inner);
+        if atlas.is_some() {
+            *atlas = Some(Arc::new(Atlas::build(config.clone(), self.font.as_ref())?));
+        }
+        *self.config.write().unwrap_or_else(PoisonError::into_inner) = config;
+        Ok(())
+    }
+
+    /// Pre-renders every glyph of the charset at a range of rotations, so
+    /// generating only blits them. Bold, slant, jitter and fragments are
+    /// then baked into each sprite instead of varying per captcha, and
+    /// size modes still rasterize glyphs per captcha.
+    pub fn with_sprite_atlas(self) -> Result<Self, CaptchaError> {
+        let atlas = Atlas::build(self.config(), self.font.as_ref())?;
+        *self.atlas.write().unwrap_or_else(PoisonError::into_inner) = Some(Arc::new(atlas));
+        Ok(self)
+    }
+
+    /// The atlas, if it fits `config`.
+    pub(crate) fn atlas_for(&self, config: &Config) -> Option<Arc<Atlas>> {
+        self.atlas
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone()
+            .filter(|atlas| atlas.fits(config, self.font.as_ref()))
+    }
+
+    pub fn generate(&self) -> Result<Captcha, CaptchaError> {
+        self.generate_inner().map(|(captcha, _)| captcha)
+    }
+
+    /// Generates with `overrides` applied to the active config, leaving
+    /// the generator unchanged. The font, sprite atlas and concurrency
+    /// limit are shared with regular generation; the atlas keeps being
+    /// used as long as the glyph size and style are unaffected.
+    pub fn generate_with_overrides(
+        &self,
+        overrides: &OverrideOptions,
+    ) -> Result<Captcha, CaptchaError> {
+        let config = self.config();
+        let result = if overrides.is_empty() {
+            self.generate_with(config)
+        } else {
+            let config = overrides.apply(&config);
+            if overrides.charset.is_some() {
+                config.check_glyphs(self.font.as_ref())?;
+            }
+            self.generate_with(Arc::new(config))
+        };
+        result.map(|(captcha, _)| captcha)
+    }
+
+    /// An endless iterator of fresh captchas, generated lazily.
+    pub fn iter(&self) -> Iter<'_> {
+        Iter { generator: self }
+    }
+
+    pub(crate) fn generate_inner(&self) -> Result<(Captcha, Layout), CaptchaError> {
+        self.generate_with(self.config())
+    }
+
+    pub(crate) fn generate_with(
+        &self,
+        config: Arc<Config>,
+    ) -> Result<(Captcha, Layout), CaptchaError> {
+        self.generate_at(config, self.next_sequence())
+    }
+
+    /// Generates the `sequence`th captcha, e.g. a dataset sample's index,
+    /// so seeded output doesn't depend on which thread gets there first.
+    pub(crate) fn generate_at(
+        &self,
+        config: Arc<Config>,
+        sequence: u64,
+    ) -> Result<(Captcha, Layout), CaptchaError> {
+        let _permit = self.permit()?;
+        self.compose_unlimited(config, sequence)?.encode()
+    }
+
+    /// Renders a captcha for `config` without encoding it, so encoding can
+    /// happen on another thread. Only composing counts towards the
+    /// concurrency limit.
+    pub(crate) fn compose(
+        &self,
+        config: Arc<Config>,
+        sequence: u64,
+    ) -> Result<Composed, CaptchaError> {
+        let _permit = self.permit()?;
+        self.compose_unlimited(config, sequence)
+    }
+
+    /// Numbers the next captcha.
+    pub(crate) fn next_sequence(&self) -> u64 {
+        self.sequence.fetch_add(1, Ordering::Relaxed)
+    }
+
+    fn permit(&self) -> Result<Option<Permit<'_>>, CaptchaError> {
+        match &self.limiter {
+            Some(limiter) => Ok(Some(limiter.acquire().ok_or(CaptchaError::Overloaded)?)),
+            None => Ok(None),
+        }
+    }
+
+    fn compose_unlimited(
+        &self,
+        config: Arc<Config>,
+        sequence: u64,
+    ) -> Result<Composed, CaptchaError> {
+        #[cfg(feature = "log")]
+        let started = std::time::Instant::now();
+
+        let rendered = self.render(&config, None, sequence)?;
+        Ok(Composed {
+            config,
+            rendered,
+            #[cfg(feature = "log")]
+            started,
+        })
+    }
+}
+
+/// A rendered captcha waiting to be encoded.
+pub(crate) struct Composed {
+    pub config: Arc<Config>,
+    rendered: Rendered,
+    #[cfg(feature = "log")]
+    started: std::time::Instant,
+}
+
+impl Composed {
+    /// Wraps a captcha rendered elsewhere, e.g. by the GPU backend.
+    #[cfg(feature = "gpu")]
+    pub(crate) fn new(config: Arc<Config>, rendered: Rendered) -> Self {
+        Self {
+            config,
+            rendered,
+            #[cfg(feature = "log")]
+            started: std::time::Instant::now(),
+        }
+    }
+
+    pub fn encode(self) -> Result<(Captcha, Layout), CaptchaError> {
+        #[cfg(feature = "log")]
+        let started = self.started;
+        let Self {
+            config, rendered, ..
+        } = self;
+        let image = match config.animation {
+            Some(animation) => {
+                animation::encode(&rendered.frames, config.format, animation.frame_delay)?
+            }
+            None => encode::encode_within(&rendered.frames[0], config.format, config.max_bytes)?,
+        };
+        let fallback_image = config
+            .animation
+            .and_then(|animation| animation.fallback)
+            .map(|fallback| {
+                let frame = &rendered.frames[fallback.index(rendered.frames.len())];
+                encode::encode_within(frame, Format::Png, config.max_bytes)
+            })
+            .transpose()?;
+
+        let mut captcha = Captcha::new(
+            rendered.answer,
+            image,
+            config.format,
+            rendered.frames[0].dimensions(),
+            config.expires_in,
+        );
+        captcha.fallback_image = fallback_image;
+        captcha.instruction = rendered.instruction;
+        captcha.stage_timings = rendered.stage_timings;
+        captcha.input_hint = InputHint::for_charset(&config.char_weights.usable(config.charset()));
+        captcha.difficulty = DifficultyReport::new(&config, &rendered.layout);
+        if config.hash_image {
+            captcha.image_sha256 = Some(sha256_hex(&captcha.image));
+        }
+
+        // The answer is deliberately left out.
+        #[cfg(feature = "log")]
+        log::debug!(
+            "generated captcha id={} length={} size={}x{} mode={:?} format={:?} bytes={} elapsed={:?}",
+            captcha.id,
+            config.length,
+            config.width,
+            config.height,
+            config.mode,
+            config.format,
+            captcha.image.len(),
+            started.elapsed(),
+        );
+
+        Ok((captcha, rendered.layout))
+    }
+}
+
+#[cfg(feature = "embedded-font")]
+const EMBEDDED_FONT: &[u8] = include_bytes!("../Arial.ttf");
+
+/// A shared generator with the default config and the bundled font, for
+/// when a single `default_generator().generate()` is all that's needed.
+/// Needs the `embedded-font` feature.
+#[cfg(feature = "embedded-font")]
+pub fn default_generator() -> &'static Generator {
+    static GENERATOR: OnceLock<Generator> = OnceLock::new();
+    GENERATOR.get_or_init(|| {
+        Generator::with_font(Config::default(), EMBEDDED_FONT).expect("bundled font is valid")
+    })
+}
+
+pub struct Iter<'a> {
+    generator: &'a Generator,
+}
+
+impl Iterator for Iter<'_> {
+    type Item = Result<Captcha, CaptchaError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        Some(self.generator.generate())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use super::*;
+
+    #[test]
+    fn renders_many_captchas_from_one_config() {
+        let generator = Generator::new(Config {
+            length: 5,
+            ..Config::default()
+        })
+        .unwrap();
+        let captchas: Vec<Captcha> = generator.iter().take(3).collect::<Result<_, _>>().unwrap();
+        let ids: HashSet<&str> = captchas.iter().map(|captcha| captcha.id.as_str()).collect();
+        assert_eq!(ids.len(), 3);
+        assert!(captchas.iter().all(|captcha| captcha.text.len() == 5));
+
+        let captcha = Config::default().generate().unwrap();
+        assert_eq!(captcha.text.len(), 4);
+        assert!(captcha.image.starts_with(b"\x89PNG"));
+    }
+
+    #[test]
+    fn swaps_configs_while_in_use() {
+        let generator = Generator::new(Config::default()).unwrap();
+        let before = generator.config();
+        generator
+            .update_config(Config {
+                length: 6,
+                ..Config::default()
+            })
+            .unwrap();
+        assert_eq!(before.length, 4);
+        assert_eq!(generator.generate().unwrap().text.len(), 6);
+
+        let unsupported = Config {
+            charset: Some("\u{e000}".into()),
+            ..Config::default()
+        };
+        assert!(matches!(
+            generator.update_config(unsupported),
+            Err(CaptchaError::FontMissingGlyph(_))
+        ));
+        assert_eq!(generator.config().length, 6);
+    }
+
+    #[test]
+    fn reports_the_missing_glyphs() {
+        let config = Config {
+            charset: Some("A\u{e001}B\u{e000}\u{e001}".into()),
+            ..Config::default()
+        };
+        let Err(CaptchaError::FontMissingGlyph(missing)) = Generator::new(config.clone()) else {
+            panic!("the missing glyphs went unreported");
+        };
+        assert_eq!(missing, ['\u{e000}', '\u{e001}']);
+        assert!(matches!(
+            config.generate(),
+            Err(CaptchaError::FontMissingGlyph(_))
+        ));
+    }
+
+    #[cfg(feature = "embedded-font")]
+    #[test]
+    fn shares_one_default_generator_across_threads() {
+        let generators = std::thread::scope(|scope| {
+            let handles: Vec<_> = (0..4)
+                .map(|_| scope.spawn(|| default_generator() as *const Generator as usize))
+                .collect();
+            handles
+                .into_iter()
+                .map(|handle| handle.join().unwrap())
+                .collect::<Vec<_>>()
+        });
+        assert!(generators.windows(2).all(|pair| pair[0] == pair[1]));
+        assert!(default_generator().generate().is_ok());
+    }
+
+    #[test]
+    fn fails_with_overloaded_past_the_concurrency_limit() {
+        let generator = Generator::new(Config::default())
+            .unwrap()
+            .with_concurrency_limit(1, Duration::ZERO);
+        let permit = generator.limiter.as_ref().unwrap().acquire();
+        assert!(matches!(
+            generator.generate(),
+            Err(CaptchaError::Overloaded)
+        ));
+        drop(permit);
+        assert!(generator.generate().is_ok());
+    }
+
+    #[test]
+    fn rejects_canvases_past_the_pixel_ceiling() {
+        let generator = Generator::new(Config::default()).unwrap();
+        let too_large = |config: Config| {
+            generator.update_config(config).unwrap();
+            matches!(
+                generator.generate(),
+                Err(CaptchaError::TooLarge { pixels, max }) if pixels > max
+            )
+        };
+        assert!(too_large(Config {
+            width: 8192,
+            height: 4096,
+            ..Config::default()
+        }));
+        assert!(too_large(Config {
+            max_pixels: Some(200 * 60),
+            width: 200,
+            height: 61,
+            ..Config::default()
+        }));
+        // Every frame of an animation counts.
+        assert!(too_large(Config {
+            max_pixels: Some(200 * 60),
+            width: 200,
+            height: 60,
+            animation: Some(crate::Animation {
+                frames: 2,
+                ..Default::default()
+            }),
+            ..Config::default()
+        }));
+        assert!(!too_large(Config {
+            max_pixels: Some(200 * 60),
+            width: 200,
+            height: 60,
+            ..Config::default()
+        }));
+    }
+
+    #[test]
+    fn fits_the_font_size_to_the_widest_glyph() {
+        let generator = Generator::new(Config::default()).unwrap();
+        let font = generator.font.as_ref();
+        let config = |charset: &str| Config {
+            charset: Some(charset.into()),
+            width: 120,
+            height: 100,
+            length: 4,
+            glyph_style: crate::GlyphStyle {
+                max_scale_x: 2.0,
+                ..crate::GlyphStyle::default()
+            },
+            ..Config::default()
+        };
+        // Stretched twice as wide, "W" no longer fits a 30 pixel slot.
+        let wide = config("WM").font_size(font);
+        let narrow = config("il").font_size(font);
+        assert!(wide < narrow, "{wide} {narrow}");
+        assert_eq!(narrow, 30.0);
+
+        let advance = font.unwrap().primary().metrics('W', wide).advance_width;
+        assert!(advance * 2.0 <= 30.0);
+    }
+}
@@ -0,0 +1,672 @@
+use rand::Rng;
+
+use crate::{BlendMode, CaptchaError};
+
+/// Per-character synthetic weight, slant and proportions, picked at random within the
+/// given ranges so stroke weight varies even with a single font file.
+///
+/// Bold, jitter and fragment lengths can't exceed the font size, slants
+/// are at most 1 either way and there are at most 32 fragments, so glyphs
+/// can't grow far beyond their slot.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct GlyphStyle {
+    /// Faux-bold radius in pixels the glyph outline is grown by.
+    pub min_bold: u32,
+    pub max_bold: u32,
+    /// Faux-italic shear, as horizontal shift per pixel of height. Positive
+    /// values lean right.
+    pub min_slant: f32,
+    pub max_slant: f32,
+    /// Horizontal scale factors, squeezing glyphs below 1 and stretching
+    /// them above, so aspect ratios differ between characters and a
+    /// single template per character no longer fits. Clamped to 0.5 to 2.
+    pub min_scale_x: f32,
+    pub max_scale_x: f32,
+    /// Vertical scale factors, picked independently of the horizontal
+    /// ones.
+    pub min_scale_y: f32,
+    pub max_scale_y: f32,
+    /// Number of gaps cut through the strokes of every glyph, starting at
+    /// random points of its outline. Humans easily bridge them, contour
+    /// following OCR does not.
+    pub fragments: u32,
+    /// Width of every gap, in pixels.
+    pub fragment_length: u32,
+    /// Largest displacement, in pixels, of points along the glyph outlines,
+    /// which are bent by smooth noise to look hand-drawn. Only applies to
+    /// [`Typeface::Font`].
+    ///
+    /// [`Typeface::Font`]: crate::Typeface::Font
+    pub jitter: f32,
+    /// Instance of a variable font each glyph is drawn in, picked at
+    /// random per character. Needs the `variable-fonts` feature and,
+    /// like `jitter`, only applies to [`Typeface::Font`]; fonts without
+    /// the axes are drawn as they are.
+    ///
+    /// [`Typeface::Font`]: crate::Typeface::Font
+    pub variation: Option<FontVariation>,
+    pub blend: BlendMode,
+}
+
+#[cfg(feature = "arbitrary")]
+impl<'a> arbitrary::Arbitrary<'a> for GlyphStyle {
+    fn arbitrary(u: &mut arbitrary::Unstructured<'a>) -> arbitrary::Result<Self> {
+        // Mostly within the limits `check` enforces, so inputs get rendered.
+        let mut float = |min: f32, max: f32| -> arbitrary::Result<f32> {
+            Ok(min + (max - min) * u.arbitrary::<u8>()? as f32 / u8::MAX as f32)
+        };
+        Ok(Self {
+            min_slant: float(-MAX_SLANT, MAX_SLANT)?,
+            max_slant: float(-MAX_SLANT, MAX_SLANT)?,
+            min_scale_x: float(0.0, 3.0)?,
+            max_scale_x: float(0.0, 3.0)?,
+            min_scale_y: float(0.0, 3.0)?,
+            max_scale_y: float(0.0, 3.0)?,
+            jitter: float(0.0, 8.0)?,
+            min_bold: u.int_in_range(0..=8)?,
+            max_bold: u.int_in_range(0..=8)?,
+            fragments: u.int_in_range(0..=MAX_FRAGMENTS)?,
+            fragment_length: u.int_in_range(0..=16)?,
+            variation: u.arbitrary()?,
+            blend: u.arbitrary()?,
+        })
+    }
+}
+
+/// Ranges of the weight and width axes of a variable font, in the units
+/// of the font's `wght` and `wdth` axes: weights from 1 to 1000, e.g. 400
+/// for regular and 700 for bold, and widths in percent of the normal
+/// width. Values beyond what the font supports are clamped by it.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub struct FontVariation {
+    pub min_weight: f32,
+    pub max_weight: f32,
+    pub min_width: f32,
+    pub max_width: f32,
+}
+
+impl Default for FontVariation {
+    fn default() -> Self {
+        Self {
+            min_weight: 300.0,
+            max_weight: 800.0,
+            min_width: 75.0,
+            max_width: 115.0,
+        }
+    }
+}
+
+impl FontVariation {
+    /// Random `wght` and `wdth` axis values.
+    pub(crate) fn pick(&self, rng: &mut impl Rng) -> [([u8; 4], f32); 2] {
+        let weight = random_between(
+            self.min_weight.clamp(1.0, 1000.0),
+            self.max_weight.clamp(1.0, 1000.0),
+            rng,
+        );
+        let width = random_between(
+            self.min_width.clamp(25.0, 200.0),
+            self.max_width.clamp(25.0, 200.0),
+            rng,
+        );
+        [(*b"wght", weight), (*b"wdth", width)]
+    }
+
+    /// How much wider than normal glyphs can get.
+    pub(crate) fn max_stretch(&self) -> f32 {
+        (self.max_width / 100.0).clamp(1.0, 2.0)
+    }
+}
+
+impl Default for GlyphStyle {
+    fn default() -> Self {
+        Self {
+            min_bold: 0,
+            max_bold: 0,
+            min_slant: 0.0,
+            max_slant: 0.0,
+            min_scale_x: 1.0,
+            max_scale_x: 1.0,
+            min_scale_y: 1.0,
+            max_scale_y: 1.0,
+            fragments: 0,
+            fragment_length: 3,
+            jitter: 0.0,
+            variation: None,
+            blend: BlendMode::Normal,
+        }
+    }
+}
+
+/// A rasterized glyph's coverage, before it is colored and composited.
+pub(crate) struct Mask {
+    pub width: u32,
+    pub height: u32,
+    pub alpha: Vec<u8>,
+}
+
+impl Mask {
+    pub fn new(width: usize, height: usize, alpha: Vec<u8>) -> Self {
+        Self {
+            width: width as u32,
+            height: height as u32,
+            alpha,
+        }
+    }
+
+    fn get(&self, x: i64, y: i64) -> u8 {
+        if x < 0 || y < 0 || x >= self.width as i64 || y >= self.height as i64 {
+            0
+        } else {
+            self.alpha[(y as u32 * self.width + x as u32) as usize]
+        }
+    }
+
+    /// Grows the coverage by `radius` pixels with a square max filter.
+    pub fn dilate(&mut self, radius: u32, max_pixels: Option<u64>) -> Result<(), CaptchaError> {
+        if radius == 0 {
+            return Ok(());
+        }
+        let r = radius as i64;
+        let (width, height) = checked_size(
+            self.width as u64 + 2 * radius as u64,
+            self.height as u64 + 2 * radius as u64,
+            max_pixels,
+        )?;
+
+        // Separable: horizontal pass into the padded size, then vertical.
+        let mut horizontal = vec![0u8; (width * height) as usize];
+        for y in 0..height as i64 {
+            for x in 0..width as i64 {
+                horizontal[(y * width as i64 + x) as usize] = (x - 2 * r..=x)
+                    .map(|sx| self.get(sx, y - r))
+                    .max()
+                    .unwrap_or(0);
+            }
+        }
+
+        let mut alpha = vec![0u8; (width * height) as usize];
+        for y in 0..height as i64 {
+            for x in 0..width as i64 {
+                alpha[(y * width as i64 + x) as usize] = (y - r..=y + r)
+                    .filter(|&sy| sy >= 0 && sy < height as i64)
+                    .map(|sy| horizontal[(sy * width as i64 + x) as usize])
+                    .max()
+                    .unwrap_or(0);
+            }
+        }
+
+        *self = Self {
+            width,
+            height,
+            alpha,
+        };
+        Ok(())
+    }
+
+    /// Resizes by `sx` horizontally and `sy` vertically, bilinearly.
+    pub fn scale(&mut self, sx: f32, sy: f32, max_pixels: Option<u64>) -> Result<(), CaptchaError> {
+        let sx = sx.clamp(MIN_SCALE, MAX_SCALE);
+        let sy = sy.clamp(MIN_SCALE, MAX_SCALE);
+        if sx == 1.0 && sy == 1.0 {
+            return Ok(());
+        }
+        let (width, height) = checked_size(
+            ((self.width as f32 * sx).round() as u64).max(1),
+            ((self.height as f32 * sy).round() as u64).max(1),
+            max_pixels,
+        )?;
+        let (sx, sy) = (
+            width as f32 / self.width.max(1) as f32,
+            height as f32 / self.height.max(1) as f32,
+        );
+        let mut alpha = vec![0u8; (width * height) as usize];
+
+        for y in 0..height {
+            let fy = (y as f32 + 0.5) / sy - 0.5;
+            let (y0, ty) = (fy.floor(), fy - fy.floor());
+            for x in 0..width {
+                let fx = (x as f32 + 0.5) / sx - 0.5;
+                let (x0, tx) = (fx.floor(), fx - fx.floor());
+                let (x0, y0) = (x0 as i64, y0 as i64);
+                let lerp = |a: u8, b: u8, t: f32| a as f32 + (b as f32 - a as f32) * t;
+                let top = lerp(self.get(x0, y0), self.get(x0 + 1, y0), tx);
+                let bottom = lerp(self.get(x0, y0 + 1), self.get(x0 + 1, y0 + 1), tx);
+                alpha[(y * width + x) as usize] = (top + (bottom - top) * ty).round() as u8;
+            }
+        }
+
+        *self = Self {
+            width,
+            height,
+            alpha,
+        };
+        Ok(())
+    }
+
+    /// Shears horizontally by `slant` pixels per row, keeping the bottom row
+    /// in place.
+    pub fn shear(&mut self, slant: f32, max_pixels: Option<u64>) -> Result<(), CaptchaError> {
+        if slant == 0.0 {
+            return Ok(());
+        }
+        let extra = (slant.abs() * self.height as f32).ceil() as u32;
+        let (width, _) = checked_size(
+            self.width as u64 + extra as u64,
+            self.height as u64,
+            max_pixels,
+        )?;
+        let mut alpha = vec![0u8; (width * self.height) as usize];
+
+        for y in 0..self.height {
+            let shift =
+                slant * (self.height - 1 - y) as f32 + if slant < 0.0 { extra as f32 } else { 0.0 };
+            for x in 0..width {
+                let sx = x as f32 - shift;
+                let x0 = sx.floor();
+                let t = sx - x0;
+                let a = self.get(x0 as i64, y as i64) as f32;
+                let b = self.get(x0 as i64 + 1, y as i64) as f32;
+                alpha[(y * width + x) as usize] = (a + (b - a) * t).round() as u8;
+            }
+        }
+
+        self.width = width;
+        self.alpha = alpha;
+        Ok(())
+    }
+}
+
+/// Fails with [`CaptchaError::TooLarge`] for masks of more than
+/// `max_pixels`. Masks index their pixels with `u32`, so their area has to
+/// fit one either way.
+fn checked_size(
+    width: u64,
+    height: u64,
+    max_pixels: Option<u64>,
+) -> Result<(u32, u32), CaptchaError> {
+    let max = max_pixels.map_or(u32::MAX as u64, |max| max.min(u32::MAX as u64));
+    match width.checked_mul(height) {
+        Some(pixels) if pixels <= max => Ok((width as u32, height as u32)),
+        pixels => Err(CaptchaError::TooLarge {
+            pixels: pixels.unwrap_or(u64::MAX),
+            max,
+        }),
+    }
+}
+
+impl Mask {
+    /// Cuts `count` gaps of `length` pixels straight through the stroke,
+    /// each starting at a random outline pixel.
+    pub fn fragment(&mut self, count: u32, length: u32, rng: &mut impl Rng) {
+        let contour: Vec<(i64, i64)> = (0..self.height as i64)
+            .flat_map(|y| (0..self.width as i64).map(move |x| (x, y)))
+            .filter(|&(x, y)| {
+                self.get(x, y) >= 128
+                    && [(1, 0), (-1, 0), (0, 1), (0, -1)]
+                        .iter()
+                        .any(|&(dx, dy)| self.get(x + dx, y + dy) < 128)
+            })
+            .collect();
+        if contour.is_empty() {
+            return;
+        }
+
+        let half = length as f32 / 2.0;
+        let max_depth = (self.width.max(self.height) / 2) as f32;
+        for _ in 0..count {
+            let (x, y) = contour[rng.random_range(0..contour.len())];
+
+            // The coverage gradient points into the stroke.
+            let gx = self.get(x + 1, y) as f32 - self.get(x - 1, y) as f32;
+            let gy = self.get(x, y + 1) as f32 - self.get(x, y - 1) as f32;
+            let norm = (gx * gx + gy * gy).sqrt();
+            if norm == 0.0 {
+                continue;
+            }
+            let (nx, ny) = (gx / norm, gy / norm);
+            let (tx, ty) = (-ny, nx);
+
+            let mut depth = 1.0;
+            while depth < max_depth
+                && self.get(
+                    (x as f32 + nx * depth).round() as i64,
+                    (y as f32 + ny * depth).round() as i64,
+                ) >= 128
+            {
+                depth += 1.0;
+            }
+
+            let mut s = -half;
+            while s <= half {
+                let mut d = -1.0;
+                while d <= depth + 1.0 {
+                    let px = (x as f32 + tx * s + nx * d).round() as i64;
+                    let py = (y as f32 + ty * s + ny * d).round() as i64;
+                    if px >= 0 && py >= 0 && px < self.width as i64 && py < self.height as i64 {
+                        self.alpha[(py as u32 * self.width + px as u32) as usize] = 0;
+                    }
+                    d += 0.5;
+                }
+                s += 0.5;
+            }
+        }
+    }
+}
+
+/// Bounds of [`GlyphStyle`]'s scale factors.
+const MIN_SCALE: f32 = 0.5;
+const MAX_SCALE: f32 = 2.0;
+/// Limits [`GlyphStyle::check`] enforces.
+const MAX_SLANT: f32 = 1.0;
+const MAX_FRAGMENTS: u32 = 32;
+
+impl GlyphStyle {
+    /// Fails with [`CaptchaError::InvalidInput`] past the documented limits
+    /// for glyphs rasterized at `font_size`.
+    pub(crate) fn check(&self, font_size: f32) -> Result<(), CaptchaError> {
+        let size = font_size.max(1.0);
+        let invalid = |message: String| Err(CaptchaError::InvalidInput(message));
+        let bold = self.min_bold.max(self.max_bold);
+        if bold as f32 > size {
+            return invalid(format!(
+                "glyph bold of {bold} exceeds the font size of {size}"
+            ));
+        }
+        if ![self.min_slant, self.max_slant]
+            .iter()
+            .all(|slant| slant.abs() <= MAX_SLANT)
+        {
+            return invalid(format!("glyph slants must be within ±{MAX_SLANT}"));
+        }
+        if self.jitter.is_nan() || self.jitter.abs() > size {
+            return invalid(format!("glyph jitter exceeds the font size of {size}"));
+        }
+        if self.fragments > MAX_FRAGMENTS
+            || (self.fragments > 0 && self.fragment_length as f32 > size)
+        {
+            return invalid(format!(
+                "glyphs take at most {MAX_FRAGMENTS} fragments no longer than the font size of {size}"
+            ));
+        }
+        if ![
+            self.min_scale_x,
+            self.max_scale_x,
+            self.min_scale_y,
+            self.max_scale_y,
+        ]
+        .iter()
+        .all(|scale| scale.is_finite())
+        {
+            return invalid("glyph scales must be finite".into());
+        }
+        Ok(())
+    }
+
+    /// Styles `mask`, failing with [`CaptchaError::TooLarge`] once it would
+    /// grow past `max_pixels`.
+    pub(crate) fn apply(
+        &self,
+        mask: &mut Mask,
+        max_pixels: Option<u64>,
+        rng: &mut impl Rng,
+    ) -> Result<(), CaptchaError> {
+        let sx = random_scale(self.min_scale_x, self.max_scale_x, rng);
+        let sy = random_scale(self.min_scale_y, self.max_scale_y, rng);
+        mask.scale(sx, sy, max_pixels)?;
+
+        if self.max_bold > self.min_bold {
+            mask.dilate(rng.random_range(self.min_bold..=self.max_bold), max_pixels)?;
+        } else {
+            mask.dilate(self.min_bold, max_pixels)?;
+        }
+
+        if self.max_slant > self.min_slant {
+            mask.shear(
+                rng.random_range(self.min_slant..=self.max_slant),
+                max_pixels,
+            )?;
+        } else {
+            mask.shear(self.min_slant, max_pixels)?;
+        }
+
+        mask.fragment(self.fragments, self.fragment_length, rng);
+        Ok(())
+    }
+}
+
+fn random_scale(min: f32, max: f32, rng: &mut impl Rng) -> f32 {
+    random_between(
+        min.clamp(MIN_SCALE, MAX_SCALE),
+        max.clamp(MIN_SCALE, MAX_SCALE),
+        rng,
+    )
+}
+
+/// A value from `min` to `max`, both finite, or `min` when the range is
+/// empty.
+fn random_between(min: f32, max: f32, rng: &mut impl Rng) -> f32 {
+    if max > min {
+        rng.random_range(min..=max)
+    } else {
+        min
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Config;
+
+    fn square(size: usize) -> Mask {
+        Mask::new(size, size, vec![255; size * size])
+    }
+
+    #[test]
+    fn accepts_tiny_font_sizes_without_fragments() {
+        let style = GlyphStyle::default();
+        assert_eq!(style.fragments, 0);
+        assert!(style.check(0.0).is_ok());
+        assert!(style.check(1.0).is_ok());
+
+        let fragmented = GlyphStyle {
+            fragments: 2,
+            ..GlyphStyle::default()
+        };
+        assert!(fragmented.check(1.0).is_err());
+        assert!(fragmented.check(40.0).is_ok());
+    }
+
+    #[test]
+    fn generates_at_tiny_font_sizes() {
+        for config in [
+            Config {
+                width: 1,
+                ..Config::default()
+            },
+            Config {
+                length: 200,
+                ..Config::default()
+            },
+        ] {
+            config.generate().unwrap();
+        }
+    }
+
+    #[test]
+    fn rejects_styles_past_the_limits() {
+        let invalid = [
+            GlyphStyle {
+                max_bold: 50,
+                ..GlyphStyle::default()
+            },
+            GlyphStyle {
+                max_slant: 1.5,
+                ..GlyphStyle::default()
+            },
+            GlyphStyle {
+                min_slant: f32::NAN,
+                ..GlyphStyle::default()
+            },
+            GlyphStyle {
+                jitter: f32::NAN,
+                ..GlyphStyle::default()
+            },
+            GlyphStyle {
+                max_scale_x: f32::NAN,
+                ..GlyphStyle::default()
+            },
+            GlyphStyle {
+                fragments: MAX_FRAGMENTS + 1,
+                ..GlyphStyle::default()
+            },
+        ];
+        for style in invalid {
+            assert!(matches!(
+                style.check(40.0),
+                Err(CaptchaError::InvalidInput(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn bounds_styled_masks() {
+        let mut mask = square(10);
+        mask.dilate(2, None).unwrap();
+        assert_eq!((mask.width, mask.height), (14, 14));
+
+        assert!(matches!(
+            square(10).dilate(5, Some(100)),
+            Err(CaptchaError::TooLarge { max: 100, .. })
+        ));
+        assert!(matches!(
+            square(10).scale(2.0, 2.0, Some(100)),
+            Err(CaptchaError::TooLarge { .. })
+        ));
+        assert!(matches!(
+            square(10).shear(1.0, Some(100)),
+            Err(CaptchaError::TooLarge { .. })
+        ));
+    }
+
+    #[test]
+    fn emboldens_and_slants_within_the_configured_range() {
+        use rand::SeedableRng;
+
+        let style = GlyphStyle {
+            min_bold: 1,
+            max_bold: 3,
+            min_slant: 0.2,
+            max_slant: 0.4,
+            ..GlyphStyle::default()
+        };
+        let inked = |mask: &Mask| mask.alpha.iter().filter(|&&alpha| alpha >= 128).count();
+        for seed in 0..16 {
+            let mut mask = square(10);
+            let mut rng = rand::rngs::StdRng::seed_from_u64(seed);
+            style.apply(&mut mask, None, &mut rng).unwrap();
+            // Bold grows both sides by the radius, the slant then widens
+            // the rows by up to 0.4 pixels each.
+            let bold = (mask.height - 10) / 2;
+            assert!((1..=3).contains(&bold), "{bold}");
+            let extra = mask.width - mask.height;
+            assert!((3..=7).contains(&extra), "{extra}");
+            assert!(inked(&mask) >= 12 * 12);
+        }
+
+        // A slant leaning left shifts the top row left of the bottom one.
+        let mut mask = square(10);
+        mask.shear(-0.5, None).unwrap();
+        assert_eq!((mask.width, mask.height), (15, 10));
+        assert_eq!(mask.get(1, 0), 255);
+        assert_eq!(mask.get(1, 9), 0);
+        assert_eq!(mask.get(14, 9), 255);
+    }
+
+    #[test]
+    fn squeezes_within_the_configured_range() {
+        use rand::SeedableRng;
+
+        let style = GlyphStyle {
+            min_scale_x: 0.6,
+            max_scale_x: 0.8,
+            min_scale_y: 1.2,
+            max_scale_y: 1.5,
+            ..GlyphStyle::default()
+        };
+        let mut widths = Vec::new();
+        for seed in 0..32 {
+            let mut mask = square(20);
+            let mut rng = rand::rngs::StdRng::seed_from_u64(seed);
+            style.apply(&mut mask, None, &mut rng).unwrap();
+            assert!((12..=16).contains(&mask.width), "{}", mask.width);
+            assert!((24..=30).contains(&mask.height), "{}", mask.height);
+            widths.push(mask.width);
+        }
+        assert!(widths.iter().any(|&width| width != widths[0]));
+
+        // Out of range factors are clamped to 0.5 to 2.
+        let clamped = GlyphStyle {
+            min_scale_x: 0.1,
+            max_scale_x: 0.1,
+            min_scale_y: 5.0,
+            max_scale_y: 5.0,
+            ..GlyphStyle::default()
+        };
+        let mut mask = square(20);
+        clamped.apply(&mut mask, None, &mut rand::rng()).unwrap();
+        assert_eq!((mask.width, mask.height), (10, 40));
+    }
+
+    #[test]
+    fn picks_variable_axes_within_the_configured_range() {
+        use rand::SeedableRng;
+
+        let variation = FontVariation {
+            min_weight: 350.0,
+            max_weight: 650.0,
+            min_width: 80.0,
+            max_width: 110.0,
+        };
+        let mut rng = rand::rngs::StdRng::seed_from_u64(7);
+        let picks: Vec<_> = (0..64).map(|_| variation.pick(&mut rng)).collect();
+        for [(wght, weight), (wdth, width)] in &picks {
+            assert_eq!((wght, wdth), (b"wght", b"wdth"));
+            assert!((350.0..=650.0).contains(weight), "{weight}");
+            assert!((80.0..=110.0).contains(width), "{width}");
+        }
+        assert!(picks.iter().any(|pick| pick[0].1 != picks[0][0].1));
+
+        // Values past the axes' limits are clamped to them.
+        let extreme = FontVariation {
+            min_weight: -5.0,
+            max_weight: -5.0,
+            min_width: 500.0,
+            max_width: 500.0,
+        };
+        let [(_, weight), (_, width)] = extreme.pick(&mut rng);
+        assert_eq!((weight, width), (1.0, 200.0));
+        assert_eq!(extreme.max_stretch(), 2.0);
+    }
+
+    #[test]
+    fn cuts_gaps_through_strokes() {
+        let bar = || {
+            let alpha = (0..12 * 24).map(|i| if (2..10).contains(&(i / 24)) { 255 } else { 0 });
+            Mask::new(24, 12, alpha.collect())
+        };
+        let inked = |mask: &Mask| mask.alpha.iter().filter(|&&alpha| alpha >= 128).count();
+
+        let mut mask = bar();
+        mask.fragment(0, 3, &mut rand::rng());
+        assert_eq!(inked(&mask), 24 * 8);
+        mask.fragment(2, 3, &mut rand::rng());
+        let left = inked(&mask);
+        assert!(left < 24 * 8 && left > 24 * 8 / 4, "{left}");
+
+        let mut empty = Mask::new(4, 4, vec![0; 16]);
+        empty.fragment(2, 3, &mut rand::rng());
+        assert_eq!(inked(&empty), 0);
+    }
+}
@@ -0,0 +1,828 @@
+//! The experimental GPU backend of [`RendererBackend::Gpu`]. The CPU still
+//! picks the text and lays out every captcha up to its noise, see
+//! [`Prepared`]; the GPU composites the glyphs and strokes the noise of a
+//! whole batch of captchas, each a tile of one texture that is read back
+//! at once.
+//!
+//! [`RendererBackend::Gpu`]: crate::RendererBackend::Gpu
+
+use std::{sync::mpsc, time::Instant};
+
+use image::RgbaImage;
+use wgpu::util::DeviceExt;
+
+use crate::{
+    BlendMode, CaptchaError, Config, Format, Generator, Stage, StageTiming,
+    render::{MAX_PATHS, NoiseStroke, Prepared, Rendered, random_curve, random_line},
+};
+
+/// Largest side of the tiled target and of the glyph atlas.
+const MAX_SIZE: u32 = 4096;
+
+/// Shaders of both pipelines. Positions are in target pixels, colors
+/// premultiplied like on the CPU.
+const SHADER: &str = r#"
+struct Globals {
+    size: vec2<f32>,
+}
+
+@group(0) @binding(0) var<uniform> globals: Globals;
+@group(0) @binding(1) var atlas: texture_2d<f32>;
+
+fn to_clip(pixel: vec2<f32>, depth: f32) -> vec4<f32> {
+    let ndc = pixel / globals.size * 2.0 - 1.0;
+    return vec4<f32>(ndc.x, -ndc.y, depth, 1.0);
+}
+
+fn corner(index: u32) -> vec2<f32> {
+    return vec2<f32>(f32(index & 1u), f32(index >> 1u));
+}
+
+struct Sprite {
+    @builtin(position) position: vec4<f32>,
+    @location(0) texel: vec2<f32>,
+}
+
+@vertex
+fn sprite_vertex(
+    @builtin(vertex_index) index: u32,
+    @location(0) rect: vec4<f32>,
+    @location(1) source: vec2<f32>,
+) -> Sprite {
+    let offset = corner(index) * rect.zw;
+    var out: Sprite;
+    out.position = to_clip(rect.xy + offset, 0.0);
+    out.texel = source + offset;
+    return out;
+}
+
+@fragment
+fn sprite_fragment(in: Sprite) -> @location(0) vec4<f32> {
+    return textureLoad(atlas, vec2<i32>(floor(in.texel)), 0);
+}
+
+struct Segment {
+    @builtin(position) position: vec4<f32>,
+    @location(0) pixel: vec2<f32>,
+    @location(1) @interpolate(flat) ends: vec4<f32>,
+    @location(2) @interpolate(flat) half_width: f32,
+    @location(3) @interpolate(flat) color: vec4<f32>,
+    @location(4) @interpolate(flat) clip: vec4<f32>,
+}
+
+@vertex
+fn segment_vertex(
+    @builtin(vertex_index) index: u32,
+    @location(0) ends: vec4<f32>,
+    @location(1) shape: vec2<f32>,
+    @location(2) color: vec4<f32>,
+    @location(3) clip: vec4<f32>,
+) -> Segment {
+    let a = ends.xy;
+    let b = ends.zw;
+    let reach = shape.x + 1.0;
+    var direction = vec2<f32>(1.0, 0.0);
+    if (distance(a, b) > 0.0) {
+        direction = normalize(b - a);
+    }
+    let normal = vec2<f32>(-direction.y, direction.x);
+    let c = corner(index);
+    let pixel = mix(a - direction * reach, b + direction * reach, c.x)
+        + normal * reach * (c.y * 2.0 - 1.0);
+
+    var out: Segment;
+    out.position = to_clip(pixel, shape.y);
+    out.pixel = pixel;
+    out.ends = ends;
+    out.half_width = shape.x;
+    out.color = color;
+    out.clip = clip;
+    return out;
+}
+
+@fragment
+fn segment_fragment(in: Segment) -> @location(0) vec4<f32> {
+    if (any(in.pixel < in.clip.xy) || any(in.pixel >= in.clip.zw)) {
+        discard;
+    }
+    let a = in.ends.xy;
+    let along = in.ends.zw - a;
+    let t = clamp(dot(in.pixel - a, along) / max(dot(along, along), 1e-6), 0.0, 1.0);
+    let coverage = clamp(in.half_width + 0.5 - distance(in.pixel, a + along * t), 0.0, 1.0);
+    if (coverage <= 0.0) {
+        discard;
+    }
+    return in.color * coverage;
+}
+"#;
+
+/// Fails when `config` uses something only the CPU backend draws.
+pub(crate) fn check(config: &Config) -> Result<(), CaptchaError> {
+    let unsupported = |what: &str| {
+        Err(CaptchaError::InvalidInput(format!(
+            "the GPU backend can't draw {what}, use the CPU backend"
+        )))
+    };
+    if config.animation.is_some() {
+        return unsupported("animations");
+    }
+    if config.glyph_style.blend != BlendMode::Normal || config.line_style.blend != BlendMode::Normal
+    {
+        return unsupported("blend modes other than normal");
+    }
+    if config.line_style.max_glyph_coverage < 1.0 {
+        return unsupported("lines kept off the glyphs");
+    }
+    let mut stages = config
+        .pipeline
+        .iter()
+        .chain(config.pipeline_choices.iter().flatten());
+    if let Some(stage) =
+        stages.find(|stage| !matches!(stage, Stage::Lines { .. } | Stage::Curves { .. }))
+    {
+        return unsupported(&format!("{stage:?} stages"));
+    }
+    Ok(())
+}
+
+pub(crate) struct GpuRenderer {
+    device: wgpu::Device,
+    queue: wgpu::Queue,
+    layout: wgpu::BindGroupLayout,
+    sprites: wgpu::RenderPipeline,
+    segments: wgpu::RenderPipeline,
+    max_size: u32,
+}
+
+/// A captcha waiting for its batch to be drawn.
+struct Scene {
+    prepared: Prepared,
+    strokes: Vec<NoiseStroke>,
+    stage_timings: Vec<StageTiming>,
+}
+
+impl GpuRenderer {
+    /// Uses the first adapter wgpu finds, which may be a software one.
+    pub(crate) fn new() -> Result<Self, CaptchaError> {
+        let instance = wgpu::Instance::new(wgpu::InstanceDescriptor::new_without_display_handle());
+        let adapter =
+            pollster::block_on(instance.request_adapter(&wgpu::RequestAdapterOptions::default()))
+                .map_err(gpu_error)?;
+        let (device, queue) = pollster::block_on(adapter.request_device(&wgpu::DeviceDescriptor {
+            label: Some("captchagen"),
+            required_limits:
+                wgpu::Limits::downlevel_webgl2_defaults().using_resolution(adapter.limits()),
+            ..Default::default()
+        }))
+        .map_err(gpu_error)?;
+        let max_size = device.limits().max_texture_dimension_2d.min(MAX_SIZE);
+
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("captchagen"),
+            source: wgpu::ShaderSource::Wgsl(SHADER.into()),
+        });
+        let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("captchagen"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::VERTEX,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+            ],
+        });
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("captchagen"),
+            bind_group_layouts: &[Some(&layout)],
+            immediate_size: 0,
+        });
+        let pipeline = |entry: &str, attributes: &[wgpu::VertexAttribute], depth: bool| {
+            let stride = attributes
+                .iter()
+                .map(|attribute| attribute.offset + attribute.format.size())
+                .max()
+                .unwrap_or(0);
+            device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                label: Some(entry),
+                layout: Some(&pipeline_layout),
+                vertex: wgpu::VertexState {
+                    module: &shader,
+                    entry_point: Some(&format!("{entry}_vertex")),
+                    compilation_options: Default::default(),
+                    buffers: &[Some(wgpu::VertexBufferLayout {
+                        array_stride: stride,
+                        step_mode: wgpu::VertexStepMode::Instance,
+                        attributes,
+                    })],
+                },
+                primitive: wgpu::PrimitiveState {
+                    topology: wgpu::PrimitiveTopology::TriangleStrip,
+                    ..Default::default()
+                },
+                // Strokes are drawn nearer and nearer, and every pixel is
+                // only inked once per stroke, so the pieces and segments of
+                // half-transparent strokes don't add up where they meet.
+                depth_stencil: Some(wgpu::DepthStencilState {
+                    format: wgpu::TextureFormat::Depth32Float,
+                    depth_write_enabled: Some(depth),
+                    depth_compare: Some(match depth {
+                        true => wgpu::CompareFunction::Less,
+                        false => wgpu::CompareFunction::Always,
+                    }),
+                    stencil: Default::default(),
+                    bias: Default::default(),
+                }),
+                multisample: Default::default(),
+                fragment: Some(wgpu::FragmentState {
+                    module: &shader,
+                    entry_point: Some(&format!("{entry}_fragment")),
+                    compilation_options: Default::default(),
+                    targets: &[Some(wgpu::ColorTargetState {
+                        format: wgpu::TextureFormat::Rgba8Unorm,
+                        blend: Some(wgpu::BlendState::PREMULTIPLIED_ALPHA_BLENDING),
+                        write_mask: wgpu::ColorWrites::ALL,
+                    })],
+                }),
+                multiview_mask: None,
+                cache: None,
+            })
+        };
+        let sprites = pipeline(
+            "sprite",
+            &wgpu::vertex_attr_array![0 => Float32x4, 1 => Float32x2],
+            false,
+        );
+        let segments = pipeline(
+            "segment",
+            &wgpu::vertex_attr_array![
+                0 => Float32x4, 1 => Float32x2, 2 => Float32x4, 3 => Float32x4
+            ],
+            true,
+        );
+
+        Ok(Self {
+            device,
+            queue,
+            layout,
+            sprites,
+            segments,
+            max_size,
+        })
+    }
+
+    /// Renders `count` captchas, numbered from `first`, with `generator`'s
+    /// font and the already [`check`]ed `config`, in batches as large as
+    /// the target allows.
+    pub(crate) fn render(
+        &self,
+        generator: &Generator,
+        config: &Config,
+        first: u64,
+        count: usize,
+    ) -> Result<Vec<Rendered>, CaptchaError> {
+        let (width, height) = (config.width, config.height);
+        if width > self.max_size || height > self.max_size {
+            return Err(CaptchaError::InvalidInput(format!(
+                "the GPU backend draws captchas of at most {0}x{0} pixels",
+                self.max_size
+            )));
+        }
+        let columns = (self.max_size / width) as usize;
+        let capacity = columns * (self.max_size / height) as usize;
+
+        let mut rendered = Vec::with_capacity(count);
+        let mut batch = Vec::new();
+        let mut atlas = Shelves::new(self.max_size);
+        let place_all = |atlas: &mut Shelves, scene: &Scene| {
+            scene
+                .prepared
+                .layers
+                .iter()
+                .all(|(layer, ..)| atlas.place(layer.width(), layer.height()).is_some())
+        };
+        for sequence in first..first + count as u64 {
+            let scene = scene(generator, config, sequence)?;
+            let mut probe = atlas;
+            if batch.len() < capacity && place_all(&mut probe, &scene) {
+                atlas = probe;
+            } else {
+                let full = std::mem::take(&mut batch);
+                rendered.extend(self.draw(generator, config, columns, full)?);
+                atlas = Shelves::new(self.max_size);
+                if !place_all(&mut atlas, &scene) {
+                    return Err(CaptchaError::InvalidInput(
+                        "the glyphs of a captcha don't fit the GPU atlas".into(),
+                    ));
+                }
+            }
+            batch.push(scene);
+        }
+        rendered.extend(self.draw(generator, config, columns, batch)?);
+        Ok(rendered)
+    }
+
+    fn draw(
+        &self,
+        generator: &Generator,
+        config: &Config,
+        columns: usize,
+        batch: Vec<Scene>,
+    ) -> Result<Vec<Rendered>, CaptchaError> {
+        if batch.is_empty() {
+            return Ok(Vec::new());
+        }
+        let (width, height) = (config.width, config.height);
+        let target_width = width * columns.min(batch.len()) as u32;
+        let target_height = height * batch.len().div_ceil(columns) as u32;
+        let tile = |index: usize| {
+            (
+                (index % columns) as u32 * width,
+                (index / columns) as u32 * height,
+            )
+        };
+
+        // Backgrounds go straight into the target; glyphs into the atlas.
+        let mut base = RgbaImage::new(target_width, target_height);
+        let mut atlas = Shelves::new(self.max_size);
+        let mut placed = Vec::new();
+        let mut sprites = Vec::new();
+        for (index, scene) in batch.iter().enumerate() {
+            let (x, y) = tile(index);
+            image::imageops::replace(&mut base, &scene.prepared.canvas, x as i64, y as i64);
+            for (layer, lx, ly) in &scene.prepared.layers {
+                // The same places the batch was assembled with.
+                let (ax, ay) = atlas
+                    .place(layer.width(), layer.height())
+                    .expect("batches fit the atlas");
+                placed.push((layer, ax, ay));
+                // Cut to the tile, as glyphs may reach over the canvas.
+                let left = (*lx).max(0);
+                let top = (*ly).max(0);
+                let right = (lx + layer.width() as i64).min(width as i64);
+                let bottom = (ly + layer.height() as i64).min(height as i64);
+                if left >= right || top >= bottom {
+                    continue;
+                }
+                sprites.extend(floats(&[
+                    (x as i64 + left) as f32,
+                    (y as i64 + top) as f32,
+                    (right - left) as f32,
+                    (bottom - top) as f32,
+                    (ax as i64 + left - lx) as f32,
+                    (ay as i64 + top - ly) as f32,
+                ]));
+            }
+        }
+        let mut atlas_image = RgbaImage::new(self.max_size, atlas.height().max(1));
+        for (layer, ax, ay) in placed {
+            image::imageops::replace(&mut atlas_image, layer, ax as i64, ay as i64);
+        }
+
+        let strokes: usize = batch.iter().map(|scene| scene.strokes.len()).sum();
+        let mut segments = Vec::new();
+        let mut drawn = 0;
+        for (index, scene) in batch.iter().enumerate() {
+            let (x, y) = tile(index);
+            let (x, y) = (x as f32, y as f32);
+            let clip = [x, y, x + width as f32, y + height as f32];
+            for noise in &scene.strokes {
+                drawn += 1;
+                let depth = 1.0 - drawn as f32 / (strokes + 1) as f32;
+                let alpha = noise.alpha as f32 / 255.0;
+                let [r, g, b] = noise.color.map(|channel| channel as f32 / 255.0 * alpha);
+                for (points, stroke_width) in noise.stroke.dashes() {
+                    for pair in points.windows(2) {
+                        let ((ax, ay), (bx, by)) = (pair[0], pair[1]);
+                        segments.extend(floats(&[
+                            x + ax,
+                            y + ay,
+                            x + bx,
+                            y + by,
+                            stroke_width / 2.0,
+                            depth,
+                            r,
+                            g,
+                            b,
+                            alpha,
+                        ]));
+                        segments.extend(floats(&clip));
+                    }
+                }
+            }
+        }
+
+        let frames = self.run(
+            (target_width, target_height),
+            &base,
+            &atlas_image,
+            &sprites,
+            &segments,
+        )?;
+        let font = generator.font.as_ref();
+        batch
+            .into_iter()
+            .enumerate()
+            .map(|(index, scene)| {
+                let (x, y) = tile(index);
+                let frame = image::imageops::crop_imm(&frames, x, y, width, height).to_image();
+                scene
+                    .prepared
+                    .finish(config, font, vec![frame], scene.stage_timings)
+            })
+            .collect()
+    }
+
+    /// Draws the sprite and segment instances over `base` and reads the
+    /// result back.
+    fn run(
+        &self,
+        (width, height): (u32, u32),
+        base: &RgbaImage,
+        atlas: &RgbaImage,
+        sprites: &[u8],
+        segments: &[u8],
+    ) -> Result<RgbaImage, CaptchaError> {
+        let device = &self.device;
+        let texture = |label: &str, size: (u32, u32), format, usage| {
+            device.create_texture(&wgpu::TextureDescriptor {
+                label: Some(label),
+                size: wgpu::Extent3d {
+                    width: size.0,
+                    height: size.1,
+                    depth_or_array_layers: 1,
+                },
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format,
+                usage,
+                view_formats: &[],
+            })
+        };
+        let upload = |texture: &wgpu::Texture, image: &RgbaImage| {
+            self.queue.write_texture(
+                texture.as_image_copy(),
+                image.as_raw(),
+                wgpu::TexelCopyBufferLayout {
+                    offset: 0,
+                    bytes_per_row: Some(image.width() * 4),
+                    rows_per_image: None,
+                },
+                texture.size(),
+            );
+        };
+
+        let target = texture(
+            "target",
+            (width, height),
+            wgpu::TextureFormat::Rgba8Unorm,
+            wgpu::TextureUsages::RENDER_ATTACHMENT
+                | wgpu::TextureUsages::COPY_DST
+                | wgpu::TextureUsages::COPY_SRC,
+        );
+        upload(&target, base);
+        let depth = texture(
+            "depth",
+            (width, height),
+            wgpu::TextureFormat::Depth32Float,
+            wgpu::TextureUsages::RENDER_ATTACHMENT,
+        );
+        let atlas_texture = texture(
+            "atlas",
+            atlas.dimensions(),
+            wgpu::TextureFormat::Rgba8Unorm,
+            wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+        );
+        upload(&atlas_texture, atlas);
+
+        let globals = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("globals"),
+            contents: &floats(&[width as f32, height as f32, 0.0, 0.0]).collect::<Vec<_>>(),
+            usage: wgpu::BufferUsages::UNIFORM,
+        });
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("captchagen"),
+            layout: &self.layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: globals.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::TextureView(
+                        &atlas_texture.create_view(&Default::default()),
+                    ),
+                },
+            ],
+        });
+        let instances = |label: &str, contents: &[u8]| {
+            // Empty buffers aren't allowed.
+            let contents = if contents.is_empty() {
+                &[0; 4]
+            } else {
+                contents
+            };
+            device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some(label),
+                contents,
+                usage: wgpu::BufferUsages::VERTEX,
+            })
+        };
+        let sprite_buffer = instances("sprites", sprites);
+        let segment_buffer = instances("segments", segments);
+
+        let padded_row = (width * 4).next_multiple_of(wgpu::COPY_BYTES_PER_ROW_ALIGNMENT);
+        let readback = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("readback"),
+            size: padded_row as u64 * height as u64,
+            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+
+        let mut encoder = device.create_command_encoder(&Default::default());
+        {
+            let target_view = target.create_view(&Default::default());
+            let depth_view = depth.create_view(&Default::default());
+            let mut pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("captchagen"),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view: &target_view,
+                    depth_slice: None,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Load,
+                        store: wgpu::StoreOp::Store,
+                    },
+                })],
+                depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                    view: &depth_view,
+                    depth_ops: Some(wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(1.0),
+                        store: wgpu::StoreOp::Discard,
+                    }),
+                    stencil_ops: None,
+                }),
+                ..Default::default()
+            });
+            pass.set_bind_group(0, &bind_group, &[]);
+            pass.set_pipeline(&self.sprites);
+            pass.set_vertex_buffer(0, sprite_buffer.slice(..));
+            pass.draw(0..4, 0..(sprites.len() / SPRITE_BYTES) as u32);
+            pass.set_pipeline(&self.segments);
+            pass.set_vertex_buffer(0, segment_buffer.slice(..));
+            pass.draw(0..4, 0..(segments.len() / SEGMENT_BYTES) as u32);
+        }
+        encoder.copy_texture_to_buffer(
+            target.as_image_copy(),
+            wgpu::TexelCopyBufferInfo {
+                buffer: &readback,
+                layout: wgpu::TexelCopyBufferLayout {
+                    offset: 0,
+                    bytes_per_row: Some(padded_row),
+                    rows_per_image: None,
+                },
+            },
+            target.size(),
+        );
+        self.queue.submit([encoder.finish()]);
+
+        let (sender, receiver) = mpsc::channel();
+        readback
+            .slice(..)
+            .map_async(wgpu::MapMode::Read, move |result| {
+                let _ = sender.send(result);
+            });
+        device
+            .poll(wgpu::PollType::wait_indefinitely())
+            .map_err(gpu_error)?;
+        receiver.recv().map_err(gpu_error)?.map_err(gpu_error)?;
+
+        let mapped = readback.slice(..).get_mapped_range().map_err(gpu_error)?;
+        let mut pixels = Vec::with_capacity(width as usize * height as usize * 4);
+        for row in mapped.chunks(padded_row as usize) {
+            pixels.extend_from_slice(&row[..width as usize * 4]);
+        }
+        drop(mapped);
+        readback.unmap();
+        Ok(RgbaImage::from_raw(width, height, pixels).expect("rows were copied whole"))
+    }
+}
+
+/// Bytes of one sprite and one segment instance.
+const SPRITE_BYTES: usize = 6 * 4;
+const SEGMENT_BYTES: usize = 14 * 4;
+
+/// Lays out the captcha and its noise on the CPU, drawing nothing.
+fn scene(generator: &Generator, config: &Config, sequence: u64) -> Result<Scene, CaptchaError> {
+    let mut prepared = generator.prepare(config, None, sequence)?;
+    let opaque_curves = matches!(config.format, Format::Dithered { .. });
+    let mut strokes = Vec::new();
+    let mut stage_timings = Vec::with_capacity(prepared.stages.len());
+    for stage in prepared.stages.clone() {
+        let started = Instant::now();
+        let (count, curves) = match stage {
+            Stage::Lines { count } => (count, false),
+            Stage::Curves { count } => (count, true),
+            _ => unreachable!("checked by gpu::check"),
+        };
+        for _ in 0..count.min(MAX_PATHS) {
+            let (style, guard) = (&config.line_style, &prepared.guard);
+            let rng = &mut prepared.rng;
+            let noise = match curves {
+                true => random_curve(
+                    config.width,
+                    config.height,
+                    style,
+                    guard,
+                    opaque_curves,
+                    rng,
+                ),
+                false => random_line(config.width, config.height, style, guard, rng),
+            };
+            prepared.noise.push(noise.path);
+            strokes.push(noise);
+        }
+        stage_timings.push(StageTiming {
+            stage,
+            elapsed: started.elapsed(),
+        });
+    }
+    Ok(Scene {
+        prepared,
+        strokes,
+        stage_timings,
+    })
+}
+
+/// Packs rectangles into rows of a `size` wide texture.
+#[derive(Clone, Copy)]
+struct Shelves {
+    size: u32,
+    x: u32,
+    y: u32,
+    row_height: u32,
+}
+
+impl Shelves {
+    fn new(size: u32) -> Self {
+        Self {
+            size,
+            x: 0,
+            y: 0,
+            row_height: 0,
+        }
+    }
+
+    fn place(&mut self, width: u32, height: u32) -> Option<(u32, u32)> {
+        if width > self.size {
+            return None;
+        }
+        if self.x + width > self.size {
+            self.y += self.row_height;
+            self.x = 0;
+            self.row_height = 0;
+        }
+        if self.y + height > self.size {
+            return None;
+        }
+        let at = (self.x, self.y);
+        self.x += width;
+        self.row_height = self.row_height.max(height);
+        Some(at)
+    }
+
+    /// Height of the rows used so far.
+    fn height(&self) -> u32 {
+        self.y + self.row_height
+    }
+}
+
+fn floats(values: &[f32]) -> impl Iterator<Item = u8> + '_ {
+    values.iter().flat_map(|value| value.to_le_bytes())
+}
+
+fn gpu_error(err: impl std::fmt::Display) -> CaptchaError {
+    CaptchaError::Gpu(err.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Animation, RngSource};
+
+    /// Tests using it are ignored, as CI machines have no adapter; run
+    /// them with `cargo test --features gpu -- --ignored` on one that has.
+    fn renderer() -> GpuRenderer {
+        GpuRenderer::new().expect("a GPU adapter")
+    }
+
+    fn seeded() -> Config {
+        Config {
+            answer_rng: RngSource::Seeded(1),
+            visual_rng: RngSource::Seeded(2),
+            ..Config::default()
+        }
+    }
+
+    fn difference(a: &RgbaImage, b: &RgbaImage) -> f64 {
+        let total: u64 = a
+            .as_raw()
+            .iter()
+            .zip(b.as_raw())
+            .map(|(a, b)| a.abs_diff(*b) as u64)
+            .sum();
+        total as f64 / a.as_raw().len() as f64
+    }
+
+    #[test]
+    #[ignore = "needs a GPU adapter"]
+    fn draws_what_the_cpu_draws() {
+        let renderer = renderer();
+        let config = seeded();
+        let generator = Generator::new(config.clone()).unwrap();
+        let cpu = generator.render(&config, None, 3).unwrap();
+        let gpu = renderer
+            .render(&generator, &config, 3, 1)
+            .unwrap()
+            .remove(0);
+
+        assert_eq!(gpu.answer.expose(), cpu.answer.expose());
+        assert_eq!(gpu.layout.to_json(), cpu.layout.to_json());
+        assert_eq!(gpu.frames[0].dimensions(), cpu.frames[0].dimensions());
+        let difference = difference(&gpu.frames[0], &cpu.frames[0]);
+        // Antialiasing differs along the edges of the strokes.
+        assert!(difference < 2.0, "{difference}");
+    }
+
+    #[test]
+    #[ignore = "needs a GPU adapter"]
+    fn splits_batches_past_the_target() {
+        let mut renderer = renderer();
+        renderer.max_size = 256;
+        let config = seeded();
+        let generator = Generator::new(config.clone()).unwrap();
+        let rendered = renderer.render(&generator, &config, 0, 7).unwrap();
+
+        assert_eq!(rendered.len(), 7);
+        for (sequence, gpu) in rendered.iter().enumerate() {
+            let cpu = generator.render(&config, None, sequence as u64).unwrap();
+            assert_eq!(gpu.layout.to_json(), cpu.layout.to_json());
+            assert!(difference(&gpu.frames[0], &cpu.frames[0]) < 2.0);
+        }
+    }
+
+    #[test]
+    fn rejects_what_only_the_cpu_draws() {
+        assert!(check(&Config::default()).is_ok());
+        let unsupported = [
+            Config {
+                animation: Some(Animation::default()),
+                ..Config::default()
+            },
+            Config {
+                pipeline: vec![Stage::Wave {
+                    amplitude: 2.0,
+                    wavelength: 20.0,
+                }],
+                ..Config::default()
+            },
+            Config {
+                line_style: crate::LineStyle {
+                    blend: BlendMode::Multiply,
+                    ..Default::default()
+                },
+                ..Config::default()
+            },
+        ];
+        for config in unsupported {
+            assert!(matches!(check(&config), Err(CaptchaError::InvalidInput(_))));
+        }
+    }
+
+    #[test]
+    fn packs_rectangles_into_rows() {
+        let mut shelves = Shelves::new(10);
+        assert_eq!(shelves.place(6, 3), Some((0, 0)));
+        assert_eq!(shelves.place(4, 2), Some((6, 0)));
+        assert_eq!(shelves.place(5, 4), Some((0, 3)));
+        assert_eq!(shelves.height(), 7);
+        assert_eq!(shelves.place(11, 1), None);
+        assert_eq!(shelves.place(6, 4), None);
+    }
+}
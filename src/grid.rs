@@ -0,0 +1,296 @@
+use std::time::{Duration, SystemTime};
+
+use image::{Rgba, RgbaImage, imageops};
+use rand::{Rng, rng, seq::SliceRandom};
+
+use crate::{
+    CaptchaError, Color, DEFAULT_MAX_PIXELS, Format,
+    captcha::{expires_at, is_expired, random_id},
+    encode::encode,
+};
+
+/// A caller-supplied picture and what it shows.
+pub struct LabeledImage {
+    pub label: String,
+    pub image: RgbaImage,
+}
+
+/// "Select all images containing X" captchas composed from caller-supplied
+/// pictures.
+#[derive(Clone)]
+pub struct GridConfig {
+    pub rows: u32,
+    pub columns: u32,
+    /// Side length of every (square) cell.
+    pub cell_size: u32,
+    pub gap: u32,
+    pub background_color: Color,
+    pub expires_in: Option<Duration>,
+    pub format: Format,
+    /// Ceiling on the pixels of the grid image, like
+    /// [`Config::max_pixels`], so untrusted sizes can't cause huge
+    /// allocations. `None` disables the check.
+    ///
+    /// [`Config::max_pixels`]: crate::Config::max_pixels
+    pub max_pixels: Option<u64>,
+}
+
+impl Default for GridConfig {
+    fn default() -> Self {
+        Self {
+            rows: 3,
+            columns: 3,
+            cell_size: 100,
+            gap: 4,
+            background_color: Color::WHITE,
+            expires_in: None,
+            format: Format::Png,
+            max_pixels: Some(DEFAULT_MAX_PIXELS),
+        }
+    }
+}
+
+/// Where a cell sits in the grid image, in pixels.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Cell {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+pub struct GridCaptcha {
+    pub id: String,
+    /// The label the user is asked to select.
+    pub label: String,
+    pub instruction: String,
+    pub image: Vec<u8>,
+    pub format: Format,
+    pub width: u32,
+    pub height: u32,
+    /// Cells in row-major order; selections refer to indices into this.
+    pub cells: Vec<Cell>,
+    /// Sorted indices of the cells showing `label`.
+    pub correct: Vec<usize>,
+    pub issued_at: SystemTime,
+    pub expires_at: Option<SystemTime>,
+}
+
+impl GridConfig {
+    /// Composes a grid from `images`. At least one image must carry a label
+    /// different from the others, and there must be enough images to fill
+    /// every cell.
+    pub fn generate(&self, images: &[LabeledImage]) -> Result<GridCaptcha, CaptchaError> {
+        if self.rows == 0 || self.columns == 0 || self.cell_size == 0 {
+            return Err(CaptchaError::InvalidInput(
+                "rows, columns and cell size must be at least 1".into(),
+            ));
+        }
+        let (width, height) = self.size()?;
+        let cell_count = (self.rows * self.columns) as usize;
+        if images.len() < cell_count {
+            return Err(CaptchaError::InvalidInput(format!(
+                "need at least {cell_count} images, got {}",
+                images.len()
+            )));
+        }
+
+        let mut rng = rng();
+
+        let mut labels: Vec<&str> = images.iter().map(|i| i.label.as_str()).collect();
+        labels.sort_unstable();
+        labels.dedup();
+        if labels.len() < 2 {
+            return Err(CaptchaError::InvalidInput(
+                "images need at least two distinct labels".to_string(),
+            ));
+        }
+
+        let mut order: Vec<usize> = (0..images.len()).collect();
+        order.shuffle(&mut rng);
+
+        let label = labels[rng.random_range(0..labels.len())];
+        let (matching, others): (Vec<usize>, Vec<usize>) =
+            order.into_iter().partition(|&i| images[i].label == label);
+
+        let max_correct = matching.len().min(cell_count / 2).max(1);
+        let correct_count = rng.random_range(1..=max_correct);
+        let other_count = cell_count.checked_sub(correct_count).ok_or_else(|| {
+            CaptchaError::InvalidInput(format!(
+                "{correct_count} correct cells don't fit {cell_count} cells"
+            ))
+        })?;
+        if others.len() < other_count {
+            return Err(CaptchaError::InvalidInput(format!(
+                "not enough images without the label {label:?}"
+            )));
+        }
+
+        let mut picked: Vec<usize> = matching[..correct_count]
+            .iter()
+            .chain(&others[..other_count])
+            .copied()
+            .collect();
+        picked.shuffle(&mut rng);
+
+        let step = self.cell_size + self.gap;
+        let background_color = Rgba(self.background_color.to_rgba());
+        let mut img = RgbaImage::from_pixel(width, height, background_color);
+
+        let mut cells = Vec::with_capacity(cell_count);
+        let mut correct = Vec::new();
+        for (index, &image_index) in picked.iter().enumerate() {
+            let cell = Cell {
+                x: self.gap + (index as u32 % self.columns) * step,
+                y: self.gap + (index as u32 / self.columns) * step,
+                width: self.cell_size,
+                height: self.cell_size,
+            };
+
+            let resized = imageops::resize(
+                &images[image_index].image,
+                self.cell_size,
+                self.cell_size,
+                imageops::FilterType::Triangle,
+            );
+            imageops::overlay(&mut img, &resized, cell.x as i64, cell.y as i64);
+
+            if images[image_index].label == label {
+                correct.push(index);
+            }
+            cells.push(cell);
+        }
+
+        let issued_at = SystemTime::now();
+        Ok(GridCaptcha {
+            id: random_id(),
+            label: label.to_string(),
+            instruction: format!("Select all images containing {label}"),
+            image: encode(&img, self.format)?,
+            format: self.format,
+            width: img.width(),
+            height: img.height(),
+            cells,
+            correct,
+            issued_at,
+            expires_at: expires_at(issued_at, self.expires_in),
+        })
+    }
+
+    /// Pixel size of the grid image. Fails past [`GridConfig::max_pixels`]
+    /// or when it doesn't fit `u32`, which also keeps the cell count and
+    /// positions from overflowing.
+    fn size(&self) -> Result<(u32, u32), CaptchaError> {
+        let too_large = || CaptchaError::InvalidInput("the grid is too large for an image".into());
+        let step = self.cell_size.checked_add(self.gap).ok_or_else(too_large)?;
+        let side = |cells: u32| cells.checked_mul(step)?.checked_add(self.gap);
+        let width = side(self.columns).ok_or_else(too_large)?;
+        let height = side(self.rows).ok_or_else(too_large)?;
+        let pixels = width as u64 * height as u64;
+        match self.max_pixels {
+            Some(max) if pixels > max => Err(CaptchaError::TooLarge { pixels, max }),
+            _ if pixels > u32::MAX as u64 => Err(too_large()),
+            _ => Ok((width, height)),
+        }
+    }
+}
+
+impl GridCaptcha {
+    pub fn is_expired(&self) -> bool {
+        is_expired(self.expires_at)
+    }
+
+    pub fn content_type(&self) -> &'static str {
+        self.format.content_type()
+    }
+
+    /// The selection must contain exactly the correct cells, in any order.
+    pub fn verify(&self, selected: &[usize]) -> bool {
+        !self.is_expired() && selection_answer(selected) == selection_answer(&self.correct)
+    }
+}
+
+/// Canonical answer string for a set of selected cells, so selections can
+/// be checked through a [`crate::CaptchaStore`] like text answers.
+pub fn selection_answer(selected: &[usize]) -> String {
+    let mut selected = selected.to_vec();
+    selected.sort_unstable();
+    selected.dedup();
+    selected
+        .iter()
+        .map(|i| i.to_string())
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn images(labels: &[&str]) -> Vec<LabeledImage> {
+        labels
+            .iter()
+            .map(|label| LabeledImage {
+                label: label.to_string(),
+                image: RgbaImage::new(8, 8),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn selects_the_cells_showing_the_label() {
+        let config = GridConfig {
+            rows: 2,
+            columns: 2,
+            cell_size: 10,
+            gap: 1,
+            ..GridConfig::default()
+        };
+        let grid = config
+            .generate(&images(&["cat", "dog", "dog", "cat", "cat", "dog"]))
+            .unwrap();
+        assert_eq!((grid.width, grid.height), (23, 23));
+        assert_eq!(grid.cells.len(), 4);
+        assert_eq!(
+            grid.cells[3],
+            Cell {
+                x: 12,
+                y: 12,
+                width: 10,
+                height: 10
+            }
+        );
+        assert!((1..=2).contains(&grid.correct.len()));
+        assert!(grid.instruction.ends_with(&grid.label));
+
+        let mut reversed = grid.correct.clone();
+        reversed.reverse();
+        assert!(grid.verify(&reversed));
+        assert!(!grid.verify(&[]));
+    }
+
+    #[test]
+    fn rejects_grids_past_the_pixel_ceiling() {
+        let config = GridConfig {
+            cell_size: 65535,
+            ..GridConfig::default()
+        };
+        let err = config.generate(&images(&["a"; 9])).err().unwrap();
+        assert!(matches!(err, CaptchaError::TooLarge { .. }));
+
+        let config = GridConfig {
+            cell_size: u32::MAX,
+            max_pixels: None,
+            ..GridConfig::default()
+        };
+        let err = config.generate(&images(&["a"; 9])).err().unwrap();
+        assert!(matches!(err, CaptchaError::InvalidInput(_)));
+    }
+
+    #[test]
+    fn needs_enough_distinct_images() {
+        let config = GridConfig::default();
+        assert!(config.generate(&images(&["a", "b", "a", "b"])).is_err());
+        assert!(config.generate(&images(&["a"; 9])).is_err());
+    }
+}
@@ -0,0 +1,155 @@
+use rand::Rng;
+
+use crate::Answer;
+use sha2::{Digest, Sha256};
+
+const SHA256_PREFIX: &str = "sha256$";
+
+/// Hashes a captcha answer with a random salt, in the form
+/// `sha256$<salt>$<digest>`. Answers are compared case-insensitively, so
+/// they are lowercased first.
+pub fn hash_answer(answer: &str) -> String {
+    let salt: [u8; 16] = rand::rng().random();
+    let digest = sha256(&salt, answer);
+
+    format!("{SHA256_PREFIX}{}${}", to_hex(&salt), to_hex(&digest))
+}
+
+/// Hashes a captcha answer with Argon2id. Much slower than [`hash_answer`],
+/// for stores whose backing may be exposed to offline attacks.
+#[cfg(feature = "argon2")]
+pub fn hash_answer_argon2(answer: &str) -> String {
+    use argon2::{
+        Argon2,
+        password_hash::{PasswordHasher, SaltString},
+    };
+
+    let salt: [u8; 16] = rand::rng().random();
+    let salt = SaltString::encode_b64(&salt).unwrap();
+
+    Argon2::default()
+        .hash_password(Answer::normalized(answer).expose().as_bytes(), &salt)
+        .unwrap()
+        .to_string()
+}
+
+/// Lowercase hex SHA-256 of `bytes`.
+pub(crate) fn sha256_hex(bytes: &[u8]) -> String {
+    to_hex(&Sha256::digest(bytes))
+}
+
+/// Checks `answer` against a hash produced by [`hash_answer`] (or
+/// `hash_answer_argon2` with the `argon2` feature).
+pub fn verify_hashed(hash: &str, answer: &str) -> bool {
+    if let Some(rest) = hash.strip_prefix(SHA256_PREFIX) {
+        let Some((salt, digest)) = rest.split_once('$') else {
+            return false;
+        };
+        let (Some(salt), Some(digest)) = (from_hex(salt), from_hex(digest)) else {
+            return false;
+        };
+
+        return constant_time_eq(&sha256(&salt, answer), &digest);
+    }
+
+    #[cfg(feature = "argon2")]
+    if hash.starts_with("$argon2") {
+        use argon2::{
+            Argon2,
+            password_hash::{PasswordHash, PasswordVerifier},
+        };
+
+        let Ok(hash) = PasswordHash::new(hash) else {
+            return false;
+        };
+        return Argon2::default()
+            .verify_password(Answer::normalized(answer).expose().as_bytes(), &hash)
+            .is_ok();
+    }
+
+    false
+}
+
+fn sha256(salt: &[u8], answer: &str) -> [u8; 32] {
+    let mut hasher = Sha256::new();
+    hasher.update(salt);
+    hasher.update(Answer::normalized(answer).expose().as_bytes());
+    hasher.finalize().into()
+}
+
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+pub(crate) fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+pub(crate) fn from_hex(hex: &str) -> Option<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) || !hex.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).ok())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn verifies_the_hashed_answer() {
+        let hash = hash_answer("x7Kp");
+        assert!(verify_hashed(&hash, "x7Kp"));
+        assert!(verify_hashed(&hash, "X7KP"));
+        assert!(!verify_hashed(&hash, "x7K"));
+        assert!(!verify_hashed(&hash, ""));
+    }
+
+    #[test]
+    fn salts_every_hash() {
+        assert_ne!(hash_answer("x7Kp"), hash_answer("x7Kp"));
+    }
+
+    #[test]
+    fn rejects_malformed_hashes() {
+        let hash = hash_answer("x7Kp");
+        let (salted, digest) = hash.rsplit_once('$').unwrap();
+        assert!(!verify_hashed(&format!("{salted}$00{digest}"), "x7Kp"));
+        assert!(!verify_hashed(&format!("{salted}$zz"), "x7Kp"));
+        assert!(!verify_hashed(
+            &hash.replacen("sha256", "sha512", 1),
+            "x7Kp"
+        ));
+        assert!(!verify_hashed("sha256$", "x7Kp"));
+        assert!(!verify_hashed("", ""));
+    }
+
+    #[cfg(feature = "argon2")]
+    #[test]
+    fn verifies_argon2_hashes() {
+        let hash = hash_answer_argon2("x7Kp");
+        assert!(verify_hashed(&hash, "X7KP"));
+        assert!(!verify_hashed(&hash, "x7K"));
+    }
+
+    #[test]
+    fn hex_round_trips() {
+        let bytes = [0, 1, 0xab, 0xff];
+        assert_eq!(to_hex(&bytes), "0001abff");
+        assert_eq!(from_hex("0001abff").unwrap(), bytes);
+        assert_eq!(from_hex("abc"), None);
+        assert_eq!(from_hex("zz"), None);
+        assert_eq!(from_hex("+f"), None);
+    }
+
+    #[test]
+    fn hashes_bytes_to_lowercase_hex() {
+        assert_eq!(
+            sha256_hex(b"abc"),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+    }
+}
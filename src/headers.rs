@@ -0,0 +1,92 @@
+use crate::{CachedImage, Captcha, hash::sha256_hex};
+
+/// HTTP headers for serving a captcha image, as (name, value) pairs for
+/// any framework. Every captcha is unique, so browsers, proxies and CDNs
+/// are told not to keep it at all; `Pragma` covers HTTP/1.0 caches.
+///
+/// With `token`, an `ETag` derived from it is added, which changes with
+/// every captcha even when two images happen to be identical.
+pub fn response_headers(
+    content_type: &str,
+    content_length: usize,
+    token: Option<&str>,
+) -> Vec<(&'static str, String)> {
+    let mut headers = vec![
+        ("Content-Type", content_type.to_string()),
+        ("Content-Length", content_length.to_string()),
+        ("Cache-Control", "no-store, no-cache, max-age=0".to_string()),
+        ("Pragma", "no-cache".to_string()),
+        ("Expires", "0".to_string()),
+    ];
+    if let Some(token) = token {
+        headers.push(("ETag", format!("\"{}\"", token_digest(token))));
+    }
+    headers
+}
+
+/// `url` with a `v` query parameter derived from `token`, the same value
+/// as the `ETag` of [`response_headers`], so image URLs change with every
+/// captcha and no cache can serve an earlier one. Keeps any query `url`
+/// already has.
+pub fn cache_busting_url(url: &str, token: &str) -> String {
+    let separator = if url.contains('?') { '&' } else { '?' };
+    format!("{url}{separator}v={}", token_digest(token))
+}
+
+fn token_digest(token: &str) -> String {
+    sha256_hex(token.as_bytes())[..32].to_string()
+}
+
+impl Captcha {
+    /// [`response_headers`] for this captcha's image.
+    pub fn headers(&self, token: Option<&str>) -> Vec<(&'static str, String)> {
+        response_headers(self.content_type(), self.image.len(), token)
+    }
+}
+
+impl CachedImage {
+    /// [`response_headers`] for the cached image.
+    pub fn headers(&self, token: Option<&str>) -> Vec<(&'static str, String)> {
+        response_headers(self.content_type, self.image.len(), token)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn forbids_caching() {
+        let headers = response_headers("image/png", 42, None);
+        assert_eq!(
+            headers,
+            [
+                ("Content-Type", "image/png".to_string()),
+                ("Content-Length", "42".to_string()),
+                ("Cache-Control", "no-store, no-cache, max-age=0".to_string()),
+                ("Pragma", "no-cache".to_string()),
+                ("Expires", "0".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn tags_and_busts_per_token() {
+        let digest = &sha256_hex(b"token")[..32];
+        let headers = response_headers("image/png", 42, Some("token"));
+        assert_eq!(headers.last().unwrap(), &("ETag", format!("\"{digest}\"")));
+        assert_ne!(
+            response_headers("image/png", 42, Some("other")).last(),
+            headers.last()
+        );
+
+        assert_eq!(
+            cache_busting_url("/captcha.png", "token"),
+            format!("/captcha.png?v={digest}")
+        );
+        assert_eq!(
+            cache_busting_url("/captcha?id=1", "token"),
+            format!("/captcha?id=1&v={digest}")
+        );
+    }
+}
@@ -0,0 +1,134 @@
+/// What users will type, so frontends can pick a fitting on-screen keyboard.
+/// Answers are matched case-insensitively, so letters can be entered in
+/// either case. For input validation, see [`Config::answer_pattern`], which
+/// follows the configured charset.
+///
+/// [`Config::answer_pattern`]: crate::Config::answer_pattern
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum InputHint {
+    Numeric,
+    #[default]
+    Alphanumeric,
+}
+
+impl InputHint {
+    pub(crate) fn for_charset(charset: &str) -> Self {
+        if charset.chars().all(|c| c.is_ascii_digit()) {
+            InputHint::Numeric
+        } else {
+            InputHint::Alphanumeric
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            InputHint::Numeric => "numeric",
+            InputHint::Alphanumeric => "alphanumeric",
+        }
+    }
+
+    /// Value for the HTML `inputmode` attribute.
+    pub fn input_mode(&self) -> &'static str {
+        match self {
+            InputHint::Numeric => "numeric",
+            InputHint::Alphanumeric => "text",
+        }
+    }
+}
+
+/// A regex character class of `chars`, with consecutive runs as ranges.
+pub(crate) fn character_class(chars: impl IntoIterator<Item = char>) -> String {
+    let mut chars: Vec<char> = chars.into_iter().collect();
+    chars.sort_unstable();
+    chars.dedup();
+
+    let mut class = String::from("[");
+    let mut index = 0;
+    while index < chars.len() {
+        let start = chars[index];
+        let mut end = index;
+        while end + 1 < chars.len() && chars[end + 1] as u32 == chars[end] as u32 + 1 {
+            end += 1;
+        }
+        push_escaped(&mut class, start);
+        if end - index >= 2 {
+            class.push('-');
+            push_escaped(&mut class, chars[end]);
+        } else {
+            chars[index + 1..=end]
+                .iter()
+                .for_each(|&c| push_escaped(&mut class, c));
+        }
+        index = end + 1;
+    }
+    class.push(']');
+    class
+}
+
+/// Escapes everything the HTML `pattern` attribute's `v` flag reserves in
+/// a class, besides what plain classes need.
+fn push_escaped(class: &mut String, c: char) {
+    if matches!(
+        c,
+        '\\' | ']' | '[' | '^' | '-' | '(' | ')' | '{' | '}' | '|' | '/'
+    ) {
+        class.push('\\');
+    }
+    class.push(c);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Config, Generator, Mode, Numerals};
+
+    #[test]
+    fn hints_keyboards_from_the_charset() {
+        assert_eq!(InputHint::for_charset("0123456789"), InputHint::Numeric);
+        assert_eq!(
+            InputHint::for_charset("0123456789a"),
+            InputHint::Alphanumeric
+        );
+        assert_eq!(InputHint::for_charset("٠١٢"), InputHint::Alphanumeric);
+
+        let config = Config {
+            charset: Some("0123456789".into()),
+            ..Config::default()
+        };
+        let captcha = Generator::new(config).unwrap().generate().unwrap();
+        assert_eq!(captcha.input_hint, InputHint::Numeric);
+        assert_eq!(captcha.input_hint.input_mode(), "numeric");
+    }
+
+    #[test]
+    fn collapses_runs_into_ranges() {
+        assert_eq!(character_class("zxbadc".chars()), "[a-dxz]");
+        assert_eq!(character_class("abab".chars()), "[ab]");
+        assert_eq!(character_class("]-/|".chars()), r"[\-\/\]\|]");
+    }
+
+    #[test]
+    fn describes_every_possible_answer() {
+        let config = |mode, numerals| Config {
+            charset: Some("0123ab".into()),
+            length: 4,
+            mode,
+            numerals,
+            ..Config::default()
+        };
+        let patterns = [
+            config(Mode::Plain, Numerals::Ascii),
+            config(Mode::Colors { count: 2 }, Numerals::Ascii),
+            config(Mode::Plain, Numerals::ArabicIndic),
+        ]
+        .map(|config| config.answer_pattern());
+        assert_eq!(
+            patterns,
+            [
+                "^[0-3ABab]{4}$",
+                "^[0-3ABab]{1,3}$",
+                "^[0-3ABab\u{660}-\u{663}]{4}$",
+            ]
+        );
+    }
+}
@@ -0,0 +1,193 @@
+use std::{
+    collections::{BTreeSet, HashMap},
+    sync::{Arc, Mutex},
+    time::{Duration, SystemTime},
+};
+
+use crate::{Captcha, captcha::is_expired};
+
+/// Keeps the encoded images of recently issued captchas, so reloading a page
+/// can show the same image again instead of a new captcha whose answer the
+/// store doesn't know. Entries are dropped after the window or when the
+/// captcha expires, whichever is first.
+pub struct ImageCache {
+    window: Duration,
+    capacity: usize,
+    entries: Mutex<Entries>,
+}
+
+#[derive(Default)]
+struct Entries {
+    by_id: HashMap<String, Entry>,
+    /// The ids with an end, soonest first, so expired ones are found
+    /// without scanning every image.
+    by_expiry: BTreeSet<(SystemTime, String)>,
+}
+
+struct Entry {
+    image: CachedImage,
+    expires_at: Option<SystemTime>,
+}
+
+#[derive(Clone, Debug)]
+pub struct CachedImage {
+    pub image: Arc<[u8]>,
+    pub content_type: &'static str,
+}
+
+/// Default of [`ImageCache::with_limits`].
+const DEFAULT_CAPACITY: usize = 1 << 14;
+
+impl ImageCache {
+    /// Holds up to about sixteen thousand images, see
+    /// [`ImageCache::with_limits`].
+    pub fn new(window: Duration) -> Self {
+        Self::with_limits(window, DEFAULT_CAPACITY)
+    }
+
+    /// Holds at most `capacity` images. While that many are cached, each
+    /// insert drops the image that would have expired first.
+    pub fn with_limits(window: Duration, capacity: usize) -> Self {
+        Self {
+            window,
+            capacity,
+            entries: Mutex::new(Entries::default()),
+        }
+    }
+
+    pub fn insert(&self, captcha: &Captcha) {
+        let cached_until = captcha.issued_at.checked_add(self.window);
+        let expires_at = match (cached_until, captcha.expires_at) {
+            (Some(cached_until), Some(expires_at)) => Some(cached_until.min(expires_at)),
+            (cached_until, expires_at) => cached_until.or(expires_at),
+        };
+
+        let mut entries = self.entries.lock().unwrap();
+        let now = SystemTime::now();
+        while let Some((expires_at, _)) = entries.by_expiry.first()
+            && *expires_at <= now
+        {
+            let (_, expired) = entries.by_expiry.pop_first().expect("checked above");
+            entries.by_id.remove(&expired);
+        }
+        entries.remove(&captcha.id);
+        if entries.by_id.len() >= self.capacity {
+            let Some((_, soonest)) = entries.by_expiry.pop_first() else {
+                return;
+            };
+            entries.by_id.remove(&soonest);
+        }
+
+        if let Some(expires_at) = expires_at {
+            entries.by_expiry.insert((expires_at, captcha.id.clone()));
+        }
+        entries.by_id.insert(
+            captcha.id.clone(),
+            Entry {
+                image: CachedImage {
+                    image: captcha.image.as_slice().into(),
+                    content_type: captcha.content_type(),
+                },
+                expires_at,
+            },
+        );
+    }
+
+    /// The image of captcha `id`, while it's cached.
+    pub fn get(&self, id: &str) -> Option<CachedImage> {
+        let entries = self.entries.lock().unwrap();
+        entries
+            .by_id
+            .get(id)
+            .filter(|entry| !is_expired(entry.expires_at))
+            .map(|entry| entry.image.clone())
+    }
+
+    /// Drops the image of captcha `id`, e.g. once it was verified.
+    pub fn remove(&self, id: &str) {
+        self.entries.lock().unwrap().remove(id);
+    }
+}
+
+impl Entries {
+    fn remove(&mut self, id: &str) {
+        if let Some(Entry {
+            expires_at: Some(expires_at),
+            ..
+        }) = self.by_id.remove(id)
+        {
+            self.by_expiry.remove(&(expires_at, id.to_string()));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Answer, Format};
+
+    fn captcha(expires_in: Option<Duration>) -> Captcha {
+        Captcha::new(
+            Answer::new("x7Kp".into()),
+            vec![1, 2, 3],
+            Format::Png,
+            (1, 1),
+            expires_in,
+        )
+    }
+
+    fn tracked(cache: &ImageCache) -> (usize, usize) {
+        let entries = cache.entries.lock().unwrap();
+        (entries.by_id.len(), entries.by_expiry.len())
+    }
+
+    #[test]
+    fn serves_the_same_image_again() {
+        let cache = ImageCache::new(Duration::from_secs(60));
+        let captcha = captcha(None);
+        cache.insert(&captcha);
+        let cached = cache.get(&captcha.id).unwrap();
+        assert_eq!(&*cached.image, captcha.image.as_slice());
+        assert_eq!(cached.content_type, "image/png");
+        assert!(cache.get("other").is_none());
+
+        cache.remove(&captcha.id);
+        assert!(cache.get(&captcha.id).is_none());
+        assert_eq!(tracked(&cache), (0, 0));
+    }
+
+    #[test]
+    fn drops_images_after_the_window_or_expiry() {
+        let cache = ImageCache::new(Duration::from_secs(60));
+        let mut old = captcha(None);
+        old.issued_at -= Duration::from_secs(61);
+        let expired = captcha(Some(Duration::ZERO));
+        cache.insert(&old);
+        cache.insert(&expired);
+        assert!(cache.get(&old.id).is_none());
+        assert!(cache.get(&expired.id).is_none());
+
+        // Expired entries are dropped when others are inserted.
+        cache.insert(&captcha(None));
+        assert_eq!(tracked(&cache), (1, 1));
+    }
+
+    #[test]
+    fn bounds_the_images_cached() {
+        let cache = ImageCache::with_limits(Duration::from_secs(60), 2);
+        let soonest = captcha(Some(Duration::from_secs(10)));
+        let later = captcha(Some(Duration::from_secs(30)));
+        cache.insert(&soonest);
+        cache.insert(&later);
+        // Caching the same id again replaces it.
+        cache.insert(&later);
+        assert_eq!(tracked(&cache), (2, 2));
+
+        let newest = captcha(None);
+        cache.insert(&newest);
+        assert!(cache.get(&soonest.id).is_none());
+        assert!(cache.get(&later.id).is_some());
+        assert!(cache.get(&newest.id).is_some());
+        assert_eq!(tracked(&cache), (2, 2));
+    }
+}
@@ -0,0 +1,109 @@
+use fontdue::Font;
+
+use crate::{BlendMode, SizeQuestion, blend::blend, composite};
+use image::{Rgba, RgbaImage, imageops};
+
+/// A question rendered below the captcha, telling the user which part of
+/// the shown characters makes up the answer.
+#[derive(Clone, Debug)]
+pub struct Instruction {
+    pub text: String,
+    pub question: Question,
+}
+
+#[derive(Clone, Debug)]
+pub enum Question {
+    /// 1-based positions of the characters to enter.
+    Positions(Vec<usize>),
+    /// Only the characters drawn in `color` are to be entered. `name` is an
+    /// English color name, meant as a key for localized phrasing.
+    Color {
+        name: String,
+        color: [u8; 3],
+        /// 1-based positions of the characters drawn in `color`.
+        positions: Vec<usize>,
+    },
+    /// The answer depends on the drawn character sizes.
+    Size {
+        question: SizeQuestion,
+        /// Font size factor of every shown character.
+        scales: Vec<f32>,
+    },
+}
+
+/// English phrasing of a question, used unless the config supplies its own.
+pub fn default_instruction_text(question: &Question) -> String {
+    match question {
+        Question::Positions(positions) => {
+            let mut numbers: Vec<String> = positions.iter().map(|p| p.to_string()).collect();
+            match numbers.len() {
+                1 => format!("Enter character {}", numbers[0]),
+                _ => {
+                    let last = numbers.pop().unwrap();
+                    format!("Enter characters {} and {last}", numbers.join(", "))
+                }
+            }
+        }
+        Question::Color { name, .. } => format!("Type the {name} characters"),
+        Question::Size { question, .. } => match question {
+            SizeQuestion::Largest => "Type the largest character".to_string(),
+            SizeQuestion::Smallest => "Type the smallest character".to_string(),
+            SizeQuestion::LargestToSmallest => "Type from largest to smallest".to_string(),
+            SizeQuestion::SmallestToLargest => "Type from smallest to largest".to_string(),
+        },
+    }
+}
+
+/// Returns `img` extended by a strip at the bottom carrying `text`, with
+/// `img` and `background_color` premultiplied.
+pub(crate) fn render_instruction(
+    img: &RgbaImage,
+    font: &Font,
+    text: &str,
+    color: [u8; 3],
+    background_color: Rgba<u8>,
+) -> RgbaImage {
+    let strip_height = (img.height() / 4).max(16);
+    let mut font_size = strip_height as f32 * 0.7;
+
+    // Shrink the font when the text doesn't fit on one line.
+    let text_width = |size: f32| -> f32 {
+        text.chars()
+            .map(|c| font.metrics(c, size).advance_width)
+            .sum()
+    };
+    let available = img.width() as f32 * 0.95;
+    if text_width(font_size) > available {
+        font_size *= available / text_width(font_size);
+    }
+
+    let mut out = RgbaImage::from_pixel(img.width(), img.height() + strip_height, background_color);
+    imageops::replace(&mut out, img, 0, 0);
+
+    let ascent = font
+        .horizontal_line_metrics(font_size)
+        .map(|m| m.ascent)
+        .unwrap_or(font_size);
+    let baseline = img.height() as f32 + (strip_height as f32 - font_size) / 2.0 + ascent;
+    let mut x = (img.width() as f32 - text_width(font_size)) / 2.0;
+
+    for c in text.chars() {
+        let (metrics, bitmap) = font.rasterize(c, font_size);
+        if metrics.width > 0 && metrics.height > 0 {
+            let rgba_data = bitmap
+                .into_iter()
+                .flat_map(|alpha| {
+                    composite::premultiply_pixel(Rgba([color[0], color[1], color[2], alpha])).0
+                })
+                .collect();
+            let glyph = RgbaImage::from_raw(metrics.width as u32, metrics.height as u32, rgba_data)
+                .unwrap();
+            let px = (x + metrics.xmin as f32) as i64;
+            let py = (baseline - metrics.height as f32 - metrics.ymin as f32) as i64;
+            blend(&mut out, &glyph, px, py, BlendMode::Normal);
+        }
+        x += metrics.advance_width;
+    }
+
+    out
+}
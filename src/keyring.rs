@@ -0,0 +1,282 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, PoisonError, RwLock},
+};
+
+use crate::{
+    CaptchaChallenge, CaptchaError, ReplayCache, SolveTimePolicy, TokenKey, VerifyOutcome,
+};
+
+/// Named [`TokenKey`]s, for rotating keys without invalidating the tokens
+/// already handed out, or for a key per tenant.
+///
+/// Tokens are signed with the active key and carry its id in front, as
+/// `<key id>.<token>`. Every key in the ring is accepted, so after
+/// [`KeyRing::rotate`] tokens of the previous key keep verifying until
+/// they expire; remove the old key once they have.
+pub struct KeyRing {
+    keys: RwLock<Keys>,
+}
+
+struct Keys {
+    active: String,
+    by_id: HashMap<String, Arc<TokenKey>>,
+}
+
+impl KeyRing {
+    /// A ring signing with `key`. Key ids may consist of ASCII letters,
+    /// digits, `-` and `_`.
+    pub fn new(id: &str, key: impl Into<Vec<u8>>) -> Result<Self, CaptchaError> {
+        check_id(id)?;
+        Ok(Self {
+            keys: RwLock::new(Keys {
+                active: id.to_string(),
+                by_id: HashMap::from([(id.to_string(), Arc::new(TokenKey::new(key)))]),
+            }),
+        })
+    }
+
+    /// Accepts tokens signed with `key` from now on, without signing with
+    /// it. Replaces any key with the same id.
+    pub fn insert(&self, id: &str, key: impl Into<Vec<u8>>) -> Result<(), CaptchaError> {
+        check_id(id)?;
+        self.write()
+            .by_id
+            .insert(id.to_string(), Arc::new(TokenKey::new(key)));
+        Ok(())
+    }
+
+    /// Inserts `key` and signs new tokens with it.
+    pub fn rotate(&self, id: &str, key: impl Into<Vec<u8>>) -> Result<(), CaptchaError> {
+        check_id(id)?;
+        let mut keys = self.write();
+        keys.by_id
+            .insert(id.to_string(), Arc::new(TokenKey::new(key)));
+        keys.active = id.to_string();
+        Ok(())
+    }
+
+    /// Stops accepting tokens of the key `id`. The active key can't be
+    /// removed; returns whether a key was.
+    pub fn remove(&self, id: &str) -> bool {
+        let mut keys = self.write();
+        keys.active != id && keys.by_id.remove(id).is_some()
+    }
+
+    /// Id of the key new tokens are signed with.
+    pub fn active_id(&self) -> String {
+        self.read().active.clone()
+    }
+
+    pub fn issue_token<C: CaptchaChallenge>(&self, challenge: &C) -> String {
+        let (id, key) = self.active();
+        format!("{id}.{}", key.issue_token(challenge))
+    }
+
+    /// See [`TokenKey::issue_session_token`].
+    pub fn issue_session_token<C: CaptchaChallenge>(&self, challenge: &C, session: &str) -> String {
+        let (id, key) = self.active();
+        format!("{id}.{}", key.issue_session_token(challenge, session))
+    }
+
+    /// [`VerifyOutcome::UnknownId`] as well for tokens of keys not in the
+    /// ring.
+    pub fn verify_token(&self, token: &str, answer: &str) -> VerifyOutcome {
+        self.with_key(token, |key, token| key.verify_token(token, answer))
+    }
+
+    pub fn verify_session_token(&self, token: &str, answer: &str, session: &str) -> VerifyOutcome {
+        self.with_key(token, |key, token| {
+            key.verify_session_token(token, answer, session)
+        })
+    }
+
+    /// See [`TokenKey::verify_token_once`].
+    pub fn verify_token_once(
+        &self,
+        token: &str,
+        answer: &str,
+        cache: &impl ReplayCache,
+    ) -> VerifyOutcome {
+        self.with_key(token, |key, token| {
+            key.verify_token_once(token, answer, cache)
+        })
+    }
+
+    pub fn verify_session_token_once(
+        &self,
+        token: &str,
+        answer: &str,
+        session: &str,
+        cache: &impl ReplayCache,
+    ) -> VerifyOutcome {
+        self.with_key(token, |key, token| {
+            key.verify_session_token_once(token, answer, session, cache)
+        })
+    }
+
+    /// See [`TokenKey::verify_token_timed`].
+    pub fn verify_token_timed(
+        &self,
+        token: &str,
+        answer: &str,
+        policy: &impl SolveTimePolicy,
+    ) -> VerifyOutcome {
+        self.with_key(token, |key, token| {
+            key.verify_token_timed(token, answer, policy)
+        })
+    }
+
+    pub fn verify_session_token_timed(
+        &self,
+        token: &str,
+        answer: &str,
+        session: &str,
+        policy: &impl SolveTimePolicy,
+    ) -> VerifyOutcome {
+        self.with_key(token, |key, token| {
+            key.verify_session_token_timed(token, answer, session, policy)
+        })
+    }
+
+    fn active(&self) -> (String, Arc<TokenKey>) {
+        let keys = self.read();
+        (keys.active.clone(), keys.by_id[&keys.active].clone())
+    }
+
+    /// Runs `verify` with the key named in front of `token` and the rest
+    /// of the token.
+    fn with_key(
+        &self,
+        token: &str,
+        verify: impl FnOnce(&TokenKey, &str) -> VerifyOutcome,
+    ) -> VerifyOutcome {
+        let Some((id, token)) = token.split_once('.') else {
+            return VerifyOutcome::UnknownId;
+        };
+        // The lock isn't held while verifying, which may hit a replay cache.
+        let Some(key) = self.read().by_id.get(id).cloned() else {
+            return VerifyOutcome::UnknownId;
+        };
+        verify(&key, token)
+    }
+
+    fn read(&self) -> std::sync::RwLockReadGuard<'_, Keys> {
+        self.keys.read().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn write(&self) -> std::sync::RwLockWriteGuard<'_, Keys> {
+        self.keys.write().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+fn check_id(id: &str) -> Result<(), CaptchaError> {
+    let valid = !id.is_empty()
+        && id
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+    if valid {
+        Ok(())
+    } else {
+        Err(CaptchaError::InvalidInput(format!(
+            "key id {id:?} must be non-empty ASCII letters, digits, - or _"
+        )))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::{
+        MemoryReplayCache, MinSolveTime,
+        test_util::{challenge, in_a_minute},
+    };
+
+    #[test]
+    fn accepts_the_previous_key_after_rotating() {
+        let ring = KeyRing::new("2025", "old").unwrap();
+        let old = ring.issue_token(&challenge("a", in_a_minute()));
+        ring.rotate("2026", "new").unwrap();
+        let new = ring.issue_token(&challenge("b", in_a_minute()));
+
+        assert_eq!(ring.active_id(), "2026");
+        assert!(old.starts_with("2025.") && new.starts_with("2026."));
+        assert_eq!(ring.verify_token(&old, "7"), VerifyOutcome::Correct);
+        assert_eq!(ring.verify_token(&new, "7"), VerifyOutcome::Correct);
+        assert_eq!(ring.verify_token(&old, "8"), VerifyOutcome::Wrong);
+    }
+
+    #[test]
+    fn rejects_tokens_under_another_key_id() {
+        let ring = KeyRing::new("2025", "old").unwrap();
+        ring.insert("2026", "new").unwrap();
+        let token = ring.issue_token(&challenge("a", in_a_minute()));
+        assert_eq!(
+            ring.verify_token(&token.replacen("2025", "2026", 1), "7"),
+            VerifyOutcome::UnknownId
+        );
+        assert_eq!(
+            ring.verify_token(&token.replacen("2025", "2024", 1), "7"),
+            VerifyOutcome::UnknownId
+        );
+        assert_eq!(ring.verify_token("2025", "7"), VerifyOutcome::UnknownId);
+    }
+
+    #[test]
+    fn removes_only_inactive_keys() {
+        let ring = KeyRing::new("2025", "old").unwrap();
+        let old = ring.issue_token(&challenge("a", in_a_minute()));
+        ring.rotate("2026", "new").unwrap();
+
+        assert!(!ring.remove("2026"));
+        assert!(ring.remove("2025"));
+        assert!(!ring.remove("2025"));
+        assert_eq!(ring.verify_token(&old, "7"), VerifyOutcome::UnknownId);
+    }
+
+    #[test]
+    fn verifies_session_tokens_once() {
+        let ring = KeyRing::new("2025", "old").unwrap();
+        let cache = MemoryReplayCache::new();
+        let token = ring.issue_session_token(&challenge("a", in_a_minute()), "session");
+        assert_eq!(
+            ring.verify_session_token(&token, "7", "other"),
+            VerifyOutcome::UnknownId
+        );
+        assert_eq!(
+            ring.verify_session_token_once(&token, "7", "session", &cache),
+            VerifyOutcome::Correct
+        );
+        assert_eq!(
+            ring.verify_session_token_once(&token, "7", "session", &cache),
+            VerifyOutcome::AlreadyUsed
+        );
+    }
+
+    #[test]
+    fn verifies_solve_times() {
+        let ring = KeyRing::new("2025", "old").unwrap();
+        let policy = MinSolveTime(Duration::from_secs(60));
+        let token = ring.issue_token(&challenge("a", in_a_minute()));
+        assert_eq!(
+            ring.verify_token_timed(&token, "7", &policy),
+            VerifyOutcome::TooFast
+        );
+        let token = ring.issue_session_token(&challenge("a", in_a_minute()), "session");
+        assert_eq!(
+            ring.verify_session_token_timed(&token, "7", "session", &policy),
+            VerifyOutcome::TooFast
+        );
+    }
+
+    #[test]
+    fn checks_key_ids() {
+        assert!(KeyRing::new("", "key").is_err());
+        assert!(KeyRing::new("a.b", "key").is_err());
+        let ring = KeyRing::new("a-b_1", "key").unwrap();
+        assert!(ring.rotate("c d", "key").is_err());
+        assert_eq!(ring.active_id(), "a-b_1");
+    }
+}
@@ -0,0 +1,291 @@
+use std::fmt::Write;
+
+use crate::{
+    Answer,
+    json::{self, JsonObject},
+};
+
+/// Where every glyph and noise element of a captcha was drawn.
+#[derive(Clone, Debug)]
+pub struct Layout {
+    /// All shown characters, which may be more than the answer. Redacted
+    /// like the answer since it gives it away.
+    pub text: Answer,
+    pub width: u32,
+    pub height: u32,
+    pub glyphs: Vec<GlyphBox>,
+    pub noise: Vec<NoisePath>,
+}
+
+/// Axis-aligned box around a drawn (rotated) glyph.
+#[derive(Clone, Copy, Debug)]
+pub struct GlyphBox {
+    pub char: char,
+    pub x: i32,
+    pub y: i32,
+    pub width: u32,
+    pub height: u32,
+    /// In radians.
+    pub rotation: f32,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub enum NoisePath {
+    Line {
+        from: (f32, f32),
+        to: (f32, f32),
+        color: [u8; 3],
+    },
+    /// A cubic curve whose two control points coincide.
+    Curve {
+        from: (f32, f32),
+        control: (f32, f32),
+        to: (f32, f32),
+        color: [u8; 3],
+    },
+    /// Bounds of a faint, clipped decoy character.
+    Fragment {
+        x: i32,
+        y: i32,
+        width: u32,
+        height: u32,
+    },
+}
+
+impl NoisePath {
+    /// Moves the path by `dx`, `dy` pixels, e.g. after cropping.
+    pub(crate) fn translate(&mut self, dx: f32, dy: f32) {
+        let shift = |(x, y): (f32, f32)| (x + dx, y + dy);
+        match self {
+            NoisePath::Line { from, to, .. } => {
+                *from = shift(*from);
+                *to = shift(*to);
+            }
+            NoisePath::Curve {
+                from, control, to, ..
+            } => {
+                *from = shift(*from);
+                *control = shift(*control);
+                *to = shift(*to);
+            }
+            NoisePath::Fragment { x, y, .. } => {
+                *x += dx as i32;
+                *y += dy as i32;
+            }
+        }
+    }
+}
+
+impl Layout {
+    pub fn to_json(&self) -> String {
+        let glyphs = json::array(self.glyphs.iter().map(|glyph| {
+            JsonObject::new()
+                .string("char", &glyph.char.to_string())
+                .number("x", glyph.x)
+                .number("y", glyph.y)
+                .number("width", glyph.width)
+                .number("height", glyph.height)
+                .number("rotation", glyph.rotation)
+                .finish()
+        }));
+        let noise = json::array(self.noise.iter().map(|path| {
+            match *path {
+                NoisePath::Line { from, to, color } => JsonObject::new()
+                    .string("kind", "line")
+                    .raw("from", &point(from))
+                    .raw("to", &point(to))
+                    .string("color", &hex_color(color))
+                    .finish(),
+                NoisePath::Curve {
+                    from,
+                    control,
+                    to,
+                    color,
+                } => JsonObject::new()
+                    .string("kind", "curve")
+                    .raw("from", &point(from))
+                    .raw("control", &point(control))
+                    .raw("to", &point(to))
+                    .string("color", &hex_color(color))
+                    .finish(),
+                NoisePath::Fragment {
+                    x,
+                    y,
+                    width,
+                    height,
+                } => JsonObject::new()
+                    .string("kind", "fragment")
+                    .number("x", x)
+                    .number("y", y)
+                    .number("width", width)
+                    .number("height", height)
+                    .finish(),
+            }
+        }));
+
+        JsonObject::new()
+            .string("text", self.text.expose())
+            .number("width", self.width)
+            .number("height", self.height)
+            .raw("glyphs", &glyphs)
+            .raw("noise", &noise)
+            .finish()
+    }
+
+    /// An SVG of the same size as the captcha outlining every glyph box and
+    /// noise path, meant to be laid over the image when debugging tests.
+    pub fn to_svg(&self) -> String {
+        let mut svg = format!(
+            r#"<svg xmlns="http://www.w3.org/2000/svg" width="{0}" height="{1}" viewBox="0 0 {0} {1}">"#,
+            self.width, self.height
+        );
+
+        for glyph in &self.glyphs {
+            let _ = write!(
+                svg,
+                r#"<rect x="{}" y="{}" width="{}" height="{}" fill="none" stroke="red"/>"#,
+                glyph.x, glyph.y, glyph.width, glyph.height
+            );
+            let _ = write!(
+                svg,
+                r#"<text x="{}" y="{}" font-size="10" fill="red">{}</text>"#,
+                glyph.x,
+                glyph.y + 10,
+                escape_xml(&glyph.char.to_string())
+            );
+        }
+
+        for path in &self.noise {
+            let (d, color) = match *path {
+                NoisePath::Fragment {
+                    x,
+                    y,
+                    width,
+                    height,
+                } => {
+                    let _ = write!(
+                        svg,
+                        r#"<rect x="{x}" y="{y}" width="{width}" height="{height}" fill="none" stroke="gray" stroke-dasharray="2"/>"#
+                    );
+                    continue;
+                }
+                NoisePath::Line { from, to, color } => {
+                    (format!("M{} {} L{} {}", from.0, from.1, to.0, to.1), color)
+                }
+                NoisePath::Curve {
+                    from,
+                    control,
+                    to,
+                    color,
+                } => (
+                    format!(
+                        "M{} {} C{} {} {} {} {} {}",
+                        from.0, from.1, control.0, control.1, control.0, control.1, to.0, to.1
+                    ),
+                    color,
+                ),
+            };
+            let _ = write!(
+                svg,
+                r#"<path d="{d}" fill="none" stroke="{}"/>"#,
+                hex_color(color)
+            );
+        }
+
+        svg.push_str("</svg>");
+        svg
+    }
+}
+
+fn point((x, y): (f32, f32)) -> String {
+    format!("[{x},{y}]")
+}
+
+fn hex_color(color: [u8; 3]) -> String {
+    format!("#{:02x}{:02x}{:02x}", color[0], color[1], color[2])
+}
+
+fn escape_xml(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Generator;
+
+    fn layout() -> Layout {
+        Layout {
+            text: Answer::new("\"a".into()),
+            width: 20,
+            height: 10,
+            glyphs: vec![GlyphBox {
+                char: '"',
+                x: 1,
+                y: -2,
+                width: 5,
+                height: 8,
+                rotation: 0.5,
+            }],
+            noise: vec![
+                NoisePath::Line {
+                    from: (0.0, 1.5),
+                    to: (20.0, 3.0),
+                    color: [255, 0, 16],
+                },
+                NoisePath::Fragment {
+                    x: 3,
+                    y: 4,
+                    width: 2,
+                    height: 2,
+                },
+            ],
+        }
+    }
+
+    #[test]
+    fn exports_json() {
+        assert_eq!(
+            layout().to_json(),
+            concat!(
+                r#"{"text":"\"a","width":20,"height":10,"#,
+                r#""glyphs":[{"char":"\"","x":1,"y":-2,"width":5,"height":8,"rotation":0.5}],"#,
+                r##""noise":[{"kind":"line","from":[0,1.5],"to":[20,3],"color":"#ff0010"},"##,
+                r#"{"kind":"fragment","x":3,"y":4,"width":2,"height":2}]}"#,
+            )
+        );
+    }
+
+    #[test]
+    fn outlines_everything_in_svg() {
+        let svg = layout().to_svg();
+        assert!(
+            svg.starts_with(r#"<svg xmlns="http://www.w3.org/2000/svg" width="20" height="10""#)
+        );
+        assert_eq!(svg.matches("<rect").count(), 2);
+        assert!(svg.contains(r#"<text x="1" y="8" font-size="10" fill="red">&quot;</text>"#));
+        assert!(svg.contains(r##"<path d="M0 1.5 L20 3" fill="none" stroke="#ff0010"/>"##));
+    }
+
+    #[test]
+    fn records_every_glyph_and_noise_path() {
+        let generator = Generator::new(Default::default()).unwrap();
+        let (captcha, layout) = generator.generate_inner().unwrap();
+        let config = generator.config();
+        assert_eq!(layout.text.expose(), captcha.text.expose());
+        let chars: String = layout.glyphs.iter().map(|glyph| glyph.char).collect();
+        assert_eq!(chars, captcha.text.expose());
+        assert_eq!((layout.width, layout.height), (config.width, config.height));
+        assert_eq!(layout.noise.len(), 7);
+    }
+
+    #[test]
+    fn redacts_the_text_in_debug_output() {
+        let debug = format!("{:?}", layout());
+        assert!(debug.starts_with("Layout { text: Answer(<redacted>), width: 20"));
+        assert!(!debug.contains("\"a"));
+    }
+}
@@ -1,266 +1,493 @@
-use std::{f32::consts::PI, io::Cursor};
-
-use fontdue::Font;
-use image::{Rgb, Rgba, RgbaImage, imageops};
-use imageproc::geometric_transformations::Interpolation;
-use rand::{Rng, rng, seq::IndexedRandom};
-use raqote::{Color, DrawOptions, DrawTarget, PathBuilder, SolidSource, Source, StrokeStyle};
-
+use std::time::Duration;
+
+use outline::LoadedFont;
+
+mod animation;
+mod answer;
+mod arithmetic;
+mod atlas;
+mod background;
+pub mod bench;
+mod blend;
+#[cfg(feature = "calibrate")]
+mod calibrate;
+mod captcha;
+mod chain;
+mod challenge;
+mod charset;
+mod chat;
+mod color;
+mod composite;
+mod dataset;
+mod decoy;
+#[cfg(feature = "degrade")]
+mod degrade;
+mod dither;
+mod effect;
+#[cfg(feature = "email")]
+mod email;
+mod encode;
+mod error;
+mod exclusion;
+mod fill;
+mod generator;
+mod glyph;
+#[cfg(feature = "gpu")]
+mod gpu;
+mod grid;
+mod hash;
+mod headers;
+mod hint;
+mod image_cache;
+mod instruction;
+mod json;
+mod keyring;
+mod layout;
+mod limit;
+mod mode;
+mod numerals;
+mod outline;
+mod overrides;
+#[cfg(feature = "pdf")]
+mod pdf;
+mod pipeline;
+mod png_chunks;
+mod pool;
+mod presets;
+mod preview;
+mod quota;
+mod render;
+mod report;
+mod script;
+#[cfg(feature = "snapshot")]
+mod snapshot;
+mod source;
+#[cfg(feature = "stego")]
+mod stego;
+mod store;
+mod stroke;
+#[cfg(feature = "svg")]
+mod svg;
+mod sweep;
+mod terminal;
+#[cfg(test)]
+mod test_util;
+mod text;
+mod theme;
+mod token;
+mod typeface;
+mod variant;
+mod verify;
+
+pub use animation::{Animation, FallbackFrame, Ripple};
+pub use answer::{Answer, AnswerFormat};
+pub use arithmetic::{ArithmeticChallenge, ArithmeticConfig};
+pub use background::Background;
+pub use blend::BlendMode;
+#[cfg(feature = "calibrate")]
+pub use calibrate::Calibration;
+pub use captcha::Captcha;
+pub use chain::{ChainProgress, ChallengeChain};
+pub use challenge::CaptchaChallenge;
+pub use charset::{CharWeights, TextStyle};
+pub use chat::ChatMessage;
+pub use color::{Color, ParseColorError};
+pub use dataset::{DatasetOptions, RendererBackend};
+pub use decoy::DecoyStyle;
+#[cfg(feature = "degrade")]
+pub use degrade::{Degradation, degrade};
+pub use effect::Effect;
+#[cfg(feature = "email")]
+pub use email::EmailImage;
+pub use encode::Format;
+pub use error::CaptchaError;
+pub use fill::{ColorScheme, GradientDirection};
+#[cfg(feature = "embedded-font")]
+pub use generator::default_generator;
+pub use generator::{Generator, Iter};
+pub use glyph::{FontVariation, GlyphStyle};
+pub use grid::{Cell, GridCaptcha, GridConfig, LabeledImage, selection_answer};
+#[cfg(feature = "argon2")]
+pub use hash::hash_answer_argon2;
+pub use hash::{hash_answer, verify_hashed};
+pub use headers::{cache_busting_url, response_headers};
+pub use hint::InputHint;
+pub use image_cache::{CachedImage, ImageCache};
+pub use instruction::{Instruction, Question, default_instruction_text};
+pub use keyring::KeyRing;
+pub use layout::{GlyphBox, Layout, NoisePath};
+pub use mode::{Mode, SizeQuestion};
+pub use numerals::Numerals;
+pub use outline::FallbackFont;
+pub use overrides::{Difficulty, OverrideOptions};
+pub use pipeline::{Stage, StageKind, StageTiming};
+pub use pool::CaptchaPool;
+pub use presets::Preset;
+pub use preview::Preview;
+pub use quota::IssuanceQuota;
+pub use report::DifficultyReport;
+pub use script::Script;
+#[cfg(feature = "snapshot")]
+pub use snapshot::Snapshot;
+pub use source::RngSource;
+#[cfg(feature = "stego")]
+pub use stego::KioskKey;
+pub use store::{CaptchaStore, MemoryStore, StoredCaptcha};
+pub use stroke::LineStyle;
+pub use sweep::Sweeper;
+pub use text::{CharsetText, SharedTextProvider, TextProvider, WordList};
+pub use theme::Theme;
+pub use token::{MemoryReplayCache, ReplayCache, TokenKey};
+pub use typeface::Typeface;
+pub use variant::{Variant, Variants};
+pub use verify::{MinSolveTime, SolveTimePolicy, VerifyOutcome};
+
+#[derive(Clone, Debug)]
 pub struct Config {
     pub length: u32,
     pub width: u32,
     pub height: u32,
-    pub color: [u8; 3],
-    pub background_color: [u8; 3],
+    /// Crops the image to the text plus this many pixels on each side,
+    /// within `width` × `height`, so it hugs the text. The captcha's
+    /// `width` and `height` give the size actually rendered.
+    pub crop_margin: Option<u32>,
+    /// Text color. Its alpha makes glyphs translucent; in
+    /// [`Mode::Colors`] only the alpha is used.
+    pub color: Color,
+    pub color_scheme: ColorScheme,
+    /// A translucent background only shows in formats with alpha.
+    pub background_color: Color,
+    pub background: Background,
+    /// How long a generated captcha stays valid. `None` never expires.
+    pub expires_in: Option<Duration>,
+    pub mode: Mode,
+    /// Phrases the instruction shown for non-plain modes; replace it to
+    /// localize the rendered text.
+    pub instruction_text: fn(&Question) -> String,
+    pub format: Format,
+    /// Byte budget of the encoded image. Larger PNGs are re-encoded with ever
+    /// smaller palettes and JPEG quality is lowered; other formats are left
+    /// as they are. When nothing fits the smallest encoding is used.
+    pub max_bytes: Option<usize>,
+    /// Ceiling on the pixels rendered, width × height × animation frames,
+    /// so untrusted dimensions can't cause huge allocations. Every glyph is
+    /// held to it as well while it is styled, and [`GlyphStyle`] bounds
+    /// how far glyphs grow beyond the font size, so working memory stays
+    /// within a small multiple of this many pixels. `None` disables the
+    /// check.
+    pub max_pixels: Option<u64>,
+    /// Fills in [`Captcha::image_sha256`].
+    pub hash_image: bool,
+    pub line_style: LineStyle,
+    pub glyph_style: GlyphStyle,
+    /// Pixels added to the even gaps between glyphs; negative values push
+    /// glyphs into each other. The text stays centered.
+    pub glyph_spacing: f32,
+    /// Largest fraction, from 0 to 1, of the smaller glyph's ink two
+    /// neighbouring glyphs may share. Glyphs overlapping more are moved
+    /// apart, so tight spacing and rotation can't merge them beyond
+    /// reading; 1 allows any overlap.
+    pub max_glyph_overlap: f32,
+    pub typeface: Typeface,
+    /// Characters answers are drawn from instead of the typeface's own
+    /// set. Dot matrix glyphs exist for digits and ASCII letters, seven
+    /// segments only for digits.
+    pub charset: Option<String>,
+    pub text_style: TextStyle,
+    /// Makes answer texts instead of `text_style`, `charset` and
+    /// `char_weights`. Glyphs are sized to fit each text rather than for
+    /// `length` characters.
+    pub text_provider: Option<SharedTextProvider>,
+    pub char_weights: CharWeights,
+    /// How digits are drawn, see [`Numerals`].
+    pub numerals: Numerals,
+    pub decoys: DecoyStyle,
+    /// Noise, warps and filters drawn over the text, in order. The
+    /// default draws five lines and two curves.
+    pub pipeline: Vec<Stage>,
+    /// When not empty, every captcha draws its noise with one of these
+    /// pipelines, picked at random, instead of `pipeline`, so a denoiser
+    /// can't learn a single kind of noise.
+    pub pipeline_choices: Vec<Vec<Stage>>,
+    pub animation: Option<Animation>,
+    /// Picks the answer text and which part of it is asked for.
+    pub answer_rng: RngSource,
+    /// Drives everything else: backgrounds, placement, distortion and
+    /// noise. Seed it to render reproducibly.
+    pub visual_rng: RngSource,
 }
 
+/// 16 megapixels, far beyond any sensible captcha.
+pub(crate) const DEFAULT_MAX_PIXELS: u64 = 1 << 24;
+
 impl Default for Config {
     fn default() -> Self {
         Self {
             length: 4,
             width: 240,
             height: 80,
-            color: [0, 0, 0],
-            background_color: [255, 255, 255],
+            color: Color::BLACK,
+            color_scheme: ColorScheme::Solid,
+            background_color: Color::WHITE,
+            background: Background::Solid,
+            expires_in: None,
+            mode: Mode::Plain,
+            instruction_text: default_instruction_text,
+            format: Format::Png,
+            max_bytes: None,
+            crop_margin: None,
+            max_pixels: Some(DEFAULT_MAX_PIXELS),
+            hash_image: false,
+            line_style: LineStyle::default(),
+            glyph_style: GlyphStyle::default(),
+            glyph_spacing: 0.0,
+            max_glyph_overlap: 1.0,
+            typeface: Typeface::Font,
+            charset: None,
+            text_style: TextStyle::Random,
+            text_provider: None,
+            char_weights: CharWeights::default(),
+            numerals: Numerals::Ascii,
+            decoys: DecoyStyle::default(),
+            pipeline: pipeline::default_pipeline(),
+            pipeline_choices: Vec::new(),
+            animation: None,
+            answer_rng: RngSource::Os,
+            visual_rng: RngSource::Thread,
         }
     }
 }
 
-impl Config {
-    pub fn generate(&self) -> Result<(String, Vec<u8>), Box<dyn std::error::Error>> {
-        let charset: Vec<char> = "23456789ABCDEFGHJKLMNPQRSTUVWXYZabcdefghijkmnpqrstuvwxyz"
-            .chars()
-            .collect();
-        let mut rng = rng();
-        let captcha_text: String = (0..self.length)
-            .map(|_| *charset.choose(&mut rng).unwrap())
-            .collect();
-
-        let font_data = std::fs::read("Arial.ttf")?;
-        let font = Font::from_bytes(font_data, fontdue::FontSettings::default())?;
-
-        let font_size = (self.width / self.length).min(self.height);
-
-        let width = self.width;
-        let height = self.height;
+/// Sizes stay small so fuzzing explores configs quickly.
+#[cfg(feature = "arbitrary")]
+impl<'a> arbitrary::Arbitrary<'a> for Config {
+    fn arbitrary(u: &mut arbitrary::Unstructured<'a>) -> arbitrary::Result<Self> {
+        Ok(Self {
+            length: u.int_in_range(0..=12)?,
+            width: u.int_in_range(0..=480)?,
+            height: u.int_in_range(0..=160)?,
+            color: u.arbitrary()?,
+            color_scheme: u.arbitrary()?,
+            background_color: u.arbitrary()?,
+            background: u.arbitrary()?,
+            expires_in: u.arbitrary()?,
+            mode: u.arbitrary()?,
+            instruction_text: default_instruction_text,
+            format: u.arbitrary()?,
+            max_bytes: u.arbitrary()?,
+            crop_margin: u.arbitrary()?,
+            max_pixels: u.arbitrary()?,
+            hash_image: u.arbitrary()?,
+            line_style: u.arbitrary()?,
+            glyph_style: u.arbitrary()?,
+            glyph_spacing: u.arbitrary()?,
+            max_glyph_overlap: u.arbitrary()?,
+            typeface: u.arbitrary()?,
+            charset: u.arbitrary()?,
+            text_style: u.arbitrary()?,
+            text_provider: None,
+            char_weights: u.arbitrary()?,
+            numerals: u.arbitrary()?,
+            decoys: u.arbitrary()?,
+            pipeline: u.arbitrary()?,
+            pipeline_choices: u.arbitrary()?,
+            animation: if u.arbitrary()? {
+                Some(Animation {
+                    frames: u.int_in_range(0..=3)?,
+                    frame_delay: u.arbitrary()?,
+                    ripple: u.arbitrary()?,
+                    fallback: u.arbitrary()?,
+                })
+            } else {
+                None
+            },
+            answer_rng: u.arbitrary()?,
+            visual_rng: u.arbitrary()?,
+        })
+    }
+}
 
-        let background_color = Rgba([
-            self.background_color[0],
-            self.background_color[1],
-            self.background_color[2],
-            255,
-        ]);
+impl Config {
+    /// Characters answers are drawn from.
+    pub fn charset(&self) -> &str {
+        self.charset
+            .as_deref()
+            .unwrap_or_else(|| self.typeface.charset())
+    }
 
-        let mut img = RgbaImage::new(width, height);
-        for pixel in img.pixels_mut() {
-            *pixel = background_color
+    /// Fails with [`CaptchaError::TooLarge`] past [`Config::max_pixels`].
+    pub(crate) fn check_size(&self) -> Result<(), CaptchaError> {
+        let frames = self
+            .animation
+            .map_or(1, |animation| animation.frames.max(1));
+        let pixels = self.width as u64 * self.height as u64 * frames as u64;
+        match self.max_pixels {
+            Some(max) if pixels > max => Err(CaptchaError::TooLarge { pixels, max }),
+            _ => Ok(()),
         }
+    }
 
-        let rasterized_fonts = captcha_text
-            .chars()
-            .map(|c| font.rasterize(c, font_size as f32))
-            .collect::<Vec<_>>();
-
-        let fonts_width: f32 = rasterized_fonts.iter().map(|x| x.0.advance_width).sum();
-        let spacing = (self.width as f32 - fonts_width) / (self.length as f32 + 1.0);
+    /// Size glyphs are rasterized at: the widest glyph of the charset, made
+    /// wider by bold, slant and stretching, fits an even share of the
+    /// width, and no glyph is taller than the image.
+    pub(crate) fn font_size(&self, font: Option<&LoadedFont>) -> f32 {
+        self.fitted_font_size(font, self.charset(), self.length)
+    }
 
-        let mut x_offset = spacing; // 起始 X 位置
+    /// Like [`Config::font_size`], but fitting the glyphs of `text`, e.g.
+    /// from [`Config::text_provider`], rather than any text of the charset.
+    pub(crate) fn text_font_size(&self, font: Option<&LoadedFont>, text: &str) -> f32 {
+        self.fitted_font_size(font, text, text.chars().count() as u32)
+    }
 
-        for (metrics, bitmap) in rasterized_fonts {
-            let mut rgba_data = Vec::with_capacity((metrics.width * metrics.height * 4) as usize);
-            for alpha in bitmap {
-                rgba_data.push(self.color[0]);
-                rgba_data.push(self.color[1]);
-                rgba_data.push(self.color[2]);
-                rgba_data.push(alpha);
+    fn fitted_font_size(&self, font: Option<&LoadedFont>, chars: &str, length: u32) -> f32 {
+        // Fonts scale linearly, so measuring at any size will do.
+        const MEASURE_SIZE: f32 = 100.0;
+
+        let slot = self.width as f32 / length.max(1) as f32;
+        let fitted = match (&self.typeface, font) {
+            (Typeface::Font, Some(font)) => {
+                let widest = chars
+                    .chars()
+                    .map(|c| {
+                        let metrics = font
+                            .resolve(self.glyph_char(c))
+                            .1
+                            .metrics(self.glyph_char(c), MEASURE_SIZE);
+                        metrics.advance_width.max(metrics.width as f32) / MEASURE_SIZE
+                    })
+                    .fold(0.0, f32::max);
+                let style = &self.glyph_style;
+                let slant = style.min_slant.abs().max(style.max_slant.abs());
+                let bold = 2.0 * style.max_bold as f32;
+                let stretch = style
+                    .variation
+                    .map_or(1.0, |variation| variation.max_stretch());
+                (slot - bold).max(1.0)
+                    / (widest * style.max_scale_x.clamp(1.0, 2.0) * stretch + slant).max(1.0)
             }
+            // Dot matrix and seven-segment glyphs are narrower than tall.
+            _ => slot,
+        };
+        let stretch = self.glyph_style.max_scale_y.clamp(1.0, 2.0);
+        fitted.min(self.height as f32 / stretch).floor()
+    }
 
-            let font_img =
-                RgbaImage::from_raw(metrics.width as u32, metrics.height as u32, rgba_data)
-                    .unwrap();
-
-            let rotate_angle = (PI / 8.0) * rng.random_range(-1.0..1.0);
-
-            let (rotated_width, rotated_height) =
-                rotated_rect_size(metrics.width as f32, metrics.height as f32, rotate_angle);
-
-            let mut expanded = RgbaImage::new(rotated_width as u32, rotated_height as u32);
-            imageops::overlay(
-                &mut expanded,
-                &font_img,
-                ((rotated_width as u32 - font_img.width()) / 2) as i64,
-                ((rotated_height as u32 - font_img.height()) / 2) as i64,
-            );
-
-            let rotated = imageproc::geometric_transformations::rotate_about_center(
-                &mut expanded,
-                rotate_angle,
-                Interpolation::Bilinear,
-                Rgba([255, 255, 255, 255]),
-            );
-
-            let px = (x_offset as i64) - (rotated_width as i64 - font_img.width() as i64) / 2;
-            let py = ((self.height as f32 - rotated.height() as f32) / 2.0) as i64;
-            imageops::overlay(&mut img, &rotated, px, py);
-
-            x_offset += (metrics.advance_width as f32) as f32 + spacing as f32;
+    /// Fails with [`CaptchaError::FontMissingGlyph`] when `font`, or the
+    /// font-free typeface, can't draw the whole charset.
+    pub(crate) fn check_glyphs(&self, font: Option<&LoadedFont>) -> Result<(), CaptchaError> {
+        let missing = self.missing_glyphs(font, self.charset());
+        if missing.is_empty() {
+            Ok(())
+        } else {
+            Err(CaptchaError::FontMissingGlyph(missing))
         }
+    }
 
-        // imageproc::noise::gaussian_noise_mut(&mut img, 0.0, 50.0, 50);
-
-        for _ in 0..5 {
-            draw_line(&mut img);
+    /// Characters of `text` that `font`, or the font-free typeface, can't
+    /// draw, sorted and without duplicates.
+    pub(crate) fn missing_glyphs(&self, font: Option<&LoadedFont>, text: &str) -> Vec<char> {
+        let chars = text.chars().map(|c| self.glyph_char(c));
+        match (&self.typeface, font) {
+            (Typeface::Font, Some(font)) => font.missing(chars),
+            (typeface, _) => typeface.missing(chars),
         }
+    }
 
-        for _ in 0..2 {
-            draw_cubic_line(&mut img);
+    /// The character drawn for answer character `c`.
+    pub(crate) fn glyph_char(&self, c: char) -> char {
+        match self.typeface {
+            Typeface::Font => self.numerals.localize(c),
+            _ => c,
         }
-
-        let mut buffer = Cursor::new(Vec::new());
-        img.write_to(&mut buffer, image::ImageFormat::Png).unwrap();
-
-        Ok((captcha_text, buffer.into_inner()))
     }
 
-    #[cfg(feature = "base64")]
-    pub fn generate_base64(&self) -> Result<(String, String), Box<dyn std::error::Error>> {
-        use base64::{Engine, engine::general_purpose};
-
-        let (text, buffer) = self.generate()?;
-
-        let base64_string = general_purpose::STANDARD.encode(buffer);
-
-        Ok((text, base64_string))
+    /// A lower bound in bits on how hard answers are to guess, to check
+    /// deployments against a minimum. Accounts for the length, charset and
+    /// its weights, the text style, case-insensitive matching, repeated
+    /// characters being allowed and modes asking for only part of the
+    /// text. Visual difficulty and [`Config::text_provider`] aren't
+    /// included.
+    pub fn entropy_bits(&self) -> f64 {
+        let mut bits =
+            self.text_style
+                .position_bits(&self.char_weights, self.charset(), self.length);
+        // Modes asking for part of the text ask for the easiest part at
+        // worst.
+        bits.sort_by(f64::total_cmp);
+        bits.truncate(self.mode.min_answer_length(self.length) as usize);
+        bits.iter().sum()
     }
-}
 
-fn rotated_rect_size(width: f32, height: f32, angle: f32) -> (f32, f32) {
-    let cos_a = angle.cos();
-    let sin_a = angle.sin();
-
-    // 原图的四个角相对于中心的偏移
-    let hw = width / 2.0;
-    let hh = height / 2.0;
-
-    // 四个角点
-    let corners = [(-hw, -hh), (hw, -hh), (hw, hh), (-hw, hh)];
-
-    let mut min_x = f32::INFINITY;
-    let mut max_x = f32::NEG_INFINITY;
-    let mut min_y = f32::INFINITY;
-    let mut max_y = f32::NEG_INFINITY;
-
-    for &(x, y) in &corners {
-        // 旋转每个角点
-        let rx = x * cos_a - y * sin_a;
-        let ry = x * sin_a + y * cos_a;
-        min_x = min_x.min(rx);
-        max_x = max_x.max(rx);
-        min_y = min_y.min(ry);
-        max_y = max_y.max(ry);
+    /// A regular expression, anchored with `^` and `$`, matching every
+    /// answer this config can ask for, e.g. `^[0-9A-Za-z]{4}$`, so client
+    /// side validation can follow the server's settings. Letters are
+    /// listed in both cases, since answers match case-insensitively, and
+    /// digits both in ASCII and in the configured [`Numerals`] system.
+    /// Providers' texts can't be described, so with
+    /// [`Config::text_provider`] any non-empty answer matches.
+    pub fn answer_pattern(&self) -> String {
+        if self.text_provider.is_some() {
+            return "^.+$".into();
+        }
+        let usable = self.char_weights.usable(self.charset());
+        let chars = usable
+            .chars()
+            .filter(|c| self.text_style != TextStyle::Pronounceable || c.is_alphabetic())
+            .flat_map(|c| {
+                [
+                    c.to_ascii_lowercase(),
+                    c.to_ascii_uppercase(),
+                    self.numerals.localize(c),
+                ]
+            });
+        let class = hint::character_class(chars);
+        let (min, max) = (
+            self.mode.min_answer_length(self.length),
+            self.mode.max_answer_length(self.length),
+        );
+        match min == max {
+            true => format!("^{class}{{{min}}}$"),
+            false => format!("^{class}{{{min},{max}}}$"),
+        }
     }
 
-    let rotated_width = max_x - min_x;
-    let rotated_height = max_y - min_y;
-
-    (rotated_width, rotated_height)
-}
-
-fn merge(img: &mut RgbaImage, dt: DrawTarget) {
-    let width = dt.width();
-    let height = dt.height();
-
-    let mut rgba_data = Vec::with_capacity((width * height * 4) as usize);
-
-    for color in dt.into_vec() {
-        let a = (color >> 24 & 0xFF) as u8;
-        let r = (color >> 16 & 0xFF) as u8;
-        let g = (color >> 8 & 0xFF) as u8;
-        let b = (color >> 0 & 0xFF) as u8;
-        rgba_data.push(r);
-        rgba_data.push(g);
-        rgba_data.push(b);
-        rgba_data.push(a);
+    /// Renders a single captcha. Use a [`Generator`] when rendering many, so
+    /// the font is only loaded once. Before 0.2 this returned the answer and
+    /// the image as a tuple, see `CHANGELOG.md`.
+    pub fn generate(&self) -> Result<Captcha, CaptchaError> {
+        Generator::new(self.clone())?.generate()
     }
 
-    let font_img = RgbaImage::from_raw(width as u32, height as u32, rgba_data).unwrap();
-
-    imageops::overlay(img, &font_img, 0, 0);
-}
-
-fn random_color() -> Rgb<u8> {
-    let mut rng = rand::rng();
-
-    let r = rng.random_range(0..=255);
-    let g = rng.random_range(0..=255);
-    let b = rng.random_range(0..=255);
-
-    Rgb([r, g, b])
-}
-
-fn draw_line(img: &mut RgbaImage) {
-    let mut rng = rand::rng();
-    let width = img.width();
-    let height = img.height();
-
-    let x1 = rng.random_range(0..width);
-    let y1 = rng.random_range(0..height);
-    let x2 = rng.random_range(0..width);
-    let y2 = rng.random_range(0..height);
-
-    let mut dt = DrawTarget::new(width.try_into().unwrap(), height.try_into().unwrap());
-    let mut pb = PathBuilder::new();
-
-    pb.move_to(x1 as f32, y1 as f32);
-    pb.line_to(x2 as f32, y2 as f32);
-    let path = pb.finish();
-
-    let color = random_color();
-
-    dt.stroke(
-        &path,
-        &Source::Solid(SolidSource::from(Color::new(
-            255, color.0[0], color.0[1], color.0[2],
-        ))),
-        &StrokeStyle::default(),
-        &DrawOptions::new(),
-    );
-
-    merge(img, dt);
-}
-
-fn draw_cubic_line(img: &mut RgbaImage) {
-    let mut rng = rand::rng();
-    let width = img.width();
-    let height = img.height();
-
-    let x1 = 0;
-    let y1 = rng.random_range(0..height);
-    let x2 = width;
-    let y2 = rng.random_range(0..height);
-
-    let cx = rng.random_range((width / 4)..(width / 4 * 3));
-    let cy = rng.random_range(0..height);
+    /// Like [`Config::generate`], also returning what was drawn where, so
+    /// automated UI tests can check a captcha without OCR. Never send the
+    /// layout to clients: it gives the answer away.
+    #[cfg(feature = "ground-truth")]
+    pub fn generate_with_layout(&self) -> Result<(Captcha, Layout), CaptchaError> {
+        Generator::new(self.clone())?.generate_inner()
+    }
 
-    let mut dt = DrawTarget::new(width.try_into().unwrap(), height.try_into().unwrap());
-    let mut pb = PathBuilder::new();
+    /// Renders `text` for design-time previews, e.g. while tuning a config
+    /// in an admin UI. Random choices use a fixed seed, so the same config
+    /// and text always look the same. Never serve previews as captchas:
+    /// the text is chosen by the caller and the result is predictable.
+    pub fn preview(&self, text: &str) -> Result<Preview, CaptchaError> {
+        let config = Config {
+            answer_rng: RngSource::Seeded(0),
+            visual_rng: RngSource::Seeded(0),
+            ..self.clone()
+        };
+        Generator::new(config)?.preview(text)
+    }
 
-    pb.move_to(x1 as f32, y1 as f32);
-    pb.cubic_to(
-        cx as f32, cy as f32, cx as f32, cy as f32, x2 as f32, y2 as f32,
-    );
-    let path = pb.finish();
+    #[cfg(feature = "base64")]
+    pub fn generate_base64(&self) -> Result<(Answer, String), CaptchaError> {
+        use base64::{Engine, engine::general_purpose};
 
-    let color = random_color();
+        let captcha = self.generate()?;
 
-    dt.stroke(
-        &path,
-        &Source::Solid(SolidSource::from(Color::new(
-            128, color.0[0], color.0[1], color.0[2],
-        ))),
-        &StrokeStyle::default(),
-        &DrawOptions::new(),
-    );
+        let base64_string = general_purpose::STANDARD.encode(captcha.image);
 
-    merge(img, dt);
+        Ok((captcha.text, base64_string))
+    }
 }
@@ -0,0 +1,84 @@
+use std::{
+    sync::{Condvar, Mutex, PoisonError},
+    time::{Duration, Instant},
+};
+
+/// A counting semaphore whose waiters give up after a timeout.
+pub(crate) struct Limiter {
+    max: usize,
+    timeout: Duration,
+    running: Mutex<usize>,
+    released: Condvar,
+}
+
+pub(crate) struct Permit<'a> {
+    limiter: &'a Limiter,
+}
+
+impl Limiter {
+    pub fn new(max: usize, timeout: Duration) -> Self {
+        Self {
+            max: max.max(1),
+            timeout,
+            running: Mutex::new(0),
+            released: Condvar::new(),
+        }
+    }
+
+    /// Waits for a free slot, or returns `None` once the timeout passed.
+    pub fn acquire(&self) -> Option<Permit<'_>> {
+        let deadline = Instant::now() + self.timeout;
+        let mut running = self.running.lock().unwrap_or_else(PoisonError::into_inner);
+        while *running >= self.max {
+            let remaining = deadline.checked_duration_since(Instant::now())?;
+            running = self
+                .released
+                .wait_timeout(running, remaining)
+                .unwrap_or_else(PoisonError::into_inner)
+                .0;
+        }
+        *running += 1;
+        Some(Permit { limiter: self })
+    }
+}
+
+impl Drop for Permit<'_> {
+    fn drop(&mut self) {
+        *self
+            .limiter
+            .running
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner) -= 1;
+        self.limiter.released.notify_one();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+
+    use super::*;
+
+    #[test]
+    fn times_out_past_the_limit() {
+        let limiter = Limiter::new(2, Duration::from_millis(10));
+        let first = limiter.acquire().unwrap();
+        let _second = limiter.acquire().unwrap();
+        assert!(limiter.acquire().is_none());
+
+        drop(first);
+        assert!(limiter.acquire().is_some());
+    }
+
+    #[test]
+    fn hands_released_slots_to_waiters() {
+        let limiter = Limiter::new(1, Duration::from_secs(10));
+        thread::scope(|scope| {
+            let permit = limiter.acquire().unwrap();
+            let waiter = scope.spawn(|| limiter.acquire().is_some());
+            thread::sleep(Duration::from_millis(20));
+            drop(permit);
+            assert!(waiter.join().unwrap());
+        });
+    }
+}
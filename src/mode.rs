@@ -0,0 +1,312 @@
+use rand::{
+    Rng,
+    seq::{SliceRandom, index},
+};
+
+use crate::{Answer, Question};
+
+/// Colors used by [`Mode::Colors`], picked to stay distinguishable from each
+/// other on light backgrounds.
+const PALETTE: [(&str, [u8; 3]); 3] = [
+    ("red", [214, 39, 40]),
+    ("green", [44, 160, 44]),
+    ("blue", [31, 119, 180]),
+];
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum Mode {
+    /// The answer is every character shown.
+    #[default]
+    Plain,
+    /// Only `count` randomly picked characters are asked for, e.g.
+    /// "Enter characters 2, 4 and 5".
+    Positions { count: u32 },
+    /// Characters are drawn in `count` (2 or 3) colors and only those of
+    /// one color are asked for, e.g. "Type the red characters".
+    Colors { count: u32 },
+    /// Characters are drawn at clearly different sizes and the answer
+    /// depends on them, e.g. "Type the largest character".
+    Sizes(SizeQuestion),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum SizeQuestion {
+    Largest,
+    Smallest,
+    LargestToSmallest,
+    SmallestToLargest,
+}
+
+/// What a mode decided for one captcha text.
+pub(crate) struct Plan {
+    pub answer: Answer,
+    pub question: Option<Question>,
+    /// Fill color of every shown character.
+    pub colors: Vec<[u8; 3]>,
+    /// Font size factor of every shown character.
+    pub scales: Vec<f32>,
+}
+
+impl Mode {
+    /// The fewest characters an answer to a `length` long text can have.
+    pub(crate) fn min_answer_length(&self, length: u32) -> u32 {
+        match *self {
+            Mode::Plain => length,
+            Mode::Positions { count } => count.max(1).min(length),
+            Mode::Colors { .. } => length.min(1),
+            Mode::Sizes(SizeQuestion::Largest | SizeQuestion::Smallest) => length.min(1),
+            Mode::Sizes(_) => length,
+        }
+    }
+
+    /// The most characters an answer to a `length` long text can have.
+    pub(crate) fn max_answer_length(&self, length: u32) -> u32 {
+        match *self {
+            Mode::Plain => length,
+            Mode::Positions { count } => count.max(1).min(length),
+            // Never the whole text, unless it is a single character.
+            Mode::Colors { .. } => length.saturating_sub(1).max(length.min(1)),
+            Mode::Sizes(SizeQuestion::Largest | SizeQuestion::Smallest) => length.min(1),
+            Mode::Sizes(_) => length,
+        }
+    }
+
+    pub(crate) fn plan(&self, text: &str, color: [u8; 3], rng: &mut impl Rng) -> Plan {
+        let chars: Vec<char> = text.chars().collect();
+
+        let plan = match *self {
+            Mode::Plain => Plan {
+                answer: Answer::new(text.to_string()),
+                question: None,
+                colors: vec![color; chars.len()],
+                scales: vec![1.0; chars.len()],
+            },
+            Mode::Positions { count } => {
+                let count = (count as usize).clamp(1, chars.len());
+                let mut positions = index::sample(rng, chars.len(), count).into_vec();
+                positions.sort_unstable();
+
+                Plan {
+                    answer: Answer::new(positions.iter().map(|&i| chars[i]).collect()),
+                    question: Some(Question::Positions(
+                        positions.into_iter().map(|i| i + 1).collect(),
+                    )),
+                    colors: vec![color; chars.len()],
+                    scales: vec![1.0; chars.len()],
+                }
+            }
+            Mode::Colors { count } => {
+                let count = (count as usize).clamp(2, PALETTE.len());
+                let mut assigned: Vec<usize> = (0..chars.len())
+                    .map(|_| rng.random_range(0..count))
+                    .collect();
+
+                let target = assigned[rng.random_range(0..chars.len())];
+                // Make sure the answer is never the whole text.
+                if chars.len() > 1 && assigned.iter().all(|&c| c == target) {
+                    let i = rng.random_range(0..chars.len());
+                    assigned[i] = (target + rng.random_range(1..count)) % count;
+                }
+
+                let positions: Vec<usize> = (0..chars.len())
+                    .filter(|&i| assigned[i] == target)
+                    .collect();
+                let (name, target_color) = PALETTE[target];
+
+                Plan {
+                    answer: Answer::new(positions.iter().map(|&i| chars[i]).collect()),
+                    question: Some(Question::Color {
+                        name: name.to_string(),
+                        color: target_color,
+                        positions: positions.into_iter().map(|i| i + 1).collect(),
+                    }),
+                    colors: assigned.into_iter().map(|c| PALETTE[c].1).collect(),
+                    scales: vec![1.0; chars.len()],
+                }
+            }
+            Mode::Sizes(question) => {
+                let scales = size_scales(question, chars.len(), rng);
+
+                let mut by_size: Vec<usize> = (0..chars.len()).collect();
+                by_size.sort_by(|&a, &b| scales[b].total_cmp(&scales[a]));
+                let picked = match question {
+                    SizeQuestion::Largest => vec![by_size[0]],
+                    SizeQuestion::Smallest => vec![by_size[by_size.len() - 1]],
+                    SizeQuestion::LargestToSmallest => by_size,
+                    SizeQuestion::SmallestToLargest => by_size.into_iter().rev().collect(),
+                };
+
+                Plan {
+                    answer: Answer::new(picked.iter().map(|&i| chars[i]).collect()),
+                    question: Some(Question::Size {
+                        question,
+                        scales: scales.clone(),
+                    }),
+                    colors: vec![color; chars.len()],
+                    scales,
+                }
+            }
+        };
+
+        #[cfg(feature = "zeroize")]
+        zeroize::Zeroize::zeroize(&mut { chars });
+
+        plan
+    }
+}
+
+const MIN_SCALE: f32 = 0.45;
+
+/// Picks per-character scales so the asked-for sizes stand out: a single
+/// clear outlier for largest/smallest, evenly spread steps for orderings.
+fn size_scales(question: SizeQuestion, len: usize, rng: &mut impl Rng) -> Vec<f32> {
+    match question {
+        SizeQuestion::Largest | SizeQuestion::Smallest => {
+            let (target, others) = match question {
+                SizeQuestion::Largest => (1.0, MIN_SCALE..0.7),
+                _ => (MIN_SCALE, 0.75..1.0),
+            };
+            let target_index = rng.random_range(0..len);
+            (0..len)
+                .map(|i| {
+                    if i == target_index {
+                        target
+                    } else {
+                        rng.random_range(others.clone())
+                    }
+                })
+                .collect()
+        }
+        SizeQuestion::LargestToSmallest | SizeQuestion::SmallestToLargest => {
+            let step = (1.0 - MIN_SCALE) / (len.max(2) - 1) as f32;
+            let mut scales: Vec<f32> = (0..len).map(|i| MIN_SCALE + step * i as f32).collect();
+            scales.shuffle(rng);
+            scales
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Config, Generator};
+
+    #[test]
+    fn asks_for_sorted_positions() {
+        let mut rng = rand::rng();
+        for count in [0, 1, 3, 6, 10] {
+            let plan = Mode::Positions { count }.plan("abcdef", [0; 3], &mut rng);
+            let Some(Question::Positions(positions)) = plan.question else {
+                panic!("no positions asked for");
+            };
+            assert_eq!(positions.len(), count.clamp(1, 6) as usize);
+            assert!(positions.is_sorted() && positions.iter().all(|&p| (1..=6).contains(&p)));
+            let picked: String = positions
+                .iter()
+                .map(|&p| "abcdef".as_bytes()[p - 1] as char)
+                .collect();
+            assert_eq!(plan.answer.expose(), picked);
+        }
+    }
+
+    #[test]
+    fn asks_for_some_characters_of_one_color() {
+        let mut rng = rand::rng();
+        for _ in 0..100 {
+            for count in [2, 3] {
+                let plan = Mode::Colors { count }.plan("abcdef", [0; 3], &mut rng);
+                let Some(Question::Color {
+                    color, positions, ..
+                }) = plan.question
+                else {
+                    panic!("no color asked for");
+                };
+                let inked: Vec<usize> = (1..=6).filter(|&p| plan.colors[p - 1] == color).collect();
+                assert_eq!(positions, inked);
+                assert!((1..6).contains(&positions.len()));
+                let picked: String = positions
+                    .iter()
+                    .map(|&p| "abcdef".as_bytes()[p - 1] as char)
+                    .collect();
+                assert_eq!(plan.answer.expose(), picked);
+            }
+        }
+        let plan = Mode::Colors { count: 2 }.plan("a", [0; 3], &mut rng);
+        assert_eq!(plan.answer.expose(), "a");
+    }
+
+    #[test]
+    fn orders_characters_by_size() {
+        let mut rng = rand::rng();
+        let questions = [
+            (SizeQuestion::Largest, 1),
+            (SizeQuestion::Smallest, 1),
+            (SizeQuestion::LargestToSmallest, 5),
+            (SizeQuestion::SmallestToLargest, 5),
+        ];
+        for (question, length) in questions {
+            let mode = Mode::Sizes(question);
+            assert_eq!(mode.min_answer_length(5), length);
+            assert_eq!(mode.max_answer_length(5), length);
+            for _ in 0..50 {
+                let plan = mode.plan("abcde", [0; 3], &mut rng);
+                let sizes: Vec<f32> = plan
+                    .answer
+                    .expose()
+                    .bytes()
+                    .map(|c| plan.scales[(c - b'a') as usize])
+                    .collect();
+                assert_eq!(sizes.len(), length as usize);
+                let (largest, smallest) = (1.0, MIN_SCALE);
+                match question {
+                    SizeQuestion::Largest => assert_eq!(sizes, [largest]),
+                    SizeQuestion::Smallest => assert_eq!(sizes, [smallest]),
+                    SizeQuestion::LargestToSmallest => assert!(sizes.is_sorted_by(|a, b| a > b)),
+                    SizeQuestion::SmallestToLargest => assert!(sizes.is_sorted_by(|a, b| a < b)),
+                }
+                // The asked-for character stands out.
+                if length == 1 {
+                    let close = plan
+                        .scales
+                        .iter()
+                        .filter(|&&scale| (scale - sizes[0]).abs() < 0.05);
+                    assert_eq!(close.count(), 1);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn phrases_questions_with_the_config() {
+        let config = Config {
+            mode: Mode::Colors { count: 3 },
+            instruction_text: |question| match question {
+                Question::Color { name, .. } => format!("Tippe die Zeichen in {name}"),
+                _ => unreachable!(),
+            },
+            ..Config::default()
+        };
+        let captcha = Generator::new(config).unwrap().generate().unwrap();
+        let instruction = captcha.instruction.unwrap();
+        assert!(instruction.text.starts_with("Tippe die Zeichen in "));
+    }
+
+    #[test]
+    fn adds_the_question_below_the_image() {
+        let config = Config {
+            mode: Mode::Positions { count: 2 },
+            ..Config::default()
+        };
+        let captcha = Generator::new(config.clone()).unwrap().generate().unwrap();
+        let instruction = captcha.instruction.unwrap();
+        assert!(instruction.text.starts_with("Enter characters "));
+        assert_eq!(captcha.text.expose().chars().count(), 2);
+        assert!(captcha.height > config.height);
+    }
+}
@@ -0,0 +1,108 @@
+use std::borrow::Cow;
+
+/// The digits captchas show. Answers stay ASCII, and verification accepts
+/// digits of any of these systems, so people can type whichever their
+/// keyboard produces. Other numerals are only drawn with
+/// [`Typeface::Font`], which needs a font that has them.
+///
+/// [`Typeface::Font`]: crate::Typeface::Font
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub enum Numerals {
+    #[default]
+    Ascii,
+    /// ٠١٢٣٤٥٦٧٨٩, used with Arabic in most of the Middle East.
+    ArabicIndic,
+    /// ۰۱۲۳۴۵۶۷۸۹, used with Persian and Urdu.
+    ExtendedArabicIndic,
+    /// ०१२३४५६७८९
+    Devanagari,
+}
+
+const SYSTEMS: [Numerals; 3] = [
+    Numerals::ArabicIndic,
+    Numerals::ExtendedArabicIndic,
+    Numerals::Devanagari,
+];
+
+impl Numerals {
+    fn zero(&self) -> char {
+        match self {
+            Numerals::Ascii => '0',
+            Numerals::ArabicIndic => '\u{660}',
+            Numerals::ExtendedArabicIndic => '\u{6F0}',
+            Numerals::Devanagari => '\u{966}',
+        }
+    }
+
+    /// `c` in this system if it's an ASCII digit, otherwise `c` itself.
+    pub fn localize(&self, c: char) -> char {
+        if !c.is_ascii_digit() {
+            return c;
+        }
+        char::from_u32(self.zero() as u32 + (c as u32 - '0' as u32)).unwrap_or(c)
+    }
+}
+
+/// `text` with the digits of every supported system replaced by ASCII
+/// digits.
+pub(crate) fn to_ascii(text: &str) -> Cow<'_, str> {
+    if text.is_ascii() {
+        return Cow::Borrowed(text);
+    }
+    Cow::Owned(
+        text.chars()
+            .map(|c| {
+                SYSTEMS
+                    .iter()
+                    .find_map(|system| {
+                        let digit = (c as u32).checked_sub(system.zero() as u32)?;
+                        (digit < 10).then(|| char::from(b'0' + digit as u8))
+                    })
+                    .unwrap_or(c)
+            })
+            .collect(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn localizes_ascii_digits_only() {
+        assert_eq!(Numerals::Ascii.localize('7'), '7');
+        assert_eq!(Numerals::ArabicIndic.localize('7'), '\u{667}');
+        assert_eq!(Numerals::ExtendedArabicIndic.localize('0'), '\u{6F0}');
+        assert_eq!(Numerals::Devanagari.localize('9'), '\u{96F}');
+        assert_eq!(Numerals::Devanagari.localize('x'), 'x');
+    }
+
+    #[test]
+    fn maps_every_system_back_to_ascii() {
+        for system in SYSTEMS {
+            let localized: String = "0123456789x".chars().map(|c| system.localize(c)).collect();
+            assert_eq!(to_ascii(&localized), "0123456789x");
+        }
+        assert!(matches!(to_ascii("12ab"), Cow::Borrowed("12ab")));
+        assert_eq!(to_ascii("\u{965}\u{970}é"), "\u{965}\u{970}é");
+    }
+
+    #[test]
+    fn keeps_answers_ascii_while_drawing_local_digits() {
+        let config = crate::Config {
+            charset: Some("0123456789".into()),
+            numerals: Numerals::ArabicIndic,
+            ..Default::default()
+        };
+        let captcha = config.generate().unwrap();
+        assert!(captcha.text.expose().chars().all(|c| c.is_ascii_digit()));
+        let typed: String = captcha
+            .text
+            .expose()
+            .chars()
+            .map(|c| Numerals::ArabicIndic.localize(c))
+            .collect();
+        assert!(captcha.verify(&typed));
+    }
+}
@@ -0,0 +1,339 @@
+use std::{f32::consts::TAU, sync::Arc};
+
+use fontdue::{Font, FontSettings};
+use rand::Rng;
+use raqote::{DrawOptions, DrawTarget, PathBuilder, SolidSource, Source, Winding};
+use ttf_parser::{Face, OutlineBuilder};
+
+use crate::{CaptchaError, Script, glyph::Mask};
+
+/// One font of a fallback chain, see [`Generator::with_fonts`].
+///
+/// [`Generator::with_fonts`]: crate::Generator::with_fonts
+#[derive(Clone, Debug)]
+pub struct FallbackFont<'a> {
+    pub data: &'a [u8],
+    /// Scripts this font is tried first for, ahead of the fonts before it
+    /// in the chain, e.g. [`Script::Han`] for a CJK font.
+    pub scripts: Vec<Script>,
+}
+
+/// An ordered chain of parsed fonts, each with its raw data, which is
+/// needed to get at the glyph outlines fontdue doesn't expose.
+pub(crate) struct LoadedFont {
+    faces: Vec<FontFace>,
+}
+
+struct FontFace {
+    font: Font,
+    data: Arc<[u8]>,
+    scripts: Vec<Script>,
+}
+
+impl LoadedFont {
+    pub fn new(data: &[u8]) -> Result<Self, CaptchaError> {
+        Self::chain(&[FallbackFont {
+            data,
+            scripts: Vec::new(),
+        }])
+    }
+
+    pub fn chain(fonts: &[FallbackFont]) -> Result<Self, CaptchaError> {
+        if fonts.is_empty() {
+            return Err(CaptchaError::InvalidInput(
+                "a font chain needs at least one font".into(),
+            ));
+        }
+        let faces = fonts
+            .iter()
+            .map(|font| {
+                Ok(FontFace {
+                    font: Font::from_bytes(font.data, FontSettings::default())
+                        .map_err(CaptchaError::Font)?,
+                    data: font.data.into(),
+                    scripts: font.scripts.clone(),
+                })
+            })
+            .collect::<Result<_, CaptchaError>>()?;
+        Ok(Self { faces })
+    }
+
+    /// The first font of the chain, for text that isn't drawn glyph by
+    /// glyph, such as instructions.
+    pub fn primary(&self) -> &Font {
+        &self.faces[0].font
+    }
+
+    /// Where in the chain the font `c` is drawn with is, and that font:
+    /// the first with a glyph for `c` among those routed its script, else
+    /// the first with one at all, else the primary font. Glyphs cached
+    /// per character need the position as part of their key.
+    pub fn resolve(&self, c: char) -> (usize, &Font) {
+        let has_glyph = |face: &FontFace| face.font.lookup_glyph_index(c) != 0;
+        let routed = Script::of(c).and_then(|script| {
+            self.faces
+                .iter()
+                .position(|face| face.scripts.contains(&script) && has_glyph(face))
+        });
+        let index = routed
+            .or_else(|| self.faces.iter().position(has_glyph))
+            .unwrap_or(0);
+        (index, &self.faces[index].font)
+    }
+
+    /// The characters of `chars` no font of the chain has a glyph for,
+    /// without duplicates.
+    pub fn missing(&self, chars: impl Iterator<Item = char>) -> Vec<char> {
+        let mut missing: Vec<char> = chars
+            .filter(|&c| {
+                !c.is_whitespace()
+                    && self
+                        .faces
+                        .iter()
+                        .all(|face| face.font.lookup_glyph_index(c) == 0)
+            })
+            .collect();
+        missing.sort_unstable();
+        missing.dedup();
+        missing
+    }
+
+    /// Rasterizes `c` from its outline after displacing every outline point
+    /// by low-frequency noise of up to `amplitude` pixels, so strokes wobble
+    /// like drawn by hand, with the variable font axes set to `axes`.
+    /// `None` when the font has no outline for `c`.
+    pub fn rasterize_outline(
+        &self,
+        c: char,
+        size: f32,
+        amplitude: f32,
+        axes: &[([u8; 4], f32)],
+        rng: &mut impl Rng,
+    ) -> Option<(Mask, f32)> {
+        let (index, _) = self.resolve(c);
+        #[cfg_attr(not(feature = "variable-fonts"), allow(unused_mut))]
+        let mut face = Face::parse(&self.faces[index].data, 0).ok()?;
+        #[cfg(feature = "variable-fonts")]
+        for &(tag, value) in axes {
+            // Fonts without the axis are left as they are.
+            let _ = face.set_variation(ttf_parser::Tag::from_bytes(&tag), value);
+        }
+        #[cfg(not(feature = "variable-fonts"))]
+        let _ = axes;
+        let id = face.glyph_index(c)?;
+        let mut contours = Contours::default();
+        let bbox = face.outline_glyph(id, &mut contours)?;
+
+        let scale = size / face.units_per_em() as f32;
+        let pad = amplitude.ceil() + 1.0;
+        let width = ((bbox.x_max - bbox.x_min) as f32 * scale + 2.0 * pad).ceil() as i32;
+        let height = ((bbox.y_max - bbox.y_min) as f32 * scale + 2.0 * pad).ceil() as i32;
+
+        // Two sine waves per axis, with wavelengths around the glyph size,
+        // make for smooth, irregular displacement.
+        let waves: Vec<[f32; 4]> = (0..4)
+            .map(|_| {
+                let wavelength = size * rng.random_range(0.3..0.8);
+                let angle = rng.random_range(0.0..TAU);
+                [
+                    angle.cos() * TAU / wavelength,
+                    angle.sin() * TAU / wavelength,
+                    rng.random_range(0.0..TAU),
+                    amplitude / 2.0,
+                ]
+            })
+            .collect();
+        let wave = |waves: &[[f32; 4]], x: f32, y: f32| {
+            waves
+                .iter()
+                .map(|[fx, fy, phase, amplitude]| amplitude * (fx * x + fy * y + phase).sin())
+                .sum::<f32>()
+        };
+
+        let mut path = PathBuilder::new();
+        for contour in &contours.points {
+            for (index, &(x, y)) in contour.iter().enumerate() {
+                let x = (x - bbox.x_min as f32) * scale + pad;
+                let y = (bbox.y_max as f32 - y) * scale + pad;
+                let x = x + wave(&waves[..2], x, y);
+                let y = y + wave(&waves[2..], x, y);
+                if index == 0 {
+                    path.move_to(x, y);
+                } else {
+                    path.line_to(x, y);
+                }
+            }
+            path.close();
+        }
+        let mut path = path.finish();
+        path.winding = Winding::NonZero;
+
+        let mut dt = DrawTarget::new(width, height);
+        dt.fill(
+            &path,
+            &Source::Solid(SolidSource::from_unpremultiplied_argb(255, 255, 255, 255)),
+            &DrawOptions::new(),
+        );
+        let alpha = dt
+            .get_data()
+            .iter()
+            .map(|pixel| (pixel >> 24) as u8)
+            .collect();
+
+        let advance = face.glyph_hor_advance(id).unwrap_or(0) as f32 * scale;
+        Some((Mask::new(width as usize, height as usize, alpha), advance))
+    }
+}
+
+/// Collects glyph outlines as closed polylines in font units.
+#[derive(Default)]
+struct Contours {
+    points: Vec<Vec<(f32, f32)>>,
+}
+
+/// Segments every curve is flattened into.
+const CURVE_STEPS: usize = 8;
+
+impl Contours {
+    fn last(&self) -> (f32, f32) {
+        self.points
+            .last()
+            .and_then(|contour| contour.last())
+            .copied()
+            .unwrap_or_default()
+    }
+
+    fn push(&mut self, point: (f32, f32)) {
+        if let Some(contour) = self.points.last_mut() {
+            contour.push(point);
+        }
+    }
+}
+
+impl OutlineBuilder for Contours {
+    fn move_to(&mut self, x: f32, y: f32) {
+        self.points.push(vec![(x, y)]);
+    }
+
+    fn line_to(&mut self, x: f32, y: f32) {
+        self.push((x, y));
+    }
+
+    fn quad_to(&mut self, x1: f32, y1: f32, x: f32, y: f32) {
+        let (x0, y0) = self.last();
+        for step in 1..=CURVE_STEPS {
+            let t = step as f32 / CURVE_STEPS as f32;
+            let u = 1.0 - t;
+            self.push((
+                u * u * x0 + 2.0 * u * t * x1 + t * t * x,
+                u * u * y0 + 2.0 * u * t * y1 + t * t * y,
+            ));
+        }
+    }
+
+    fn curve_to(&mut self, x1: f32, y1: f32, x2: f32, y2: f32, x: f32, y: f32) {
+        let (x0, y0) = self.last();
+        for step in 1..=CURVE_STEPS {
+            let t = step as f32 / CURVE_STEPS as f32;
+            let u = 1.0 - t;
+            self.push((
+                u * u * u * x0 + 3.0 * u * u * t * x1 + 3.0 * u * t * t * x2 + t * t * t * x,
+                u * u * u * y0 + 3.0 * u * u * t * y1 + 3.0 * u * t * t * y2 + t * t * t * y,
+            ));
+        }
+    }
+
+    fn close(&mut self) {}
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::{SeedableRng, rngs::StdRng};
+
+    use super::*;
+
+    fn arial() -> LoadedFont {
+        LoadedFont::new(&std::fs::read("Arial.ttf").unwrap()).unwrap()
+    }
+
+    fn coverage(mask: &Mask) -> u64 {
+        mask.alpha.iter().map(|&a| a as u64).sum()
+    }
+
+    #[test]
+    fn fills_outlines_like_fontdue_without_jitter() {
+        let font = arial();
+        let mut rng = StdRng::seed_from_u64(1);
+        let (outlined, advance) = font
+            .rasterize_outline('R', 40.0, 0.0, &[], &mut rng)
+            .unwrap();
+        let (metrics, bitmap) = font.primary().rasterize('R', 40.0);
+        let expected: u64 = bitmap.iter().map(|&a| a as u64).sum();
+        let difference = coverage(&outlined).abs_diff(expected) as f64;
+        assert!(difference / (expected as f64) < 0.05);
+        assert!((advance - metrics.advance_width).abs() < 0.5);
+    }
+
+    #[test]
+    fn jitters_outlines_differently_per_rng() {
+        let font = arial();
+        let outline = |seed| {
+            let mut rng = StdRng::seed_from_u64(seed);
+            font.rasterize_outline('R', 40.0, 3.0, &[], &mut rng)
+                .unwrap()
+                .0
+                .alpha
+        };
+        assert_eq!(outline(1), outline(1));
+        assert_ne!(outline(1), outline(2));
+    }
+
+    #[test]
+    fn routes_scripts_the_primary_font_lacks() {
+        let arial = std::fs::read("Arial.ttf").unwrap();
+        // Renaming its `cmap` table leaves a font with no glyph for any
+        // character.
+        let mut blank = arial.clone();
+        let tables = u16::from_be_bytes([arial[4], arial[5]]) as usize;
+        let record = (0..tables)
+            .map(|table| 12 + 16 * table)
+            .find(|&record| &arial[record..record + 4] == b"cmap")
+            .unwrap();
+        blank[record..record + 4].copy_from_slice(b"xmap");
+
+        let font = |data, scripts| FallbackFont { data, scripts };
+        let chain = LoadedFont::chain(&[
+            font(&blank, Vec::new()),
+            font(&arial, vec![Script::Latin]),
+            font(&arial, vec![Script::Greek]),
+        ])
+        .unwrap();
+        assert_eq!(chain.resolve('α').0, 2);
+        assert_eq!(chain.resolve('A').0, 1);
+        assert_eq!(chain.resolve('7').0, 1);
+        assert_eq!(chain.resolve('\u{E000}').0, 0);
+        assert_eq!(chain.missing("7αA\u{E000}".chars()), ['\u{E000}']);
+        let mut rng = StdRng::seed_from_u64(1);
+        assert!(
+            chain
+                .rasterize_outline('α', 40.0, 0.0, &[], &mut rng)
+                .is_some()
+        );
+
+        // A routed font without the glyph is skipped.
+        let chain =
+            LoadedFont::chain(&[font(&arial, Vec::new()), font(&blank, vec![Script::Greek])])
+                .unwrap();
+        assert_eq!(chain.resolve('α').0, 0);
+    }
+
+    #[test]
+    fn has_no_outline_for_missing_glyphs() {
+        let mut rng = StdRng::seed_from_u64(1);
+        assert!(
+            arial()
+                .rasterize_outline('\u{E000}', 40.0, 3.0, &[], &mut rng)
+                .is_none()
+        );
+    }
+}
@@ -0,0 +1,221 @@
+use crate::{Config, DecoyStyle, GlyphStyle, LineStyle, Stage, StageKind};
+
+/// Changes to a [`Generator`]'s config for a single captcha, so one shared
+/// generator can serve endpoints with different requirements, see
+/// [`Generator::generate_with_overrides`].
+///
+/// [`Generator`]: crate::Generator
+/// [`Generator::generate_with_overrides`]: crate::Generator::generate_with_overrides
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct OverrideOptions {
+    pub length: Option<u32>,
+    /// Replaces [`Config::charset`].
+    pub charset: Option<String>,
+    pub difficulty: Option<Difficulty>,
+    /// Pipeline stages of these kinds are left out, e.g. the costly
+    /// [`StageKind::Wave`] while shedding load.
+    pub skip_stages: Vec<StageKind>,
+}
+
+impl OverrideOptions {
+    pub(crate) fn is_empty(&self) -> bool {
+        self.length.is_none()
+            && self.charset.is_none()
+            && self.difficulty.is_none()
+            && self.skip_stages.is_empty()
+    }
+
+    pub(crate) fn apply(&self, config: &Config) -> Config {
+        let mut config = config.clone();
+        if let Some(length) = self.length {
+            config.length = length;
+        }
+        if let Some(charset) = &self.charset {
+            config.charset = Some(charset.clone());
+        }
+        if let Some(difficulty) = self.difficulty {
+            difficulty.apply(&mut config);
+        }
+        let skipped = |stage: &Stage| self.skip_stages.contains(&stage.kind());
+        config.pipeline.retain(|stage| !skipped(stage));
+        for pipeline in &mut config.pipeline_choices {
+            pipeline.retain(|stage| !skipped(stage));
+        }
+        config
+    }
+}
+
+/// Canned glyph distortion, line and decoy settings, from clean to heavily
+/// obstructed. Blend modes, colors and the other settings are kept. The
+/// `calibrate` feature measures how far apart they are for a config.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum Difficulty {
+    Easy,
+    Medium,
+    Hard,
+}
+
+impl Difficulty {
+    pub fn apply(&self, config: &mut Config) {
+        let (glyph_style, line_style, decoys) = match self {
+            Difficulty::Easy => (
+                GlyphStyle {
+                    min_bold: 0,
+                    max_bold: 0,
+                    min_slant: 0.0,
+                    max_slant: 0.0,
+                    fragments: 0,
+                    jitter: 0.0,
+                    ..config.glyph_style
+                },
+                LineStyle {
+                    min_width: 1.0,
+                    max_width: 1.0,
+                    wobble: 0.0,
+                    ..config.line_style.clone()
+                },
+                0,
+            ),
+            Difficulty::Medium => (
+                GlyphStyle {
+                    min_bold: 0,
+                    max_bold: 1,
+                    min_slant: -0.15,
+                    max_slant: 0.15,
+                    fragments: 0,
+                    jitter: 1.0,
+                    ..config.glyph_style
+                },
+                LineStyle {
+                    min_width: 1.0,
+                    max_width: 2.0,
+                    wobble: 1.5,
+                    ..config.line_style.clone()
+                },
+                2,
+            ),
+            Difficulty::Hard => (
+                GlyphStyle {
+                    min_bold: 0,
+                    max_bold: 2,
+                    min_slant: -0.3,
+                    max_slant: 0.3,
+                    fragments: 2,
+                    jitter: 2.0,
+                    ..config.glyph_style
+                },
+                LineStyle {
+                    min_width: 1.5,
+                    max_width: 3.0,
+                    wobble: 3.0,
+                    ..config.line_style.clone()
+                },
+                5,
+            ),
+        };
+        config.glyph_style = glyph_style;
+        config.line_style = line_style;
+        config.decoys = DecoyStyle {
+            count: decoys,
+            ..config.decoys
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{CaptchaError, FontVariation, Generator};
+
+    #[test]
+    fn applies_only_what_is_set() {
+        let config = Config::default();
+        let unchanged = OverrideOptions::default();
+        assert!(unchanged.is_empty());
+        assert_eq!(unchanged.apply(&config).length, config.length);
+
+        let overrides = OverrideOptions {
+            length: Some(7),
+            charset: Some("ABC".into()),
+            ..Default::default()
+        };
+        assert!(!overrides.is_empty());
+        let applied = overrides.apply(&config);
+        assert_eq!((applied.length, applied.charset()), (7, "ABC"));
+        assert_eq!(applied.glyph_style, config.glyph_style);
+    }
+
+    #[test]
+    fn obstructs_more_the_harder_it_gets() {
+        let mut config = Config::default();
+        let levels = [Difficulty::Easy, Difficulty::Medium, Difficulty::Hard].map(|difficulty| {
+            difficulty.apply(&mut config);
+            (
+                config.decoys.count,
+                config.line_style.wobble,
+                config.glyph_style.jitter,
+            )
+        });
+        assert_eq!(levels[0], (0, 0.0, 0.0));
+        assert!(levels[0] < levels[1] && levels[1] < levels[2]);
+        // Even the hardest style is valid at small font sizes.
+        assert!(config.glyph_style.check(20.0).is_ok());
+    }
+
+    #[test]
+    fn keeps_settings_difficulties_leave_alone() {
+        let variation = Some(FontVariation::default());
+        for difficulty in [Difficulty::Easy, Difficulty::Medium, Difficulty::Hard] {
+            let mut config = Config {
+                glyph_style: GlyphStyle {
+                    min_scale_x: 0.7,
+                    max_scale_x: 1.3,
+                    min_scale_y: 0.9,
+                    max_scale_y: 1.1,
+                    fragment_length: 5,
+                    variation,
+                    ..GlyphStyle::default()
+                },
+                ..Config::default()
+            };
+            difficulty.apply(&mut config);
+            let style = config.glyph_style;
+            assert_eq!(
+                (style.min_scale_x, style.max_scale_x),
+                (0.7, 1.3),
+                "{difficulty:?}"
+            );
+            assert_eq!((style.min_scale_y, style.max_scale_y), (0.9, 1.1));
+            assert_eq!(style.fragment_length, 5);
+            assert_eq!(style.variation, variation);
+        }
+    }
+
+    #[test]
+    fn overrides_a_single_captcha() {
+        let generator = Generator::new(Config::default()).unwrap();
+        let overrides = OverrideOptions {
+            length: Some(6),
+            charset: Some("2345".into()),
+            difficulty: Some(Difficulty::Hard),
+            ..Default::default()
+        };
+        let captcha = generator.generate_with_overrides(&overrides).unwrap();
+        assert_eq!(captcha.text.len(), 6);
+        assert!(captcha.text.expose().chars().all(|c| "2345".contains(c)));
+        assert_eq!(generator.config().length, Config::default().length);
+        assert_eq!(generator.generate().unwrap().text.len(), 4);
+
+        let missing = OverrideOptions {
+            charset: Some("\u{E000}".into()),
+            ..Default::default()
+        };
+        assert!(matches!(
+            generator.generate_with_overrides(&missing),
+            Err(CaptchaError::FontMissingGlyph(_))
+        ));
+    }
+}
@@ -0,0 +1,97 @@
+use std::io::Write;
+
+use flate2::{Compression, write::ZlibEncoder};
+use image::RgbaImage;
+
+/// A single-page PDF showing `img` at 72 dpi, so one pixel is one point.
+/// The alpha channel is dropped.
+pub(crate) fn encode(img: &RgbaImage) -> std::io::Result<Vec<u8>> {
+    let (width, height) = img.dimensions();
+
+    let mut deflate = ZlibEncoder::new(Vec::new(), Compression::default());
+    for pixel in img.pixels() {
+        deflate.write_all(&pixel.0[..3])?;
+    }
+    let pixels = deflate.finish()?;
+    let contents = format!("q {width} 0 0 {height} 0 0 cm /Im0 Do Q");
+
+    let mut pdf = b"%PDF-1.4\n%\xe2\xe3\xcf\xd3\n".to_vec();
+    let mut offsets = Vec::new();
+    let mut object = |pdf: &mut Vec<u8>, body: &[u8]| {
+        offsets.push(pdf.len());
+        pdf.extend_from_slice(format!("{} 0 obj\n", offsets.len()).as_bytes());
+        pdf.extend_from_slice(body);
+        pdf.extend_from_slice(b"\nendobj\n");
+    };
+
+    object(&mut pdf, b"<< /Type /Catalog /Pages 2 0 R >>");
+    object(&mut pdf, b"<< /Type /Pages /Kids [3 0 R] /Count 1 >>");
+    object(
+        &mut pdf,
+        format!(
+            "<< /Type /Page /Parent 2 0 R /MediaBox [0 0 {width} {height}] \
+             /Resources << /XObject << /Im0 5 0 R >> >> /Contents 4 0 R >>"
+        )
+        .as_bytes(),
+    );
+    object(
+        &mut pdf,
+        format!(
+            "<< /Length {} >>\nstream\n{contents}\nendstream",
+            contents.len()
+        )
+        .as_bytes(),
+    );
+    let mut image = format!(
+        "<< /Type /XObject /Subtype /Image /Width {width} /Height {height} \
+         /ColorSpace /DeviceRGB /BitsPerComponent 8 /Filter /FlateDecode /Length {} >>\nstream\n",
+        pixels.len()
+    )
+    .into_bytes();
+    image.extend_from_slice(&pixels);
+    image.extend_from_slice(b"\nendstream");
+    object(&mut pdf, &image);
+
+    let xref = pdf.len();
+    let mut table = format!("xref\n0 {}\n0000000000 65535 f \n", offsets.len() + 1);
+    for offset in &offsets {
+        table.push_str(&format!("{offset:010} 00000 n \n"));
+    }
+    table.push_str(&format!(
+        "trailer\n<< /Size {} /Root 1 0 R >>\nstartxref\n{xref}\n%%EOF\n",
+        offsets.len() + 1
+    ));
+    pdf.extend_from_slice(table.as_bytes());
+
+    Ok(pdf)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn find(haystack: &[u8], needle: &str) -> Option<usize> {
+        haystack
+            .windows(needle.len())
+            .rposition(|window| window == needle.as_bytes())
+    }
+
+    #[test]
+    fn writes_one_page_of_the_image_size() {
+        let pdf = encode(&RgbaImage::new(30, 12)).unwrap();
+        assert!(pdf.starts_with(b"%PDF-1.4\n"));
+        assert!(pdf.ends_with(b"%%EOF\n"));
+        assert!(find(&pdf, "/MediaBox [0 0 30 12]").is_some());
+        assert!(find(&pdf, "/Width 30 /Height 12").is_some());
+
+        // The trailer points at the table, the table at every object.
+        let xref = find(&pdf, "xref\n0 6\n").unwrap();
+        let table = std::str::from_utf8(&pdf[xref..]).unwrap();
+        let startxref = table.lines().skip_while(|line| *line != "startxref").nth(1);
+        assert_eq!(startxref, Some(xref.to_string().as_str()));
+        for (number, entry) in table.lines().skip(3).take(5).enumerate() {
+            let offset: usize = entry[..10].parse().unwrap();
+            assert!(pdf[offset..].starts_with(format!("{} 0 obj\n", number + 1).as_bytes()));
+        }
+    }
+}
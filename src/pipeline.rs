@@ -0,0 +1,383 @@
+use std::{f32::consts::TAU, time::Duration};
+
+use image::{Rgba, RgbaImage};
+use imageproc::filter::gaussian_blur_f32;
+use rand::Rng;
+
+use crate::Effect;
+
+/// One step of [`Config::pipeline`], the ordered recipe applied to the
+/// background and text. Stages draw from [`Config::visual_rng`] in order,
+/// so with a seeded source a saved pipeline replays exactly.
+///
+/// [`Config::pipeline`]: crate::Config::pipeline
+/// [`Config::visual_rng`]: crate::Config::visual_rng
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum Stage {
+    /// Straight interference lines in [`Config::line_style`].
+    ///
+    /// [`Config::line_style`]: crate::Config::line_style
+    Lines {
+        count: u32,
+    },
+    /// Half-transparent curves across the image, also in the line style.
+    Curves {
+        count: u32,
+    },
+    /// Bends everything drawn so far along a sine wave of `amplitude`
+    /// pixels, at a random phase.
+    Wave {
+        amplitude: f32,
+        wavelength: f32,
+    },
+    /// Lays a grid of `cells` by `cells` over the image, moves every grid
+    /// point by up to `displacement` pixels in a random direction and
+    /// resamples the image along the bent grid, for an uneven distortion
+    /// that, unlike the wave, doesn't repeat.
+    Mesh {
+        cells: u32,
+        displacement: f32,
+    },
+    /// Twists the image around `count` random points: pixels within
+    /// `radius` pixels turn by up to `angle` radians, most at the center,
+    /// fading to none at the edge.
+    Swirl {
+        count: u32,
+        radius: f32,
+        angle: f32,
+    },
+    /// Gaussian blur with a standard deviation of `sigma` pixels.
+    Blur {
+        sigma: f32,
+    },
+    Effect(Effect),
+}
+
+/// Which kind of [`Stage`] something is, regardless of its settings, to
+/// switch stages off per captcha with [`OverrideOptions::skip_stages`].
+///
+/// [`OverrideOptions::skip_stages`]: crate::OverrideOptions::skip_stages
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum StageKind {
+    Lines,
+    Curves,
+    Wave,
+    Mesh,
+    Swirl,
+    Blur,
+    Effect,
+}
+
+impl Stage {
+    pub fn kind(&self) -> StageKind {
+        match self {
+            Stage::Lines { .. } => StageKind::Lines,
+            Stage::Curves { .. } => StageKind::Curves,
+            Stage::Wave { .. } => StageKind::Wave,
+            Stage::Mesh { .. } => StageKind::Mesh,
+            Stage::Swirl { .. } => StageKind::Swirl,
+            Stage::Blur { .. } => StageKind::Blur,
+            Stage::Effect(_) => StageKind::Effect,
+        }
+    }
+}
+
+/// How long one stage of the pipeline took, summed over the frames of an
+/// animation, see [`Captcha::stage_timings`].
+///
+/// [`Captcha::stage_timings`]: crate::Captcha::stage_timings
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct StageTiming {
+    pub stage: Stage,
+    pub elapsed: Duration,
+}
+
+/// Five lines and two curves, the noise captchas always had.
+pub(crate) fn default_pipeline() -> Vec<Stage> {
+    vec![Stage::Lines { count: 5 }, Stage::Curves { count: 2 }]
+}
+
+/// Resamples `img` bilinearly, taking every pixel from where `mapping`
+/// puts it in the source. Points past the edges read the nearest edge
+/// pixel, so warped images stay opaque along their borders, and channels
+/// are rounded rather than truncated, so warps don't darken the image.
+pub(crate) fn warp(img: &RgbaImage, mapping: impl Fn(f32, f32) -> (f32, f32)) -> RgbaImage {
+    let (width, height) = img.dimensions();
+    if width == 0 || height == 0 {
+        return img.clone();
+    }
+    let (max_x, max_y) = ((width - 1) as f32, (height - 1) as f32);
+    RgbaImage::from_fn(width, height, |x, y| {
+        let (sx, sy) = mapping(x as f32, y as f32);
+        // `max` first, so NaN reads the first pixel.
+        let (sx, sy) = (sx.max(0.0).min(max_x), sy.max(0.0).min(max_y));
+        let (left, top) = (sx.floor(), sy.floor());
+        let (tx, ty) = (sx - left, sy - top);
+        let (x0, y0) = (left as u32, top as u32);
+        let (x1, y1) = ((x0 + 1).min(width - 1), (y0 + 1).min(height - 1));
+        let [tl, tr, bl, br] = [(x0, y0), (x1, y0), (x0, y1), (x1, y1)]
+            .map(|(x, y)| img.get_pixel(x, y).0.map(f32::from));
+        Rgba(std::array::from_fn(|c| {
+            let upper = tl[c] + (tr[c] - tl[c]) * tx;
+            let lower = bl[c] + (br[c] - bl[c]) * tx;
+            (upper + (lower - upper) * ty).round() as u8
+        }))
+    })
+}
+
+pub(crate) fn wave(
+    img: &RgbaImage,
+    amplitude: f32,
+    wavelength: f32,
+    rng: &mut impl Rng,
+) -> RgbaImage {
+    if amplitude == 0.0 || !amplitude.is_finite() || !wavelength.is_normal() {
+        return img.clone();
+    }
+    let amplitude = amplitude.clamp(-32.0, 32.0);
+    let phase = rng.random_range(0.0..TAU);
+    warp(img, |x, y| {
+        let dy = amplitude * (TAU * x / wavelength + phase).sin();
+        (x, y + dy)
+    })
+}
+
+pub(crate) fn mesh(
+    img: &RgbaImage,
+    cells: u32,
+    displacement: f32,
+    rng: &mut impl Rng,
+) -> RgbaImage {
+    if displacement == 0.0 || !displacement.is_finite() {
+        return img.clone();
+    }
+    let displacement = displacement.abs().min(32.0);
+    let cells = cells.clamp(1, 32) as usize;
+    let points = cells + 1;
+    let offsets: Vec<(f32, f32)> = (0..points * points)
+        .map(|_| {
+            let angle = rng.random_range(0.0..TAU);
+            let distance = rng.random_range(0.0..=displacement);
+            (angle.cos() * distance, angle.sin() * distance)
+        })
+        .collect();
+
+    let (width, height) = img.dimensions();
+    let cell_width = width.max(1) as f32 / cells as f32;
+    let cell_height = height.max(1) as f32 / cells as f32;
+    warp(img, |x, y| {
+        // Interpolates the offsets of the four points around the pixel.
+        let gx = (x / cell_width).clamp(0.0, cells as f32);
+        let gy = (y / cell_height).clamp(0.0, cells as f32);
+        let (col, row) = ((gx as usize).min(cells - 1), (gy as usize).min(cells - 1));
+        let (tx, ty) = (gx - col as f32, gy - row as f32);
+        let at = |col: usize, row: usize| offsets[row * points + col];
+        let lerp =
+            |a: (f32, f32), b: (f32, f32), t: f32| (a.0 + (b.0 - a.0) * t, a.1 + (b.1 - a.1) * t);
+        let top = lerp(at(col, row), at(col + 1, row), tx);
+        let bottom = lerp(at(col, row + 1), at(col + 1, row + 1), tx);
+        let (dx, dy) = lerp(top, bottom, ty);
+        (x + dx, y + dy)
+    })
+}
+
+pub(crate) fn swirl(
+    img: &RgbaImage,
+    count: u32,
+    radius: f32,
+    angle: f32,
+    rng: &mut impl Rng,
+) -> RgbaImage {
+    if count == 0 || angle == 0.0 || !angle.is_finite() || !radius.is_normal() {
+        return img.clone();
+    }
+    let (width, height) = img.dimensions();
+    let radius = radius.abs().min(width.max(height) as f32);
+    let angle = angle.clamp(-TAU, TAU);
+    let centers: Vec<(f32, f32)> = (0..count.min(16))
+        .map(|_| {
+            (
+                rng.random_range(0.0..width.max(1) as f32),
+                rng.random_range(0.0..height.max(1) as f32),
+            )
+        })
+        .collect();
+
+    warp(img, |mut x, mut y| {
+        for &(cx, cy) in &centers {
+            let (dx, dy) = (x - cx, y - cy);
+            let distance = (dx * dx + dy * dy).sqrt();
+            if distance >= radius {
+                continue;
+            }
+            let falloff = 1.0 - distance / radius;
+            let (sin, cos) = (angle * falloff * falloff).sin_cos();
+            x = cx + dx * cos - dy * sin;
+            y = cy + dx * sin + dy * cos;
+        }
+        (x, y)
+    })
+}
+
+pub(crate) fn blur(img: &RgbaImage, sigma: f32) -> RgbaImage {
+    // Larger kernels only cost time; the text is long gone by then.
+    let sigma = sigma.min(16.0);
+    if sigma > 0.0 {
+        gaussian_blur_f32(img, sigma)
+    } else {
+        img.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::{SeedableRng, rngs::StdRng};
+
+    use super::*;
+    use crate::{Config, Generator, OverrideOptions, RngSource};
+
+    fn image(pipeline: Vec<Stage>) -> Vec<u8> {
+        let config = Config {
+            answer_rng: RngSource::Seeded(1),
+            visual_rng: RngSource::Seeded(2),
+            pipeline,
+            ..Config::default()
+        };
+        Generator::new(config).unwrap().generate().unwrap().image
+    }
+
+    #[test]
+    fn replays_seeded_pipelines_in_order() {
+        let wave = Stage::Wave {
+            amplitude: 3.0,
+            wavelength: 40.0,
+        };
+        let stages = vec![
+            Stage::Lines { count: 3 },
+            wave,
+            Stage::Mesh {
+                cells: 4,
+                displacement: 2.0,
+            },
+            Stage::Swirl {
+                count: 2,
+                radius: 20.0,
+                angle: 1.0,
+            },
+            Stage::Blur { sigma: 0.5 },
+        ];
+        assert_eq!(image(stages.clone()), image(stages.clone()));
+        let mut reordered = stages.clone();
+        reordered.swap(0, 1);
+        assert_ne!(image(stages), image(reordered));
+    }
+
+    #[test]
+    fn leaves_images_alone_without_distortion() {
+        let img = RgbaImage::from_fn(8, 8, |x, y| Rgba([x as u8 * 30, y as u8 * 30, 0, 255]));
+        let mut rng = rand::rng();
+        for amplitude in [0.0, f32::NAN] {
+            assert_eq!(wave(&img, amplitude, 10.0, &mut rng), img);
+        }
+        assert_eq!(wave(&img, 2.0, 0.0, &mut rng), img);
+        assert_eq!(mesh(&img, 4, f32::INFINITY, &mut rng), img);
+        assert_eq!(swirl(&img, 2, f32::NAN, 1.0, &mut rng), img);
+        assert_eq!(swirl(&img, 0, 4.0, 1.0, &mut rng), img);
+        assert_eq!(blur(&img, -1.0), img);
+    }
+
+    #[test]
+    fn keeps_edges_opaque() {
+        let gray = Rgba([90, 90, 90, 255]);
+        let img = RgbaImage::from_pixel(30, 20, gray);
+        for seed in 0..50 {
+            let mut rng = StdRng::seed_from_u64(seed);
+            // Neither transparent edges nor truncated channels.
+            for warped in [
+                wave(&img, 3.0, 10.0, &mut rng),
+                mesh(&img, 4, 3.0, &mut rng),
+                swirl(&img, 2, 8.0, 2.0, &mut rng),
+            ] {
+                assert!(warped.pixels().all(|&pixel| pixel == gray), "{seed}");
+            }
+        }
+    }
+
+    /// Brightness rising by four levels per column.
+    fn ramp() -> RgbaImage {
+        RgbaImage::from_fn(60, 40, |x, _| {
+            let value = x as u8 * 4;
+            Rgba([value, value, value, 255])
+        })
+    }
+
+    #[test]
+    fn bends_the_mesh_within_its_displacement() {
+        let img = ramp();
+        let warped = mesh(&img, 4, 2.0, &mut StdRng::seed_from_u64(1));
+        assert_eq!(warped.dimensions(), img.dimensions());
+        assert_ne!(warped, img);
+        // No pixel moves further than two columns, eight levels.
+        for (x, y, pixel) in warped.enumerate_pixels() {
+            assert_eq!(pixel[3], 255);
+            assert!(pixel[0].abs_diff(img.get_pixel(x, y)[0]) <= 9, "{x},{y}");
+        }
+    }
+
+    #[test]
+    fn swirls_only_within_the_radius() {
+        let img = RgbaImage::from_fn(60, 40, |x, y| match (x / 2 + y / 2) % 2 {
+            0 => Rgba([0, 0, 0, 255]),
+            _ => Rgba([255, 255, 255, 255]),
+        });
+        let swirled = swirl(&img, 1, 8.0, 2.0, &mut StdRng::seed_from_u64(1));
+        assert_eq!(swirled.dimensions(), img.dimensions());
+        let changed: Vec<(u32, u32)> = swirled
+            .enumerate_pixels()
+            .filter(|&(x, y, pixel)| pixel != img.get_pixel(x, y))
+            .map(|(x, y, _)| (x, y))
+            .collect();
+        assert!(!changed.is_empty());
+        // Everything changed fits the circle around the random center.
+        let span = |coordinate: fn(&(u32, u32)) -> u32| {
+            let values = changed.iter().map(coordinate);
+            values.clone().max().unwrap() - values.min().unwrap()
+        };
+        assert!(span(|&(x, _)| x) <= 16 && span(|&(_, y)| y) <= 16);
+        assert!(swirled.pixels().all(|pixel| pixel[3] == 255));
+    }
+
+    #[test]
+    fn times_stages_unless_skipped() {
+        let config = Config {
+            pipeline: vec![
+                Stage::Lines { count: 2 },
+                Stage::Blur { sigma: 0.5 },
+                Stage::Lines { count: 1 },
+            ],
+            ..Config::default()
+        };
+        let generator = Generator::new(config.clone()).unwrap();
+        let stages = |captcha: crate::Captcha| -> Vec<Stage> {
+            captcha
+                .stage_timings
+                .iter()
+                .map(|timing| timing.stage)
+                .collect()
+        };
+        assert_eq!(stages(generator.generate().unwrap()), config.pipeline);
+
+        let overrides = OverrideOptions {
+            skip_stages: vec![StageKind::Lines],
+            ..OverrideOptions::default()
+        };
+        let captcha = generator.generate_with_overrides(&overrides).unwrap();
+        assert_eq!(stages(captcha), [Stage::Blur { sigma: 0.5 }]);
+    }
+}
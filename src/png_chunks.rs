@@ -0,0 +1,122 @@
+use crate::CaptchaError;
+
+const SIGNATURE: &[u8; 8] = b"\x89PNG\r\n\x1a\n";
+
+/// Chunks that carry the image itself. Everything else is dropped from
+/// encoded PNGs.
+const IMAGE_CHUNKS: [&[u8; 4]; 8] = [
+    b"IHDR", b"PLTE", b"tRNS", b"IDAT", b"IEND", b"acTL", b"fcTL", b"fdAT",
+];
+
+/// The chunks of `png` as (offset, type, data).
+pub(crate) fn chunks(png: &[u8]) -> impl Iterator<Item = (usize, &[u8], &[u8])> {
+    let mut offset = if png.starts_with(SIGNATURE) {
+        SIGNATURE.len()
+    } else {
+        png.len()
+    };
+    std::iter::from_fn(move || {
+        let start = offset;
+        let length = u32::from_be_bytes(png.get(start..start + 4)?.try_into().ok()?) as usize;
+        let kind = png.get(start + 4..start + 8)?;
+        let data = png.get(start + 8..(start + 8).checked_add(length)?)?;
+        offset = start + 12 + length;
+        Some((start, kind, data))
+    })
+}
+
+/// Data of the first chunk of type `kind`.
+#[cfg(feature = "stego")]
+pub(crate) fn find<'a>(png: &'a [u8], kind: &[u8; 4]) -> Option<&'a [u8]> {
+    chunks(png)
+        .find(|(_, chunk, _)| chunk == kind)
+        .map(|(_, _, data)| data)
+}
+
+/// Inserts a chunk right before `IEND`.
+pub(crate) fn insert(png: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) -> Result<(), CaptchaError> {
+    let end = chunks(png)
+        .find(|(_, chunk, _)| chunk == b"IEND")
+        .map(|(start, _, _)| start)
+        .ok_or_else(|| CaptchaError::InvalidInput("image is not a valid PNG".into()))?;
+
+    let mut chunk = Vec::with_capacity(data.len() + 12);
+    chunk.extend((data.len() as u32).to_be_bytes());
+    chunk.extend(kind);
+    chunk.extend(data);
+    chunk.extend(crc32fast::hash(&chunk[4..]).to_be_bytes());
+    png.splice(end..end, chunk);
+    Ok(())
+}
+
+/// Drops every chunk besides the image data, such as timestamps, text
+/// and color profiles an encoder may add.
+pub(crate) fn strip(png: &mut Vec<u8>) {
+    let metadata: Vec<(usize, usize)> = chunks(png)
+        .filter(|(_, kind, _)| !IMAGE_CHUNKS.iter().any(|image| image == kind))
+        .map(|(start, _, data)| (start, data.len() + 12))
+        .collect();
+    for &(start, length) in metadata.iter().rev() {
+        png.drain(start..start + length);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use image::{ImageFormat, RgbaImage};
+
+    use super::*;
+
+    fn encoded() -> Vec<u8> {
+        let img = RgbaImage::from_fn(3, 2, |x, y| {
+            image::Rgba([x as u8 * 80, y as u8 * 120, 7, 255])
+        });
+        let mut png = Vec::new();
+        img.write_to(&mut std::io::Cursor::new(&mut png), ImageFormat::Png)
+            .unwrap();
+        png
+    }
+
+    fn decode(png: &[u8]) -> RgbaImage {
+        image::load_from_memory_with_format(png, ImageFormat::Png)
+            .unwrap()
+            .to_rgba8()
+    }
+
+    fn kinds(png: &[u8]) -> Vec<&[u8]> {
+        chunks(png).map(|(_, kind, _)| kind).collect()
+    }
+
+    #[test]
+    fn inserts_chunks_with_valid_crcs() {
+        let mut png = encoded();
+        insert(&mut png, b"tEXt", b"Comment\0hello").unwrap();
+        assert_eq!(kinds(&png).last(), Some(&&b"IEND"[..]));
+        assert_eq!(kinds(&png)[kinds(&png).len() - 2], b"tEXt");
+        for (start, _, data) in chunks(&png) {
+            let end = start + 8 + data.len();
+            let crc = u32::from_be_bytes(png[end..end + 4].try_into().unwrap());
+            assert_eq!(crc, crc32fast::hash(&png[start + 4..end]));
+        }
+        assert_eq!(decode(&png), decode(&encoded()));
+
+        assert!(insert(&mut b"not a png".to_vec(), b"tEXt", b"").is_err());
+    }
+
+    #[test]
+    fn strips_metadata_chunks() {
+        let mut png = encoded();
+        insert(&mut png, b"tEXt", b"Comment\0hello").unwrap();
+        insert(&mut png, b"eXIf", b"MM\0*\0\0\0\x08\0\0").unwrap();
+        insert(&mut png, b"tIME", &[7, 234, 10, 15, 12, 0, 0]).unwrap();
+        strip(&mut png);
+        assert!(
+            kinds(&png)
+                .iter()
+                .all(|kind| IMAGE_CHUNKS.iter().any(|image| image == kind))
+        );
+        assert_eq!(kinds(&png).first(), Some(&&b"IHDR"[..]));
+        assert_eq!(kinds(&png).last(), Some(&&b"IEND"[..]));
+        assert_eq!(decode(&png), decode(&encoded()));
+    }
+}
@@ -0,0 +1,253 @@
+use std::{
+    sync::{
+        Arc, Mutex, Weak,
+        atomic::{AtomicU64, Ordering},
+        mpsc::{Receiver, SyncSender, sync_channel},
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use crate::{Captcha, CaptchaError, CaptchaStore, Generator, generator::Composed};
+
+/// A queue of pre-rendered captchas refilled by a background thread.
+/// Captchas that fail to render are counted, see
+/// [`CaptchaPool::failures`], and refilling resumes after a pause.
+///
+/// Popping a captcha registers its answer in the store, so answers of
+/// captchas that were never handed out are not tracked.
+pub struct CaptchaPool<S: CaptchaStore> {
+    generator: Arc<Generator>,
+    max_age: Duration,
+    receiver: Mutex<Receiver<(Instant, Captcha)>>,
+    store: Arc<S>,
+    failures: Failures,
+}
+
+impl<S: CaptchaStore> CaptchaPool<S> {
+    pub fn new(generator: Generator, capacity: usize, max_age: Duration, store: Arc<S>) -> Self {
+        Self::with_encoders(generator, capacity, max_age, store, 0)
+    }
+
+    /// Like [`CaptchaPool::new`], but encodes images on `encoders` extra
+    /// threads, so the background thread composes the next captcha while
+    /// earlier ones are still being encoded. With 0 the background thread
+    /// does both.
+    pub fn with_encoders(
+        generator: Generator,
+        capacity: usize,
+        max_age: Duration,
+        store: Arc<S>,
+        encoders: usize,
+    ) -> Self {
+        let (sender, receiver) = sync_channel(capacity);
+
+        let generator = Arc::new(generator);
+        let worker_generator = Arc::downgrade(&generator);
+        let failures = Failures::default();
+        let worker_failures = failures.clone();
+        if encoders == 0 {
+            thread::spawn(move || refill(worker_generator, worker_failures, sender));
+        } else {
+            thread::spawn(move || {
+                refill_pipelined(worker_generator, worker_failures, encoders, sender)
+            });
+        }
+
+        Self {
+            generator,
+            max_age,
+            receiver: Mutex::new(receiver),
+            store,
+            failures,
+        }
+    }
+
+    /// Takes a ready captcha, rendering one inline if the pool has run dry.
+    pub fn pop(&self) -> Result<Captcha, CaptchaError> {
+        let mut captcha = loop {
+            let pooled = self.receiver.lock().unwrap().try_recv();
+            match pooled {
+                // Stale entries are dropped; the worker refills the freed slot.
+                Ok((created_at, _)) if created_at.elapsed() > self.max_age => continue,
+                Ok((_, captcha)) => break captcha,
+                Err(_) => break self.generator.generate()?,
+            }
+        };
+
+        captcha.reissue(self.generator.config().expires_in);
+        self.store.issue(&captcha);
+
+        Ok(captcha)
+    }
+
+    pub fn store(&self) -> &S {
+        &self.store
+    }
+
+    /// Captchas the background threads failed to render or encode, for
+    /// metrics. They skip those and keep refilling.
+    pub fn failures(&self) -> u64 {
+        self.failures.0.load(Ordering::Relaxed)
+    }
+}
+
+/// Pause after a failed captcha, doubled with every further failure in a
+/// row, e.g. while the concurrency limit is saturated.
+const MIN_BACKOFF: Duration = Duration::from_millis(10);
+const MAX_BACKOFF: Duration = Duration::from_secs(1);
+
+/// Counts and logs failures of the background threads, which skip the
+/// failed captcha and carry on.
+#[derive(Clone, Default)]
+struct Failures(Arc<AtomicU64>);
+
+impl Failures {
+    fn record(&self, _err: &CaptchaError) {
+        self.0.fetch_add(1, Ordering::Relaxed);
+
+        #[cfg(feature = "log")]
+        log::warn!("failed to pre-render a pooled captcha: {_err}");
+    }
+}
+
+/// Runs `create` until it succeeds, backing off between failures. `None`
+/// once the pool is gone.
+fn retry<T>(
+    generator: &Weak<Generator>,
+    failures: &Failures,
+    create: impl Fn(&Generator) -> Result<T, CaptchaError>,
+) -> Option<T> {
+    let mut backoff = MIN_BACKOFF;
+    loop {
+        // Not kept alive by the worker, so a dropped pool stops it.
+        let result = create(&*generator.upgrade()?);
+        match result {
+            Ok(value) => return Some(value),
+            Err(err) => {
+                failures.record(&err);
+                thread::sleep(backoff);
+                backoff = (backoff * 2).min(MAX_BACKOFF);
+            }
+        }
+    }
+}
+
+fn refill(generator: Weak<Generator>, failures: Failures, sender: SyncSender<(Instant, Captcha)>) {
+    while let Some(captcha) = retry(&generator, &failures, Generator::generate) {
+        // Blocks while the pool is full and fails once the pool has been dropped.
+        if sender.send((Instant::now(), captcha)).is_err() {
+            return;
+        }
+    }
+}
+
+fn refill_pipelined(
+    generator: Weak<Generator>,
+    failures: Failures,
+    encoders: usize,
+    sender: SyncSender<(Instant, Captcha)>,
+) {
+    let (composed_sender, composed) = sync_channel::<Composed>(encoders);
+    // Every encoder holds the receiver, so composing stops once all of them
+    // have exited.
+    let composed = Arc::new(Mutex::new(composed));
+    for _ in 0..encoders {
+        let composed = composed.clone();
+        let failures = failures.clone();
+        let sender = sender.clone();
+        thread::spawn(move || {
+            loop {
+                let next = composed.lock().unwrap().recv();
+                let Ok(next) = next else {
+                    return;
+                };
+                let captcha = match next.encode() {
+                    Ok((captcha, _)) => captcha,
+                    Err(err) => {
+                        failures.record(&err);
+                        continue;
+                    }
+                };
+                if sender.send((Instant::now(), captcha)).is_err() {
+                    return;
+                }
+            }
+        });
+    }
+    drop((composed, sender));
+
+    let compose =
+        |generator: &Generator| generator.compose(generator.config(), generator.next_sequence());
+    while let Some(next) = retry(&generator, &failures, compose) {
+        if composed_sender.send(next).is_err() {
+            return;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Config, MemoryStore, VerifyOutcome};
+
+    fn pool(config: Config, encoders: usize) -> CaptchaPool<MemoryStore> {
+        CaptchaPool::with_encoders(
+            Generator::new(config).unwrap(),
+            2,
+            Duration::from_secs(60),
+            Arc::new(MemoryStore::new()),
+            encoders,
+        )
+    }
+
+    #[test]
+    fn registers_popped_captchas() {
+        for encoders in [0, 2] {
+            let pool = pool(Config::default(), encoders);
+            for _ in 0..4 {
+                let captcha = pool.pop().unwrap();
+                assert_eq!(
+                    pool.store().verify(&captcha.id, captcha.text.expose()),
+                    VerifyOutcome::Correct
+                );
+            }
+            assert_eq!(pool.failures(), 0);
+        }
+    }
+
+    #[test]
+    fn keeps_refilling_after_failures() {
+        let pool = pool(
+            Config {
+                width: 0,
+                ..Config::default()
+            },
+            0,
+        );
+        assert!(matches!(pool.pop(), Err(CaptchaError::InvalidInput(_))));
+
+        let started = Instant::now();
+        while pool.failures() < 3 && started.elapsed() < Duration::from_secs(5) {
+            thread::sleep(MIN_BACKOFF);
+        }
+        assert!(pool.failures() >= 3);
+    }
+
+    #[test]
+    fn replaces_stale_captchas() {
+        let pool = CaptchaPool::new(
+            Generator::new(Config::default()).unwrap(),
+            1,
+            Duration::ZERO,
+            Arc::new(MemoryStore::new()),
+        );
+        thread::sleep(Duration::from_millis(50));
+        let captcha = pool.pop().unwrap();
+        assert!(!captcha.is_expired());
+        assert_eq!(
+            pool.store().verify(&captcha.id, captcha.text.expose()),
+            VerifyOutcome::Correct
+        );
+    }
+}
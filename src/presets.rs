@@ -0,0 +1,311 @@
+use std::{
+    collections::BTreeMap,
+    sync::{LazyLock, PoisonError, RwLock},
+    time::Duration,
+};
+
+use crate::{CaptchaError, Config, Difficulty, Format, Mode, Theme};
+
+/// A named captcha config, see [`Config::preset`]: the operational
+/// settings below plus a [`Theme`] for the look. With the `theme` feature
+/// presets load from TOML, one table per name:
+///
+/// ```toml
+/// [login-easy]
+/// length = 4
+/// charset = "ABCDEFGHJKMNPQRSTUVWXYZ23456789"
+/// expires_in_secs = 300
+/// theme = { difficulty = "easy" }
+///
+/// [signup-hard]
+/// length = 7
+/// width = 200
+/// mode = { positions = { count = 4 } }
+/// theme = { difficulty = "hard", color = "#1d3557" }
+/// ```
+///
+/// Settings left out keep [`Config::default`]'s values.
+#[derive(Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default, deny_unknown_fields))]
+pub struct Preset {
+    pub length: Option<u32>,
+    /// Replaces [`Config::charset`].
+    pub charset: Option<String>,
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+    pub mode: Option<Mode>,
+    pub format: Option<Format>,
+    /// Replaces [`Config::expires_in`].
+    pub expires_in_secs: Option<u64>,
+    pub theme: Theme,
+}
+
+impl Preset {
+    /// [`Config::default`] with the preset's settings.
+    pub fn config(&self) -> Config {
+        let mut config = Config::default();
+        self.apply(&mut config);
+        config
+    }
+
+    /// Overwrites the settings the preset has, the theme last.
+    pub fn apply(&self, config: &mut Config) {
+        if let Some(length) = self.length {
+            config.length = length;
+        }
+        if let Some(charset) = &self.charset {
+            config.charset = Some(charset.clone());
+        }
+        if let Some(width) = self.width {
+            config.width = width;
+        }
+        if let Some(height) = self.height {
+            config.height = height;
+        }
+        if let Some(mode) = self.mode {
+            config.mode = mode;
+        }
+        if let Some(format) = self.format {
+            config.format = format;
+        }
+        if let Some(secs) = self.expires_in_secs {
+            config.expires_in = Some(Duration::from_secs(secs));
+        }
+        self.theme.apply(config);
+    }
+}
+
+/// Presets by name, shared by the whole process. Starts out with `easy`,
+/// `medium` and `hard`, one per [`Difficulty`].
+static PRESETS: LazyLock<RwLock<BTreeMap<String, Preset>>> = LazyLock::new(|| {
+    let presets = [
+        ("easy", Difficulty::Easy),
+        ("medium", Difficulty::Medium),
+        ("hard", Difficulty::Hard),
+    ]
+    .map(|(name, difficulty)| {
+        let preset = Preset {
+            theme: Theme {
+                difficulty: Some(difficulty),
+                ..Theme::default()
+            },
+            ..Preset::default()
+        };
+        (name.to_string(), preset)
+    });
+    RwLock::new(BTreeMap::from(presets))
+});
+
+impl Config {
+    /// The config registered as `name`, so a codebase can refer to captcha
+    /// styles such as `"login-easy"` by name. `None` when no such preset is
+    /// registered.
+    pub fn preset(name: &str) -> Option<Config> {
+        let presets = PRESETS.read().unwrap_or_else(PoisonError::into_inner);
+        presets.get(name).map(Preset::config)
+    }
+
+    /// Registers `preset` as `name`, replacing any preset of that name.
+    /// Names consist of ASCII letters, digits, `-` and `_`. Fails when the
+    /// resulting config can't render, e.g. a zero size, or leaves no way
+    /// to pick an answer, e.g. an empty charset.
+    pub fn register_preset(name: &str, preset: Preset) -> Result<(), CaptchaError> {
+        validate(name, &preset)?;
+        PRESETS
+            .write()
+            .unwrap_or_else(PoisonError::into_inner)
+            .insert(name.to_string(), preset);
+        Ok(())
+    }
+
+    /// Every registered preset, e.g. to serialize them or list them in an
+    /// admin page.
+    pub fn presets() -> BTreeMap<String, Preset> {
+        PRESETS
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone()
+    }
+
+    /// Registers a preset for every table of `toml`, named after its key
+    /// and in the format shown on [`Preset`]. Nothing is registered unless
+    /// all of them are valid, so a service can load its presets at startup
+    /// and refuse to start on a broken file. Returns how many were
+    /// registered.
+    #[cfg(feature = "theme")]
+    pub fn register_presets_toml(toml: &str) -> Result<usize, CaptchaError> {
+        let presets: BTreeMap<String, Preset> =
+            toml::from_str(toml).map_err(|err| CaptchaError::InvalidInput(err.to_string()))?;
+        for (name, preset) in &presets {
+            validate(name, preset)
+                .map_err(|err| CaptchaError::InvalidInput(format!("preset {name:?}: {err}")))?;
+        }
+        let count = presets.len();
+        PRESETS
+            .write()
+            .unwrap_or_else(PoisonError::into_inner)
+            .extend(presets);
+        Ok(count)
+    }
+
+    /// The registered presets in the format [`Config::register_presets_toml`]
+    /// reads.
+    #[cfg(feature = "theme")]
+    pub fn presets_toml() -> Result<String, CaptchaError> {
+        toml::to_string(&Self::presets()).map_err(|err| CaptchaError::InvalidInput(err.to_string()))
+    }
+}
+
+fn validate(name: &str, preset: &Preset) -> Result<(), CaptchaError> {
+    if name.is_empty()
+        || !name
+            .bytes()
+            .all(|byte| byte.is_ascii_alphanumeric() || byte == b'-' || byte == b'_')
+    {
+        return Err(CaptchaError::InvalidInput(format!(
+            "invalid preset name {name:?}"
+        )));
+    }
+    let config = preset.config();
+    if config.length == 0 || config.width == 0 || config.height == 0 {
+        return Err(CaptchaError::InvalidInput(
+            "length, width and height must be at least 1".into(),
+        ));
+    }
+    config.check_size()?;
+    config
+        .text_style
+        .generate(
+            &config.char_weights,
+            config.charset(),
+            config.length,
+            &mut rand::rng(),
+        )
+        .map(drop)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::TextStyle;
+
+    #[test]
+    fn registers_whole_configs() {
+        let preset = Preset {
+            length: Some(6),
+            charset: Some("ABC123".into()),
+            width: Some(180),
+            mode: Some(Mode::Positions { count: 3 }),
+            expires_in_secs: Some(60),
+            theme: Theme {
+                difficulty: Some(Difficulty::Hard),
+                ..Theme::default()
+            },
+            ..Preset::default()
+        };
+        Config::register_preset("test-login", preset.clone()).unwrap();
+
+        let config = Config::preset("test-login").unwrap();
+        assert_eq!(config.length, 6);
+        assert_eq!(config.charset(), "ABC123");
+        assert_eq!(
+            (config.width, config.height),
+            (180, Config::default().height)
+        );
+        assert_eq!(config.mode, Mode::Positions { count: 3 });
+        assert_eq!(config.expires_in, Some(Duration::from_secs(60)));
+        assert_eq!(config.decoys.count, 5);
+        assert_eq!(Config::presets()["test-login"], preset);
+        assert!(Config::preset("easy").is_some());
+        assert!(Config::preset("test-unknown").is_none());
+    }
+
+    #[test]
+    fn replaces_presets_of_the_same_name() {
+        let preset = |length| Preset {
+            length: Some(length),
+            ..Preset::default()
+        };
+        Config::register_preset("test-replaced", preset(5)).unwrap();
+        Config::register_preset("test-replaced", preset(7)).unwrap();
+        assert_eq!(Config::preset("test-replaced").unwrap().length, 7);
+    }
+
+    #[test]
+    fn validates_names_and_configs() {
+        for name in ["", "login easy", "login/easy", "é"] {
+            assert!(matches!(
+                Config::register_preset(name, Preset::default()),
+                Err(CaptchaError::InvalidInput(_))
+            ));
+        }
+        let invalid = [
+            Preset {
+                length: Some(0),
+                ..Preset::default()
+            },
+            Preset {
+                width: Some(0),
+                ..Preset::default()
+            },
+            Preset {
+                charset: Some(String::new()),
+                ..Preset::default()
+            },
+            Preset {
+                charset: Some("123".into()),
+                theme: Theme {
+                    text_style: Some(TextStyle::Pronounceable),
+                    ..Theme::default()
+                },
+                ..Preset::default()
+            },
+        ];
+        for (index, preset) in invalid.into_iter().enumerate() {
+            let name = format!("test-invalid-{index}");
+            assert!(Config::register_preset(&name, preset).is_err(), "{name}");
+            assert!(Config::preset(&name).is_none());
+        }
+    }
+
+    #[cfg(feature = "theme")]
+    #[test]
+    fn loads_toml_all_or_nothing() {
+        let count = Config::register_presets_toml(
+            r##"
+            [test-toml-easy]
+            length = 5
+            expires_in_secs = 300
+            theme = { difficulty = "easy", color = "#1d3557" }
+
+            [test-toml-hard]
+            mode = { positions = { count = 2 } }
+            "##,
+        )
+        .unwrap();
+        assert_eq!(count, 2);
+        let easy = Config::preset("test-toml-easy").unwrap();
+        assert_eq!(easy.length, 5);
+        assert_eq!(easy.expires_in, Some(Duration::from_secs(300)));
+        assert_eq!(
+            Config::preset("test-toml-hard").unwrap().mode,
+            Mode::Positions { count: 2 }
+        );
+
+        let broken = r#"
+            [test-toml-valid]
+            length = 5
+
+            [test-toml-broken]
+            length = 0
+        "#;
+        assert!(Config::register_presets_toml(broken).is_err());
+        assert!(Config::preset("test-toml-valid").is_none());
+        assert!(Config::register_presets_toml("[test-toml-unknown]\nsize = 5").is_err());
+
+        let written = Config::presets_toml().unwrap();
+        let read: BTreeMap<String, Preset> = toml::from_str(&written).unwrap();
+        assert_eq!(read["test-toml-easy"], Config::presets()["test-toml-easy"]);
+    }
+}
@@ -0,0 +1,66 @@
+use image::RgbaImage;
+
+use crate::{CaptchaError, Config, Generator, Layout};
+
+/// A rendering of chosen text, see [`Config::preview`]. Not a captcha: its
+/// text is known and its randomness is fixed, so it must not be served for
+/// verification.
+pub struct Preview {
+    pub image: RgbaImage,
+    /// What was drawn where, including the text.
+    pub layout: Layout,
+}
+
+impl Generator {
+    /// Like [`Config::preview`], with this generator's font and config
+    /// as is. Previews of the same config only match with seeded
+    /// [`Config::visual_rng`] and [`Config::answer_rng`].
+    pub fn preview(&self, text: &str) -> Result<Preview, CaptchaError> {
+        let config = Config {
+            length: text.chars().count() as u32,
+            animation: None,
+            ..(*self.config()).clone()
+        };
+        let mut rendered = self.render(&config, Some(text), 0)?;
+        Ok(Preview {
+            image: rendered.frames.swap_remove(0),
+            layout: rendered.layout,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Animation;
+
+    #[test]
+    fn renders_the_same_preview_every_time() {
+        let config = Config::default();
+        let preview = config.preview("HELLO7").unwrap();
+        assert_eq!(preview.layout.text.expose(), "HELLO7");
+        let chars: String = preview
+            .layout
+            .glyphs
+            .iter()
+            .map(|glyph| glyph.char)
+            .collect();
+        assert_eq!(chars, "HELLO7");
+        assert_eq!(preview.image.dimensions(), (config.width, config.height));
+        assert_eq!(config.preview("HELLO7").unwrap().image, preview.image);
+        assert_ne!(config.preview("HELLO8").unwrap().image, preview.image);
+    }
+
+    #[test]
+    fn previews_a_single_frame() {
+        let config = Config {
+            animation: Some(Animation {
+                frames: 4,
+                ..Default::default()
+            }),
+            ..Config::default()
+        };
+        let preview = config.preview("ab").unwrap();
+        assert_eq!(preview.image.dimensions(), (config.width, config.height));
+    }
+}
@@ -0,0 +1,200 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::{Mutex, PoisonError},
+    time::{Duration, Instant},
+};
+
+use crate::CaptchaError;
+
+/// Caps the unsolved captchas issued per client, e.g. per IP address or
+/// session, within a sliding window, so a bot can't make the server render
+/// images as fast as it can ask. Checked before rendering with
+/// [`CaptchaStore::issue_for`].
+///
+/// [`CaptchaStore::issue_for`]: crate::CaptchaStore::issue_for
+pub struct IssuanceQuota {
+    max: u32,
+    window: Duration,
+    max_clients: usize,
+    clients: Mutex<Clients>,
+}
+
+#[derive(Default)]
+struct Clients {
+    issued: HashMap<String, VecDeque<Instant>>,
+    /// Every issue in the window, oldest first, so clients that stopped
+    /// asking are dropped without scanning all of them.
+    order: VecDeque<(Instant, String)>,
+}
+
+/// Default of [`IssuanceQuota::with_max_clients`].
+const DEFAULT_MAX_CLIENTS: usize = 1 << 20;
+
+impl IssuanceQuota {
+    /// Tracks up to about a million clients, see
+    /// [`IssuanceQuota::with_max_clients`].
+    pub fn new(max: u32, window: Duration) -> Self {
+        Self {
+            max,
+            window,
+            max_clients: DEFAULT_MAX_CLIENTS,
+            clients: Mutex::new(Clients::default()),
+        }
+    }
+
+    /// Tracks at most `max_clients` clients with captchas in the window.
+    /// While that many are tracked, further clients are refused, so a
+    /// flood of addresses can't grow the quota without bound.
+    pub fn with_max_clients(mut self, max_clients: usize) -> Self {
+        self.max_clients = max_clients;
+        self
+    }
+
+    /// Counts a captcha issued to `client`, or fails with
+    /// [`CaptchaError::QuotaExceeded`] when it already has the maximum, or
+    /// is new while [`IssuanceQuota::with_max_clients`] are tracked.
+    pub fn acquire(&self, client: &str) -> Result<(), CaptchaError> {
+        let now = Instant::now();
+        let mut clients = self.clients.lock().unwrap_or_else(PoisonError::into_inner);
+        let Clients { issued, order } = &mut *clients;
+        while let Some((at, _)) = order.front()
+            && now.duration_since(*at) >= self.window
+        {
+            let (_, expired) = order.pop_front().expect("checked above");
+            if let Some(queue) = issued.get_mut(&expired) {
+                self.prune(queue, now);
+                if queue.is_empty() {
+                    issued.remove(&expired);
+                }
+            }
+        }
+
+        let retry_after = |since: Option<Instant>| {
+            since.map_or(Duration::ZERO, |at| self.window - now.duration_since(at))
+        };
+        if !issued.contains_key(client) && issued.len() >= self.max_clients {
+            let retry_after = retry_after(order.front().map(|&(at, _)| at));
+            return Err(CaptchaError::QuotaExceeded { retry_after });
+        }
+        let queue = issued.entry(client.to_string()).or_default();
+        self.prune(queue, now);
+        if queue.len() >= self.max as usize {
+            let retry_after = retry_after(queue.front().copied());
+            if queue.is_empty() {
+                issued.remove(client);
+            }
+            return Err(CaptchaError::QuotaExceeded { retry_after });
+        }
+        queue.push_back(now);
+        order.push_back((now, client.to_string()));
+        Ok(())
+    }
+
+    /// Gives `client` one captcha back, e.g. once it solved one, so
+    /// people taking a few tries aren't locked out for the whole window.
+    pub fn release(&self, client: &str) {
+        let mut clients = self.clients.lock().unwrap_or_else(PoisonError::into_inner);
+        let Clients { issued, order } = &mut *clients;
+        let Some(queue) = issued.get_mut(client) else {
+            return;
+        };
+        let released = queue.pop_back();
+        if queue.is_empty() {
+            issued.remove(client);
+        }
+        // Usually issued moments ago, so near the back.
+        if let Some(index) = order
+            .iter()
+            .rposition(|(at, name)| Some(*at) == released && name == client)
+        {
+            order.remove(index);
+        }
+    }
+
+    /// Drops the issues of `queue` that left the window.
+    fn prune(&self, queue: &mut VecDeque<Instant>, now: Instant) {
+        while queue
+            .front()
+            .is_some_and(|&at| now.duration_since(at) >= self.window)
+        {
+            queue.pop_front();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread::sleep;
+
+    use super::*;
+
+    fn tracked(quota: &IssuanceQuota) -> (usize, usize) {
+        let clients = quota.clients.lock().unwrap();
+        (clients.issued.len(), clients.order.len())
+    }
+
+    #[test]
+    fn caps_captchas_per_client() {
+        let window = Duration::from_secs(60);
+        let quota = IssuanceQuota::new(2, window);
+        assert!(quota.acquire("a").is_ok());
+        assert!(quota.acquire("a").is_ok());
+        match quota.acquire("a") {
+            Err(CaptchaError::QuotaExceeded { retry_after }) => {
+                assert!(retry_after > Duration::ZERO && retry_after <= window)
+            }
+            other => panic!("expected the quota to be exceeded, got {other:?}"),
+        }
+        assert!(quota.acquire("b").is_ok());
+
+        quota.release("a");
+        assert!(quota.acquire("a").is_ok());
+        assert!(quota.acquire("a").is_err());
+    }
+
+    #[test]
+    fn forgets_released_captchas() {
+        let quota = IssuanceQuota::new(2, Duration::from_secs(60));
+        assert!(quota.acquire("a").is_ok());
+        assert!(quota.acquire("b").is_ok());
+        assert!(quota.acquire("a").is_ok());
+        quota.release("a");
+        assert_eq!(tracked(&quota), (2, 2));
+        quota.release("a");
+        quota.release("a");
+        assert_eq!(tracked(&quota), (1, 1));
+        let clients = quota.clients.lock().unwrap();
+        assert_eq!(
+            clients.order.front().map(|(_, name)| name.as_str()),
+            Some("b")
+        );
+    }
+
+    #[test]
+    fn forgets_clients_after_the_window() {
+        let quota = IssuanceQuota::new(1, Duration::from_millis(20));
+        assert!(quota.acquire("a").is_ok());
+        assert!(quota.acquire("b").is_ok());
+        assert!(quota.acquire("a").is_err());
+        sleep(Duration::from_millis(30));
+        assert!(quota.acquire("a").is_ok());
+        assert_eq!(tracked(&quota), (1, 1));
+    }
+
+    #[test]
+    fn bounds_the_clients_tracked() {
+        let quota = IssuanceQuota::new(1, Duration::from_millis(20)).with_max_clients(2);
+        assert!(quota.acquire("a").is_ok());
+        assert!(quota.acquire("b").is_ok());
+        assert!(quota.acquire("c").is_err());
+        quota.release("b");
+        assert!(quota.acquire("c").is_ok());
+        sleep(Duration::from_millis(30));
+        assert!(quota.acquire("d").is_ok());
+        assert_eq!(tracked(&quota).0, 1);
+
+        let closed = IssuanceQuota::new(0, Duration::from_secs(60));
+        assert!(closed.acquire("a").is_err());
+        assert_eq!(tracked(&closed), (0, 0));
+    }
+}
@@ -0,0 +1,884 @@
+use std::{f32::consts::PI, time::Instant};
+
+use image::{Rgba, RgbaImage, imageops};
+use imageproc::geometric_transformations::Interpolation;
+use rand::{Rng, seq::IndexedRandom};
+use raqote::{Color, DrawTarget, SolidSource, Source};
+
+use crate::{
+    Answer, BlendMode, CaptchaError, ColorScheme, Config, Format, Generator, GlyphBox, Instruction,
+    Layout, LineStyle, Mode, NoisePath, Stage, StageTiming,
+    atlas::Sprite,
+    blend::blend,
+    composite,
+    exclusion::NoiseGuard,
+    glyph::Mask,
+    instruction,
+    mode::Plan,
+    outline::LoadedFont,
+    pipeline,
+    source::SourceRng,
+    stroke::{self, Stroke},
+    typeface,
+};
+
+/// A composed captcha before encoding.
+pub(crate) struct Rendered {
+    /// A single image unless animated.
+    pub frames: Vec<RgbaImage>,
+    pub answer: Answer,
+    pub instruction: Option<Instruction>,
+    pub layout: Layout,
+    pub stage_timings: Vec<StageTiming>,
+}
+
+/// A captcha up to its noise: the canvas with the background and decoys,
+/// and the glyphs still to be composited onto it, so the noise can be
+/// drawn by either backend.
+pub(crate) struct Prepared {
+    /// Premultiplied, like everything while rendering.
+    pub canvas: RgbaImage,
+    /// Glyph images and where they go, in drawing order.
+    pub layers: Vec<(RgbaImage, i64, i64)>,
+    pub guard: NoiseGuard,
+    pub rng: SourceRng,
+    pub stages: Vec<Stage>,
+    /// Decoys so far; drawn noise paths are added.
+    pub noise: Vec<NoisePath>,
+    text: Answer,
+    plan: Plan,
+    glyphs: Vec<GlyphBox>,
+    background_color: Rgba<u8>,
+}
+
+impl Generator {
+    /// Renders a fresh answer, or `text` when given, as the `sequence`th
+    /// captcha, see [`Generator::next_sequence`].
+    pub(crate) fn render(
+        &self,
+        config: &Config,
+        text: Option<&str>,
+        sequence: u64,
+    ) -> Result<Rendered, CaptchaError> {
+        let mut prepared = self.prepare(config, text, sequence)?;
+        for (layer, x, y) in &prepared.layers {
+            blend(
+                &mut prepared.canvas,
+                layer,
+                *x,
+                *y,
+                config.glyph_style.blend,
+            );
+        }
+
+        let frame_count = match config.animation {
+            Some(animation) if animation.frames == 0 => {
+                return Err(CaptchaError::InvalidInput(
+                    "animations need at least 1 frame".into(),
+                ));
+            }
+            Some(animation) => animation.frames as usize,
+            None => 1,
+        };
+        let ripple = config.animation.and_then(|animation| animation.ripple);
+        let mut frames = Vec::with_capacity(frame_count);
+        let mut canvas = NoiseCanvas::new(config.width, config.height);
+        let mut stage_timings: Vec<StageTiming> = prepared
+            .stages
+            .iter()
+            .map(|&stage| StageTiming {
+                stage,
+                elapsed: Default::default(),
+            })
+            .collect();
+        for index in 0..frame_count {
+            let mut frame = match &ripple {
+                Some(ripple) => ripple.apply(&prepared.canvas, index as f32 / frame_count as f32),
+                None if index + 1 == frame_count => std::mem::take(&mut prepared.canvas),
+                None => prepared.canvas.clone(),
+            };
+            let paths = run_pipeline(
+                &mut stage_timings,
+                &mut frame,
+                &mut canvas,
+                &config.line_style,
+                &prepared.guard,
+                matches!(config.format, Format::Dithered { .. }),
+                &mut prepared.rng,
+            );
+            if index == 0 {
+                prepared.noise.extend(paths);
+            }
+            frames.push(frame);
+        }
+        prepared.finish(config, self.font.as_ref(), frames, stage_timings)
+    }
+
+    /// Picks the text and lays out the glyphs, leaving the noise stages.
+    pub(crate) fn prepare(
+        &self,
+        config: &Config,
+        text: Option<&str>,
+        sequence: u64,
+    ) -> Result<Prepared, CaptchaError> {
+        if config.length == 0 || config.width == 0 || config.height == 0 {
+            return Err(CaptchaError::InvalidInput(
+                "length, width and height must be at least 1".into(),
+            ));
+        }
+        config.check_size()?;
+        if self.font.is_none() {
+            // Font-free generators are built without checking the charset.
+            config.check_glyphs(None)?;
+        }
+
+        let font = self.font.as_ref();
+
+        let mut answer_rng = config.answer_rng.rng_for(sequence);
+        let (captcha_text, font_size) = match (text, &config.text_provider) {
+            (Some(text), _) => (Answer::new(text.to_string()), config.font_size(font)),
+            (None, Some(provider)) => {
+                let text = provider.next(&mut answer_rng);
+                if text.is_empty() {
+                    return Err(CaptchaError::InvalidInput(
+                        "the text provider made an empty text".into(),
+                    ));
+                }
+                let missing = config.missing_glyphs(font, &text);
+                if !missing.is_empty() {
+                    return Err(CaptchaError::InvalidInput(format!(
+                        "the text provider made characters the typeface can't draw: {missing:?}"
+                    )));
+                }
+                // Provided texts don't follow the config's length and charset.
+                let font_size = config.text_font_size(font, &text);
+                if font_size < 1.0 {
+                    return Err(CaptchaError::InvalidInput(
+                        "the text provider made a text too long to fit the width".into(),
+                    ));
+                }
+                (Answer::new(text), font_size)
+            }
+            (None, None) => (
+                Answer::new(config.text_style.generate(
+                    &config.char_weights,
+                    config.charset(),
+                    config.length,
+                    &mut answer_rng,
+                )?),
+                config.font_size(font),
+            ),
+        };
+
+        let plan = config.mode.plan(
+            captcha_text.expose(),
+            config.color.to_rgb(),
+            &mut answer_rng,
+        );
+
+        let mut rng = config.visual_rng.rng_for(sequence);
+
+        config.glyph_style.check(font_size)?;
+
+        let width = config.width;
+        let height = config.height;
+
+        // Premultiplied from here on, see `composite`.
+        let background_color =
+            composite::premultiply_pixel(Rgba(config.background_color.to_rgba()));
+
+        let mut img = config
+            .background
+            .render(width, height, background_color, &mut rng);
+
+        let noise = config.decoys.stamp(
+            &mut img,
+            &config.typeface,
+            font,
+            captcha_text.expose(),
+            font_size,
+            config.color.to_rgb(),
+            &mut rng,
+        )?;
+
+        let atlas = self
+            .atlas_for(config)
+            .filter(|atlas| atlas.size == font_size);
+        let rasterized_fonts = captcha_text
+            .expose()
+            .chars()
+            .zip(&plan.scales)
+            .map(|(c, &scale)| {
+                let c = config.glyph_char(c);
+                if let Some(sprite) = atlas
+                    .as_ref()
+                    .and_then(|atlas| atlas.sprite(font, c, scale, &mut rng))
+                {
+                    return Ok(Glyph::Sprite(sprite));
+                }
+                config
+                    .typeface
+                    .rasterize(font, c, font_size * scale, &config.glyph_style, &mut rng)
+                    .map(|(mask, advance)| Glyph::Raster(mask, advance))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let fonts_width: f32 = rasterized_fonts.iter().map(Glyph::advance).sum();
+        // Given and provided texts can differ in length from the config.
+        let shown = rasterized_fonts.len() as f32;
+        let even = (config.width as f32 - fonts_width) / (shown + 1.0);
+        let spacing = even + config.glyph_spacing;
+
+        let mut x_offset = even - config.glyph_spacing * (shown - 1.0) / 2.0; // 起始 X 位置
+
+        let color_scheme = match config.mode {
+            Mode::Colors { .. } => ColorScheme::Solid,
+            _ => config.color_scheme,
+        };
+
+        let mut text_colors = plan.colors.clone();
+        if let ColorScheme::Gradient { to, .. } = color_scheme {
+            text_colors.push(to.to_rgb());
+        }
+        let mut guard = NoiseGuard::new(&config.line_style, text_colors, width, height);
+
+        let mut glyphs = Vec::with_capacity(rasterized_fonts.len());
+        let mut layers = Vec::with_capacity(rasterized_fonts.len());
+        let mut previous: Option<(RgbaImage, i64, i64, usize)> = None;
+
+        for ((glyph, color), c) in rasterized_fonts
+            .into_iter()
+            .zip(&plan.colors)
+            .zip(captcha_text.expose().chars())
+        {
+            let advance_width = glyph.advance();
+            let (rotated, mut px, rotate_angle) = match glyph {
+                Glyph::Sprite(sprite) => (
+                    color_scheme.paint(&sprite.mask, *color, config.color.a),
+                    x_offset as i64 - sprite.inset,
+                    sprite.angle,
+                ),
+                Glyph::Raster(mut mask, _) => {
+                    config
+                        .glyph_style
+                        .apply(&mut mask, config.max_pixels, &mut rng)?;
+                    if mask.width == 0 || mask.height == 0 {
+                        // Nothing to draw, e.g. at a font size of zero.
+                        x_offset += advance_width + spacing;
+                        continue;
+                    }
+
+                    let font_img = color_scheme.paint(&mask, *color, config.color.a);
+
+                    let rotate_angle = (PI / 8.0) * rng.random_range(-1.0..1.0);
+
+                    let (rotated_width, rotated_height) =
+                        rotated_rect_size(mask.width as f32, mask.height as f32, rotate_angle);
+
+                    let mut expanded = RgbaImage::new(rotated_width as u32, rotated_height as u32);
+                    imageops::replace(
+                        &mut expanded,
+                        &font_img,
+                        ((rotated_width as u32 - font_img.width()) / 2) as i64,
+                        ((rotated_height as u32 - font_img.height()) / 2) as i64,
+                    );
+
+                    let rotated = imageproc::geometric_transformations::rotate_about_center(
+                        &expanded,
+                        rotate_angle,
+                        Interpolation::Bilinear,
+                        Rgba([0, 0, 0, 0]),
+                    );
+
+                    let px =
+                        (x_offset as i64) - (rotated_width as i64 - font_img.width() as i64) / 2;
+                    (rotated, px, rotate_angle)
+                }
+            };
+
+            let py = ((config.height as f32 - rotated.height() as f32) / 2.0) as i64;
+            let glyph_ink = ink(&rotated);
+            if let Some((prev, prev_x, prev_y, prev_ink)) = &previous
+                && config.max_glyph_overlap < 1.0
+            {
+                // Push the glyph right until it overlaps little enough.
+                let allowed = config.max_glyph_overlap * glyph_ink.min(*prev_ink) as f32;
+                let mut shift = 0;
+                while shift < rotated.width() as i64
+                    && shared_ink(prev, *prev_x, *prev_y, &rotated, px + shift, py) as f32 > allowed
+                {
+                    shift += 1;
+                }
+                px += shift;
+                x_offset += shift as f32;
+            }
+            guard.add_glyph(&rotated, px, py);
+
+            glyphs.push(GlyphBox {
+                char: c,
+                x: px as i32,
+                y: py as i32,
+                width: rotated.width(),
+                height: rotated.height(),
+                rotation: rotate_angle,
+            });
+
+            x_offset += advance_width + spacing;
+            if let Some((prev, prev_x, prev_y, _)) = previous.take() {
+                layers.push((prev, prev_x, prev_y));
+            }
+            previous = Some((rotated, px, py, glyph_ink));
+        }
+        if let Some((last, x, y, _)) = previous {
+            layers.push((last, x, y));
+        }
+
+        let stages = config
+            .pipeline_choices
+            .choose(&mut rng)
+            .unwrap_or(&config.pipeline)
+            .clone();
+
+        Ok(Prepared {
+            canvas: img,
+            layers,
+            guard,
+            rng,
+            stages,
+            noise,
+            text: captcha_text,
+            plan,
+            glyphs,
+            background_color,
+        })
+    }
+}
+
+impl Prepared {
+    /// Turns the frames drawn from this into the rendered captcha: crops
+    /// them, adds the instruction and undoes the premultiplication.
+    pub(crate) fn finish(
+        self,
+        config: &Config,
+        font: Option<&LoadedFont>,
+        mut frames: Vec<RgbaImage>,
+        stage_timings: Vec<StageTiming>,
+    ) -> Result<Rendered, CaptchaError> {
+        let Self {
+            mut noise,
+            text,
+            plan,
+            mut glyphs,
+            background_color,
+            ..
+        } = self;
+        let (width, height) = match config.crop_margin {
+            Some(margin) => crop_to_glyphs(&mut frames, &mut glyphs, &mut noise, margin),
+            None => (config.width, config.height),
+        };
+
+        let instruction = plan.question.map(|question| Instruction {
+            text: (config.instruction_text)(&question),
+            question,
+        });
+
+        if let Some(instruction) = &instruction {
+            let font = font.ok_or(typeface::NO_FONT)?.primary();
+            for frame in &mut frames {
+                *frame = instruction::render_instruction(
+                    frame,
+                    font,
+                    &instruction.text,
+                    config.color.to_rgb(),
+                    background_color,
+                );
+            }
+        }
+
+        frames.iter_mut().for_each(composite::unpremultiply);
+
+        Ok(Rendered {
+            frames,
+            answer: plan.answer,
+            instruction,
+            layout: Layout {
+                text,
+                width,
+                height,
+                glyphs,
+                noise,
+            },
+            stage_timings,
+        })
+    }
+}
+
+/// Crops every frame to the glyphs plus `margin` pixels, within the
+/// canvas, and moves the layout along. Returns the new size.
+fn crop_to_glyphs(
+    frames: &mut [RgbaImage],
+    glyphs: &mut [GlyphBox],
+    noise: &mut [NoisePath],
+    margin: u32,
+) -> (u32, u32) {
+    let (width, height) = frames[0].dimensions();
+    let margin = margin as i64;
+    let left = glyphs.iter().map(|glyph| glyph.x as i64).min();
+    let top = glyphs.iter().map(|glyph| glyph.y as i64).min();
+    let right = glyphs
+        .iter()
+        .map(|glyph| glyph.x as i64 + glyph.width as i64)
+        .max();
+    let bottom = glyphs
+        .iter()
+        .map(|glyph| glyph.y as i64 + glyph.height as i64)
+        .max();
+    let (Some(left), Some(top), Some(right), Some(bottom)) = (left, top, right, bottom) else {
+        return (width, height);
+    };
+    let left = (left - margin).clamp(0, width as i64) as u32;
+    let top = (top - margin).clamp(0, height as i64) as u32;
+    let right = (right + margin).clamp(0, width as i64) as u32;
+    let bottom = (bottom + margin).clamp(0, height as i64) as u32;
+    if right <= left || bottom <= top {
+        return (width, height);
+    }
+
+    let (cropped_width, cropped_height) = (right - left, bottom - top);
+    for frame in frames {
+        *frame = imageops::crop_imm(frame, left, top, cropped_width, cropped_height).to_image();
+    }
+    for glyph in glyphs {
+        glyph.x -= left as i32;
+        glyph.y -= top as i32;
+    }
+    for path in noise {
+        path.translate(-(left as f32), -(top as f32));
+    }
+    (cropped_width, cropped_height)
+}
+
+/// A glyph either taken from the sprite atlas, already styled and rotated,
+/// or freshly rasterized.
+enum Glyph<'a> {
+    Sprite(&'a Sprite),
+    Raster(Mask, f32),
+}
+
+impl Glyph<'_> {
+    fn advance(&self) -> f32 {
+        match self {
+            Glyph::Sprite(sprite) => sprite.advance,
+            Glyph::Raster(_, advance) => *advance,
+        }
+    }
+}
+
+pub(crate) fn rotated_rect_size(width: f32, height: f32, angle: f32) -> (f32, f32) {
+    let cos_a = angle.cos();
+    let sin_a = angle.sin();
+
+    // 原图的四个角相对于中心的偏移
+    let hw = width / 2.0;
+    let hh = height / 2.0;
+
+    // 四个角点
+    let corners = [(-hw, -hh), (hw, -hh), (hw, hh), (-hw, hh)];
+
+    let mut min_x = f32::INFINITY;
+    let mut max_x = f32::NEG_INFINITY;
+    let mut min_y = f32::INFINITY;
+    let mut max_y = f32::NEG_INFINITY;
+
+    for &(x, y) in &corners {
+        // 旋转每个角点
+        let rx = x * cos_a - y * sin_a;
+        let ry = x * sin_a + y * cos_a;
+        min_x = min_x.min(rx);
+        max_x = max_x.max(rx);
+        min_y = min_y.min(ry);
+        max_y = max_y.max(ry);
+    }
+
+    // Never smaller than the original, so it always fits inside.
+    let rotated_width = (max_x - min_x).ceil().max(width);
+    let rotated_height = (max_y - min_y).ceil().max(height);
+
+    (rotated_width, rotated_height)
+}
+
+/// Alpha from which a glyph pixel counts as ink.
+const INK: u8 = 64;
+
+fn ink(img: &RgbaImage) -> usize {
+    img.pixels().filter(|p| p[3] >= INK).count()
+}
+
+/// Pixels inked in both `a` and `b`, placed at the given offsets.
+fn shared_ink(a: &RgbaImage, ax: i64, ay: i64, b: &RgbaImage, bx: i64, by: i64) -> usize {
+    let (left, right) = (
+        ax.max(bx),
+        (ax + a.width() as i64).min(bx + b.width() as i64),
+    );
+    let (top, bottom) = (
+        ay.max(by),
+        (ay + a.height() as i64).min(by + b.height() as i64),
+    );
+    let mut shared = 0;
+    for y in top..bottom {
+        for x in left..right {
+            let pa = a.get_pixel((x - ax) as u32, (y - ay) as u32);
+            let pb = b.get_pixel((x - bx) as u32, (y - by) as u32);
+            if pa[3] >= INK && pb[3] >= INK {
+                shared += 1;
+            }
+        }
+    }
+    shared
+}
+
+/// A full-size draw target and RGBA buffer, reused for every noise path
+/// and attempt, so noise needs two canvases of memory however many paths
+/// are drawn or re-rolled.
+struct NoiseCanvas {
+    dt: DrawTarget,
+    rgba: Vec<u8>,
+}
+
+impl NoiseCanvas {
+    fn new(width: u32, height: u32) -> Self {
+        Self {
+            dt: DrawTarget::new(width as i32, height as i32),
+            rgba: Vec::with_capacity(width as usize * height as usize * 4),
+        }
+    }
+
+    fn clear(&mut self) -> &mut DrawTarget {
+        self.dt
+            .clear(SolidSource::from_unpremultiplied_argb(0, 0, 0, 0));
+        &mut self.dt
+    }
+}
+
+fn merge(img: &mut RgbaImage, canvas: &mut NoiseCanvas, mode: BlendMode) {
+    let width = canvas.dt.width();
+    let height = canvas.dt.height();
+
+    let mut rgba_data = std::mem::take(&mut canvas.rgba);
+    rgba_data.clear();
+    composite::argb_to_rgba(canvas.dt.get_data(), &mut rgba_data);
+
+    let font_img = RgbaImage::from_raw(width as u32, height as u32, rgba_data).unwrap();
+
+    blend(img, &font_img, 0, 0, mode);
+    canvas.rgba = font_img.into_raw();
+}
+
+/// Applies the stages in order, adding the time each took to its timing.
+/// Returns the noise paths drawn.
+fn run_pipeline(
+    stages: &mut [StageTiming],
+    img: &mut RgbaImage,
+    canvas: &mut NoiseCanvas,
+    style: &LineStyle,
+    guard: &NoiseGuard,
+    opaque_curves: bool,
+    rng: &mut impl Rng,
+) -> Vec<NoisePath> {
+    let mut noise = Vec::new();
+    for timing in stages {
+        let started = Instant::now();
+        match timing.stage {
+            Stage::Lines { count } => {
+                for _ in 0..count.min(MAX_PATHS) {
+                    noise.extend(draw_line(img, canvas, style, guard, rng));
+                }
+            }
+            Stage::Curves { count } => {
+                for _ in 0..count.min(MAX_PATHS) {
+                    noise.extend(draw_cubic_line(
+                        img,
+                        canvas,
+                        style,
+                        guard,
+                        opaque_curves,
+                        rng,
+                    ));
+                }
+            }
+            Stage::Wave {
+                amplitude,
+                wavelength,
+            } => *img = pipeline::wave(img, amplitude, wavelength, rng),
+            Stage::Mesh {
+                cells,
+                displacement,
+            } => *img = pipeline::mesh(img, cells, displacement, rng),
+            Stage::Swirl {
+                count,
+                radius,
+                angle,
+            } => *img = pipeline::swirl(img, count, radius, angle, rng),
+            Stage::Blur { sigma } => *img = pipeline::blur(img, sigma),
+            Stage::Effect(effect) => effect.apply(img, rng),
+        }
+        timing.elapsed += started.elapsed();
+    }
+    noise
+}
+
+/// Paths per stage, beyond which the image is a solid scribble anyway.
+pub(crate) const MAX_PATHS: u32 = 256;
+
+/// A noise path before it is drawn.
+pub(crate) struct NoiseStroke {
+    pub stroke: Stroke,
+    pub color: [u8; 3],
+    pub alpha: u8,
+    pub path: NoisePath,
+}
+
+impl NoiseStroke {
+    fn draw(&self, dt: &mut DrawTarget) {
+        let [r, g, b] = self.color;
+        let source = Source::Solid(SolidSource::from(Color::new(self.alpha, r, g, b)));
+        self.stroke.draw(dt, &source);
+    }
+}
+
+/// A random straight line across a `width` by `height` canvas.
+pub(crate) fn random_line(
+    width: u32,
+    height: u32,
+    style: &LineStyle,
+    guard: &NoiseGuard,
+    rng: &mut impl Rng,
+) -> NoiseStroke {
+    let x1 = rng.random_range(0..width);
+    let y1 = rng.random_range(0..height);
+    let x2 = rng.random_range(0..width);
+    let y2 = rng.random_range(0..height);
+
+    let from = (x1 as f32, y1 as f32);
+    let to = (x2 as f32, y2 as f32);
+    let points = if style.wobble > 0.0 || style.max_width > style.min_width {
+        stroke::subdivide_line(from, to, 4.0)
+    } else {
+        vec![from, to]
+    };
+
+    let color = guard.color(rng);
+    NoiseStroke {
+        stroke: Stroke::new(&points, style, rng),
+        color,
+        alpha: 255,
+        path: NoisePath::Line { from, to, color },
+    }
+}
+
+/// A random curve from the left to the right edge, half-transparent
+/// unless `opaque`.
+pub(crate) fn random_curve(
+    width: u32,
+    height: u32,
+    style: &LineStyle,
+    guard: &NoiseGuard,
+    opaque: bool,
+    rng: &mut impl Rng,
+) -> NoiseStroke {
+    let x1 = 0;
+    let y1 = rng.random_range(0..height);
+    let x2 = width;
+    let y2 = rng.random_range(0..height);
+
+    // Inclusive, so canvases narrower than 4 pixels still have a range.
+    let cx = rng.random_range((width / 4)..=(width / 4 * 3));
+    let cy = rng.random_range(0..height);
+
+    let from = (x1 as f32, y1 as f32);
+    let control = (cx as f32, cy as f32);
+    let to = (x2 as f32, y2 as f32);
+    let points = stroke::flatten_cubic(from, control, control, to, (width as usize / 4).max(16));
+
+    let color = guard.color(rng);
+    NoiseStroke {
+        stroke: Stroke::new(&points, style, rng),
+        color,
+        alpha: if opaque { 255 } else { 128 },
+        path: NoisePath::Curve {
+            from,
+            control,
+            to,
+            color,
+        },
+    }
+}
+
+/// Draws a [`random_line`], re-rolling any that cover too much of a glyph
+/// and dropping it when none fits.
+fn draw_line(
+    img: &mut RgbaImage,
+    canvas: &mut NoiseCanvas,
+    style: &LineStyle,
+    guard: &NoiseGuard,
+    rng: &mut impl Rng,
+) -> Option<NoisePath> {
+    for _ in 0..guard.attempts() {
+        let line = random_line(img.width(), img.height(), style, guard, rng);
+        let dt = canvas.clear();
+        line.draw(dt);
+        if guard.allows(dt) {
+            merge(img, canvas, style.blend);
+            return Some(line.path);
+        }
+    }
+    None
+}
+
+/// Like [`draw_line`], for a [`random_curve`].
+fn draw_cubic_line(
+    img: &mut RgbaImage,
+    canvas: &mut NoiseCanvas,
+    style: &LineStyle,
+    guard: &NoiseGuard,
+    opaque: bool,
+    rng: &mut impl Rng,
+) -> Option<NoisePath> {
+    for _ in 0..guard.attempts() {
+        let curve = random_curve(img.width(), img.height(), style, guard, opaque, rng);
+        let dt = canvas.clear();
+        curve.draw(dt);
+        if guard.allows(dt) {
+            merge(img, canvas, style.blend);
+            return Some(curve.path);
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::RngSource;
+
+    #[test]
+    fn counts_ink_shared_by_placed_glyphs() {
+        let glyph = RgbaImage::from_pixel(4, 4, Rgba([0, 0, 0, 255]));
+        assert_eq!(ink(&glyph), 16);
+        assert_eq!(shared_ink(&glyph, 0, 0, &glyph, 0, 0), 16);
+        assert_eq!(shared_ink(&glyph, 0, 0, &glyph, 2, 1), 6);
+        assert_eq!(shared_ink(&glyph, 0, 0, &glyph, 4, 0), 0);
+
+        let faint = RgbaImage::from_pixel(4, 4, Rgba([0, 0, 0, INK - 1]));
+        assert_eq!(shared_ink(&glyph, 0, 0, &faint, 0, 0), 0);
+    }
+
+    fn glyph(x: i32, y: i32, width: u32, height: u32) -> GlyphBox {
+        GlyphBox {
+            char: 'A',
+            x,
+            y,
+            width,
+            height,
+            rotation: 0.0,
+        }
+    }
+
+    #[test]
+    fn crops_to_the_glyphs_plus_margin() {
+        let canvas = RgbaImage::from_fn(100, 50, |x, y| Rgba([x as u8, y as u8, 0, 255]));
+        let mut frames = vec![canvas.clone(), canvas.clone()];
+        let mut glyphs = [glyph(20, 10, 10, 15), glyph(40, 12, 10, 20)];
+        let mut noise = [NoisePath::Line {
+            from: (0.0, 0.0),
+            to: (100.0, 50.0),
+            color: [0; 3],
+        }];
+        let size = crop_to_glyphs(&mut frames, &mut glyphs, &mut noise, 3);
+
+        assert_eq!(size, (53 - 17, 35 - 7));
+        for frame in &frames {
+            assert_eq!(frame.dimensions(), size);
+            assert_eq!(frame.get_pixel(0, 0), canvas.get_pixel(17, 7));
+        }
+        assert_eq!((glyphs[0].x, glyphs[0].y), (3, 3));
+        assert_eq!((glyphs[1].x, glyphs[1].y), (23, 5));
+        assert!(matches!(
+            noise[0],
+            NoisePath::Line {
+                from: (-17.0, -7.0),
+                ..
+            }
+        ));
+
+        // Margins stop at the canvas.
+        let mut frames = vec![canvas.clone()];
+        let mut glyphs = [glyph(20, 10, 10, 15)];
+        assert_eq!(
+            crop_to_glyphs(&mut frames, &mut glyphs, &mut [], 30),
+            (60, 50)
+        );
+        assert_eq!((glyphs[0].x, glyphs[0].y), (20, 10));
+    }
+
+    #[test]
+    fn reports_the_cropped_size() {
+        let config = Config {
+            crop_margin: Some(2),
+            answer_rng: RngSource::Seeded(1),
+            visual_rng: RngSource::Seeded(1),
+            ..Config::default()
+        };
+        let captcha = config.generate().unwrap();
+        let image = image::load_from_memory(&captcha.image).unwrap();
+        assert_eq!(
+            (image.width(), image.height()),
+            (captcha.width, captcha.height)
+        );
+        assert!(captcha.width < config.width && captcha.height < config.height);
+    }
+
+    #[test]
+    fn picks_a_noise_pipeline_per_captcha() {
+        let choices = vec![
+            vec![Stage::Lines { count: 2 }],
+            vec![Stage::Curves { count: 1 }],
+            vec![Stage::Blur { sigma: 0.5 }, Stage::Lines { count: 1 }],
+        ];
+        let generator = Generator::new(Config {
+            pipeline_choices: choices.clone(),
+            visual_rng: RngSource::Seeded(1),
+            ..Config::default()
+        })
+        .unwrap();
+        let mut picked = Vec::new();
+        for _ in 0..24 {
+            let captcha = generator.generate().unwrap();
+            let stages: Vec<_> = captcha
+                .stage_timings
+                .iter()
+                .map(|timing| timing.stage)
+                .collect();
+            assert!(choices.contains(&stages), "{stages:?}");
+            if !picked.contains(&stages) {
+                picked.push(stages);
+            }
+        }
+        assert_eq!(picked.len(), choices.len());
+    }
+
+    #[test]
+    fn moves_overlapping_glyphs_apart() {
+        let overlap = |max_glyph_overlap| {
+            let config = Config {
+                glyph_spacing: -40.0,
+                max_glyph_overlap,
+                answer_rng: RngSource::Seeded(1),
+                visual_rng: RngSource::Seeded(1),
+                ..Config::default()
+            };
+            config.generate().unwrap().difficulty.overlap
+        };
+        assert!(overlap(0.0) < overlap(1.0));
+    }
+}
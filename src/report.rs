@@ -0,0 +1,159 @@
+use crate::{Config, Layout, NoisePath, json::JsonObject};
+
+/// What was actually applied to a captcha, so solve rates can be
+/// correlated with real difficulty rather than the configured ranges. It
+/// says nothing about the answer and may be logged.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct DifficultyReport {
+    /// Smallest and largest glyph rotation, in radians.
+    pub min_rotation: f32,
+    pub max_rotation: f32,
+    /// Outline displacement of font glyphs, see [`GlyphStyle::jitter`].
+    ///
+    /// [`GlyphStyle::jitter`]: crate::GlyphStyle::jitter
+    pub jitter: f32,
+    /// Sideways displacement of interference lines, see
+    /// [`LineStyle::wobble`].
+    ///
+    /// [`LineStyle::wobble`]: crate::LineStyle::wobble
+    pub wobble: f32,
+    pub lines: u32,
+    pub curves: u32,
+    pub decoys: u32,
+    /// Area shared by neighbouring glyph boxes relative to the area of all
+    /// glyph boxes, from 0 for well separated glyphs.
+    pub overlap: f32,
+}
+
+impl DifficultyReport {
+    pub(crate) fn new(config: &Config, layout: &Layout) -> Self {
+        let mut report = Self {
+            jitter: config.glyph_style.jitter,
+            wobble: config.line_style.wobble,
+            ..Self::default()
+        };
+
+        if !layout.glyphs.is_empty() {
+            let rotations = layout.glyphs.iter().map(|glyph| glyph.rotation);
+            report.min_rotation = rotations.clone().fold(f32::INFINITY, f32::min);
+            report.max_rotation = rotations.fold(f32::NEG_INFINITY, f32::max);
+        }
+
+        for path in &layout.noise {
+            match path {
+                NoisePath::Line { .. } => report.lines += 1,
+                NoisePath::Curve { .. } => report.curves += 1,
+                NoisePath::Fragment { .. } => report.decoys += 1,
+            }
+        }
+
+        let area: f64 = layout
+            .glyphs
+            .iter()
+            .map(|glyph| glyph.width as f64 * glyph.height as f64)
+            .sum();
+        let shared: f64 = layout
+            .glyphs
+            .windows(2)
+            .map(|pair| {
+                let (a, b) = (&pair[0], &pair[1]);
+                let width = (a.x + a.width as i32).min(b.x + b.width as i32) - a.x.max(b.x);
+                let height = (a.y + a.height as i32).min(b.y + b.height as i32) - a.y.max(b.y);
+                width.max(0) as f64 * height.max(0) as f64
+            })
+            .sum();
+        if area > 0.0 {
+            report.overlap = (shared / area) as f32;
+        }
+
+        report
+    }
+
+    pub fn to_json(&self) -> String {
+        JsonObject::new()
+            .number("min_rotation", self.min_rotation)
+            .number("max_rotation", self.max_rotation)
+            .number("jitter", self.jitter)
+            .number("wobble", self.wobble)
+            .number("lines", self.lines)
+            .number("curves", self.curves)
+            .number("decoys", self.decoys)
+            .number("overlap", self.overlap)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Answer, GlyphBox, GlyphStyle};
+
+    fn glyph(x: i32, rotation: f32) -> GlyphBox {
+        GlyphBox {
+            char: 'a',
+            x,
+            y: 0,
+            width: 10,
+            height: 10,
+            rotation,
+        }
+    }
+
+    #[test]
+    fn summarizes_the_layout() {
+        let config = Config {
+            glyph_style: GlyphStyle {
+                jitter: 1.5,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let line = NoisePath::Line {
+            from: (0.0, 0.0),
+            to: (1.0, 1.0),
+            color: [0; 3],
+        };
+        let fragment = NoisePath::Fragment {
+            x: 0,
+            y: 0,
+            width: 1,
+            height: 1,
+        };
+        let layout = Layout {
+            text: Answer::new("aaa".into()),
+            width: 40,
+            height: 10,
+            // The middle glyph overlaps both neighbours by half its width.
+            glyphs: vec![glyph(0, -0.2), glyph(5, 0.1), glyph(10, 0.3)],
+            noise: vec![line, fragment, line],
+        };
+
+        let report = DifficultyReport::new(&config, &layout);
+        assert_eq!((report.min_rotation, report.max_rotation), (-0.2, 0.3));
+        assert_eq!(report.jitter, 1.5);
+        assert_eq!((report.lines, report.curves, report.decoys), (2, 0, 1));
+        assert!((report.overlap - 1.0 / 3.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn reports_nothing_for_empty_layouts() {
+        let layout = Layout {
+            text: Answer::new(String::new()),
+            width: 1,
+            height: 1,
+            glyphs: Vec::new(),
+            noise: Vec::new(),
+        };
+        let report = DifficultyReport::new(&Config::default(), &layout);
+        assert_eq!(report, DifficultyReport::default());
+        assert!(report.to_json().starts_with(r#"{"min_rotation":0,"#));
+    }
+
+    #[test]
+    fn comes_with_every_captcha() {
+        let captcha = Config::default().generate().unwrap();
+        let report = captcha.difficulty;
+        assert!(report.min_rotation <= report.max_rotation);
+        assert!(report.max_rotation != 0.0 || report.min_rotation != 0.0);
+    }
+}
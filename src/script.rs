@@ -0,0 +1,75 @@
+/// Writing systems a [`FallbackFont`] can be picked for.
+///
+/// [`FallbackFont`]: crate::FallbackFont
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum Script {
+    Latin,
+    Greek,
+    Cyrillic,
+    Armenian,
+    Hebrew,
+    Arabic,
+    Devanagari,
+    Thai,
+    Hangul,
+    /// Hiragana and katakana.
+    Kana,
+    /// CJK ideographs.
+    Han,
+    /// Arrows, math and technical symbols, shapes, dingbats and emoji.
+    Symbol,
+}
+
+impl Script {
+    /// The script `c` belongs to, by Unicode block. `None` for characters
+    /// every script shares, such as digits, punctuation and spaces, and
+    /// for scripts not listed.
+    pub fn of(c: char) -> Option<Script> {
+        Some(match c {
+            'A'..='Z'
+            | 'a'..='z'
+            | '\u{AA}'
+            | '\u{BA}'
+            | '\u{C0}'..='\u{D6}'
+            | '\u{D8}'..='\u{F6}'
+            | '\u{F8}'..='\u{24F}'
+            | '\u{1E00}'..='\u{1EFF}'
+            | '\u{2C60}'..='\u{2C7F}'
+            | '\u{A720}'..='\u{A7FF}'
+            | '\u{FF21}'..='\u{FF3A}'
+            | '\u{FF41}'..='\u{FF5A}' => Script::Latin,
+            '\u{370}'..='\u{3FF}' | '\u{1F00}'..='\u{1FFF}' => Script::Greek,
+            '\u{400}'..='\u{52F}' | '\u{2DE0}'..='\u{2DFF}' | '\u{A640}'..='\u{A69F}' => {
+                Script::Cyrillic
+            }
+            '\u{530}'..='\u{58F}' => Script::Armenian,
+            '\u{590}'..='\u{5FF}' | '\u{FB1D}'..='\u{FB4F}' => Script::Hebrew,
+            '\u{600}'..='\u{6FF}'
+            | '\u{750}'..='\u{77F}'
+            | '\u{8A0}'..='\u{8FF}'
+            | '\u{FB50}'..='\u{FDFF}'
+            | '\u{FE70}'..='\u{FEFF}' => Script::Arabic,
+            '\u{900}'..='\u{97F}' => Script::Devanagari,
+            '\u{E00}'..='\u{E7F}' => Script::Thai,
+            '\u{1100}'..='\u{11FF}' | '\u{3130}'..='\u{318F}' | '\u{AC00}'..='\u{D7AF}' => {
+                Script::Hangul
+            }
+            '\u{3040}'..='\u{30FF}' | '\u{31F0}'..='\u{31FF}' | '\u{FF66}'..='\u{FF9F}' => {
+                Script::Kana
+            }
+            '\u{2E80}'..='\u{2FDF}'
+            | '\u{3005}'
+            | '\u{3007}'
+            | '\u{3021}'..='\u{3029}'
+            | '\u{3400}'..='\u{4DBF}'
+            | '\u{4E00}'..='\u{9FFF}'
+            | '\u{F900}'..='\u{FAFF}'
+            | '\u{20000}'..='\u{3FFFF}' => Script::Han,
+            '\u{2190}'..='\u{2BFF}' | '\u{1F300}'..='\u{1FAFF}' => Script::Symbol,
+            _ => return None,
+        })
+    }
+}
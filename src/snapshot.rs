@@ -0,0 +1,182 @@
+use std::{env, path::Path};
+
+use image::RgbaImage;
+use imageproc::filter::gaussian_blur_f32;
+
+use crate::{CaptchaError, Config};
+
+/// Compares renderings against stored golden PNGs, to catch unintended
+/// visual changes when updating the crate or a config.
+///
+/// The image is a [`Config::preview`] of `text`, so it's the same on every
+/// run. Both images are slightly blurred before comparing, so
+/// antialiasing differences of a pixel don't count; the check fails when
+/// more than `max_differing` of the pixels still differ by more than
+/// `threshold` in some channel.
+///
+/// A missing golden file is written instead of compared, as is every file
+/// while `CAPTCHAGEN_UPDATE_SNAPSHOTS` is set, to accept intended changes.
+#[derive(Clone, Debug)]
+pub struct Snapshot {
+    pub text: String,
+    pub threshold: u8,
+    /// Share of pixels, from 0 to 1.
+    pub max_differing: f32,
+}
+
+impl Default for Snapshot {
+    fn default() -> Self {
+        Self {
+            text: "Ab3x9".to_string(),
+            threshold: 24,
+            max_differing: 0.002,
+        }
+    }
+}
+
+impl Snapshot {
+    /// Panics with the share of differing pixels on a mismatch, and saves
+    /// the new rendering next to the golden file as `<name>.new.png`.
+    pub fn assert_matches(&self, config: &Config, golden: impl AsRef<Path>) {
+        let golden = golden.as_ref();
+        let image = match config.preview(&self.text) {
+            Ok(preview) => preview.image,
+            Err(err) => panic!("rendering snapshot {} failed: {err}", golden.display()),
+        };
+        match self.compare(&image, golden) {
+            Ok(differing) if differing <= self.max_differing => {}
+            Ok(differing) => {
+                let new = golden.with_extension("new.png");
+                let saved = image.save(&new).is_ok();
+                panic!(
+                    "snapshot {} differs in {:.2}% of pixels{}",
+                    golden.display(),
+                    differing * 100.0,
+                    if saved {
+                        format!(", new rendering saved to {}", new.display())
+                    } else {
+                        String::new()
+                    }
+                );
+            }
+            Err(err) => panic!("snapshot {} failed: {err}", golden.display()),
+        }
+    }
+
+    /// Share of pixels of `image` differing from the golden file, 1 for
+    /// images of different sizes and 0 when the file was (re)written.
+    pub fn compare(
+        &self,
+        image: &RgbaImage,
+        golden: impl AsRef<Path>,
+    ) -> Result<f32, CaptchaError> {
+        let golden = golden.as_ref();
+        if env::var_os("CAPTCHAGEN_UPDATE_SNAPSHOTS").is_some() || !golden.exists() {
+            if let Some(dir) = golden.parent() {
+                std::fs::create_dir_all(dir)?;
+            }
+            image.save(golden)?;
+            return Ok(0.0);
+        }
+
+        let expected = image::open(golden)?.into_rgba8();
+        if expected.dimensions() != image.dimensions() {
+            return Ok(1.0);
+        }
+        let (expected, actual) = (
+            gaussian_blur_f32(&expected, 0.7),
+            gaussian_blur_f32(image, 0.7),
+        );
+        let differing = expected
+            .pixels()
+            .zip(actual.pixels())
+            .filter(|(a, b)| {
+                a.0.iter()
+                    .zip(&b.0)
+                    .any(|(a, b)| a.abs_diff(*b) > self.threshold)
+            })
+            .count();
+        Ok(differing as f32 / (image.width() * image.height()).max(1) as f32)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use image::Rgba;
+
+    use super::*;
+
+    fn golden(name: &str) -> PathBuf {
+        let dir = env::temp_dir().join(format!("captchagen-snapshot-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join(name).with_extension("png");
+        let _ = std::fs::remove_file(&path);
+        path
+    }
+
+    fn image() -> RgbaImage {
+        RgbaImage::from_fn(40, 20, |x, y| {
+            Rgba([(x * 6) as u8, (y * 12) as u8, 90, 255])
+        })
+    }
+
+    #[test]
+    fn writes_missing_goldens_then_compares() {
+        let path = golden("missing");
+        let snapshot = Snapshot::default();
+        assert_eq!(snapshot.compare(&image(), &path).unwrap(), 0.0);
+        assert!(path.is_file());
+        assert_eq!(snapshot.compare(&image(), &path).unwrap(), 0.0);
+        assert_eq!(
+            snapshot.compare(&RgbaImage::new(20, 20), &path).unwrap(),
+            1.0
+        );
+        let _ = std::fs::remove_file(path);
+    }
+
+    #[test]
+    fn tolerates_small_differences_only() {
+        let path = golden("tolerance");
+        let snapshot = Snapshot::default();
+        snapshot.compare(&image(), &path).unwrap();
+
+        let mut faint = image();
+        faint.put_pixel(10, 10, Rgba([255, 255, 255, 255]));
+        let mut blotched = image();
+        for x in 10..20 {
+            for y in 5..15 {
+                blotched.put_pixel(x, y, Rgba([255, 255, 255, 255]));
+            }
+        }
+        let faint = snapshot.compare(&faint, &path).unwrap();
+        let differing = snapshot.compare(&blotched, &path).unwrap();
+        assert!(faint < 0.02, "{faint}");
+        assert!(differing > 0.1, "{differing}");
+        let lenient = Snapshot {
+            threshold: 255,
+            ..Snapshot::default()
+        };
+        assert_eq!(lenient.compare(&blotched, &path).unwrap(), 0.0);
+        let _ = std::fs::remove_file(path);
+    }
+
+    #[test]
+    fn saves_mismatching_renderings() {
+        let config = Config::default();
+        let path = golden("mismatch");
+        image().save(&path).unwrap();
+        let failed =
+            std::panic::catch_unwind(|| Snapshot::default().assert_matches(&config, &path));
+        assert!(failed.is_err());
+        let new = path.with_extension("new.png");
+        assert!(new.is_file());
+        let _ = std::fs::remove_file(new);
+
+        std::fs::remove_file(&path).unwrap();
+        Snapshot::default().assert_matches(&config, &path);
+        Snapshot::default().assert_matches(&config, &path);
+        let _ = std::fs::remove_file(path);
+    }
+}
@@ -0,0 +1,156 @@
+use rand::{
+    RngCore, SeedableRng, TryRngCore,
+    rngs::{OsRng, StdRng, ThreadRng},
+};
+
+/// Where a captcha's random numbers come from.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub enum RngSource {
+    /// The thread-local generator, a CSPRNG periodically reseeded from the
+    /// OS.
+    #[default]
+    Thread,
+    /// The operating system's generator, read directly for every number.
+    Os,
+    /// A generator seeded with the given value, so output repeats exactly.
+    /// Each captcha of a [`Generator`] draws from its own stream derived
+    /// from the seed and the captcha's place in the sequence, so captchas
+    /// differ from each other while generators seeded alike agree. Never
+    /// use it for answers.
+    ///
+    /// [`Generator`]: crate::Generator
+    Seeded(u64),
+}
+
+impl RngSource {
+    pub(crate) fn rng(&self) -> SourceRng {
+        match *self {
+            RngSource::Thread => SourceRng::Thread(rand::rng()),
+            RngSource::Os => SourceRng::Os(OsRng),
+            RngSource::Seeded(seed) => SourceRng::Seeded(Box::new(StdRng::seed_from_u64(seed))),
+        }
+    }
+
+    /// The generator for the `sequence`th captcha of a generator. Seeded
+    /// sources get a separate stream per captcha.
+    pub(crate) fn rng_for(&self, sequence: u64) -> SourceRng {
+        match *self {
+            RngSource::Seeded(seed) => {
+                let mut key = [0; 32];
+                key[..8].copy_from_slice(&seed.to_le_bytes());
+                key[8..16].copy_from_slice(&sequence.to_le_bytes());
+                SourceRng::Seeded(Box::new(StdRng::from_seed(key)))
+            }
+            _ => self.rng(),
+        }
+    }
+}
+
+pub(crate) enum SourceRng {
+    Thread(ThreadRng),
+    Os(OsRng),
+    Seeded(Box<StdRng>),
+}
+
+impl RngCore for SourceRng {
+    fn next_u32(&mut self) -> u32 {
+        match self {
+            SourceRng::Thread(rng) => rng.next_u32(),
+            SourceRng::Os(rng) => rng
+                .try_next_u32()
+                .expect("OS random number generator failed"),
+            SourceRng::Seeded(rng) => rng.next_u32(),
+        }
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        match self {
+            SourceRng::Thread(rng) => rng.next_u64(),
+            SourceRng::Os(rng) => rng
+                .try_next_u64()
+                .expect("OS random number generator failed"),
+            SourceRng::Seeded(rng) => rng.next_u64(),
+        }
+    }
+
+    fn fill_bytes(&mut self, dst: &mut [u8]) {
+        match self {
+            SourceRng::Thread(rng) => rng.fill_bytes(dst),
+            SourceRng::Os(rng) => rng
+                .try_fill_bytes(dst)
+                .expect("OS random number generator failed"),
+            SourceRng::Seeded(rng) => rng.fill_bytes(dst),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Config, Generator};
+
+    fn draw(source: RngSource) -> [u64; 4] {
+        let mut rng = source.rng();
+        let mut bytes = [0; 8];
+        rng.fill_bytes(&mut bytes);
+        [
+            rng.next_u64(),
+            rng.next_u32() as u64,
+            u64::from_le_bytes(bytes),
+            rng.next_u64(),
+        ]
+    }
+
+    #[test]
+    fn repeats_seeded_sequences_only() {
+        assert_eq!(draw(RngSource::Seeded(1)), draw(RngSource::Seeded(1)));
+        assert_ne!(draw(RngSource::Seeded(1)), draw(RngSource::Seeded(2)));
+        assert_ne!(draw(RngSource::Thread), draw(RngSource::Thread));
+        assert_ne!(draw(RngSource::Os), draw(RngSource::Os));
+    }
+
+    #[test]
+    fn draws_answers_and_visuals_from_separate_sources() {
+        let generate = |answer, visual| {
+            Config {
+                answer_rng: RngSource::Seeded(answer),
+                visual_rng: RngSource::Seeded(visual),
+                ..Config::default()
+            }
+            .generate()
+            .unwrap()
+        };
+        let first = generate(1, 1);
+        let again = generate(1, 1);
+        let restyled = generate(1, 2);
+        let other = generate(2, 1);
+
+        assert_eq!(first.text.expose(), again.text.expose());
+        assert_eq!(first.image, again.image);
+        assert_eq!(first.text.expose(), restyled.text.expose());
+        assert_ne!(first.image, restyled.image);
+        assert_ne!(first.text.expose(), other.text.expose());
+    }
+
+    #[test]
+    fn varies_seeded_captchas_of_one_generator() {
+        let config = Config {
+            answer_rng: RngSource::Seeded(1),
+            visual_rng: RngSource::Seeded(2),
+            ..Config::default()
+        };
+        let generate_two = || {
+            let generator = Generator::new(config.clone()).unwrap();
+            let first = generator.generate().unwrap();
+            (first, generator.generate().unwrap())
+        };
+        let (first, second) = generate_two();
+        assert_ne!(first.text.expose(), second.text.expose());
+        assert_ne!(first.image, second.image);
+
+        let (again, _) = generate_two();
+        assert_eq!(first.text.expose(), again.text.expose());
+        assert_eq!(first.image, again.image);
+    }
+}
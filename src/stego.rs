@@ -0,0 +1,223 @@
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use aes_gcm::{
+    Aes256Gcm, Nonce,
+    aead::{Aead, KeyInit},
+};
+use rand::Rng;
+
+use crate::{
+    Answer, AnswerFormat, Captcha, CaptchaError, Format, ReplayCache, VerifyOutcome,
+    captcha::is_expired, hash::sha256_hex, png_chunks,
+};
+
+/// Type of the ancillary, private, safe-to-copy PNG chunk carrying the
+/// encrypted answer.
+const CHUNK_TYPE: &[u8; 4] = b"caPt";
+
+const NONCE_LEN: usize = 12;
+
+/// Puts an AES-256-GCM encrypted copy of a captcha's id, expiry and answer
+/// into its PNG image, so kiosks holding the same key can check answers
+/// offline, from nothing but the image shown, see
+/// [`KioskKey::extract_and_verify`]. Without the key the chunk reveals
+/// nothing; image viewers ignore it.
+pub struct KioskKey {
+    cipher: Aes256Gcm,
+}
+
+impl KioskKey {
+    pub fn new(key: &[u8; 32]) -> Self {
+        Self {
+            cipher: Aes256Gcm::new(key.into()),
+        }
+    }
+
+    /// Adds the encrypted answer to the image, which must be a plain
+    /// [`Format::Png`] or [`Format::Dithered`] PNG.
+    pub fn embed(&self, captcha: &mut Captcha) -> Result<(), CaptchaError> {
+        if !matches!(captcha.format, Format::Png | Format::Dithered { .. }) {
+            return Err(CaptchaError::InvalidInput(format!(
+                "answers can only be embedded in PNG images, not {:?}",
+                captcha.format
+            )));
+        }
+        let expiry = match captcha.expires_at {
+            Some(expires_at) => expires_at
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |since| since.as_secs())
+                .to_string(),
+            None => "-".to_string(),
+        };
+        // The id is length-prefixed, as ids are caller-supplied and may
+        // contain the separator.
+        let id = &captcha.id;
+        let plaintext = format!("{expiry}.{}.{id}{}", id.len(), captcha.text.expose());
+
+        let nonce: [u8; NONCE_LEN] = rand::rng().random();
+        let ciphertext = self
+            .cipher
+            .encrypt(&Nonce::from(nonce), plaintext.as_bytes())
+            .map_err(|_| CaptchaError::InvalidInput("encrypting the answer failed".into()))?;
+        let mut payload = nonce.to_vec();
+        payload.extend(ciphertext);
+
+        png_chunks::insert(&mut captcha.image, CHUNK_TYPE, &payload)?;
+        if captcha.image_sha256.is_some() {
+            captcha.image_sha256 = Some(sha256_hex(&captcha.image));
+        }
+        Ok(())
+    }
+
+    /// Checks `answer` against the answer embedded in `image`, which is
+    /// [`VerifyOutcome::UnknownId`] when there is none or it was made with
+    /// another key. Any number of attempts can be made until the captcha
+    /// expires; see [`KioskKey::extract_and_verify_once`] to prevent that.
+    pub fn extract_and_verify(&self, image: &[u8], answer: &str) -> VerifyOutcome {
+        match self.extract(image) {
+            Some(embedded) => embedded.verify(answer),
+            None => VerifyOutcome::UnknownId,
+        }
+    }
+
+    /// Like [`KioskKey::extract_and_verify`], allowing a single attempt per
+    /// captcha, which `cache` keeps track of.
+    pub fn extract_and_verify_once(
+        &self,
+        image: &[u8],
+        answer: &str,
+        cache: &impl ReplayCache,
+    ) -> VerifyOutcome {
+        let Some(embedded) = self.extract(image) else {
+            return VerifyOutcome::UnknownId;
+        };
+        if is_expired(embedded.expires_at) {
+            return VerifyOutcome::Expired;
+        }
+        if !cache.first_use(&embedded.id, embedded.expires_at) {
+            return VerifyOutcome::AlreadyUsed;
+        }
+        embedded.verify(answer)
+    }
+
+    fn extract(&self, image: &[u8]) -> Option<Embedded> {
+        let payload = png_chunks::find(image, CHUNK_TYPE)?;
+        let (nonce, ciphertext) = payload.split_at_checked(NONCE_LEN)?;
+        let nonce: [u8; NONCE_LEN] = nonce.try_into().ok()?;
+        let plaintext = self.cipher.decrypt(&Nonce::from(nonce), ciphertext).ok()?;
+        let plaintext = String::from_utf8(plaintext).ok()?;
+
+        let (expiry, rest) = plaintext.split_once('.')?;
+        let (id_len, rest) = rest.split_once('.')?;
+        let (id, answer) = rest.split_at_checked(id_len.parse().ok()?)?;
+        let expires_at = match expiry {
+            "-" => None,
+            seconds => Some(UNIX_EPOCH.checked_add(Duration::from_secs(seconds.parse().ok()?))?),
+        };
+        Some(Embedded {
+            id: id.to_string(),
+            expires_at,
+            answer: Answer::new(answer.to_string()),
+        })
+    }
+}
+
+struct Embedded {
+    id: String,
+    expires_at: Option<SystemTime>,
+    answer: Answer,
+}
+
+impl Embedded {
+    fn verify(&self, answer: &str) -> VerifyOutcome {
+        if is_expired(self.expires_at) {
+            VerifyOutcome::Expired
+        } else if self.answer.matches(&AnswerFormat::Text.normalize(answer)) {
+            VerifyOutcome::Correct
+        } else {
+            VerifyOutcome::Wrong
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Config, Generator, MemoryReplayCache};
+
+    fn key() -> KioskKey {
+        KioskKey::new(&[7; 32])
+    }
+
+    fn embedded(id: &str) -> Captcha {
+        let mut captcha = Generator::new(Config::default())
+            .unwrap()
+            .generate()
+            .unwrap();
+        captcha.id = id.to_string();
+        key().embed(&mut captcha).unwrap();
+        captcha
+    }
+
+    #[test]
+    fn verifies_from_the_image_alone() {
+        let captcha = embedded("a.b.c");
+        let answer = captcha.text.expose();
+        let extracted = key().extract(&captcha.image).unwrap();
+        assert_eq!(extracted.id, "a.b.c");
+        assert_eq!(extracted.expires_at, captcha.expires_at);
+        assert_eq!(
+            key().extract_and_verify(&captcha.image, answer),
+            VerifyOutcome::Correct
+        );
+        assert_eq!(
+            key().extract_and_verify(&captcha.image, "wrong"),
+            VerifyOutcome::Wrong
+        );
+    }
+
+    #[test]
+    fn trims_answers_like_other_verifiers() {
+        let captcha = embedded("a");
+        let answer = format!(" {} \n", captcha.text.expose());
+        assert_eq!(
+            key().extract_and_verify(&captcha.image, &answer),
+            VerifyOutcome::Correct
+        );
+    }
+
+    #[test]
+    fn reveals_nothing_without_the_key() {
+        let captcha = embedded("a");
+        let other = KioskKey::new(&[8; 32]);
+        assert_eq!(
+            other.extract_and_verify(&captcha.image, captcha.text.expose()),
+            VerifyOutcome::UnknownId
+        );
+    }
+
+    #[test]
+    fn allows_one_attempt_per_captcha() {
+        let captcha = embedded("a");
+        let cache = MemoryReplayCache::new();
+        let answer = captcha.text.expose();
+        assert_eq!(
+            key().extract_and_verify_once(&captcha.image, "wrong", &cache),
+            VerifyOutcome::Wrong
+        );
+        assert_eq!(
+            key().extract_and_verify_once(&captcha.image, answer, &cache),
+            VerifyOutcome::AlreadyUsed
+        );
+    }
+
+    #[test]
+    fn embeds_only_into_pngs() {
+        let config = Config {
+            format: Format::Ansi,
+            ..Config::default()
+        };
+        let mut captcha = Generator::new(config).unwrap().generate().unwrap();
+        assert!(key().embed(&mut captcha).is_err());
+    }
+}
@@ -0,0 +1,347 @@
+use std::{
+    collections::HashMap,
+    sync::Mutex,
+    time::{Duration, SystemTime},
+};
+
+use crate::{
+    AnswerFormat, ArithmeticChallenge, ArithmeticConfig, Captcha, CaptchaChallenge, CaptchaError,
+    Generator, IssuanceQuota, SolveTimePolicy, VerifyOutcome, captcha::is_expired, chain::in_chain,
+    hash::verify_hashed,
+};
+
+/// What a store keeps per issued captcha. The answer is only ever stored
+/// hashed (see [`crate::hash_answer`]).
+#[derive(Clone)]
+pub struct StoredCaptcha {
+    pub answer_hash: String,
+    /// How submitted answers are normalized before checking them against
+    /// `answer_hash`.
+    pub answer_format: AnswerFormat,
+    pub expires_at: Option<SystemTime>,
+    /// See [`CaptchaChallenge::issued_at`].
+    pub issued_at: Option<SystemTime>,
+    /// [`Captcha::image_sha256`] of the image shown, so verification can be
+    /// logged against it.
+    ///
+    /// [`Captcha::image_sha256`]: crate::Captcha::image_sha256
+    pub image_sha256: Option<String>,
+    /// How often the captcha behind this id was swapped for a new one with
+    /// [`CaptchaStore::refresh`], so callers can cap it per id.
+    pub refreshes: u32,
+    /// How often the captcha behind this id was swapped for an arithmetic
+    /// question with [`CaptchaStore::issue_alternate`], which stops at
+    /// [`ArithmeticConfig::max_alternates`].
+    pub alternates: u32,
+    /// Steps of a [`ChallengeChain`] left, counting this one, 0 outside
+    /// chains.
+    ///
+    /// [`ChallengeChain`]: crate::ChallengeChain
+    pub chain_steps: u32,
+}
+
+impl StoredCaptcha {
+    /// Time since the challenge was issued, if known.
+    pub fn solve_time(&self) -> Option<Duration> {
+        self.issued_at?.elapsed().ok()
+    }
+}
+
+/// Keeps track of issued captcha answers until they are verified.
+pub trait CaptchaStore: Send + Sync {
+    fn insert(&self, id: &str, entry: StoredCaptcha);
+
+    /// Removes and returns the entry for `id`.
+    fn take(&self, id: &str) -> Option<StoredCaptcha>;
+
+    /// Removes every expired entry and returns how many there were, see
+    /// [`Sweeper`] to do so periodically. Stores whose backend expires
+    /// entries on its own can keep the default, which does nothing.
+    ///
+    /// [`Sweeper`]: crate::Sweeper
+    fn purge_expired(&self) -> usize {
+        0
+    }
+
+    /// Registers the answer of any kind of challenge.
+    fn issue<C: CaptchaChallenge>(&self, challenge: &C)
+    where
+        Self: Sized,
+    {
+        self.insert(challenge.id(), challenge.to_stored());
+    }
+
+    /// Generates and registers a captcha for `client` if `quota` allows,
+    /// checking before anything is rendered.
+    fn issue_for(
+        &self,
+        client: &str,
+        generator: &Generator,
+        quota: &IssuanceQuota,
+    ) -> Result<Captcha, CaptchaError>
+    where
+        Self: Sized,
+    {
+        quota.acquire(client)?;
+        match generator.generate() {
+            Ok(captcha) => {
+                self.issue(&captcha);
+                Ok(captcha)
+            }
+            Err(err) => {
+                quota.release(client);
+                Err(err)
+            }
+        }
+    }
+
+    /// Swaps the challenge behind `id` for an arithmetic question that a
+    /// screen reader can read out, keeping the id and expiry so the
+    /// alternative counts as the same captcha. `None` when `id` is unknown
+    /// or expired, or was already swapped
+    /// [`ArithmeticConfig::max_alternates`] times or is part of a
+    /// [`ChallengeChain`], which leaves the current challenge in place.
+    ///
+    /// [`ChallengeChain`]: crate::ChallengeChain
+    fn issue_alternate(&self, id: &str, config: &ArithmeticConfig) -> Option<ArithmeticChallenge>
+    where
+        Self: Sized,
+    {
+        let entry = self.take(id)?;
+        if is_expired(entry.expires_at) {
+            return None;
+        }
+        if entry.alternates >= config.max_alternates || in_chain(&entry) {
+            self.insert(id, entry);
+            return None;
+        }
+        let challenge = config.question(id.to_string(), SystemTime::now(), entry.expires_at);
+        self.insert(
+            id,
+            StoredCaptcha {
+                refreshes: entry.refreshes,
+                alternates: entry.alternates + 1,
+                chain_steps: entry.chain_steps,
+                ..challenge.to_stored()
+            },
+        );
+        Some(challenge)
+    }
+
+    /// Replaces the captcha behind `id` with a new one from `generator`,
+    /// under the same id, so the old answer no longer verifies. The new
+    /// captcha gets a fresh validity window, while
+    /// [`StoredCaptcha::refreshes`] is carried over and incremented, so
+    /// rate limits keyed on the id keep counting. `None` when `id` is
+    /// unknown or expired; steps of a [`ChallengeChain`] and failures to
+    /// generate leave the old captcha in place.
+    ///
+    /// [`ChallengeChain`]: crate::ChallengeChain
+    fn refresh(&self, id: &str, generator: &Generator) -> Result<Option<Captcha>, CaptchaError>
+    where
+        Self: Sized,
+    {
+        let Some(entry) = self.take(id) else {
+            return Ok(None);
+        };
+        if is_expired(entry.expires_at) {
+            return Ok(None);
+        }
+        if in_chain(&entry) {
+            self.insert(id, entry);
+            return Ok(None);
+        }
+        let mut captcha = match generator.generate() {
+            Ok(captcha) => captcha,
+            Err(err) => {
+                self.insert(id, entry);
+                return Err(err);
+            }
+        };
+        captcha.id = id.to_string();
+        self.insert(
+            id,
+            StoredCaptcha {
+                refreshes: entry.refreshes.saturating_add(1),
+                alternates: entry.alternates,
+                chain_steps: entry.chain_steps,
+                ..captcha.to_stored()
+            },
+        );
+        Ok(Some(captcha))
+    }
+
+    /// Checks `answer` against the stored hash. An entry can only be verified
+    /// once, and expired entries never verify. Steps of a [`ChallengeChain`]
+    /// are [`VerifyOutcome::Wrong`] here, they go through
+    /// [`ChallengeChain::verify`].
+    ///
+    /// [`ChallengeChain`]: crate::ChallengeChain
+    /// [`ChallengeChain::verify`]: crate::ChallengeChain::verify
+    fn verify(&self, id: &str, answer: &str) -> VerifyOutcome {
+        check(self.take(id), answer, None)
+    }
+
+    /// Like [`CaptchaStore::verify`], but answers that `policy` finds
+    /// suspiciously fast are rejected as [`VerifyOutcome::TooFast`], right
+    /// or wrong, so bots don't learn whether they solved it.
+    fn verify_timed(&self, id: &str, answer: &str, policy: &impl SolveTimePolicy) -> VerifyOutcome {
+        check(self.take(id), answer, Some(policy))
+    }
+}
+
+fn check(
+    entry: Option<StoredCaptcha>,
+    answer: &str,
+    policy: Option<&dyn SolveTimePolicy>,
+) -> VerifyOutcome {
+    let Some(entry) = entry else {
+        return VerifyOutcome::UnknownId;
+    };
+    if is_expired(entry.expires_at) {
+        return VerifyOutcome::Expired;
+    }
+    if let (Some(policy), Some(solve_time)) = (policy, entry.solve_time())
+        && policy.is_too_fast(solve_time)
+    {
+        return VerifyOutcome::TooFast;
+    }
+    if !in_chain(&entry)
+        && verify_hashed(&entry.answer_hash, &entry.answer_format.normalize(answer))
+    {
+        VerifyOutcome::Correct
+    } else {
+        VerifyOutcome::Wrong
+    }
+}
+
+#[derive(Default)]
+pub struct MemoryStore {
+    entries: Mutex<HashMap<String, StoredCaptcha>>,
+}
+
+impl MemoryStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl CaptchaStore for MemoryStore {
+    fn insert(&self, id: &str, entry: StoredCaptcha) {
+        self.entries.lock().unwrap().insert(id.to_string(), entry);
+    }
+
+    fn take(&self, id: &str) -> Option<StoredCaptcha> {
+        self.entries.lock().unwrap().remove(id)
+    }
+
+    fn purge_expired(&self) -> usize {
+        let mut entries = self.entries.lock().unwrap();
+        let before = entries.len();
+        entries.retain(|_, entry| !is_expired(entry.expires_at));
+        before - entries.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{a_minute_ago, challenge, in_a_minute};
+
+    #[test]
+    fn round_trips() {
+        let store = MemoryStore::new();
+        store.issue(&challenge("a", in_a_minute()));
+        store.issue(&challenge("b", None));
+        assert_eq!(store.verify("a", "7"), VerifyOutcome::Correct);
+        assert_eq!(store.verify("b", "7"), VerifyOutcome::Correct);
+    }
+
+    #[test]
+    fn purges_only_expired_entries() {
+        let store = MemoryStore::new();
+        store.issue(&challenge("old", a_minute_ago()));
+        store.issue(&challenge("older", a_minute_ago()));
+        store.issue(&challenge("live", in_a_minute()));
+        store.issue(&challenge("forever", None));
+        assert_eq!(store.purge_expired(), 2);
+        assert_eq!(store.purge_expired(), 0);
+        assert_eq!(store.verify("old", "7"), VerifyOutcome::UnknownId);
+        assert_eq!(store.verify("live", "7"), VerifyOutcome::Correct);
+        assert_eq!(store.verify("forever", "7"), VerifyOutcome::Correct);
+    }
+
+    #[test]
+    fn verifies_each_entry_once() {
+        let store = MemoryStore::new();
+        store.issue(&challenge("a", in_a_minute()));
+        assert_eq!(store.verify("a", "8"), VerifyOutcome::Wrong);
+        assert_eq!(store.verify("a", "7"), VerifyOutcome::UnknownId);
+
+        store.issue(&challenge("b", in_a_minute()));
+        assert_eq!(store.verify("b", "7"), VerifyOutcome::Correct);
+        assert_eq!(store.verify("b", "7"), VerifyOutcome::UnknownId);
+        assert_eq!(store.verify("c", "7"), VerifyOutcome::UnknownId);
+    }
+
+    #[test]
+    fn stores_answers_hashed() {
+        let store = MemoryStore::new();
+        store.issue(&challenge("a", in_a_minute()));
+        let entry = store.take("a").unwrap();
+        assert!(entry.answer_hash.starts_with("sha256$"));
+        assert!(!entry.answer_hash.ends_with("$7"));
+    }
+
+    #[test]
+    fn reports_why_answers_fail() {
+        let store = MemoryStore::new();
+        store.issue(&challenge("a", a_minute_ago()));
+        store.issue(&challenge("b", in_a_minute()));
+        store.issue(&challenge("c", in_a_minute()));
+
+        let outcomes = [
+            store.verify("a", "7"),
+            store.verify("b", "8"),
+            store.verify("c", "7"),
+            store.verify("d", "7"),
+        ];
+        assert_eq!(
+            outcomes,
+            [
+                VerifyOutcome::Expired,
+                VerifyOutcome::Wrong,
+                VerifyOutcome::Correct,
+                VerifyOutcome::UnknownId,
+            ]
+        );
+        assert_eq!(
+            outcomes.map(|outcome| outcome.is_correct()),
+            [false, false, true, false]
+        );
+    }
+
+    #[test]
+    fn refreshes_under_the_same_id() {
+        let store = MemoryStore::new();
+        let generator = Generator::new(Default::default()).unwrap();
+        store.issue(&challenge("a", in_a_minute()));
+
+        let captcha = store.refresh("a", &generator).unwrap().unwrap();
+        assert_eq!(captcha.id, "a");
+        store.refresh("a", &generator).unwrap().unwrap();
+        let entry = store.take("a").unwrap();
+        assert_eq!(entry.refreshes, 2);
+        store.insert("a", entry);
+        assert_eq!(store.verify("a", "7"), VerifyOutcome::Wrong);
+    }
+
+    #[test]
+    fn refreshes_only_live_captchas() {
+        let store = MemoryStore::new();
+        let generator = Generator::new(Default::default()).unwrap();
+        store.issue(&challenge("a", a_minute_ago()));
+        assert!(store.refresh("a", &generator).unwrap().is_none());
+        assert!(store.refresh("b", &generator).unwrap().is_none());
+    }
+}
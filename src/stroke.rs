@@ -0,0 +1,374 @@
+use std::f32::consts::TAU;
+
+use rand::Rng;
+use raqote::{DrawOptions, DrawTarget, LineCap, LineJoin, PathBuilder, Source, StrokeStyle};
+
+use crate::BlendMode;
+
+/// How interference lines are stroked. The default is a plain 1px line;
+/// varying width, dashes and wobble make lines look hand-drawn and much
+/// harder to remove with a Hough transform.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+pub struct LineStyle {
+    /// The width varies smoothly along the path between these two.
+    pub min_width: f32,
+    pub max_width: f32,
+    /// Alternating dash and gap lengths; empty for a solid line.
+    pub dash: Vec<f32>,
+    /// Largest sideways displacement of the path, in pixels.
+    pub wobble: f32,
+    pub blend: BlendMode,
+    /// Smallest CIELAB difference (ΔE) between a line's color and any text
+    /// color, so no line blends in with the glyphs. Around 30 keeps lines
+    /// clearly distinct; 0 allows any color.
+    pub min_color_distance: f32,
+    /// Largest fraction, from 0 to 1, of any single glyph's pixels a line
+    /// may cover. Lines covering more are re-rolled and left out if that
+    /// keeps failing; 1 allows any overlap.
+    pub max_glyph_coverage: f32,
+}
+
+impl Default for LineStyle {
+    fn default() -> Self {
+        Self {
+            min_width: 1.0,
+            max_width: 1.0,
+            dash: Vec::new(),
+            wobble: 0.0,
+            blend: BlendMode::Normal,
+            min_color_distance: 0.0,
+            max_glyph_coverage: 1.0,
+        }
+    }
+}
+
+/// Number of pieces a path is stroked in when its width varies.
+const WIDTH_STEPS: usize = 12;
+
+/// A path styled as [`LineStyle`] asks, before it is drawn: the wobbled
+/// polyline in pieces of even width, and the dash pattern running across
+/// them.
+pub(crate) struct Stroke {
+    pub pieces: Vec<Piece>,
+    /// Alternating dash and gap lengths; empty for a solid line.
+    pub dash: Vec<f32>,
+}
+
+pub(crate) struct Piece {
+    pub points: Vec<(f32, f32)>,
+    pub width: f32,
+    /// Where in the dash pattern the piece starts.
+    pub dash_offset: f32,
+}
+
+impl Stroke {
+    pub(crate) fn new(points: &[(f32, f32)], style: &LineStyle, rng: &mut impl Rng) -> Self {
+        // Settings can come from plain data, so non-finite ones are ignored.
+        let points = if style.wobble > 0.0 && style.wobble.is_finite() {
+            wobble(points, style.wobble, rng)
+        } else {
+            points.to_vec()
+        };
+        let min_width = if style.min_width.is_finite() {
+            style.min_width
+        } else {
+            1.0
+        };
+        let max_width = if style.max_width.is_finite() {
+            style.max_width
+        } else {
+            min_width
+        };
+        let dash = if style.dash.iter().all(|length| length.is_finite()) {
+            style.dash.clone()
+        } else {
+            Vec::new()
+        };
+
+        let dash_offset = if dash.is_empty() {
+            0.0
+        } else {
+            rng.random_range(0.0..dash.iter().sum::<f32>().max(1.0))
+        };
+
+        if max_width <= min_width {
+            return Self {
+                pieces: vec![Piece {
+                    points,
+                    width: min_width,
+                    dash_offset,
+                }],
+                dash,
+            };
+        }
+
+        // Widths at both ends and two random points between, eased in between.
+        let knots: [f32; 4] = std::array::from_fn(|_| rng.random_range(min_width..=max_width));
+        let chunk = points.len().div_ceil(WIDTH_STEPS).max(1);
+        let mut travelled = 0.0;
+        let mut pieces = Vec::with_capacity(WIDTH_STEPS);
+        for (i, start) in (0..points.len().saturating_sub(1))
+            .step_by(chunk)
+            .enumerate()
+        {
+            let end = (start + chunk).min(points.len() - 1);
+            let t = (i as f32 + 0.5) / WIDTH_STEPS as f32;
+            let width = interpolate(&knots, t.min(1.0));
+
+            // Keeps the dash pattern continuous across pieces.
+            pieces.push(Piece {
+                points: points[start..=end].to_vec(),
+                width,
+                dash_offset: dash_offset + travelled,
+            });
+            travelled += length(&points[start..=end]);
+        }
+        Self { pieces, dash }
+    }
+
+    pub(crate) fn draw(&self, dt: &mut DrawTarget, source: &Source) {
+        for piece in &self.pieces {
+            let style = StrokeStyle {
+                width: piece.width,
+                cap: LineCap::Round,
+                join: LineJoin::Round,
+                dash_array: self.dash.clone(),
+                dash_offset: piece.dash_offset,
+                ..StrokeStyle::default()
+            };
+            dt.stroke(&path(&piece.points), source, &style, &DrawOptions::new());
+        }
+    }
+
+    /// The polylines that get inked, with their widths: the pieces cut
+    /// into dashes the way [`Stroke::draw`] dashes them.
+    #[cfg(feature = "gpu")]
+    pub(crate) fn dashes(&self) -> Vec<(Vec<(f32, f32)>, f32)> {
+        // Odd patterns repeat with dashes and gaps swapped, as in SVG.
+        let dash = match self.dash.len() % 2 {
+            0 => self.dash.clone(),
+            _ => self.dash.repeat(2),
+        };
+        let total: f32 = dash.iter().sum();
+        if dash.is_empty() || total <= 0.0 || dash.iter().any(|&length| length < 0.0) {
+            return self
+                .pieces
+                .iter()
+                .map(|piece| (piece.points.clone(), piece.width))
+                .collect();
+        }
+
+        let mut dashes = Vec::new();
+        for piece in &self.pieces {
+            let (mut index, mut left) = (0, piece.dash_offset.rem_euclid(total));
+            while left >= dash[index] {
+                left -= dash[index];
+                index = (index + 1) % dash.len();
+            }
+            left = dash[index] - left;
+
+            let mut current = match (index % 2, piece.points.first()) {
+                (0, Some(&first)) => vec![first],
+                _ => Vec::new(),
+            };
+            for pair in piece.points.windows(2) {
+                let (mut from, to) = (pair[0], pair[1]);
+                let mut remaining = length(pair);
+                while remaining > left {
+                    let t = left / remaining;
+                    from = (from.0 + (to.0 - from.0) * t, from.1 + (to.1 - from.1) * t);
+                    remaining -= left;
+                    if index % 2 == 0 {
+                        current.push(from);
+                        dashes.push((std::mem::take(&mut current), piece.width));
+                    } else {
+                        current = vec![from];
+                    }
+                    index = (index + 1) % dash.len();
+                    left = dash[index];
+                }
+                left -= remaining;
+                if index % 2 == 0 {
+                    current.push(to);
+                }
+            }
+            if current.len() >= 2 {
+                dashes.push((current, piece.width));
+            }
+        }
+        dashes
+    }
+}
+
+/// Points along a cubic Bézier curve.
+pub(crate) fn flatten_cubic(
+    from: (f32, f32),
+    c1: (f32, f32),
+    c2: (f32, f32),
+    to: (f32, f32),
+    steps: usize,
+) -> Vec<(f32, f32)> {
+    (0..=steps)
+        .map(|i| {
+            let t = i as f32 / steps as f32;
+            let u = 1.0 - t;
+            let (a, b, c, d) = (u * u * u, 3.0 * u * u * t, 3.0 * u * t * t, t * t * t);
+            (
+                a * from.0 + b * c1.0 + c * c2.0 + d * to.0,
+                a * from.1 + b * c1.1 + c * c2.1 + d * to.1,
+            )
+        })
+        .collect()
+}
+
+/// Points along a straight line, `spacing` pixels apart.
+pub(crate) fn subdivide_line(from: (f32, f32), to: (f32, f32), spacing: f32) -> Vec<(f32, f32)> {
+    let steps = ((length(&[from, to]) / spacing).ceil() as usize).max(1);
+    (0..=steps)
+        .map(|i| {
+            let t = i as f32 / steps as f32;
+            (from.0 + (to.0 - from.0) * t, from.1 + (to.1 - from.1) * t)
+        })
+        .collect()
+}
+
+/// Displaces points perpendicular to the overall direction by the sum of
+/// two sines with random frequencies and phases.
+fn wobble(points: &[(f32, f32)], amplitude: f32, rng: &mut impl Rng) -> Vec<(f32, f32)> {
+    let (Some(&first), Some(&last)) = (points.first(), points.last()) else {
+        return Vec::new();
+    };
+    let (dx, dy) = (last.0 - first.0, last.1 - first.1);
+    let norm = (dx * dx + dy * dy).sqrt().max(f32::EPSILON);
+    let normal = (-dy / norm, dx / norm);
+
+    let waves = [
+        (0.6, rng.random_range(1.0..3.0), rng.random_range(0.0..TAU)),
+        (0.4, rng.random_range(3.0..7.0), rng.random_range(0.0..TAU)),
+    ];
+
+    let last_index = (points.len() - 1).max(1) as f32;
+    points
+        .iter()
+        .enumerate()
+        .map(|(i, &(x, y))| {
+            let t = i as f32 / last_index;
+            let offset: f32 = waves
+                .iter()
+                .map(|&(weight, frequency, phase)| weight * (TAU * frequency * t + phase).sin())
+                .sum::<f32>()
+                * amplitude;
+            (x + normal.0 * offset, y + normal.1 * offset)
+        })
+        .collect()
+}
+
+fn interpolate(knots: &[f32], t: f32) -> f32 {
+    let scaled = t * (knots.len() - 1) as f32;
+    let i = (scaled as usize).min(knots.len() - 2);
+    let local = scaled - i as f32;
+    let eased = local * local * (3.0 - 2.0 * local);
+    knots[i] + (knots[i + 1] - knots[i]) * eased
+}
+
+fn length(points: &[(f32, f32)]) -> f32 {
+    points
+        .windows(2)
+        .map(|w| ((w[1].0 - w[0].0).powi(2) + (w[1].1 - w[0].1).powi(2)).sqrt())
+        .sum()
+}
+
+fn path(points: &[(f32, f32)]) -> raqote::Path {
+    let mut pb = PathBuilder::new();
+    if let Some(&(x, y)) = points.first() {
+        pb.move_to(x, y);
+    }
+    for &(x, y) in &points[1..] {
+        pb.line_to(x, y);
+    }
+    pb.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stroke(style: &LineStyle) -> DrawTarget {
+        let mut dt = DrawTarget::new(40, 20);
+        let source = Source::Solid(raqote::SolidSource::from_unpremultiplied_argb(255, 0, 0, 0));
+        let points = subdivide_line((2.0, 10.0), (38.0, 10.0), 2.0);
+        Stroke::new(&points, style, &mut rand::rng()).draw(&mut dt, &source);
+        dt
+    }
+
+    fn inked(dt: &DrawTarget) -> usize {
+        dt.get_data()
+            .iter()
+            .filter(|&&pixel| pixel >> 24 != 0)
+            .count()
+    }
+
+    #[test]
+    fn strokes_with_varying_widths() {
+        let thin = stroke(&LineStyle::default());
+        let thick = stroke(&LineStyle {
+            min_width: 3.0,
+            max_width: 5.0,
+            ..LineStyle::default()
+        });
+        assert!(inked(&thin) > 0);
+        assert!(inked(&thick) > inked(&thin));
+    }
+
+    #[test]
+    fn ignores_non_finite_settings() {
+        for value in [f32::NAN, f32::INFINITY] {
+            let dt = stroke(&LineStyle {
+                min_width: value,
+                max_width: value,
+                dash: vec![value, 2.0],
+                wobble: value,
+                ..LineStyle::default()
+            });
+            assert!(inked(&dt) > 0);
+            stroke(&LineStyle {
+                min_width: 1.0,
+                max_width: value,
+                ..LineStyle::default()
+            });
+        }
+    }
+
+    #[test]
+    fn flattens_curves_through_their_ends() {
+        let points = flatten_cubic((0.0, 0.0), (1.0, 5.0), (9.0, 5.0), (10.0, 0.0), 8);
+        assert_eq!(points.len(), 9);
+        assert_eq!(points[0], (0.0, 0.0));
+        assert_eq!(points[8], (10.0, 0.0));
+        assert!(points[4].1 > 0.0);
+    }
+
+    #[cfg(feature = "gpu")]
+    #[test]
+    fn cuts_pieces_into_dashes() {
+        let points = subdivide_line((2.0, 10.0), (38.0, 10.0), 2.0);
+        for (dash, inked) in [(vec![4.0, 2.0], 24.0), (vec![3.0], 18.0), (vec![], 36.0)] {
+            let style = LineStyle {
+                dash: dash.clone(),
+                ..LineStyle::default()
+            };
+            let dashes = Stroke::new(&points, &style, &mut rand::rng()).dashes();
+            let lengths: Vec<f32> = dashes
+                .iter()
+                .map(|(points, _)| points.windows(2).map(length).sum())
+                .collect();
+            let longest = dash.first().copied().unwrap_or(36.0);
+            assert!(lengths.iter().all(|&length| length <= longest + 1e-3));
+            let total: f32 = lengths.iter().sum();
+            assert!((total - inked).abs() <= longest, "{dash:?}: {total}");
+        }
+    }
+}
@@ -0,0 +1,95 @@
+use base64::{Engine, engine::general_purpose};
+
+use crate::captcha::sanitize_id;
+
+pub(crate) const SVG_CONTENT_TYPE: &str = "image/svg+xml";
+
+/// An SVG showing an encoded raster image through a `data:` URI. It
+/// references nothing external and carries no scripts, styles or event
+/// handlers, but inline it needs `img-src data:` in a page's CSP.
+pub(crate) fn wrap(image: &[u8], content_type: &str, width: u32, height: u32) -> String {
+    svg(image, content_type, width, height, "", "")
+}
+
+/// Like [`wrap`], as a `role="img"` element meant to be embedded in HTML,
+/// labelled by a `<title>` whose id is derived from `id`. An `image` that
+/// already is one of our SVGs gets the label added instead of being
+/// wrapped again.
+pub(crate) fn inline(
+    image: &[u8],
+    content_type: &str,
+    width: u32,
+    height: u32,
+    id: &str,
+    label: &str,
+) -> String {
+    let title_id = format!("captcha-{}-title", sanitize_id(id));
+    let attributes = format!(r#" role="img" aria-labelledby="{title_id}""#);
+    let title = format!(r#"<title id="{title_id}">{}</title>"#, escape(label));
+
+    if content_type == SVG_CONTENT_TYPE
+        && let Ok(svg) = std::str::from_utf8(image)
+        && let Some(end) = svg.find('>')
+    {
+        return format!("{}{attributes}>{title}{}", &svg[..end], &svg[end + 1..]);
+    }
+    svg(image, content_type, width, height, &attributes, &title)
+}
+
+fn svg(
+    image: &[u8],
+    content_type: &str,
+    width: u32,
+    height: u32,
+    attributes: &str,
+    title: &str,
+) -> String {
+    format!(
+        concat!(
+            r#"<svg xmlns="http://www.w3.org/2000/svg" width="{0}" height="{1}" viewBox="0 0 {0} {1}"{2}>"#,
+            r#"{3}<image width="{0}" height="{1}" href="data:{4};base64,{5}"/></svg>"#,
+        ),
+        width,
+        height,
+        attributes,
+        title,
+        content_type,
+        general_purpose::STANDARD.encode(image),
+    )
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sanitizes_ids_and_escapes_labels() {
+        let svg = inline(b"png", "image/png", 2, 1, r#"a"><script>1"#, "<b>&\"");
+        assert!(svg.contains(r#"aria-labelledby="captcha-ascript1-title""#));
+        assert!(svg.contains(r#"<title id="captcha-ascript1-title">&lt;b&gt;&amp;&quot;</title>"#));
+        assert!(!svg.contains("<script"));
+        assert!(
+            svg.ends_with(
+                r#"<image width="2" height="1" href="data:image/png;base64,cG5n"/></svg>"#
+            )
+        );
+    }
+
+    #[test]
+    fn labels_svgs_instead_of_wrapping_them() {
+        let wrapped = wrap(b"png", "image/png", 2, 1);
+        let labelled = inline(wrapped.as_bytes(), SVG_CONTENT_TYPE, 2, 1, "id", "label");
+        assert_eq!(labelled.matches("<svg").count(), 1);
+        assert!(labelled.starts_with(concat!(
+            r#"<svg xmlns="http://www.w3.org/2000/svg" width="2" height="1" viewBox="0 0 2 1""#,
+            r#" role="img" aria-labelledby="captcha-id-title"><title id="captcha-id-title">label</title><image"#,
+        )));
+    }
+}
@@ -0,0 +1,92 @@
+use std::{
+    sync::{
+        Arc,
+        atomic::{AtomicU64, Ordering},
+        mpsc::{self, RecvTimeoutError, Sender},
+    },
+    thread::{self, JoinHandle},
+    time::Duration,
+};
+
+use crate::CaptchaStore;
+
+/// A background thread calling [`CaptchaStore::purge_expired`] every
+/// interval, so stores of captchas that are never verified don't grow
+/// without bound. Stops when dropped.
+pub struct Sweeper {
+    stop: Option<Sender<()>>,
+    thread: Option<JoinHandle<()>>,
+    purged: Arc<AtomicU64>,
+}
+
+impl Sweeper {
+    pub fn spawn<S: CaptchaStore + 'static>(store: Arc<S>, interval: Duration) -> Self {
+        let (stop, stopped) = mpsc::channel();
+        let purged = Arc::new(AtomicU64::new(0));
+        let counter = purged.clone();
+        let thread = thread::Builder::new()
+            .name("captcha-sweeper".into())
+            .spawn(move || {
+                while let Err(RecvTimeoutError::Timeout) = stopped.recv_timeout(interval) {
+                    let count = store.purge_expired();
+                    counter.fetch_add(count as u64, Ordering::Relaxed);
+
+                    #[cfg(feature = "log")]
+                    if count > 0 {
+                        log::debug!("purged {count} expired captchas");
+                    }
+                }
+            })
+            .expect("failed to spawn sweeper thread");
+        Self {
+            stop: Some(stop),
+            thread: Some(thread),
+            purged,
+        }
+    }
+
+    /// Entries purged since the sweeper started, for metrics.
+    pub fn purged(&self) -> u64 {
+        self.purged.load(Ordering::Relaxed)
+    }
+}
+
+impl Drop for Sweeper {
+    fn drop(&mut self) {
+        // Disconnecting wakes the thread up right away.
+        drop(self.stop.take());
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Instant;
+
+    use super::*;
+    use crate::{
+        MemoryStore, VerifyOutcome,
+        test_util::{a_minute_ago, challenge, in_a_minute},
+    };
+
+    #[test]
+    fn purges_in_the_background() {
+        let store = Arc::new(MemoryStore::new());
+        store.issue(&challenge("old", a_minute_ago()));
+        store.issue(&challenge("older", a_minute_ago()));
+        store.issue(&challenge("live", in_a_minute()));
+
+        let sweeper = Sweeper::spawn(store.clone(), Duration::from_millis(5));
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while sweeper.purged() < 2 && Instant::now() < deadline {
+            thread::sleep(Duration::from_millis(5));
+        }
+        assert_eq!(sweeper.purged(), 2);
+        drop(sweeper);
+
+        assert_eq!(store.verify("old", "7"), VerifyOutcome::UnknownId);
+        assert_eq!(store.verify("live", "7"), VerifyOutcome::Correct);
+    }
+}
@@ -0,0 +1,215 @@
+use std::fmt::Write;
+
+use image::{Rgba, RgbaImage};
+
+pub(crate) const TEXT_CONTENT_TYPE: &str = "text/plain; charset=utf-8";
+
+/// Each character shows two pixels above each other as an upper half block
+/// in the top pixel's foreground and bottom pixel's background color.
+pub(crate) fn ansi_blocks(img: &RgbaImage) -> String {
+    let mut out = String::new();
+    for y in (0..img.height()).step_by(2) {
+        for x in 0..img.width() {
+            let [r, g, b] = flatten(img.get_pixel(x, y));
+            let [br, bg, bb] = match y + 1 < img.height() {
+                true => flatten(img.get_pixel(x, y + 1)),
+                false => [0, 0, 0],
+            };
+            let _ = write!(
+                out,
+                "\x1b[38;2;{r};{g};{b}m\x1b[48;2;{br};{bg};{bb}m\u{2580}"
+            );
+        }
+        out.push_str("\x1b[0m\n");
+    }
+    out
+}
+
+/// Each character shows two by four pixels as Braille dots, raised where
+/// a pixel stands out from the median brightness, i.e. the background.
+pub(crate) fn braille(img: &RgbaImage) -> String {
+    let luma: Vec<f32> = img
+        .pixels()
+        .map(|pixel| luminance(flatten(pixel)))
+        .collect();
+    let mut sorted = luma.clone();
+    sorted.sort_unstable_by(f32::total_cmp);
+    let background = sorted.get(sorted.len() / 2).copied().unwrap_or(1.0);
+
+    let (width, height) = img.dimensions();
+    let raised = |x: u32, y: u32| {
+        x < width && y < height && (luma[(y * width + x) as usize] - background).abs() > 0.25
+    };
+    // Dot numbering of the Unicode Braille block, by column and row.
+    const DOTS: [[u32; 4]; 2] = [[0x01, 0x02, 0x04, 0x40], [0x08, 0x10, 0x20, 0x80]];
+
+    let mut out = String::new();
+    for y in (0..height).step_by(4) {
+        for x in (0..width).step_by(2) {
+            let mut bits = 0;
+            for (dx, column) in DOTS.iter().enumerate() {
+                for (dy, dot) in column.iter().enumerate() {
+                    if raised(x + dx as u32, y + dy as u32) {
+                        bits |= dot;
+                    }
+                }
+            }
+            out.push(char::from_u32(0x2800 + bits).unwrap_or(' '));
+        }
+        out.push('\n');
+    }
+    out
+}
+
+/// The color of `pixel` over white.
+fn flatten(pixel: &Rgba<u8>) -> [u8; 3] {
+    let alpha = pixel[3] as u32;
+    [0, 1, 2].map(|channel| ((pixel[channel] as u32 * alpha + 255 * (255 - alpha)) / 255) as u8)
+}
+
+fn luminance([r, g, b]: [u8; 3]) -> f32 {
+    (0.299 * r as f32 + 0.587 * g as f32 + 0.114 * b as f32) / 255.0
+}
+
+/// Colors a Sixel image is quantized to, the most terminals support.
+const SIXEL_COLORS: usize = 256;
+
+/// A DEC Sixel escape sequence drawing the image, over white.
+pub(crate) fn sixel(img: &RgbaImage) -> String {
+    let (width, height) = img.dimensions();
+    let pixels: Vec<u8> = img
+        .pixels()
+        .flat_map(|pixel| {
+            let [r, g, b] = flatten(pixel);
+            [r, g, b, 255]
+        })
+        .collect();
+    let quantizer = color_quant::NeuQuant::new(10, SIXEL_COLORS, &pixels);
+    let indices: Vec<usize> = pixels
+        .chunks(4)
+        .map(|pixel| quantizer.index_of(pixel))
+        .collect();
+
+    let mut out = format!("\x1bPq\"1;1;{width};{height}");
+    for (index, color) in quantizer.color_map_rgb().chunks(3).enumerate() {
+        // Sixel colors are percentages.
+        let [r, g, b] = [0, 1, 2].map(|channel| color[channel] as u32 * 100 / 255);
+        let _ = write!(out, "#{index};2;{r};{g};{b}");
+    }
+
+    let width = width as usize;
+    for band in indices.chunks(width * 6) {
+        let rows = band.len() / width.max(1);
+        let mut used = [false; SIXEL_COLORS];
+        band.iter().for_each(|&index| used[index] = true);
+
+        for color in (0..SIXEL_COLORS).filter(|&color| used[color]) {
+            let _ = write!(out, "#{color}");
+            let mut run: Option<(u8, usize)> = None;
+            for x in 0..width {
+                let bits = (0..rows)
+                    .filter(|row| band[row * width + x] == color)
+                    .fold(0u8, |bits, row| bits | 1 << row);
+                run = match run {
+                    Some((previous, count)) if previous == bits => Some((bits, count + 1)),
+                    Some(finished) => {
+                        push_sixels(&mut out, finished);
+                        Some((bits, 1))
+                    }
+                    None => Some((bits, 1)),
+                };
+            }
+            // Nothing needs drawing after the last pixel of the color.
+            if let Some(run) = run.filter(|&(bits, _)| bits != 0) {
+                push_sixels(&mut out, run);
+            }
+            // Back to the start of the band for the next color.
+            out.push('$');
+        }
+        out.push('-');
+    }
+    out.push_str("\x1b\\");
+    out
+}
+
+fn push_sixels(out: &mut String, (bits, count): (u8, usize)) {
+    let sixel = char::from(63 + bits);
+    match count {
+        1..=3 => (0..count).for_each(|_| out.push(sixel)),
+        _ => {
+            let _ = write!(out, "!{count}{sixel}");
+        }
+    }
+}
+
+/// An iTerm2 inline image escape sequence showing the PNG `png`, also
+/// understood by WezTerm and others.
+#[cfg(feature = "base64")]
+pub(crate) fn iterm2(png: &[u8], width: u32, height: u32) -> String {
+    use base64::{Engine, engine::general_purpose};
+
+    format!(
+        "\x1b]1337;File=inline=1;size={};width={width}px;height={height}px;preserveAspectRatio=1:{}\x07",
+        png.len(),
+        general_purpose::STANDARD.encode(png)
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BLACK: Rgba<u8> = Rgba([0, 0, 0, 255]);
+    const WHITE: Rgba<u8> = Rgba([255, 255, 255, 255]);
+
+    #[test]
+    fn stacks_two_pixels_per_block() {
+        let mut img = RgbaImage::from_pixel(2, 3, Rgba([0, 0, 0, 0]));
+        img.put_pixel(0, 0, BLACK);
+        let blocks = ansi_blocks(&img);
+        let lines: Vec<&str> = blocks.lines().collect();
+        assert_eq!(lines.len(), 2);
+        // Transparent pixels show white; the missing last row black.
+        assert_eq!(
+            lines[0],
+            "\x1b[38;2;0;0;0m\x1b[48;2;255;255;255m\u{2580}\
+             \x1b[38;2;255;255;255m\x1b[48;2;255;255;255m\u{2580}\x1b[0m"
+        );
+        assert!(lines[1].starts_with("\x1b[38;2;255;255;255m\x1b[48;2;0;0;0m\u{2580}"));
+    }
+
+    #[test]
+    fn raises_dots_standing_out_from_the_background() {
+        let mut img = RgbaImage::from_pixel(4, 4, WHITE);
+        img.put_pixel(1, 3, BLACK);
+        img.put_pixel(2, 0, BLACK);
+        assert_eq!(braille(&img), "\u{2880}\u{2801}\n");
+    }
+
+    #[test]
+    fn frames_sixel_images() {
+        let img = RgbaImage::from_fn(5, 8, |x, _| if x < 2 { BLACK } else { WHITE });
+        let sixel = sixel(&img);
+        assert!(sixel.starts_with("\x1bPq\"1;1;5;8#0;2;"));
+        assert!(sixel.ends_with("-\x1b\\"));
+        // One band per six rows.
+        assert_eq!(sixel.matches('-').count(), 2);
+        // Two black columns of six pixels, then three of two.
+        assert!(sixel.contains("~~"));
+        assert!(sixel.contains("BB"));
+    }
+
+    #[cfg(feature = "base64")]
+    #[test]
+    fn wraps_pngs_for_iterm2() {
+        use base64::{Engine, engine::general_purpose};
+
+        let png = b"\x89PNG fake";
+        let sequence = iterm2(png, 120, 40);
+        let header =
+            "\x1b]1337;File=inline=1;size=9;width=120px;height=40px;preserveAspectRatio=1:";
+        assert!(sequence.starts_with(header));
+        let payload = sequence[header.len()..].strip_suffix('\x07').unwrap();
+        assert_eq!(general_purpose::STANDARD.decode(payload).unwrap(), png);
+    }
+}
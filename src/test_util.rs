@@ -0,0 +1,24 @@
+//! Fixtures shared by the unit tests.
+
+use std::time::{Duration, SystemTime};
+
+use crate::{Answer, ArithmeticChallenge};
+
+/// A challenge whose answer is `7`, cheap to make without a font.
+pub(crate) fn challenge(id: &str, expires_at: Option<SystemTime>) -> ArithmeticChallenge {
+    ArithmeticChallenge {
+        id: id.to_string(),
+        question: "3 + 4".to_string(),
+        answer: Answer::new("7".to_string()),
+        issued_at: SystemTime::now(),
+        expires_at,
+    }
+}
+
+pub(crate) fn in_a_minute() -> Option<SystemTime> {
+    Some(SystemTime::now() + Duration::from_secs(60))
+}
+
+pub(crate) fn a_minute_ago() -> Option<SystemTime> {
+    Some(SystemTime::now() - Duration::from_secs(60))
+}
@@ -0,0 +1,179 @@
+use std::{
+    fmt,
+    sync::{Arc, Mutex, PoisonError},
+};
+
+use rand::{RngCore, seq::IndexedRandom};
+
+use crate::{CaptchaError, CharWeights, TextStyle};
+
+/// Makes answer texts, for answers the built-in [`TextStyle`]s can't
+/// produce, such as product codes or locale-specific rules. Set it as
+/// [`Config::text_provider`]; rendering, noise and verification work as
+/// for any other answer. Glyphs are sized to fit each text, and texts
+/// with characters the font can't draw fail to generate with
+/// [`CaptchaError::InvalidInput`].
+///
+/// [`Config::text_provider`]: crate::Config::text_provider
+pub trait TextProvider: Send {
+    fn next(&mut self, rng: &mut dyn RngCore) -> String;
+}
+
+/// A [`TextProvider`] shared by every clone of a config and every thread
+/// generating with it.
+#[derive(Clone)]
+pub struct SharedTextProvider(Arc<Mutex<dyn TextProvider>>);
+
+impl SharedTextProvider {
+    pub fn new(provider: impl TextProvider + 'static) -> Self {
+        Self(Arc::new(Mutex::new(provider)))
+    }
+
+    pub(crate) fn next(&self, rng: &mut dyn RngCore) -> String {
+        self.0
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .next(rng)
+    }
+}
+
+impl fmt::Debug for SharedTextProvider {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("SharedTextProvider(..)")
+    }
+}
+
+/// The texts a config makes without a provider: `length` characters of
+/// `charset`, weighted and put together as the style says.
+#[derive(Clone, Debug)]
+pub struct CharsetText {
+    style: TextStyle,
+    weights: CharWeights,
+    charset: String,
+    length: u32,
+}
+
+impl CharsetText {
+    /// Fails when the weights and style leave nothing to draw from the
+    /// charset.
+    pub fn new(
+        style: TextStyle,
+        weights: CharWeights,
+        charset: impl Into<String>,
+        length: u32,
+    ) -> Result<Self, CaptchaError> {
+        let text = Self {
+            style,
+            weights,
+            charset: charset.into(),
+            length,
+        };
+        text.style
+            .generate(&text.weights, &text.charset, length, &mut rand::rng())?;
+        Ok(text)
+    }
+}
+
+impl TextProvider for CharsetText {
+    fn next(&mut self, mut rng: &mut dyn RngCore) -> String {
+        self.style
+            .generate(&self.weights, &self.charset, self.length, &mut rng)
+            .expect("settings were checked in CharsetText::new")
+    }
+}
+
+/// Answers picked from a list of words, e.g. a dictionary of easily
+/// spelled words in the users' language.
+#[derive(Clone, Debug)]
+pub struct WordList {
+    words: Vec<String>,
+}
+
+impl WordList {
+    /// Fails when there are no words besides empty ones.
+    pub fn new(words: impl IntoIterator<Item = impl Into<String>>) -> Result<Self, CaptchaError> {
+        let words: Vec<String> = words
+            .into_iter()
+            .map(Into::into)
+            .filter(|word| !word.is_empty())
+            .collect();
+        if words.is_empty() {
+            return Err(CaptchaError::InvalidInput(
+                "a word list needs at least one word".into(),
+            ));
+        }
+        Ok(Self { words })
+    }
+}
+
+impl TextProvider for WordList {
+    fn next(&mut self, mut rng: &mut dyn RngCore) -> String {
+        self.words
+            .choose(&mut rng)
+            .cloned()
+            .expect("checked in WordList::new")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Config;
+
+    fn with_words(words: &[&'static str]) -> Config {
+        Config {
+            text_provider: Some(SharedTextProvider::new(
+                WordList::new(words.iter().copied()).unwrap(),
+            )),
+            ..Config::default()
+        }
+    }
+
+    #[test]
+    fn fits_provided_texts_longer_than_the_config() {
+        let generator = crate::Generator::new(with_words(&["extraordinarily"])).unwrap();
+        let (captcha, layout) = generator.generate_inner().unwrap();
+        assert_eq!(captcha.text.expose(), "extraordinarily");
+        assert_eq!(layout.glyphs.len(), 15);
+        assert!(
+            layout
+                .glyphs
+                .iter()
+                .all(|glyph| { glyph.x >= 0 && glyph.x as u32 + glyph.width <= layout.width })
+        );
+    }
+
+    #[test]
+    fn rejects_provided_texts_the_font_cant_draw() {
+        assert!(matches!(
+            with_words(&["日本語"]).generate(),
+            Err(CaptchaError::InvalidInput(_))
+        ));
+    }
+
+    #[test]
+    fn rejects_empty_provided_texts() {
+        struct Empty;
+        impl TextProvider for Empty {
+            fn next(&mut self, _: &mut dyn RngCore) -> String {
+                String::new()
+            }
+        }
+
+        let config = Config {
+            text_provider: Some(SharedTextProvider::new(Empty)),
+            ..Config::default()
+        };
+        assert!(matches!(
+            config.generate(),
+            Err(CaptchaError::InvalidInput(_))
+        ));
+    }
+
+    #[test]
+    fn word_lists_need_a_word() {
+        assert!(WordList::new([""]).is_err());
+        let mut words = WordList::new(["", "cat"]).unwrap();
+        assert_eq!(words.next(&mut rand::rng()), "cat");
+    }
+}
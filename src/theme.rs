@@ -0,0 +1,150 @@
+use crate::{
+    Background, Color, ColorScheme, Config, DecoyStyle, Difficulty, LineStyle, Stage, TextStyle,
+};
+
+/// The look of a deployment's captchas, separate from sizes, expiry and
+/// the other operational settings, so designers can ship it as a file. With
+/// the `theme` feature it loads from TOML:
+///
+/// ```toml
+/// color = "#1d3557"
+/// background_color = "ivory"
+/// difficulty = "medium"
+/// background = { perlin = { scale = 40.0, intensity = 0.2 } }
+/// pipeline = [
+///     { lines = { count = 4 } },
+///     { effect = { scanlines = { spacing = 3, intensity = 0.2, shift = 1 } } },
+/// ]
+/// ```
+///
+/// Settings left out keep the config's values.
+#[derive(Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default, deny_unknown_fields))]
+pub struct Theme {
+    pub color: Option<Color>,
+    pub color_scheme: Option<ColorScheme>,
+    pub background_color: Option<Color>,
+    pub background: Option<Background>,
+    pub text_style: Option<TextStyle>,
+    /// Applied before `line_style` and `decoys`, which override its
+    /// choices.
+    pub difficulty: Option<Difficulty>,
+    pub line_style: Option<LineStyle>,
+    pub decoys: Option<DecoyStyle>,
+    pub pipeline: Option<Vec<Stage>>,
+    pub pipeline_choices: Option<Vec<Vec<Stage>>>,
+}
+
+impl Theme {
+    #[cfg(feature = "theme")]
+    pub fn from_toml(toml: &str) -> Result<Self, crate::CaptchaError> {
+        toml::from_str(toml).map_err(|err| crate::CaptchaError::InvalidInput(err.to_string()))
+    }
+
+    pub fn apply(&self, config: &mut Config) {
+        if let Some(difficulty) = self.difficulty {
+            difficulty.apply(config);
+        }
+        if let Some(color) = self.color {
+            config.color = color;
+        }
+        if let Some(color_scheme) = self.color_scheme {
+            config.color_scheme = color_scheme;
+        }
+        if let Some(background_color) = self.background_color {
+            config.background_color = background_color;
+        }
+        if let Some(background) = self.background {
+            config.background = background;
+        }
+        if let Some(text_style) = self.text_style {
+            config.text_style = text_style;
+        }
+        if let Some(line_style) = &self.line_style {
+            config.line_style = line_style.clone();
+        }
+        if let Some(decoys) = self.decoys {
+            config.decoys = decoys;
+        }
+        if let Some(pipeline) = &self.pipeline {
+            config.pipeline = pipeline.clone();
+        }
+        if let Some(pipeline_choices) = &self.pipeline_choices {
+            config.pipeline_choices = pipeline_choices.clone();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn applies_only_what_is_set() {
+        let theme = Theme {
+            color: Some(Color::rgb(29, 53, 87)),
+            difficulty: Some(Difficulty::Hard),
+            decoys: Some(DecoyStyle {
+                count: 1,
+                ..DecoyStyle::default()
+            }),
+            ..Theme::default()
+        };
+        let mut config = Config::default();
+        theme.apply(&mut config);
+        assert_eq!(config.color, Color::rgb(29, 53, 87));
+        // Hard would add five decoys, but `decoys` wins.
+        assert_eq!(config.decoys.count, 1);
+        assert_eq!(config.glyph_style.fragments, 2);
+        assert_eq!(config.background_color, Config::default().background_color);
+
+        let mut config = Config::default();
+        Theme::default().apply(&mut config);
+        assert_eq!(config.pipeline, Config::default().pipeline);
+    }
+
+    #[cfg(feature = "theme")]
+    #[test]
+    fn round_trips_through_toml() {
+        let theme = Theme::from_toml(
+            r##"
+            color = "#1d3557"
+            background_color = "ivory"
+            difficulty = "medium"
+            background = { perlin = { scale = 40.0, intensity = 0.2 } }
+            pipeline = [
+                { lines = { count = 4 } },
+                { effect = { scanlines = { spacing = 3, intensity = 0.2, shift = 1 } } },
+            ]
+            "##,
+        )
+        .unwrap();
+        assert_eq!(theme.color, Some(Color::rgb(0x1d, 0x35, 0x57)));
+        assert_eq!(theme.difficulty, Some(Difficulty::Medium));
+        assert_eq!(theme.pipeline.as_ref().map(Vec::len), Some(2));
+
+        let written = toml::to_string(&theme).unwrap();
+        assert_eq!(Theme::from_toml(&written).unwrap(), theme);
+    }
+
+    #[cfg(feature = "theme")]
+    #[test]
+    fn rejects_unknown_keys_and_invalid_colors() {
+        for toml in [
+            "colour = \"red\"",
+            "color = \"#12345\"",
+            "color = \"not a color\"",
+            "background_color = 3",
+            "difficulty = \"impossible\"",
+        ] {
+            assert!(
+                matches!(
+                    Theme::from_toml(toml),
+                    Err(crate::CaptchaError::InvalidInput(_))
+                ),
+                "{toml}"
+            );
+        }
+    }
+}
@@ -0,0 +1,677 @@
+use std::{
+    collections::{BTreeSet, HashMap},
+    sync::Mutex,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use hmac::{Hmac, Mac};
+use sha2::{Digest, Sha256};
+
+use crate::{
+    Answer, AnswerFormat, CaptchaChallenge, SolveTimePolicy, VerifyOutcome,
+    captcha::is_expired,
+    hash::{from_hex, to_hex},
+};
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// Signs self-contained tokens, so captchas can be verified without storing
+/// anything server side.
+///
+/// A token has the form `v3.<id>.<issued>.<expiry>.<format>.<tag>.<mac>`.
+/// The tag covers the id, the issue time in unix milliseconds and the
+/// expiry in unix seconds (`-` for unknown or none), and the
+/// [`AnswerFormat`] of the challenge (`t` or `s`), so forged tokens are
+/// told apart without an answer; the MAC also covers the normalized
+/// answer. It is safe to hand to the client as is. Ids may contain dots,
+/// which none of the other fields do.
+///
+/// Session tokens, see [`TokenKey::issue_session_token`], look the same
+/// with an `s3` prefix, and their tag and MAC also cover a session
+/// identifier.
+/// To rotate keys, sign through a [`KeyRing`].
+///
+/// [`KeyRing`]: crate::KeyRing
+pub struct TokenKey {
+    key: Vec<u8>,
+}
+
+impl TokenKey {
+    pub fn new(key: impl Into<Vec<u8>>) -> Self {
+        Self { key: key.into() }
+    }
+
+    pub fn issue_token<C: CaptchaChallenge>(&self, challenge: &C) -> String {
+        self.issue(challenge, None)
+    }
+
+    /// A token that only verifies together with `session`, e.g. the
+    /// session id or CSRF token of the page showing the captcha. An answer
+    /// relayed from another session then doesn't verify, even when right.
+    pub fn issue_session_token<C: CaptchaChallenge>(&self, challenge: &C, session: &str) -> String {
+        self.issue(challenge, Some(session))
+    }
+
+    fn issue<C: CaptchaChallenge>(&self, challenge: &C, session: Option<&str>) -> String {
+        let issued = issued_field(challenge.issued_at());
+        let expiry = expiry_field(challenge.expires_at());
+        let format = C::ANSWER_FORMAT;
+        let tag = self.tag(session, challenge.id(), &issued, &expiry, format);
+        let mac = self.mac(
+            session,
+            challenge.id(),
+            &issued,
+            &expiry,
+            format,
+            challenge.expected_answer().expose(),
+        );
+        format!(
+            "{}.{}.{issued}.{expiry}.{}.{}.{}",
+            version(session),
+            challenge.id(),
+            format.code(),
+            to_hex(&tag),
+            to_hex(&mac.finalize().into_bytes())
+        )
+    }
+
+    /// Checks `answer` against a token. Any number of attempts can be made
+    /// with the same token until it expires; see
+    /// [`TokenKey::verify_token_once`] to prevent that.
+    pub fn verify_token(&self, token: &str, answer: &str) -> VerifyOutcome {
+        self.verify(token, None, answer, None)
+    }
+
+    /// Checks `answer` against a token from
+    /// [`TokenKey::issue_session_token`], which must have been issued for
+    /// the same `session`.
+    pub fn verify_session_token(&self, token: &str, answer: &str, session: &str) -> VerifyOutcome {
+        self.verify(token, Some(session), answer, None)
+    }
+
+    /// Like [`TokenKey::verify_token`], but answers that `policy` finds
+    /// suspiciously fast are rejected as [`VerifyOutcome::TooFast`], right
+    /// or wrong, as [`CaptchaStore::verify_timed`] does. Tokens of
+    /// challenges without an issue time never count as too fast.
+    ///
+    /// [`CaptchaStore::verify_timed`]: crate::CaptchaStore::verify_timed
+    pub fn verify_token_timed(
+        &self,
+        token: &str,
+        answer: &str,
+        policy: &impl SolveTimePolicy,
+    ) -> VerifyOutcome {
+        self.verify(token, None, answer, Some(policy))
+    }
+
+    /// [`TokenKey::verify_session_token`] with a minimum solve time, like
+    /// [`TokenKey::verify_token_timed`].
+    pub fn verify_session_token_timed(
+        &self,
+        token: &str,
+        answer: &str,
+        session: &str,
+        policy: &impl SolveTimePolicy,
+    ) -> VerifyOutcome {
+        self.verify(token, Some(session), answer, Some(policy))
+    }
+
+    /// Like [`TokenKey::verify_token`], but every token only gets a single
+    /// attempt, right or wrong, which `cache` keeps track of until the
+    /// token expires. Only tokens signed with this key are recorded, and
+    /// tokens of captchas without an expiry are rejected as
+    /// [`VerifyOutcome::UnknownId`], since they'd have to be remembered
+    /// forever.
+    pub fn verify_token_once(
+        &self,
+        token: &str,
+        answer: &str,
+        cache: &impl ReplayCache,
+    ) -> VerifyOutcome {
+        self.verify_once(token, None, answer, cache)
+    }
+
+    /// [`TokenKey::verify_session_token`] with a single attempt per token,
+    /// like [`TokenKey::verify_token_once`].
+    pub fn verify_session_token_once(
+        &self,
+        token: &str,
+        answer: &str,
+        session: &str,
+        cache: &impl ReplayCache,
+    ) -> VerifyOutcome {
+        self.verify_once(token, Some(session), answer, cache)
+    }
+
+    fn verify(
+        &self,
+        token: &str,
+        session: Option<&str>,
+        answer: &str,
+        policy: Option<&dyn SolveTimePolicy>,
+    ) -> VerifyOutcome {
+        match Token::parse(token, session) {
+            Some(token) => self.verify_parsed(&token, session, answer, policy),
+            None => VerifyOutcome::UnknownId,
+        }
+    }
+
+    fn verify_once(
+        &self,
+        token: &str,
+        session: Option<&str>,
+        answer: &str,
+        cache: &impl ReplayCache,
+    ) -> VerifyOutcome {
+        let Some(token) = Token::parse(token, session) else {
+            return VerifyOutcome::UnknownId;
+        };
+        if !self.is_signed(&token, session) || token.expires_at.is_none() {
+            return VerifyOutcome::UnknownId;
+        }
+        if is_expired(token.expires_at) {
+            return VerifyOutcome::Expired;
+        }
+        if !cache.first_use(token.id, token.expires_at) {
+            return VerifyOutcome::AlreadyUsed;
+        }
+        self.verify_parsed(&token, session, answer, None)
+    }
+
+    fn verify_parsed(
+        &self,
+        token: &Token<'_>,
+        session: Option<&str>,
+        answer: &str,
+        policy: Option<&dyn SolveTimePolicy>,
+    ) -> VerifyOutcome {
+        if !self.is_signed(token, session) {
+            return VerifyOutcome::UnknownId;
+        }
+        if is_expired(token.expires_at) {
+            return VerifyOutcome::Expired;
+        }
+        if let (Some(policy), Some(solve_time)) = (policy, token.solve_time())
+            && policy.is_too_fast(solve_time)
+        {
+            return VerifyOutcome::TooFast;
+        }
+        match self
+            .mac(
+                session,
+                token.id,
+                token.issued,
+                token.expiry,
+                token.format,
+                answer,
+            )
+            .verify_slice(&token.mac)
+        {
+            Ok(()) => VerifyOutcome::Correct,
+            Err(_) => VerifyOutcome::Wrong,
+        }
+    }
+
+    /// Whether the tag of `token` was made with this key.
+    fn is_signed(&self, token: &Token<'_>, session: Option<&str>) -> bool {
+        token.tag.len() == TAG_LEN
+            && self
+                .fields(
+                    b"tag",
+                    session,
+                    token.id,
+                    token.issued,
+                    token.expiry,
+                    token.format,
+                )
+                .verify_truncated_left(&token.tag)
+                .is_ok()
+    }
+
+    fn tag(
+        &self,
+        session: Option<&str>,
+        id: &str,
+        issued: &str,
+        expiry: &str,
+        format: AnswerFormat,
+    ) -> Vec<u8> {
+        let tag = self
+            .fields(b"tag", session, id, issued, expiry, format)
+            .finalize();
+        tag.into_bytes()[..TAG_LEN].to_vec()
+    }
+
+    fn mac(
+        &self,
+        session: Option<&str>,
+        id: &str,
+        issued: &str,
+        expiry: &str,
+        format: AnswerFormat,
+        answer: &str,
+    ) -> HmacSha256 {
+        let mut mac = self.fields(b"mac", session, id, issued, expiry, format);
+        mac.update(b".");
+        mac.update(
+            Answer::normalized(&format.normalize(answer))
+                .expose()
+                .as_bytes(),
+        );
+        mac
+    }
+
+    /// An HMAC over the token fields, kept apart for tags and MACs by
+    /// `purpose`.
+    fn fields(
+        &self,
+        purpose: &[u8; 3],
+        session: Option<&str>,
+        id: &str,
+        issued: &str,
+        expiry: &str,
+        format: AnswerFormat,
+    ) -> HmacSha256 {
+        let mut mac = HmacSha256::new_from_slice(&self.key).expect("HMAC accepts any key size");
+        mac.update(purpose);
+        mac.update(b".");
+        if let Some(session) = session {
+            // Hashed, so dots in the session can't shift the other fields.
+            mac.update(b"s3.");
+            mac.update(&Sha256::digest(session.as_bytes()));
+            mac.update(b".");
+        }
+        mac.update(id.as_bytes());
+        mac.update(b".");
+        mac.update(issued.as_bytes());
+        mac.update(b".");
+        mac.update(expiry.as_bytes());
+        mac.update(b".");
+        mac.update(format.code().as_bytes());
+        mac
+    }
+}
+
+/// Bytes of the tag in tokens, which only has to resist forgery.
+const TAG_LEN: usize = 16;
+
+struct Token<'a> {
+    id: &'a str,
+    issued: &'a str,
+    issued_at: Option<SystemTime>,
+    expiry: &'a str,
+    expires_at: Option<SystemTime>,
+    format: AnswerFormat,
+    tag: Vec<u8>,
+    mac: Vec<u8>,
+}
+
+impl<'a> Token<'a> {
+    /// Only accepts the token version matching whether there's a session.
+    /// The fields are read from the end, as the id is the only one that
+    /// can contain dots.
+    fn parse(token: &'a str, session: Option<&str>) -> Option<Self> {
+        let mut parts = token.rsplitn(6, '.');
+        let (Some(mac), Some(tag), Some(format), Some(expiry), Some(issued), Some(rest)) = (
+            parts.next(),
+            parts.next(),
+            parts.next(),
+            parts.next(),
+            parts.next(),
+            parts.next(),
+        ) else {
+            return None;
+        };
+        let (prefix, id) = rest.split_once('.')?;
+        if prefix != version(session) {
+            return None;
+        }
+
+        let issued_at = match issued {
+            "-" => None,
+            millis => Some(UNIX_EPOCH.checked_add(Duration::from_millis(millis.parse().ok()?))?),
+        };
+        let expires_at = match expiry {
+            "-" => None,
+            seconds => Some(UNIX_EPOCH.checked_add(Duration::from_secs(seconds.parse().ok()?))?),
+        };
+
+        Some(Self {
+            id,
+            issued,
+            issued_at,
+            expiry,
+            expires_at,
+            format: AnswerFormat::from_code(format)?,
+            tag: from_hex(tag)?,
+            mac: from_hex(mac)?,
+        })
+    }
+
+    /// Time since the challenge was issued, if known.
+    fn solve_time(&self) -> Option<Duration> {
+        self.issued_at?.elapsed().ok()
+    }
+}
+
+fn version(session: Option<&str>) -> &'static str {
+    match session {
+        Some(_) => "s3",
+        None => "v3",
+    }
+}
+
+/// In milliseconds, as solve times are judged to fractions of a second.
+fn issued_field(issued_at: Option<SystemTime>) -> String {
+    match issued_at.and_then(|t| t.duration_since(UNIX_EPOCH).ok()) {
+        Some(duration) => duration.as_millis().to_string(),
+        None => "-".to_string(),
+    }
+}
+
+fn expiry_field(expires_at: Option<SystemTime>) -> String {
+    match expires_at.and_then(|t| t.duration_since(UNIX_EPOCH).ok()) {
+        Some(duration) => duration.as_secs().to_string(),
+        None => "-".to_string(),
+    }
+}
+
+/// Remembers which token ids were already used.
+pub trait ReplayCache: Send + Sync {
+    /// Records `id` and returns whether this is its first use. The record
+    /// is only needed until `expires_at`; `None` asks to keep it forever,
+    /// which caches may cap.
+    fn first_use(&self, id: &str, expires_at: Option<SystemTime>) -> bool;
+}
+
+/// A [`ReplayCache`] in process memory, holding at most `capacity` ids for
+/// at most `max_ttl` each, see [`MemoryReplayCache::with_limits`].
+/// Expired ids are dropped as new ones come in.
+pub struct MemoryReplayCache {
+    max_ttl: Duration,
+    capacity: usize,
+    seen: Mutex<Seen>,
+}
+
+#[derive(Default)]
+struct Seen {
+    /// Until when each id is remembered, `None` for ever.
+    by_id: HashMap<String, Option<SystemTime>>,
+    /// The ids with an end, soonest first, so expired ones are found
+    /// without scanning every id.
+    by_expiry: BTreeSet<(SystemTime, String)>,
+}
+
+/// Defaults of [`MemoryReplayCache::new`].
+const DEFAULT_MAX_TTL: Duration = Duration::from_secs(24 * 60 * 60);
+const DEFAULT_CAPACITY: usize = 1 << 20;
+
+impl Default for MemoryReplayCache {
+    fn default() -> Self {
+        Self::with_limits(DEFAULT_MAX_TTL, DEFAULT_CAPACITY)
+    }
+}
+
+impl MemoryReplayCache {
+    /// Remembers up to about a million ids for up to a day.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Remembers every id until it expires, but no longer than `max_ttl`,
+    /// which should be at least the validity of the captchas. While
+    /// `capacity` ids are remembered, every further id counts as used, so
+    /// a flood of tokens can't push out the records of earlier ones.
+    pub fn with_limits(max_ttl: Duration, capacity: usize) -> Self {
+        Self {
+            max_ttl,
+            capacity,
+            seen: Mutex::new(Seen::default()),
+        }
+    }
+}
+
+impl ReplayCache for MemoryReplayCache {
+    fn first_use(&self, id: &str, expires_at: Option<SystemTime>) -> bool {
+        let now = SystemTime::now();
+        let until = match (expires_at, now.checked_add(self.max_ttl)) {
+            (Some(expires_at), Some(cap)) => Some(expires_at.min(cap)),
+            (expires_at, cap) => expires_at.or(cap),
+        };
+
+        let mut seen = self.seen.lock().unwrap();
+        while let Some((expires_at, _)) = seen.by_expiry.first()
+            && *expires_at <= now
+        {
+            let (_, expired) = seen.by_expiry.pop_first().expect("checked above");
+            seen.by_id.remove(&expired);
+        }
+        if seen.by_id.contains_key(id) || seen.by_id.len() >= self.capacity {
+            return false;
+        }
+        seen.by_id.insert(id.to_string(), until);
+        if let Some(until) = until {
+            seen.by_expiry.insert((until, id.to_string()));
+        }
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        MinSolveTime,
+        test_util::{a_minute_ago, challenge, in_a_minute},
+    };
+
+    #[test]
+    fn round_trips() {
+        let key = TokenKey::new("secret");
+        let token = key.issue_token(&challenge("a", in_a_minute()));
+        assert_eq!(key.verify_token(&token, "7"), VerifyOutcome::Correct);
+        assert_eq!(key.verify_token(&token, "8"), VerifyOutcome::Wrong);
+
+        let token = key.issue_token(&challenge("b", None));
+        assert_eq!(key.verify_token(&token, "7"), VerifyOutcome::Correct);
+    }
+
+    #[test]
+    fn rejects_expired_tokens() {
+        let key = TokenKey::new("secret");
+        let token = key.issue_token(&challenge("a", a_minute_ago()));
+        assert_eq!(key.verify_token(&token, "7"), VerifyOutcome::Expired);
+        assert_eq!(
+            key.verify_token_once(&token, "7", &MemoryReplayCache::new()),
+            VerifyOutcome::Expired
+        );
+    }
+
+    #[test]
+    fn allows_one_attempt_per_token() {
+        let key = TokenKey::new("secret");
+        let cache = MemoryReplayCache::new();
+        let token = key.issue_token(&challenge("a", in_a_minute()));
+        assert_eq!(
+            key.verify_token_once(&token, "8", &cache),
+            VerifyOutcome::Wrong
+        );
+        assert_eq!(
+            key.verify_token_once(&token, "7", &cache),
+            VerifyOutcome::AlreadyUsed
+        );
+
+        // They'd have to be remembered forever.
+        let token = key.issue_token(&challenge("b", None));
+        assert_eq!(
+            key.verify_token_once(&token, "7", &cache),
+            VerifyOutcome::UnknownId
+        );
+    }
+
+    #[test]
+    fn records_only_signed_tokens() {
+        let key = TokenKey::new("secret");
+        let cache = MemoryReplayCache::new();
+        let forged = TokenKey::new("other").issue_token(&challenge("a", in_a_minute()));
+        assert_eq!(
+            key.verify_token_once(&forged, "7", &cache),
+            VerifyOutcome::UnknownId
+        );
+
+        let token = key.issue_token(&challenge("a", in_a_minute()));
+        assert_eq!(
+            key.verify_token_once(&token, "7", &cache),
+            VerifyOutcome::Correct
+        );
+    }
+
+    #[test]
+    fn bounds_the_replay_cache() {
+        let cache = MemoryReplayCache::with_limits(Duration::from_secs(60), 1);
+        assert!(cache.first_use("a", in_a_minute()));
+        assert!(!cache.first_use("a", in_a_minute()));
+        assert!(!cache.first_use("b", in_a_minute()));
+
+        // Records end after `max_ttl` even for later expiries.
+        let cache = MemoryReplayCache::with_limits(Duration::ZERO, 1);
+        assert!(cache.first_use("a", None));
+        assert!(cache.first_use("a", in_a_minute()));
+    }
+
+    #[test]
+    fn binds_session_tokens_to_their_session() {
+        let key = TokenKey::new("secret");
+        let cache = MemoryReplayCache::new();
+        let token = key.issue_session_token(&challenge("a", in_a_minute()), "session");
+        assert_eq!(
+            key.verify_session_token(&token, "7", "session"),
+            VerifyOutcome::Correct
+        );
+        assert_eq!(
+            key.verify_session_token(&token, "8", "session"),
+            VerifyOutcome::Wrong
+        );
+        assert_eq!(
+            key.verify_session_token(&token, "7", "other"),
+            VerifyOutcome::UnknownId
+        );
+        assert_eq!(
+            key.verify_session_token_once(&token, "7", "other", &cache),
+            VerifyOutcome::UnknownId
+        );
+        assert_eq!(
+            key.verify_session_token_once(&token, "7", "session", &cache),
+            VerifyOutcome::Correct
+        );
+    }
+
+    #[test]
+    fn keeps_session_and_plain_tokens_apart() {
+        let key = TokenKey::new("secret");
+        let session = key.issue_session_token(&challenge("a", in_a_minute()), "session");
+        let plain = key.issue_token(&challenge("a", in_a_minute()));
+        assert_eq!(key.verify_token(&session, "7"), VerifyOutcome::UnknownId);
+        assert_eq!(
+            key.verify_session_token(&plain, "7", "session"),
+            VerifyOutcome::UnknownId
+        );
+        assert_eq!(
+            key.verify_session_token(&plain.replacen("v3", "s3", 1), "7", "session"),
+            VerifyOutcome::UnknownId
+        );
+    }
+
+    #[test]
+    fn round_trips_ids_with_dots() {
+        let key = TokenKey::new("secret");
+        for id in ["grid.v1.a", ".", "a..b."] {
+            let token = key.issue_token(&challenge(id, in_a_minute()));
+            assert_eq!(
+                key.verify_token(&token, "7"),
+                VerifyOutcome::Correct,
+                "{id}"
+            );
+            assert_eq!(key.verify_token(&token, "8"), VerifyOutcome::Wrong, "{id}");
+            let session = key.issue_session_token(&challenge(id, in_a_minute()), "session");
+            assert_eq!(
+                key.verify_session_token(&session, "7", "session"),
+                VerifyOutcome::Correct
+            );
+        }
+
+        // Moving a dot between the id and the expiry breaks the tag.
+        let token = key.issue_token(&challenge("a.1", in_a_minute()));
+        let shifted = token.replacen("a.1.", "a.1", 1);
+        assert_eq!(key.verify_token(&shifted, "7"), VerifyOutcome::UnknownId);
+    }
+
+    #[test]
+    fn rejects_tampered_tokens() {
+        let key = TokenKey::new("secret");
+        let token = key.issue_token(&challenge("a", in_a_minute()));
+        let replace = |index: usize, value: &str| {
+            let mut parts: Vec<&str> = token.split('.').collect();
+            parts[index] = value;
+            key.verify_token(&parts.join("."), "7")
+        };
+
+        assert_eq!(replace(0, "v2"), VerifyOutcome::UnknownId);
+        assert_eq!(replace(1, "b"), VerifyOutcome::UnknownId);
+        assert_eq!(replace(2, "-"), VerifyOutcome::UnknownId);
+        assert_eq!(replace(2, "0"), VerifyOutcome::UnknownId);
+        assert_eq!(replace(3, "-"), VerifyOutcome::UnknownId);
+        assert_eq!(replace(4, "s"), VerifyOutcome::UnknownId);
+        assert_eq!(
+            replace(5, &"0".repeat(2 * TAG_LEN)),
+            VerifyOutcome::UnknownId
+        );
+        assert_eq!(replace(6, &"0".repeat(64)), VerifyOutcome::Wrong);
+        assert_eq!(
+            key.verify_token(&format!("{token}.0"), "7"),
+            VerifyOutcome::UnknownId
+        );
+    }
+
+    #[test]
+    fn rejects_fast_answers_right_or_wrong() {
+        let key = TokenKey::new("secret");
+        let policy = MinSolveTime(Duration::from_secs(60));
+        let token = key.issue_token(&challenge("a", in_a_minute()));
+        assert_eq!(
+            key.verify_token_timed(&token, "7", &policy),
+            VerifyOutcome::TooFast
+        );
+        assert_eq!(
+            key.verify_token_timed(&token, "8", &policy),
+            VerifyOutcome::TooFast
+        );
+        let token = key.issue_session_token(&challenge("a", in_a_minute()), "session");
+        assert_eq!(
+            key.verify_session_token_timed(&token, "7", "session", &policy),
+            VerifyOutcome::TooFast
+        );
+    }
+
+    #[test]
+    fn accepts_answers_after_the_minimum() {
+        let key = TokenKey::new("secret");
+        let policy = MinSolveTime(Duration::from_secs(1));
+        let mut slow = challenge("a", in_a_minute());
+        slow.issued_at = SystemTime::now() - Duration::from_secs(5);
+        let token = key.issue_token(&slow);
+        assert_eq!(
+            key.verify_token_timed(&token, "7", &policy),
+            VerifyOutcome::Correct
+        );
+        assert_eq!(
+            key.verify_token_timed(&token, "8", &policy),
+            VerifyOutcome::Wrong
+        );
+        let token = key.issue_session_token(&slow, "session");
+        assert_eq!(
+            key.verify_session_token_timed(&token, "7", "session", &policy),
+            VerifyOutcome::Correct
+        );
+    }
+}
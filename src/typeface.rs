@@ -0,0 +1,405 @@
+use rand::Rng;
+
+use crate::{CaptchaError, GlyphStyle, glyph::Mask, outline::LoadedFont};
+
+/// Where glyph shapes come from.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub enum Typeface {
+    /// The generator's font. Every character is drawn from its own glyph,
+    /// without text shaping, so ligatures and contextual alternates never
+    /// merge or swap answer characters.
+    #[default]
+    Font,
+    /// Glyphs built from round dots on a 5x7 grid, like a dot-matrix
+    /// display. OCR models trained on font outlines don't recognize them.
+    /// `dot` is the dot diameter relative to the grid pitch, from 0.3 to 1.
+    DotMatrix { dot: f32 },
+    /// Digits drawn as seven-segment display digits, also without a font.
+    /// Captchas then only contain digits.
+    SevenSegment {
+        /// Segment thickness relative to the digit height, e.g. 0.12.
+        thickness: f32,
+        /// Horizontal shift per pixel of height, e.g. 0.15 for a typical
+        /// display lean.
+        slant: f32,
+        /// Chance, from 0 to 1, that a lit segment has a gap cut across it.
+        broken: f32,
+    },
+}
+
+impl Typeface {
+    /// Characters captcha text is drawn from.
+    pub(crate) fn charset(&self) -> &'static str {
+        match self {
+            Typeface::SevenSegment { .. } => "0123456789",
+            _ => "23456789ABCDEFGHJKLMNPQRSTUVWXYZabcdefghijkmnpqrstuvwxyz",
+        }
+    }
+
+    /// Characters of `chars` drawn as a placeholder for lack of a shape,
+    /// sorted and without duplicates. Only the font-free typefaces are
+    /// checked; whitespace is drawn blank.
+    pub(crate) fn missing(&self, chars: impl Iterator<Item = char>) -> Vec<char> {
+        let mut missing: Vec<char> = chars
+            .filter(|&c| {
+                !c.is_whitespace()
+                    && match self {
+                        Typeface::Font => false,
+                        Typeface::DotMatrix { .. } => dot_rows(c).is_none(),
+                        Typeface::SevenSegment { .. } => segments(c).is_none(),
+                    }
+            })
+            .collect();
+        missing.sort_unstable();
+        missing.dedup();
+        missing
+    }
+
+    /// Rasterizes `c` at roughly the visual size `font` would have at
+    /// `size` pixels, returning the coverage and horizontal advance.
+    /// `style`'s jitter and variation only apply to font glyphs, see
+    /// [`GlyphStyle::jitter`].
+    ///
+    /// [`GlyphStyle::jitter`]: crate::GlyphStyle::jitter
+    pub(crate) fn rasterize(
+        &self,
+        font: Option<&LoadedFont>,
+        c: char,
+        size: f32,
+        style: &GlyphStyle,
+        rng: &mut impl Rng,
+    ) -> Result<(Mask, f32), CaptchaError> {
+        Ok(match *self {
+            Typeface::Font => {
+                let font = font.ok_or(NO_FONT)?;
+                let axes = style.variation.map(|variation| variation.pick(rng));
+                if (style.jitter > 0.0 || axes.is_some())
+                    && let Some(outlined) = font.rasterize_outline(
+                        c,
+                        size,
+                        style.jitter.max(0.0),
+                        axes.as_ref().map_or(&[], |axes| axes.as_slice()),
+                        rng,
+                    )
+                {
+                    return Ok(outlined);
+                }
+                let (metrics, bitmap) = font.resolve(c).1.rasterize(c, size);
+                (
+                    Mask::new(metrics.width, metrics.height, bitmap),
+                    metrics.advance_width,
+                )
+            }
+            Typeface::DotMatrix { dot } => dot_matrix(c, size, dot.clamp(0.3, 1.0)),
+            Typeface::SevenSegment {
+                thickness,
+                slant,
+                broken,
+            } => seven_segment(
+                c,
+                size,
+                thickness.clamp(0.04, 0.3),
+                slant,
+                broken.clamp(0.0, 1.0),
+                rng,
+            ),
+        })
+    }
+}
+
+pub(crate) const NO_FONT: CaptchaError = CaptchaError::Font("the generator has no font");
+
+fn dot_matrix(c: char, size: f32, dot: f32) -> (Mask, f32) {
+    // Seven rows span about the cap height of a font at the same size.
+    let pitch = (size * 0.7 / 7.0).max(2.0);
+    let radius = pitch * dot / 2.0;
+    let width = (5.0 * pitch).ceil() as usize;
+    let height = (7.0 * pitch).ceil() as usize;
+
+    let rows = dot_rows(c).unwrap_or(HOLLOW_BOX);
+    let mut alpha = vec![0u8; width * height];
+    for (row, bits) in rows.iter().enumerate() {
+        for column in 0..5 {
+            if bits >> (4 - column) & 1 == 0 {
+                continue;
+            }
+            let cx = (column as f32 + 0.5) * pitch;
+            let cy = (row as f32 + 0.5) * pitch;
+            let x0 = (cx - radius - 1.0).max(0.0) as usize;
+            let y0 = (cy - radius - 1.0).max(0.0) as usize;
+            let x1 = ((cx + radius + 1.0) as usize).min(width - 1);
+            let y1 = ((cy + radius + 1.0) as usize).min(height - 1);
+            for y in y0..=y1 {
+                for x in x0..=x1 {
+                    let distance = (x as f32 + 0.5 - cx).hypot(y as f32 + 0.5 - cy);
+                    let coverage = (radius + 0.5 - distance).clamp(0.0, 1.0);
+                    let pixel = &mut alpha[y * width + x];
+                    *pixel = (*pixel).max((coverage * 255.0) as u8);
+                }
+            }
+        }
+    }
+
+    (Mask::new(width, height, alpha), 6.0 * pitch)
+}
+
+/// Lit segments of a digit, bits 0 to 6 being segments a to g: top, upper
+/// right, lower right, bottom, lower left, upper left and middle. Other
+/// characters are drawn as [`DASH`].
+fn segments(c: char) -> Option<u8> {
+    Some(match c {
+        '0' => 0b011_1111,
+        '1' => 0b000_0110,
+        '2' => 0b101_1011,
+        '3' => 0b100_1111,
+        '4' => 0b110_0110,
+        '5' => 0b110_1101,
+        '6' => 0b111_1101,
+        '7' => 0b000_0111,
+        '8' => 0b111_1111,
+        '9' => 0b110_1111,
+        _ => return None,
+    })
+}
+
+/// Just the middle segment.
+const DASH: u8 = 0b100_0000;
+
+fn seven_segment(
+    c: char,
+    size: f32,
+    thickness: f32,
+    slant: f32,
+    broken: f32,
+    rng: &mut impl Rng,
+) -> (Mask, f32) {
+    let digit_height = size * 0.7;
+    let digit_width = digit_height * 0.55;
+    let t = (digit_height * thickness).max(1.0);
+    let shift = slant * digit_height;
+    let width = (digit_width + shift.abs()).ceil() as usize + 1;
+    let height = digit_height.ceil() as usize + 1;
+
+    let (left, right) = (t / 2.0, digit_width - t / 2.0);
+    let (top, middle, bottom) = (t / 2.0, digit_height / 2.0, digit_height - t / 2.0);
+    let ends = [
+        ((left, top), (right, top)),
+        ((right, top), (right, middle)),
+        ((right, middle), (right, bottom)),
+        ((left, bottom), (right, bottom)),
+        ((left, middle), (left, bottom)),
+        ((left, top), (left, middle)),
+        ((left, middle), (right, middle)),
+    ];
+
+    let lit = segments(c).unwrap_or(DASH);
+    let segments: Vec<_> = ends
+        .iter()
+        .enumerate()
+        .filter(|(index, _)| lit >> index & 1 == 1)
+        .map(|(_, &(from, to))| {
+            // Position of the gap along the segment, if it's broken.
+            let gap = rng
+                .random_bool(broken as f64)
+                .then(|| rng.random_range(0.3..0.7));
+            (from, to, gap)
+        })
+        .collect();
+
+    // Coverage is estimated from 4x4 samples per pixel.
+    const SAMPLES: usize = 4;
+    let mut alpha = vec![0u8; width * height];
+    for y in 0..height {
+        for x in 0..width {
+            let mut hits = 0;
+            for sy in 0..SAMPLES {
+                for sx in 0..SAMPLES {
+                    let py = y as f32 + (sy as f32 + 0.5) / SAMPLES as f32;
+                    // Undo the slant so segments can be tested upright.
+                    let px =
+                        x as f32 + (sx as f32 + 0.5) / SAMPLES as f32 - shift.max(0.0) + slant * py;
+                    if segments
+                        .iter()
+                        .any(|&(from, to, gap)| in_segment(px, py, from, to, gap, t))
+                    {
+                        hits += 1;
+                    }
+                }
+            }
+            alpha[y * width + x] = (hits * 255 / (SAMPLES * SAMPLES)) as u8;
+        }
+    }
+
+    (Mask::new(width, height, alpha), width as f32 + t)
+}
+
+/// Whether a point lies on a segment with pointed ends, leaving a small
+/// gap towards the neighbouring segments like displays do.
+fn in_segment(
+    x: f32,
+    y: f32,
+    from: (f32, f32),
+    to: (f32, f32),
+    gap: Option<f32>,
+    thickness: f32,
+) -> bool {
+    let (dx, dy) = (to.0 - from.0, to.1 - from.1);
+    let length = dx.hypot(dy);
+    let (ux, uy) = (dx / length, dy / length);
+    let along = (x - from.0) * ux + (y - from.1) * uy;
+    let across = ((x - from.0) * uy - (y - from.1) * ux).abs();
+
+    if let Some(gap) = gap
+        && (along - gap * length).abs() < thickness * 0.4
+    {
+        return false;
+    }
+
+    let half = thickness / 2.0;
+    let overhang = (along - length / 2.0).abs() - (length / 2.0 - half * 0.6);
+    across <= half - overhang.max(0.0)
+}
+
+/// Rows of a 5x7 glyph, top to bottom, with the leftmost dot in bit 4.
+/// Characters outside the table are drawn as [`HOLLOW_BOX`].
+fn dot_rows(c: char) -> Option<[u8; 7]> {
+    Some(match c {
+        '0' => [0x0E, 0x11, 0x13, 0x15, 0x19, 0x11, 0x0E],
+        '1' => [0x04, 0x0C, 0x04, 0x04, 0x04, 0x04, 0x0E],
+        '2' => [0x0E, 0x11, 0x01, 0x02, 0x04, 0x08, 0x1F],
+        '3' => [0x1F, 0x02, 0x04, 0x02, 0x01, 0x11, 0x0E],
+        '4' => [0x02, 0x06, 0x0A, 0x12, 0x1F, 0x02, 0x02],
+        '5' => [0x1F, 0x10, 0x1E, 0x01, 0x01, 0x11, 0x0E],
+        '6' => [0x06, 0x08, 0x10, 0x1E, 0x11, 0x11, 0x0E],
+        '7' => [0x1F, 0x01, 0x02, 0x04, 0x08, 0x08, 0x08],
+        '8' => [0x0E, 0x11, 0x11, 0x0E, 0x11, 0x11, 0x0E],
+        '9' => [0x0E, 0x11, 0x11, 0x0F, 0x01, 0x02, 0x0C],
+        'A' => [0x0E, 0x11, 0x11, 0x11, 0x1F, 0x11, 0x11],
+        'B' => [0x1E, 0x11, 0x11, 0x1E, 0x11, 0x11, 0x1E],
+        'C' => [0x0E, 0x11, 0x10, 0x10, 0x10, 0x11, 0x0E],
+        'D' => [0x1C, 0x12, 0x11, 0x11, 0x11, 0x12, 0x1C],
+        'E' => [0x1F, 0x10, 0x10, 0x1E, 0x10, 0x10, 0x1F],
+        'F' => [0x1F, 0x10, 0x10, 0x1E, 0x10, 0x10, 0x10],
+        'G' => [0x0E, 0x11, 0x10, 0x17, 0x11, 0x11, 0x0F],
+        'H' => [0x11, 0x11, 0x11, 0x1F, 0x11, 0x11, 0x11],
+        'I' => [0x0E, 0x04, 0x04, 0x04, 0x04, 0x04, 0x0E],
+        'J' => [0x07, 0x02, 0x02, 0x02, 0x02, 0x12, 0x0C],
+        'K' => [0x11, 0x12, 0x14, 0x18, 0x14, 0x12, 0x11],
+        'L' => [0x10, 0x10, 0x10, 0x10, 0x10, 0x10, 0x1F],
+        'M' => [0x11, 0x1B, 0x15, 0x15, 0x11, 0x11, 0x11],
+        'N' => [0x11, 0x11, 0x19, 0x15, 0x13, 0x11, 0x11],
+        'O' => [0x0E, 0x11, 0x11, 0x11, 0x11, 0x11, 0x0E],
+        'P' => [0x1E, 0x11, 0x11, 0x1E, 0x10, 0x10, 0x10],
+        'Q' => [0x0E, 0x11, 0x11, 0x11, 0x15, 0x12, 0x0D],
+        'R' => [0x1E, 0x11, 0x11, 0x1E, 0x14, 0x12, 0x11],
+        'S' => [0x0F, 0x10, 0x10, 0x0E, 0x01, 0x01, 0x1E],
+        'T' => [0x1F, 0x04, 0x04, 0x04, 0x04, 0x04, 0x04],
+        'U' => [0x11, 0x11, 0x11, 0x11, 0x11, 0x11, 0x0E],
+        'V' => [0x11, 0x11, 0x11, 0x11, 0x11, 0x0A, 0x04],
+        'W' => [0x11, 0x11, 0x11, 0x15, 0x15, 0x15, 0x0A],
+        'X' => [0x11, 0x11, 0x0A, 0x04, 0x0A, 0x11, 0x11],
+        'Y' => [0x11, 0x11, 0x11, 0x0A, 0x04, 0x04, 0x04],
+        'Z' => [0x1F, 0x01, 0x02, 0x04, 0x08, 0x10, 0x1F],
+        'a' => [0x00, 0x00, 0x0E, 0x01, 0x0F, 0x11, 0x0F],
+        'b' => [0x10, 0x10, 0x16, 0x19, 0x11, 0x11, 0x1E],
+        'c' => [0x00, 0x00, 0x0E, 0x10, 0x10, 0x11, 0x0E],
+        'd' => [0x01, 0x01, 0x0D, 0x13, 0x11, 0x11, 0x0F],
+        'e' => [0x00, 0x00, 0x0E, 0x11, 0x1F, 0x10, 0x0E],
+        'f' => [0x06, 0x09, 0x08, 0x1C, 0x08, 0x08, 0x08],
+        'g' => [0x00, 0x0F, 0x11, 0x11, 0x0F, 0x01, 0x0E],
+        'h' => [0x10, 0x10, 0x16, 0x19, 0x11, 0x11, 0x11],
+        'i' => [0x04, 0x00, 0x0C, 0x04, 0x04, 0x04, 0x0E],
+        'j' => [0x02, 0x00, 0x06, 0x02, 0x02, 0x12, 0x0C],
+        'k' => [0x10, 0x10, 0x12, 0x14, 0x18, 0x14, 0x12],
+        'l' => [0x0C, 0x04, 0x04, 0x04, 0x04, 0x04, 0x0E],
+        'm' => [0x00, 0x00, 0x1A, 0x15, 0x15, 0x11, 0x11],
+        'n' => [0x00, 0x00, 0x16, 0x19, 0x11, 0x11, 0x11],
+        'o' => [0x00, 0x00, 0x0E, 0x11, 0x11, 0x11, 0x0E],
+        'p' => [0x00, 0x00, 0x1E, 0x11, 0x1E, 0x10, 0x10],
+        'q' => [0x00, 0x00, 0x0D, 0x13, 0x0F, 0x01, 0x01],
+        'r' => [0x00, 0x00, 0x16, 0x19, 0x10, 0x10, 0x10],
+        's' => [0x00, 0x00, 0x0E, 0x10, 0x0E, 0x01, 0x1E],
+        't' => [0x08, 0x08, 0x1C, 0x08, 0x08, 0x09, 0x06],
+        'u' => [0x00, 0x00, 0x11, 0x11, 0x11, 0x13, 0x0D],
+        'v' => [0x00, 0x00, 0x11, 0x11, 0x11, 0x0A, 0x04],
+        'w' => [0x00, 0x00, 0x11, 0x11, 0x15, 0x15, 0x0A],
+        'x' => [0x00, 0x00, 0x11, 0x0A, 0x04, 0x0A, 0x11],
+        'y' => [0x00, 0x00, 0x11, 0x11, 0x0F, 0x01, 0x0E],
+        'z' => [0x00, 0x00, 0x1F, 0x02, 0x04, 0x08, 0x1F],
+        _ => return None,
+    })
+}
+
+const HOLLOW_BOX: [u8; 7] = [0x1F, 0x11, 0x11, 0x11, 0x11, 0x11, 0x1F];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Config, Generator, RngSource};
+
+    #[test]
+    fn draws_dot_matrix_captchas_without_a_font() {
+        let config = Config {
+            typeface: Typeface::DotMatrix { dot: 0.8 },
+            answer_rng: RngSource::Seeded(1),
+            visual_rng: RngSource::Seeded(1),
+            ..Default::default()
+        };
+        let captcha = Generator::without_font(config).generate().unwrap();
+        assert!(captcha.text.expose().chars().all(|c| dot_rows(c).is_some()));
+    }
+
+    #[test]
+    fn reports_characters_without_dot_rows() {
+        let typeface = Typeface::DotMatrix { dot: 0.8 };
+        assert!(typeface.missing(typeface.charset().chars()).is_empty());
+        assert_eq!(typeface.missing("é a€é".chars()), ['é', '€']);
+        assert!(Typeface::Font.missing("é€".chars()).is_empty());
+    }
+
+    #[test]
+    fn scales_dots_with_the_size() {
+        let (small, small_advance) = dot_matrix('A', 20.0, 0.8);
+        let (large, large_advance) = dot_matrix('A', 80.0, 0.8);
+        assert!(large.width > small.width && large.height > small.height);
+        assert!(large_advance > small_advance);
+    }
+
+    #[test]
+    fn draws_seven_segment_captchas_from_digits() {
+        let config = Config {
+            typeface: Typeface::SevenSegment {
+                thickness: 0.12,
+                slant: 0.15,
+                broken: 0.5,
+            },
+            answer_rng: RngSource::Seeded(2),
+            visual_rng: RngSource::Seeded(2),
+            ..Default::default()
+        };
+        assert_eq!(config.charset(), "0123456789");
+        let captcha = Generator::without_font(config).generate().unwrap();
+        assert!(captcha.text.expose().chars().all(|c| c.is_ascii_digit()));
+    }
+
+    #[test]
+    fn lights_more_segments_for_eights_than_ones() {
+        let coverage = |c| {
+            let mut rng = rand::rng();
+            let (mask, _) = seven_segment(c, 40.0, 0.12, 0.0, 0.0, &mut rng);
+            mask.alpha.iter().map(|&a| a as u32).sum::<u32>()
+        };
+        assert!(coverage('8') > coverage('0'));
+        assert!(coverage('0') > coverage('1'));
+        // Characters without segments are drawn as the middle dash alone.
+        assert!(coverage('x') < coverage('1'));
+        assert!(coverage('x') > 0);
+    }
+
+    #[test]
+    fn needs_a_font_for_the_font_typeface() {
+        let generator = Generator::without_font(Config::default());
+        assert!(matches!(generator.generate(), Err(CaptchaError::Font(_))));
+    }
+}
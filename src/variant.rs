@@ -0,0 +1,107 @@
+use rand::{rng, seq::IndexedRandom};
+
+use crate::{Captcha, CaptchaError, Config, Generator};
+
+/// A named config taking part in an experiment.
+pub struct Variant {
+    pub name: String,
+    /// Relative share of generations, compared to the other variants.
+    pub weight: u32,
+    pub config: Config,
+}
+
+/// Picks one of several configs at random per captcha, so solve and bot
+/// rates can be compared between them.
+pub struct Variants {
+    generators: Vec<(String, u32, Generator)>,
+}
+
+impl Variants {
+    pub fn new(variants: Vec<Variant>) -> Result<Self, CaptchaError> {
+        if variants.iter().all(|variant| variant.weight == 0) {
+            return Err(CaptchaError::InvalidInput(
+                "at least one variant needs a non-zero weight".into(),
+            ));
+        }
+        if variants
+            .iter()
+            .try_fold(0u32, |total, variant| total.checked_add(variant.weight))
+            .is_none()
+        {
+            return Err(CaptchaError::InvalidInput(
+                "variant weights must add up to at most u32::MAX".into(),
+            ));
+        }
+
+        let generators = variants
+            .into_iter()
+            .map(|variant| {
+                Generator::new(variant.config)
+                    .map(|generator| (variant.name, variant.weight, generator))
+            })
+            .collect::<Result<_, _>>()?;
+
+        Ok(Self { generators })
+    }
+
+    /// Renders a captcha with a randomly chosen variant, returning the
+    /// variant's name alongside it.
+    pub fn generate(&self) -> Result<(&str, Captcha), CaptchaError> {
+        let (name, _, generator) = self
+            .generators
+            .choose_weighted(&mut rng(), |(_, weight, _)| *weight)
+            .expect("weights were checked in Variants::new");
+
+        Ok((name, generator.generate()?))
+    }
+
+    /// The generator of the variant called `name`, e.g. to tune it with
+    /// [`Generator::update_config`].
+    pub fn get(&self, name: &str) -> Option<&Generator> {
+        self.generators
+            .iter()
+            .find(|(variant, _, _)| variant == name)
+            .map(|(_, _, generator)| generator)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn variant(name: &str, weight: u32, length: u32) -> Variant {
+        Variant {
+            name: name.into(),
+            weight,
+            config: Config {
+                length,
+                ..Config::default()
+            },
+        }
+    }
+
+    #[test]
+    fn generates_with_weighted_variants() {
+        let variants = Variants::new(vec![variant("short", 1, 3), variant("off", 0, 5)]).unwrap();
+        for _ in 0..3 {
+            let (name, captcha) = variants.generate().unwrap();
+            assert_eq!((name, captcha.text.len()), ("short", 3));
+        }
+        assert_eq!(variants.get("off").unwrap().config().length, 5);
+        assert!(variants.get("missing").is_none());
+    }
+
+    #[test]
+    fn rejects_unusable_weights() {
+        for weights in [vec![], vec![0, 0], vec![u32::MAX, 1]] {
+            let variants = weights
+                .into_iter()
+                .map(|weight| variant("a", weight, 4))
+                .collect();
+            assert!(matches!(
+                Variants::new(variants),
+                Err(CaptchaError::InvalidInput(_))
+            ));
+        }
+    }
+}
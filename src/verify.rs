@@ -0,0 +1,111 @@
+use std::time::Duration;
+
+/// The result of checking an answer, telling apart why it was rejected so
+/// servers can respond and log accordingly. Only [`VerifyOutcome::Correct`]
+/// should let the request through.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum VerifyOutcome {
+    Correct,
+    Wrong,
+    Expired,
+    /// The id used up its attempts. Not returned by this crate, which gives
+    /// every captcha a single attempt; for stores that allow several.
+    TooManyAttempts,
+    /// The captcha was already answered. Stores that forget answered ids,
+    /// like [`MemoryStore`], report [`VerifyOutcome::UnknownId`] instead.
+    ///
+    /// [`MemoryStore`]: crate::MemoryStore
+    AlreadyUsed,
+    /// No such captcha, or a malformed token.
+    UnknownId,
+    /// Submitted faster than a person could have, see [`SolveTimePolicy`].
+    TooFast,
+    /// A honeypot form field was filled in. Not returned by this crate; for
+    /// servers to report such requests alongside the other outcomes.
+    Honeypot,
+}
+
+impl VerifyOutcome {
+    pub fn is_correct(&self) -> bool {
+        *self == VerifyOutcome::Correct
+    }
+}
+
+/// Decides which solve times are suspicious, i.e. likely automated.
+pub trait SolveTimePolicy {
+    fn is_too_fast(&self, solve_time: Duration) -> bool;
+}
+
+/// Flags answers submitted in less than the given time.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct MinSolveTime(pub Duration);
+
+impl Default for MinSolveTime {
+    /// 1.5 seconds, about as fast as people type a short code.
+    fn default() -> Self {
+        Self(Duration::from_millis(1500))
+    }
+}
+
+impl SolveTimePolicy for MinSolveTime {
+    fn is_too_fast(&self, solve_time: Duration) -> bool {
+        solve_time < self.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::SystemTime;
+
+    use super::*;
+    use crate::{
+        CaptchaStore, MemoryStore,
+        test_util::{challenge, in_a_minute},
+    };
+
+    #[test]
+    fn flags_answers_faster_than_the_minimum() {
+        let policy = MinSolveTime::default();
+        assert!(policy.is_too_fast(Duration::from_millis(200)));
+        assert!(!policy.is_too_fast(Duration::from_secs(2)));
+    }
+
+    #[test]
+    fn rejects_fast_answers_right_or_wrong() {
+        let store = MemoryStore::new();
+        let policy = MinSolveTime(Duration::from_secs(60));
+        store.issue(&challenge("a", in_a_minute()));
+        store.issue(&challenge("b", in_a_minute()));
+        assert_eq!(
+            store.verify_timed("a", "7", &policy),
+            VerifyOutcome::TooFast
+        );
+        assert_eq!(
+            store.verify_timed("b", "8", &policy),
+            VerifyOutcome::TooFast
+        );
+    }
+
+    #[test]
+    fn accepts_answers_after_the_minimum() {
+        let store = MemoryStore::new();
+        let policy = MinSolveTime(Duration::from_secs(1));
+        let mut slow = challenge("a", in_a_minute());
+        slow.issued_at = SystemTime::now() - Duration::from_secs(5);
+        store.issue(&slow);
+        assert_eq!(
+            store.verify_timed("a", "7", &policy),
+            VerifyOutcome::Correct
+        );
+
+        // Unknown issue times never count as too fast.
+        store.issue(&slow);
+        let mut entry = store.take("a").unwrap();
+        entry.issued_at = None;
+        store.insert("a", entry);
+        assert_eq!(
+            store.verify_timed("a", "7", &policy),
+            VerifyOutcome::Correct
+        );
+    }
+}